@@ -1,3 +1,5 @@
+use crate::{error::design, Error};
+
 #[derive(Debug, PartialEq, Clone)]
 pub enum BinVerb {
     Plus,
@@ -16,7 +18,6 @@ pub enum BinVerb {
     Equal,
     NotEqual,
     Neg(Box<BinVerb>),
-    Empty,
 }
 
 #[derive(Debug, PartialEq, Clone)]
@@ -26,20 +27,6 @@ pub enum UniVerb {
     Minus,
 }
 
-#[derive(Debug, PartialEq, Clone)]
-pub enum Label {
-    ResultRewrite,
-    InstSource,
-    Commit,
-}
-
-#[derive(Debug, PartialEq, Clone)]
-pub enum DSLKeyword {
-    Transition,
-    Reset,
-    Complete,
-}
-
 #[derive(Debug, PartialEq, Clone)]
 pub enum ASTNode {
     Top(Vec<ASTNode>),
@@ -49,12 +36,6 @@ pub enum ASTNode {
         variable: String,
     },
 
-    Integer(i32),
-
-    Decimal(f64),
-
-    Str(String),
-
     Ident(String),
 
     ConstVal(String),
@@ -66,6 +47,7 @@ pub enum ASTNode {
     VariableDeclaration {
         typed_identifier: Box<ASTNode>, // type_identifier
         expr: Option<Box<ASTNode>>,
+        doc: Option<String>, // the `///` lines above it.
     },
 
     Assignment {
@@ -83,22 +65,50 @@ pub enum ASTNode {
     StructureDelcaration {
         s_type: String,
         name: String,
+        attributes: Vec<ASTNode>, // list of attribute
         statement: Box<ASTNode>,
+        doc: Option<String>,
     },
 
-    InternalFuncDecl(Box<ASTNode>),
+    Attribute {
+        name: String,
+        value: Option<Box<ASTNode>>, // const value or qualified name.
+    },
+
+    // i32 add(i32 a, i32 b) { ... }
+    InternalFuncDecl {
+        typed_identifier: Box<ASTNode>, // return type and name
+        params: Vec<ASTNode>,           // list of typed_identifier
+        body: Box<ASTNode>,
+        doc: Option<String>,
+    },
+
+    Return(Box<ASTNode>),
+
+    // `import "path" as namespace;`, the namespace defaults to the file's stem.
+    Import {
+        path: String,
+        namespace: Option<String>,
+    },
+
+    // `include "path";`
+    Include(String),
+
+    EnumDeclaration {
+        name: String,
+        variants: Vec<String>,
+    },
 
     CatchBlock {
         keyword: String,
         qualified_name: Box<ASTNode>, // function call, etc.
         idents: Vec<ASTNode>,         // arguments
+        source: Option<Box<ASTNode>>, // the unit raising the event.
         block: Box<ASTNode>,          // statements
     },
 
     Block(Vec<ASTNode>),
 
-    Expr(Expr),
-
     Listen {
         block: Box<ASTNode>,
         catch_block: Box<ASTNode>,
@@ -114,7 +124,7 @@ pub enum ASTNode {
     Await {
         keyword: String,
         call: Option<Box<ASTNode>>,
-        when_block: Box<ASTNode>,
+        when_block: Box<ASTNode>, // block of when.
     },
 
     When {
@@ -136,17 +146,291 @@ pub enum ASTNode {
         rhs: Box<ASTNode>,
     },
 
+    // assert(expr) and assume(expr), `always` is written at the controller level.
+    Assert {
+        expr: Box<ASTNode>,
+        always: bool,
+    },
+    Assume {
+        expr: Box<ASTNode>,
+        always: bool,
+    },
+
     UnuaryOp {
         verb: UniVerb,
         term: Box<ASTNode>,
     },
 
+    // `// ...` or `/* ... */`, only kept for the formatter. A trailing comment goes on the line of
+    // the statement before it.
+    Comment {
+        text: String,
+        trailing: bool,
+    },
+
     None,
 }
 
-#[derive(Debug, PartialEq, Clone)]
-pub enum Expr {
-    List(Vec<Expr>),
+impl BinVerb {
+    // The operator as written in AQL, a negated comparison is written as its opposite.
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            BinVerb::Plus => "+",
+            BinVerb::Minus => "-",
+            BinVerb::Times => "*",
+            BinVerb::Divide => "/",
+            BinVerb::And => "&",
+            BinVerb::Or => "|",
+            BinVerb::Xor => "^",
+            BinVerb::SmallerThan => "<",
+            BinVerb::LargerThan => ">",
+            BinVerb::SmallerOrEqual => "<=",
+            BinVerb::LargerOrEqual => ">=",
+            BinVerb::LeftShift => "<<",
+            BinVerb::RightShift => ">>",
+            BinVerb::Equal => "==",
+            BinVerb::NotEqual => "!=",
+            BinVerb::Neg(verb) => match verb.symbol() {
+                "<" => ">=",
+                ">" => "<=",
+                "<=" => ">",
+                ">=" => "<",
+                "==" => "!=",
+                "!=" => "==",
+                symbol => panic!("{} can't be negated.", symbol),
+            },
+        }
+    }
+}
+
+fn sources(nodes: &[ASTNode]) -> Result<Vec<String>, Error> {
+    nodes.iter().map(|node| node.to_source()).collect()
+}
+
+impl ASTNode {
+    // Any node as AQL source on one line, statements without their trailing `;`. A `None` the
+    // parser left for an expression it doesn't support is an error, the source would lose it.
+    pub fn to_source(&self) -> Result<String, Error> {
+        let ret = match self {
+            ASTNode::Ident(name) | ASTNode::ConstVal(name) => name.trim().to_string(),
+            ASTNode::QualifiedName { names } => sources(names)?.join("."),
+            ASTNode::BinOp { verb, lhs, rhs } => {
+                // The grammar takes one operator per term, so nested operators keep their parentheses.
+                let operand = |node: &ASTNode| match node {
+                    ASTNode::BinOp { .. } => Ok(format!("({})", node.to_source()?)),
+                    _ => node.to_source(),
+                };
+                format!("{} {} {}", operand(lhs)?, verb.symbol(), operand(rhs)?)
+            }
+            ASTNode::UnuaryOp { verb, term } => {
+                let verb = match verb {
+                    UniVerb::Not => "!",
+                    UniVerb::Tiled => "~",
+                    UniVerb::Minus => "-",
+                };
+                match term.as_ref() {
+                    ASTNode::BinOp { .. } => format!("{}({})", verb, term.to_source()?),
+                    _ => format!("{}{}", verb, term.to_source()?),
+                }
+            }
+            ASTNode::Call {
+                qualified_name,
+                list,
+            } => format!("{}({})", qualified_name.to_source()?, list.to_source()?),
+            ASTNode::ExprList(exprs) => sources(exprs)?.join(", "),
+            ASTNode::TypedIdentifier { aql_type, variable } => {
+                format!("{} {}", aql_type.trim(), variable.trim())
+            }
+            ASTNode::VariableDeclaration {
+                typed_identifier,
+                expr,
+                ..
+            } => match expr {
+                Some(expr) => format!("{} = {}", typed_identifier.to_source()?, expr.to_source()?),
+                None => typed_identifier.to_source()?,
+            },
+            ASTNode::Assignment { name, expr } => {
+                format!("{} = {}", name.trim(), expr.to_source()?)
+            }
+            ASTNode::Transition { action, ident } => format!("{} {}", action, ident.to_source()?),
+            ASTNode::Return(expr) => format!("return {}", expr.to_source()?),
+            ASTNode::Assert { expr, always } | ASTNode::Assume { expr, always } => {
+                let keyword = match self {
+                    ASTNode::Assert { .. } => "assert",
+                    _ => "assume",
+                };
+                let always = if *always { " always" } else { "" };
+                format!("{}{}({})", keyword, always, expr.to_source()?)
+            }
+            ASTNode::Attribute { name, value } => match value {
+                Some(value) => format!("{}: {}", name, value.to_source()?),
+                None => name.clone(),
+            },
+            ASTNode::Comment { text, .. } => text.clone(),
+            ASTNode::Import {
+                path,
+                namespace: Some(namespace),
+            } => format!("import \"{}\" as {}", path, namespace),
+            ASTNode::Import { path, .. } => format!("import \"{}\"", path),
+            ASTNode::Include(path) => format!("include \"{}\"", path),
+            ASTNode::EnumDeclaration { name, variants } => {
+                format!("enum {} {{ {} }}", name, variants.join(", "))
+            }
+            ASTNode::Declaration(decl) => decl.to_source()?,
+            // One declaration per line, so `//` comments are kept.
+            ASTNode::Top(decls) => {
+                let mut lines = vec![];
+                for decl in decls {
+                    let line = match decl {
+                        ASTNode::Comment { text, .. } => Some(text.clone()),
+                        ASTNode::Declaration(decl) => decl.statement()?,
+                        _ => decl.statement()?,
+                    };
+                    lines.extend(line);
+                }
+                lines.join("\n")
+            }
+            // Compound statements go on one line, the formatter lays them out.
+            ASTNode::Block(stmts) => {
+                let mut lines = vec![];
+                for stmt in stmts {
+                    lines.extend(stmt.statement()?);
+                }
+                match lines.is_empty() {
+                    true => String::from("{}"),
+                    false => format!("{{ {} }}", lines.join(" ")),
+                }
+            }
+            ASTNode::StructureDelcaration {
+                s_type,
+                name,
+                attributes,
+                statement,
+                ..
+            } => {
+                let attributes = match attributes.is_empty() {
+                    true => String::new(),
+                    false => format!(" ({})", sources(attributes)?.join(", ")),
+                };
+                format!("{} {}{} {}", s_type, name, attributes, statement.block()?)
+            }
+            ASTNode::InternalFuncDecl {
+                typed_identifier,
+                params,
+                body,
+                ..
+            } => format!(
+                "{}({}) {}",
+                typed_identifier.to_source()?,
+                sources(params)?.join(", "),
+                body.block()?
+            ),
+            ASTNode::Conditional {
+                expr,
+                if_blk,
+                else_blk,
+            } => {
+                let ret = format!("if ({}) {}", expr.to_source()?, if_blk.block()?);
+                match else_blk.as_ref() {
+                    ASTNode::None => ret,
+                    ASTNode::Conditional { .. } => {
+                        format!("{} else {}", ret, else_blk.to_source()?)
+                    }
+                    _ => format!("{} else {}", ret, else_blk.block()?),
+                }
+            }
+            ASTNode::Await {
+                call, when_block, ..
+            } => match call {
+                Some(call) => format!("await {} {}", call.to_source()?, when_block.block()?),
+                None => format!("await {}", when_block.block()?),
+            },
+            ASTNode::When {
+                call, ident, block, ..
+            } => format!(
+                "when {} from {} {}",
+                call.to_source()?,
+                ident.to_source()?,
+                block.block()?
+            ),
+            ASTNode::Listen { block, catch_block } => {
+                let mut ret = format!("listen {}", block.block()?);
+                if let ASTNode::Block(catches) = catch_block.as_ref() {
+                    for catch in catches {
+                        ret += &format!(" {}", catch.to_source()?);
+                    }
+                }
+                ret
+            }
+            ASTNode::CatchBlock {
+                qualified_name,
+                idents,
+                source,
+                block,
+                ..
+            } => {
+                let mut ret = format!(
+                    "handle {}({})",
+                    qualified_name.to_source()?,
+                    sources(idents)?.join(", ")
+                );
+                if let Some(source) = source {
+                    ret += &format!(" from {}", source.to_source()?);
+                }
+                format!("{} {}", ret, block.block()?)
+            }
+            ASTNode::None => {
+                return design!("An expression the parser doesn't support yet was left out.")
+            }
+        };
+        Ok(ret)
+    }
+
+    // A statement inside a one-line block, compound statements end with their block. Comments are
+    // left out, a `//` comment would swallow the rest of the line.
+    fn statement(&self) -> Result<Option<String>, Error> {
+        let ret = match self {
+            ASTNode::Comment { .. } => return Ok(None),
+            ASTNode::Block(_)
+            | ASTNode::StructureDelcaration { .. }
+            | ASTNode::InternalFuncDecl { .. }
+            | ASTNode::EnumDeclaration { .. }
+            | ASTNode::Conditional { .. }
+            | ASTNode::Await { .. }
+            | ASTNode::When { .. }
+            | ASTNode::Listen { .. }
+            | ASTNode::CatchBlock { .. } => self.to_source()?,
+            _ => format!("{};", self.to_source()?),
+        };
+        Ok(Some(ret))
+    }
+
+    // Bodies that aren't blocks are wrapped in one, an empty body is `{}`.
+    fn block(&self) -> Result<String, Error> {
+        match self {
+            ASTNode::Block(_) => self.to_source(),
+            ASTNode::None => Ok(String::from("{}")),
+            _ => ASTNode::Block(vec![self.clone()]).to_source(),
+        }
+    }
+
+    // A plain name, either an identifier or a qualified name with a single part.
+    pub fn name(&self) -> Option<String> {
+        match self {
+            ASTNode::Ident(name) => Some(name.trim().to_string()),
+            ASTNode::QualifiedName { names } if names.len() == 1 => names[0].name(),
+            _ => None,
+        }
+    }
 
-    DSLTerm,
+    // The parts of a qualified name joined with dots, `lsq.ReadyToIssue`.
+    pub fn dotted(&self) -> Option<String> {
+        match self {
+            ASTNode::QualifiedName { names } => {
+                let names: Option<Vec<String>> = names.iter().map(|name| name.name()).collect();
+                names.map(|names| names.join("."))
+            }
+            _ => self.name(),
+        }
+    }
 }
@@ -1,10 +1,20 @@
 use std::{
     collections::{HashMap, VecDeque},
     hash::Hash,
+    mem,
     rc::Rc,
 };
 
-use crate::{ast::BinVerb, ASTNode};
+use crate::{
+    ast::BinVerb,
+    error::design,
+    function::{self, Functions},
+    is_comparison,
+    memory_model::{self, MemoryModel},
+    mlir,
+    utils::{ConversionTable, Ordering},
+    ASTNode, Error,
+};
 use uuid::Uuid;
 
 #[derive(Debug, PartialEq, Clone)]
@@ -14,12 +24,36 @@ pub struct Transition {
     pub actions: Option<Vec<ASTNode>>,
 }
 
+impl Transition {
+    // The guards as AQL, `true` when there are none.
+    pub fn guard_source(&self) -> Result<String, Error> {
+        let guards = self
+            .guards
+            .iter()
+            .flatten()
+            .map(|guard| guard.to_source())
+            .collect::<Result<Vec<_>, _>>()?;
+        if guards.is_empty() {
+            Ok(String::from("true"))
+        } else {
+            Ok(guards.join(" && "))
+        }
+    }
+
+    pub fn action_source(&self) -> Result<Vec<String>, Error> {
+        self.actions
+            .iter()
+            .flatten()
+            .map(|action| action.to_source())
+            .collect()
+    }
+}
+
 #[derive(Debug, Clone)]
 struct Vertex {
     transition_target: Option<String>,
     label: Uuid,
     condition: Option<ASTNode>,
-    is_else: bool,
 }
 
 impl Vertex {
@@ -28,7 +62,6 @@ impl Vertex {
             transition_target: None,
             label,
             condition: None,
-            is_else: false,
         }
     }
 }
@@ -54,9 +87,7 @@ impl Transition {
                 guards.push(guard);
             }
             None => {
-                let mut temp = vec![];
-                temp.push(guard);
-                self.guards = Some(temp);
+                self.guards = Some(vec![guard]);
             }
         }
     }
@@ -67,9 +98,7 @@ impl Transition {
                 actions.push(action);
             }
             None => {
-                let mut temp = vec![];
-                temp.push(action);
-                self.actions = Some(temp);
+                self.actions = Some(vec![action]);
             }
         }
     }
@@ -117,6 +146,12 @@ pub struct State {
     pub scope: Scope,
     pub insts: Vec<Inst>,
     pub next: Transitions,
+    pub calls: Vec<ASTNode>, // requests to other units, issued while in this state.
+    pub terminal: bool,      // tagged `complete`, the entry is done once it gets here.
+    // assert/assume holding while in this state, in every state for the controller entry.
+    pub properties: Vec<ASTNode>,
+    pub body: Option<Rc<ControlFlow>>, // the blocks and branches the transitions come from.
+    pub doc: Option<String>,           // the `///` lines above the declaration.
 }
 
 #[derive(Debug, Clone)]
@@ -158,12 +193,120 @@ impl ControlFlow {
     }
 }
 
+// The unit on the other side of a port: the callee of a request, or the source of an event.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Channel {
+    pub unit: Option<String>,
+    pub method: String,
+    pub arg: Option<usize>, // None for the valid bit.
+}
+
+// A single input or output of the generated machine.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Port {
+    pub name: String,
+    pub ty: String,
+    pub channel: Option<Channel>,
+}
+
+impl Port {
+    pub fn new(name: &str, ty: &str, unit: Option<&str>, method: &str, arg: Option<usize>) -> Self {
+        Port {
+            name: String::from(name),
+            ty: String::from(ty),
+            channel: Some(Channel {
+                unit: unit.map(String::from),
+                method: String::from(method),
+                arg,
+            }),
+        }
+    }
+}
+
+// Used when the state_queue doesn't declare `num_entries`.
+pub const DEFAULT_QUEUE_DEPTH: u32 = 8;
+
+// The storage holding one copy of the controller entry per queue slot.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StateQueue {
+    pub name: String,
+    pub ordering: Ordering,
+    pub num_entries: u32,
+}
+
 #[derive(Debug)]
 pub struct StateMachine {
     pub fsm_name: String,
     pub entry: String,
-    pub map: HashMap<Scope, Rc<State>>,
     pub cfgs: Vec<Rc<State>>,
+    pub inputs: Vec<Port>,
+    pub outputs: Vec<Port>,
+    pub queue: Option<StateQueue>,
+    pub types: ConversionTable,
+}
+
+impl StateMachine {
+    // What convert produced for one controller, for `--emit cfg`.
+    pub fn describe(&self) -> Result<String, Error> {
+        let mut ret = format!("controller {}\n", self.fsm_name);
+        ret += &format!("  init_entry {}\n", self.entry);
+        if let Some(queue) = &self.queue {
+            ret += &format!(
+                "  state_queue {} {:?} {} entries\n",
+                queue.name, queue.ordering, queue.num_entries
+            );
+        }
+        for port in &self.inputs {
+            ret += &format!("  input {}: {}\n", port.name, port.ty);
+        }
+        for port in &self.outputs {
+            ret += &format!("  output {}: {}\n", port.name, port.ty);
+        }
+
+        for cfg in &self.cfgs {
+            if cfg.scope.is_structure(&Structure::ControllerEntry) {
+                for Inst::Stmt(stmt) in &cfg.insts {
+                    if let ASTNode::VariableDeclaration { .. } = stmt {
+                        ret += &format!("  variable {}\n", stmt.to_source()?);
+                    }
+                }
+            }
+        }
+
+        for cfg in &self.cfgs {
+            if !cfg.scope.is_structure(&Structure::State) {
+                continue;
+            }
+            let terminal = if cfg.terminal { " (complete)" } else { "" };
+            ret += &format!("  state {}{}\n", cfg.scope.name, terminal);
+            for call in &cfg.calls {
+                ret += &format!("    call {}\n", call.to_source()?);
+            }
+            for (label, property) in properties(self, cfg) {
+                ret += &format!("    {}: {}\n", label, property.to_source()?);
+            }
+            for (i, transition) in cfg.next.trans.iter().enumerate() {
+                ret += &format!(
+                    "    {}: -> {} if {}",
+                    i,
+                    transition.target,
+                    transition.guard_source()?
+                );
+                let actions = transition.action_source()?;
+                if !actions.is_empty() {
+                    ret += &format!(" / {}", actions.join("; "));
+                }
+                ret += "\n";
+            }
+        }
+        Ok(ret)
+    }
+}
+
+impl Default for StateMachine {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl StateMachine {
@@ -171,22 +314,20 @@ impl StateMachine {
         Self {
             fsm_name: String::new(),
             entry: String::new(),
-            map: HashMap::new(),
             cfgs: vec![],
+            // Filled in from the events the states wait for and the requests they make.
+            inputs: vec![],
+            outputs: vec![],
+            queue: None,
+            types: ConversionTable::new(),
         }
     }
 
-    pub fn get_cfg(&self, scope: &Scope) -> Rc<State> {
-        Rc::clone(&self.map[scope])
-    }
-
-    pub fn get_cfg_structure(&self, key: Structure) -> Rc<State> {
-        for cfg in &self.cfgs {
-            if cfg.scope.is_structure(&key) {
-                return Rc::clone(&cfg);
-            }
-        }
-        panic!("The program didn't provide us the init state.");
+    pub fn get_cfg_structure(&self, key: Structure) -> Option<Rc<State>> {
+        self.cfgs
+            .iter()
+            .find(|cfg| cfg.scope.is_structure(&key))
+            .map(Rc::clone)
     }
 
     pub fn get_cfg_name_by_structure(&self, key: Structure) -> String {
@@ -198,10 +339,16 @@ impl StateMachine {
         String::new()
     }
 
-    pub fn insert_cfg(&mut self, scope: Scope, cfg: State) {
-        let cfg_cell = Rc::new(cfg);
-        self.map.insert(scope, Rc::clone(&cfg_cell));
-        self.cfgs.push(Rc::clone(&cfg_cell));
+    pub fn insert_cfg(&mut self, cfg: State) {
+        self.cfgs.push(Rc::new(cfg));
+    }
+
+    // The documentation of the controller entry.
+    pub fn doc(&self) -> Option<&str> {
+        self.cfgs
+            .iter()
+            .find(|cfg| cfg.scope.is_structure(&Structure::ControllerEntry))
+            .and_then(|cfg| cfg.doc.as_deref())
     }
 }
 
@@ -211,6 +358,11 @@ impl State {
             scope,
             insts: vec![],
             next: Transitions::new(),
+            calls: vec![],
+            terminal: false,
+            properties: vec![],
+            body: None,
+            doc: None,
         }
     }
 
@@ -220,13 +372,6 @@ impl State {
 }
 
 impl Scope {
-    pub fn new() -> Self {
-        Scope {
-            label: Structure::None,
-            name: String::new(),
-        }
-    }
-
     pub fn from(label: Structure, name: String) -> Self {
         Scope { label, name }
     }
@@ -240,7 +385,7 @@ fn collect_transitions(
     root: &ControlFlow,
     label2cfg: &HashMap<Uuid, Rc<ControlFlow>>,
     transition_labels: &Vec<Uuid>,
-) -> Transitions {
+) -> Result<Transitions, Error> {
     // let mut conditionals = vec![];
     // let mut actions = vec![];
     let mut transitions = Transitions::new();
@@ -248,53 +393,106 @@ fn collect_transitions(
     for target in transition_labels {
         let mut paths: Vec<VecDeque<Vertex>> = vec![];
         let mut path: VecDeque<Vertex> = VecDeque::new();
-        dfs(&target, root, &mut paths, &mut path, None, false); // The first stmt is unconditional.
+        dfs(target, root, &mut paths, &mut path, None, false)?; // The first stmt is unconditional.
 
         // println!("paths: {:?}\n", paths);
-        let mut conditonals = vec![];
-        let mut actions = vec![];
-
         for path in paths {
+            let mut conditonals = vec![];
+            let mut actions = vec![];
+            // State-local declarations seen so far, guards read them through their value.
+            let mut temporaries = HashMap::new();
+
             for vertex in path {
                 // add condition along the way. todo(add else condition, negation)
-                match vertex.condition {
-                    Some(cond) => {
-                        conditonals.push(cond);
-                    }
-                    None => {}
+                if let Some(cond) = vertex.condition {
+                    conditonals.push(substitute(&cond, &temporaries));
                 }
 
                 let control_flow = label2cfg.get(&vertex.label).unwrap();
                 for inst in &control_flow.insts {
                     // We ignore the transition and after that.
                     let Inst::Stmt(stmt) = inst;
-                    if let ASTNode::Transition { .. } = stmt {
-                        break;
+                    match stmt {
+                        ASTNode::Transition { .. } => break,
+                        ASTNode::VariableDeclaration {
+                            typed_identifier,
+                            expr,
+                            ..
+                        } => {
+                            if let ASTNode::TypedIdentifier { variable, .. } =
+                                typed_identifier.as_ref()
+                            {
+                                let value = match expr {
+                                    Some(expr) => substitute(expr, &temporaries),
+                                    None => ASTNode::ConstVal(String::from("0")),
+                                };
+                                temporaries.insert(variable.clone(), value);
+                            }
+                        }
+                        ASTNode::Assignment { name, expr } if temporaries.contains_key(name) => {
+                            let value = substitute(expr, &temporaries);
+                            temporaries.insert(name.clone(), value);
+                        }
+                        _ => {}
                     }
 
                     actions.push(stmt.clone());
                 }
 
-                match vertex.transition_target {
-                    Some(target) => {
-                        let mut transition = Transition::new(target);
-                        for guard in &conditonals {
-                            transition.insert_guard(guard.clone());
-                        }
-
-                        for action in &actions {
-                            transition.insert_action(action.clone());
-                        }
+                if let Some(target) = vertex.transition_target {
+                    let mut transition = Transition::new(target);
+                    for guard in &conditonals {
+                        transition.insert_guard(guard.clone());
+                    }
 
-                        transitions.insert(transition);
+                    for action in &actions {
+                        transition.insert_action(action.clone());
                     }
-                    None => {}
+
+                    transitions.insert(transition);
                 }
             }
         }
     }
 
-    transitions
+    Ok(transitions)
+}
+
+// Replaces temporaries by the expression they hold.
+pub fn substitute(node: &ASTNode, temporaries: &HashMap<String, ASTNode>) -> ASTNode {
+    match node {
+        ASTNode::Ident(name) => match temporaries.get(name) {
+            Some(value) => value.clone(),
+            None => node.clone(),
+        },
+        ASTNode::QualifiedName { names } if names.len() == 1 => match &names[0] {
+            ASTNode::Ident(name) if temporaries.contains_key(name) => temporaries[name].clone(),
+            _ => node.clone(),
+        },
+        ASTNode::BinOp { verb, lhs, rhs } => ASTNode::BinOp {
+            verb: verb.clone(),
+            lhs: Box::new(substitute(lhs, temporaries)),
+            rhs: Box::new(substitute(rhs, temporaries)),
+        },
+        ASTNode::UnuaryOp { verb, term } => ASTNode::UnuaryOp {
+            verb: verb.clone(),
+            term: Box::new(substitute(term, temporaries)),
+        },
+        ASTNode::Call {
+            qualified_name,
+            list,
+        } => ASTNode::Call {
+            qualified_name: qualified_name.clone(),
+            list: Box::new(substitute(list, temporaries)),
+        },
+        ASTNode::ExprList(exprs) => ASTNode::ExprList(
+            exprs
+                .iter()
+                .map(|expr| substitute(expr, temporaries))
+                .collect(),
+        ),
+        _ => node.clone(),
+    }
 }
 
 fn dfs(
@@ -304,7 +502,7 @@ fn dfs(
     path: &mut VecDeque<Vertex>,
     cond: Option<ASTNode>,
     is_else: bool,
-) {
+) -> Result<(), Error> {
     // search transition, and record the actions and conditions through the traveral.
     // conds must be expr and actions must be statements.
     // if let ASTNode::Transition { action, ident } = root {
@@ -328,7 +526,7 @@ fn dfs(
                     transition_target = Some(target);
                     terminate = true;
                 } else {
-                    panic!("The last stmt is not the transition, invalid syntax.");
+                    return design!("transition goes to a state, not {}.", ident.to_source()?);
                 }
             }
             // It's ok if there is no transition at the end.
@@ -342,28 +540,32 @@ fn dfs(
         // We negate the conditional in the else branch.
         if let Some(c) = cond {
             // TODO(Optimization)
-            if let ASTNode::BinOp { verb, lhs, rhs } = c {
-                let negation_verb = BinVerb::Neg(Box::new(verb));
-                let condition = Some(ASTNode::BinOp {
-                    verb: negation_verb,
-                    lhs,
-                    rhs,
-                });
-                path.push_back(Vertex {
-                    transition_target,
-                    label: node.label,
-                    condition,
-                    is_else,
-                });
-            } else {
-                todo!("Unimplemented operation.");
+            match c {
+                ASTNode::BinOp { verb, lhs, rhs } if is_comparison(&verb) => {
+                    let negation_verb = BinVerb::Neg(Box::new(verb));
+                    let condition = Some(ASTNode::BinOp {
+                        verb: negation_verb,
+                        lhs,
+                        rhs,
+                    });
+                    path.push_back(Vertex {
+                        transition_target,
+                        label: node.label,
+                        condition,
+                    });
+                }
+                c => {
+                    return design!(
+                        "if ({}) has an else branch, its condition should be a comparison.",
+                        c.to_source()?
+                    )
+                }
             }
         } else {
             path.push_back(Vertex {
                 transition_target,
                 label: node.label,
                 condition: cond,
-                is_else,
             });
         }
     } else {
@@ -371,7 +573,6 @@ fn dfs(
             transition_target,
             label: node.label,
             condition: cond,
-            is_else,
         });
     }
 
@@ -379,26 +580,298 @@ fn dfs(
         paths.push(path.clone());
     } else if !terminate {
         // If we didn't find any transitions in this graph, we can move further.
-        match &node.lhs {
-            Some(lhs) => {
-                if !path.contains(&Vertex::comparator(lhs.label.clone())) {
-                    dfs(target, &lhs, paths, path, node.cond.clone(), false);
+        if let Some(lhs) = &node.lhs {
+            if !path.contains(&Vertex::comparator(lhs.label)) {
+                dfs(target, lhs, paths, path, node.cond.clone(), false)?;
+            }
+        }
+
+        if let Some(rhs) = &node.rhs {
+            if !path.contains(&Vertex::comparator(rhs.label)) {
+                dfs(target, rhs, paths, path, node.cond.clone(), true)?;
+            }
+        }
+    }
+
+    path.pop_back();
+    Ok(())
+}
+
+fn append_block(node: ASTNode, rest: &[ASTNode]) -> ASTNode {
+    let mut stmts = match node {
+        ASTNode::Block(stmts) => stmts,
+        ASTNode::None => vec![],
+        stmt => vec![stmt],
+    };
+    stmts.extend_from_slice(rest);
+    ASTNode::Block(stmts)
+}
+
+// The ports of a request or an event between two units start with `{unit}_{method}`.
+pub fn port_prefix(unit: &str, method: &str) -> String {
+    format!("{}_{}", mlir::symbol(unit), method)
+}
+
+// Input ports carrying an event raised by another unit: `{unit}_{event}_valid` and one port per
+// argument.
+fn event_ports(
+    source: Option<String>,
+    event: &ASTNode,
+    args: &[String],
+) -> Result<(String, Vec<Port>), Error> {
+    let name = match event {
+        ASTNode::QualifiedName { names } => names.last().and_then(|name| name.name()),
+        _ => event.name(),
+    };
+    let Some(event) = name else {
+        return design!("Events should be named, not {}.", event.to_source()?);
+    };
+    let prefix = match &source {
+        Some(source) => port_prefix(source, &event),
+        None => event.clone(),
+    };
+    let source = source.as_deref();
+
+    let mut ports = vec![Port::new(
+        &format!("{}_valid", prefix),
+        "i1",
+        source,
+        &event,
+        None,
+    )];
+    for (i, arg) in args.iter().enumerate() {
+        let name = format!("{}_{}", prefix, arg);
+        ports.push(Port::new(&name, "i32", source, &event, Some(i)));
+    }
+    Ok((prefix, ports))
+}
+
+// if ({prefix}_valid == 1) { i32 arg = {prefix}_arg; ...stmts } else { ...otherwise }
+fn event_branch(prefix: &str, args: &[String], block: ASTNode, otherwise: ASTNode) -> ASTNode {
+    let mut stmts = vec![];
+    for arg in args {
+        stmts.push(ASTNode::VariableDeclaration {
+            typed_identifier: Box::new(ASTNode::TypedIdentifier {
+                aql_type: String::from("i32"),
+                variable: arg.clone(),
+            }),
+            expr: Some(Box::new(ASTNode::Ident(format!("{}_{}", prefix, arg)))),
+            doc: None,
+        });
+    }
+    let ASTNode::Block(block) = append_block(block, &[]) else {
+        unreachable!()
+    };
+    stmts.extend(block);
+
+    ASTNode::Conditional {
+        expr: Box::new(ASTNode::BinOp {
+            verb: BinVerb::Equal,
+            lhs: Box::new(ASTNode::Ident(format!("{}_valid", prefix))),
+            rhs: Box::new(ASTNode::ConstVal(String::from("1"))),
+        }),
+        if_blk: Box::new(ASTNode::Block(stmts)),
+        else_blk: Box::new(otherwise),
+    }
+}
+
+fn insert_port(ports: &mut Vec<Port>, port: Port) {
+    if !ports.iter().any(|p| p.name == port.name) {
+        ports.push(port);
+    }
+}
+
+// Lowers listen/await into plain conditionals on event inputs, so the rest of the conversion
+// only deals with transitions. Handlers are checked before the listened block and the first
+// event that fires wins. An await without any event stays in the state.
+fn desugar(
+    node: ASTNode,
+    inputs: &mut Vec<Port>,
+    calls: &mut Vec<ASTNode>,
+) -> Result<ASTNode, Error> {
+    let ret = match node {
+        ASTNode::Block(stmts) => {
+            let mut ret = vec![];
+            for stmt in stmts {
+                match desugar(stmt, inputs, calls)? {
+                    ASTNode::Block(inner) => ret.extend(inner),
+                    stmt => ret.push(stmt),
+                }
+            }
+            ASTNode::Block(ret)
+        }
+        ASTNode::Conditional {
+            expr,
+            if_blk,
+            else_blk,
+        } => ASTNode::Conditional {
+            expr,
+            if_blk: Box::new(desugar(*if_blk, inputs, calls)?),
+            else_blk: Box::new(desugar(*else_blk, inputs, calls)?),
+        },
+        ASTNode::Listen { block, catch_block } => {
+            let mut ret = desugar(*block, inputs, calls)?;
+            let catches = match *catch_block {
+                ASTNode::Block(catches) => catches,
+                catch => vec![catch],
+            };
+
+            for catch in catches.into_iter().rev() {
+                if let ASTNode::CatchBlock {
+                    qualified_name,
+                    idents,
+                    source,
+                    block,
+                    ..
+                } = catch
+                {
+                    let args: Vec<String> = idents.iter().filter_map(|i| i.name()).collect();
+                    let source = source.and_then(|source| source.name());
+                    let (prefix, ports) = event_ports(source, &qualified_name, &args)?;
+                    for port in ports {
+                        insert_port(inputs, port);
+                    }
+                    let block = desugar(*block, inputs, calls)?;
+                    ret = event_branch(&prefix, &args, block, ret);
+                }
+            }
+            ret
+        }
+        ASTNode::Await {
+            call, when_block, ..
+        } => {
+            let mut ret = ASTNode::None;
+            let whens = match *when_block {
+                ASTNode::Block(whens) => whens,
+                when => vec![when],
+            };
+
+            for when in whens.into_iter().rev() {
+                if let ASTNode::When {
+                    call, ident, block, ..
+                } = when
+                {
+                    let ASTNode::Call {
+                        qualified_name,
+                        list,
+                    } = *call
+                    else {
+                        return design!(
+                            "when should wait for an event, not {}.",
+                            call.to_source()?
+                        );
+                    };
+                    let args: Vec<String> = match *list {
+                        ASTNode::ExprList(args) => args.iter().filter_map(|a| a.name()).collect(),
+                        _ => vec![],
+                    };
+                    let (prefix, ports) = event_ports(ident.name(), &qualified_name, &args)?;
+                    for port in ports {
+                        insert_port(inputs, port);
+                    }
+                    let block = desugar(*block, inputs, calls)?;
+                    ret = event_branch(&prefix, &args, block, ret);
                 }
             }
-            None => (),
+
+            match call {
+                Some(call) => {
+                    let call = desugar(*call, inputs, calls)?;
+                    ASTNode::Block(vec![call, ret])
+                }
+                None => ret,
+            }
+        }
+        ASTNode::Call {
+            ref qualified_name, ..
+        } => {
+            // Unit.request(args) is a request to another unit.
+            if let ASTNode::QualifiedName { names } = qualified_name.as_ref() {
+                // The same call can show up in several branches of the state.
+                if names.len() > 1 && !calls.contains(&node) {
+                    calls.push(node.clone());
+                }
+            }
+            node
         }
+        _ => node,
+    };
+    Ok(ret)
+}
 
-        match &node.rhs {
-            Some(rhs) => {
-                if !path.contains(&Vertex::comparator(rhs.label.clone())) {
-                    dfs(target, &rhs, paths, path, node.cond.clone(), true);
+// The request a call makes: (unit, method, args).
+pub fn get_request(call: &ASTNode) -> Option<(String, String, Vec<ASTNode>)> {
+    if let ASTNode::Call {
+        qualified_name,
+        list,
+    } = call
+    {
+        if let ASTNode::QualifiedName { names } = qualified_name.as_ref() {
+            let names: Vec<String> = names.iter().filter_map(|n| n.name()).collect();
+            if names.len() > 1 {
+                let method = names[names.len() - 1].clone();
+                let unit = names[..names.len() - 1].join("_");
+                let args = match list.as_ref() {
+                    ASTNode::ExprList(args) => args.clone(),
+                    _ => vec![],
+                };
+                return Some((unit, method, args));
+            }
+        }
+    }
+    None
+}
+
+// The declared type of every controller entry variable.
+pub fn variable_types(cfgs: &StateMachine) -> Result<HashMap<String, String>, Error> {
+    let mut ret = HashMap::new();
+    for cfg in &cfgs.cfgs {
+        if !cfg.scope.is_structure(&Structure::ControllerEntry) {
+            continue;
+        }
+        for inst in &cfg.insts {
+            let Inst::Stmt(stmt) = inst;
+            if let ASTNode::VariableDeclaration {
+                typed_identifier, ..
+            } = stmt
+            {
+                if let ASTNode::TypedIdentifier { aql_type, variable } = typed_identifier.as_ref() {
+                    let mlir_type = cfgs.types.convert(aql_type)?.mlir_type();
+                    ret.insert(variable.clone(), mlir_type);
                 }
             }
-            None => (),
+        }
+    }
+    Ok(ret)
+}
+
+// Every request a state makes becomes `{unit}_{method}_valid` plus one output per argument.
+fn collect_outputs(cfgs: &mut StateMachine) -> Result<(), Error> {
+    let types = variable_types(cfgs)?;
+    let mut outputs = vec![];
+
+    for cfg in &cfgs.cfgs {
+        for call in &cfg.calls {
+            let Some((unit, method, args)) = get_request(call) else {
+                continue;
+            };
+            let prefix = port_prefix(&unit, &method);
+            let valid = format!("{}_valid", prefix);
+            let unit = Some(unit.as_str());
+            insert_port(&mut outputs, Port::new(&valid, "i1", unit, &method, None));
+            for (i, arg) in args.iter().enumerate() {
+                let ty = arg
+                    .name()
+                    .and_then(|name| types.get(&name).cloned())
+                    .unwrap_or(String::from("i32"));
+                let name = format!("{}_arg{}", prefix, i);
+                insert_port(&mut outputs, Port::new(&name, &ty, unit, &method, Some(i)));
+            }
         }
     }
 
-    path.pop_back();
+    cfgs.outputs = outputs;
+    Ok(())
 }
 
 // We use the control flow graph here to execute some analysis (DFS, BFS, etc).
@@ -406,12 +879,12 @@ fn get_cfg(
     blk: &ASTNode,
     transition_labels: &mut Vec<Uuid>,
     label2cfg: &mut HashMap<Uuid, Rc<ControlFlow>>,
-) -> Rc<ControlFlow> {
+) -> Result<Rc<ControlFlow>, Error> {
     // Get the structure_decl inner block.
     let mut cfg = ControlFlow::new();
 
     if let ASTNode::Block(blk) = blk {
-        for stmt_raw in blk {
+        for (i, stmt_raw) in blk.iter().enumerate() {
             match stmt_raw.clone() {
                 ASTNode::Assignment { .. } => {
                     let inst = Inst::Stmt(stmt_raw.clone());
@@ -426,24 +899,35 @@ fn get_cfg(
                     let inst = Inst::Stmt(stmt_raw.clone());
                     cfg.add_new_inst(inst);
                     transition_labels.push(cfg.label);
+                    break; // anything after the transition is unreachable.
                 }
                 ASTNode::Conditional {
                     expr,
                     if_blk,
                     else_blk,
                 } => {
+                    // The statements after the conditional continue both branches.
+                    let rest = &blk[i + 1..];
                     cfg.add_cond(*expr);
-                    cfg.add_next_lhs(get_cfg(&*if_blk, transition_labels, label2cfg));
-
-                    if let ASTNode::None = *else_blk {
-                    } else {
-                        cfg.add_next_rhs(get_cfg(&*else_blk, transition_labels, label2cfg));
-                        break; // reaheability.
-                    }
+                    cfg.add_next_lhs(get_cfg(
+                        &append_block(*if_blk, rest),
+                        transition_labels,
+                        label2cfg,
+                    )?);
+                    cfg.add_next_rhs(get_cfg(
+                        &append_block(*else_blk, rest),
+                        transition_labels,
+                        label2cfg,
+                    )?);
+                    break; // reaheability.
+                }
+                ASTNode::Call { .. } => {
+                    // Requests are outputs of the state, they are collected by desugar.
                 }
-                _ => {
-                    println!("Unimplementated stmt.");
+                ASTNode::Assert { .. } | ASTNode::Assume { .. } => {
+                    return design!("assert and assume only go at the top level of a state.");
                 }
+                stmt => return design!("{} can't go in a state.", stmt.to_source()?),
             }
         }
     }
@@ -451,10 +935,67 @@ fn get_cfg(
     let cfg = Rc::new(cfg);
     label2cfg.insert(cfg.label, cfg.clone());
 
-    cfg
+    Ok(cfg)
 }
 
-fn convert_struct(s_type: &str, name: &str, node: ASTNode, cfgs: &mut StateMachine) {
+fn is_property(node: &ASTNode) -> bool {
+    matches!(node, ASTNode::Assert { .. } | ASTNode::Assume { .. })
+}
+
+// LQ.lq_run.assert0 for the first property of state lq_run, LQ.assume1 for the second property of
+// the controller itself.
+pub fn property_label(
+    controller: &str,
+    state: Option<&str>,
+    index: usize,
+    property: &ASTNode,
+) -> String {
+    let keyword = match property {
+        ASTNode::Assume { .. } => "assume",
+        _ => "assert",
+    };
+    match state {
+        Some(state) => format!("{}.{}.{}{}", controller, state, keyword, index),
+        None => format!("{}.{}{}", controller, keyword, index),
+    }
+}
+
+// The properties checked while in `state` with their label, its own and then the controller's.
+pub fn properties<'a>(cfgs: &'a StateMachine, state: &'a State) -> Vec<(String, &'a ASTNode)> {
+    let mut ret = vec![];
+    for (i, property) in state.properties.iter().enumerate() {
+        let label = property_label(&cfgs.fsm_name, Some(&state.scope.name), i, property);
+        ret.push((label, property));
+    }
+    for cfg in &cfgs.cfgs {
+        if cfg.scope.is_structure(&Structure::ControllerEntry) {
+            for (i, property) in cfg.properties.iter().enumerate() {
+                ret.push((property_label(&cfgs.fsm_name, None, i, property), property));
+            }
+        }
+    }
+    ret
+}
+
+// Splits the assert/assume statements of a state body from the rest.
+pub fn take_properties(node: ASTNode) -> (Vec<ASTNode>, ASTNode) {
+    match node {
+        ASTNode::Block(stmts) => {
+            let (properties, stmts) = stmts.into_iter().partition(is_property);
+            (properties, ASTNode::Block(stmts))
+        }
+        node => (vec![], node),
+    }
+}
+
+fn convert_struct(
+    s_type: &str,
+    name: &str,
+    attributes: &[ASTNode],
+    node: ASTNode,
+    doc: Option<String>,
+    cfgs: &mut StateMachine,
+) -> Result<(), Error> {
     // node is the structure_declaration.
 
     let structure;
@@ -474,16 +1015,44 @@ fn convert_struct(s_type: &str, name: &str, node: ASTNode, cfgs: &mut StateMachi
     let scope = Scope::from(structure.clone(), String::from(name));
 
     if let Structure::State = structure {
+        let (properties, node) = take_properties(node);
+        if properties.iter().any(|property| {
+            matches!(
+                property,
+                ASTNode::Assert { always: true, .. } | ASTNode::Assume { always: true, .. }
+            )
+        }) {
+            return design!(
+                "state {}: `always` properties go in the controller or its controller_entry.",
+                name
+            );
+        }
+        let mut calls = vec![];
+        let node = desugar(node, &mut cfgs.inputs, &mut calls)?;
+
         let mut transitions_labels = vec![];
         let mut label2cfg = HashMap::new();
-        let cfg = get_cfg(&node, &mut transitions_labels, &mut label2cfg);
-        let transitions = collect_transitions(&cfg, &label2cfg, &transitions_labels);
-
-        let mut cfg = State::new(scope.clone());
+        // get_cfg doesn't know which state it is in.
+        let cfg = get_cfg(&node, &mut transitions_labels, &mut label2cfg).map_err(|e| match e {
+            Error::Design(message) => Error::Design(format!("{} {}: {}", s_type, name, message)),
+            e => e,
+        })?;
+        let transitions = collect_transitions(&cfg, &label2cfg, &transitions_labels)?;
+        let body = cfg;
+
+        let mut cfg = State::new(scope);
         cfg.next = transitions;
-        cfgs.insert_cfg(scope, cfg)
+        cfg.calls = calls;
+        cfg.properties = properties;
+        cfg.body = Some(body);
+        cfg.doc = doc;
+        cfg.terminal = attributes.iter().any(|attribute| {
+            matches!(attribute, ASTNode::Attribute { name, value: None } if name == "complete")
+        });
+        cfgs.insert_cfg(cfg)
     } else {
-        let mut cfg = State::new(scope.clone());
+        let mut cfg = State::new(scope);
+        cfg.doc = doc;
         if let ASTNode::Block(blk) = node {
             let mut transitions = Transitions::new();
 
@@ -510,6 +1079,9 @@ fn convert_struct(s_type: &str, name: &str, node: ASTNode, cfgs: &mut StateMachi
                         let inst = Inst::Stmt(stmt_raw);
                         cfg.insert_inst(inst);
                     }
+                    ASTNode::Assert { .. } | ASTNode::Assume { .. } => {
+                        cfg.properties.push(stmt_raw);
+                    }
                     ASTNode::Transition { action, ident } => {
                         // Direct transition without any conditions.
                         if action == "transition" {
@@ -518,61 +1090,208 @@ fn convert_struct(s_type: &str, name: &str, node: ASTNode, cfgs: &mut StateMachi
                                 let transition = Transition::new(target.clone());
                                 transitions.insert(transition);
                             }
-                        } else if action == "complete" {
-                        } else if action == "reset" {
-                        } else {
-                            panic!("unknown action.");
+                        } else if action != "complete" && action != "reset" {
+                            return design!("{} {}: unknown action {}.", s_type, name, action);
                         }
                     }
-                    _ => {
-                        println!("Unimplementated stmt.");
+                    stmt => {
+                        return design!(
+                            "{} {}: {} can't go in a {}.",
+                            s_type,
+                            name,
+                            stmt.to_source()?,
+                            s_type
+                        );
                     }
                 }
             }
 
             cfg.next = transitions;
-            cfgs.insert_cfg(scope, cfg)
+            cfgs.insert_cfg(cfg)
         }
     }
+    Ok(())
 }
 
-pub fn convert(node: ASTNode) -> StateMachine {
-    let mut cfgs = StateMachine::new();
+// A structure declaration waiting to be converted into the machine of its controller.
+#[derive(Debug, Clone)]
+pub struct OwnedStructure {
+    pub s_type: String,
+    pub name: String,
+    pub attributes: Vec<ASTNode>,
+    pub statement: ASTNode,
+    pub doc: Option<String>,
+}
 
-    let ret = match node {
-        ASTNode::Top(decls) => {
-            // search for the
-            for decl in decls {
-                // should only be structure declaration or internal_func_decl.
-                if let ASTNode::Declaration(structure) = decl {
-                    match *structure {
-                        ASTNode::StructureDelcaration {
-                            s_type,
-                            name,
-                            statement,
-                        } => {
-                            convert_struct(&s_type, &name, *statement, &mut cfgs);
-                            // statement can be block or instructions, in this case, it's block.
-                        }
-                        ASTNode::InternalFuncDecl(_) => {
-                            todo!()
-                        }
-                        _ => {
-                            todo!() // report errors.
-                        }
-                    }
+// Structures grouped by the controller that owns them, in declaration order.
+#[derive(Default)]
+struct Controllers {
+    groups: Vec<(String, Vec<OwnedStructure>)>,
+    unowned: Vec<OwnedStructure>,
+    // `assert always` written in a controller block, moved into its controller entry.
+    properties: Vec<(String, ASTNode)>,
+}
+
+impl Controllers {
+    fn group(&mut self, owner: &str) -> &mut Vec<OwnedStructure> {
+        let index = match self.groups.iter().position(|(name, _)| name == owner) {
+            Some(index) => index,
+            None => {
+                self.groups.push((String::from(owner), vec![]));
+                self.groups.len() - 1
+            }
+        };
+        &mut self.groups[index].1
+    }
+
+    fn insert(&mut self, owner: Option<String>, structure: OwnedStructure) {
+        match owner {
+            Some(owner) => self.group(&owner).push(structure),
+            None => self.unowned.push(structure),
+        }
+    }
+
+    // Explicit `controller X { ... }` nesting.
+    fn insert_controller(&mut self, name: &str, body: ASTNode) -> Result<(), Error> {
+        // Make sure empty controllers still show up in the right order.
+        self.group(name);
+
+        if let ASTNode::Block(stmts) = body {
+            for stmt in stmts {
+                if let ASTNode::StructureDelcaration {
+                    s_type,
+                    name: inner_name,
+                    attributes,
+                    statement,
+                    doc,
+                } = stmt
+                {
+                    self.group(name).push(OwnedStructure {
+                        s_type,
+                        name: inner_name,
+                        attributes,
+                        statement: *statement,
+                        doc,
+                    });
+                } else if is_property(&stmt) {
+                    self.properties.push((String::from(name), stmt));
+                } else {
+                    return design!(
+                        "controller {}: {} can't go in a controller, only structures and \
+                         `assert always` can.",
+                        name,
+                        stmt.to_source()?
+                    );
                 }
             }
         }
-        _ => todo!(),
-    };
+        Ok(())
+    }
+
+    // Structures without an owner join the controller sharing their name
+    // (`state_queue ROB` and `controller_entry ROB`), otherwise the first controller entry
+    // declared at the top level, which is how single-controller files are written.
+    fn resolve(
+        mut self,
+        default: Option<String>,
+    ) -> Result<Vec<(String, Vec<OwnedStructure>)>, Error> {
+        for structure in std::mem::take(&mut self.unowned) {
+            let owner = if self.groups.iter().any(|(name, _)| *name == structure.name) {
+                structure.name.clone()
+            } else if let Some(default) = &default {
+                default.clone()
+            } else {
+                return design!(
+                    "{} {} doesn't belong to any controller.",
+                    structure.s_type,
+                    structure.name
+                );
+            };
+            self.group(&owner).push(structure);
+        }
+
+        for (owner, property) in std::mem::take(&mut self.properties) {
+            let entry = self
+                .group(&owner)
+                .iter_mut()
+                .find(|structure| structure.s_type == "controller_entry");
+            match entry {
+                Some(OwnedStructure {
+                    statement: ASTNode::Block(stmts),
+                    ..
+                }) => stmts.push(property),
+                _ => return design!("controller {} has no controller_entry to check.", owner),
+            }
+        }
+        Ok(self.groups)
+    }
+}
+
+fn get_owner(attributes: &[ASTNode]) -> Result<Option<String>, Error> {
+    for attribute in attributes {
+        if let ASTNode::Attribute {
+            name,
+            value: Some(value),
+        } = attribute
+        {
+            if name == "controller" {
+                if let Some(owner) = value.dotted() {
+                    return Ok(Some(owner));
+                }
+                return design!(
+                    "The controller attribute should name a controller, not {}.",
+                    value.to_source()?
+                );
+            }
+        }
+    }
+    Ok(None)
+}
+
+fn convert_controller(
+    structures: Vec<OwnedStructure>,
+    types: &ConversionTable,
+) -> Result<StateMachine, Error> {
+    let mut cfgs = StateMachine::new();
+    cfgs.types = types.clone();
+
+    for structure in structures {
+        // statement can be block or instructions, in this case, it's block.
+        convert_struct(
+            &structure.s_type,
+            &structure.name,
+            &structure.attributes,
+            structure.statement,
+            structure.doc,
+            &mut cfgs,
+        )?;
+    }
+
+    collect_outputs(&mut cfgs)?;
+
+    cfgs.queue = cfgs
+        .cfgs
+        .iter()
+        .find(|cfg| cfg.scope.is_structure(&Structure::StateQueue))
+        .map(|cfg| convert_queue(cfg))
+        .transpose()?;
 
     // find the controller entry.
     // iterate all the instructions until find init = <State>.
-    // if not find, panic an error.
+    // if not find, report an error.
 
     let key = Structure::ControllerEntry;
-    let ret = cfgs.get_cfg_structure(key.clone());
+    let Some(ret) = cfgs.get_cfg_structure(key.clone()) else {
+        let names: Vec<&str> = cfgs
+            .cfgs
+            .iter()
+            .map(|cfg| cfg.scope.name.as_str())
+            .collect();
+        return design!(
+            "The controller of {} has no controller_entry.",
+            names.join(", ")
+        );
+    };
     cfgs.fsm_name = cfgs.get_cfg_name_by_structure(key);
 
     for inst in &ret.insts {
@@ -580,17 +1299,142 @@ pub fn convert(node: ASTNode) -> StateMachine {
         if let ASTNode::Assignment { name, expr } = stmt {
             if "init_entry".eq(name.trim()) {
                 // expr must be Qualified name.
-                if let ASTNode::QualifiedName { names } = *expr.clone() {
-                    let state = &names[0];
-                    if let ASTNode::Ident(entry) = state {
-                        cfgs.entry = String::from(entry);
+                if let Some(entry) = expr.dotted() {
+                    cfgs.entry = entry;
+                } else {
+                    return design!("Init entry should be a state, not {}.", expr.to_source()?);
+                }
+            }
+        }
+    }
+
+    Ok(cfgs)
+}
+
+// state_queue ROB {
+//   element_ordering ordering = FIFO;
+//   int num_entries = 16;
+// }
+fn convert_queue(cfg: &State) -> Result<StateQueue, Error> {
+    let mut queue = StateQueue {
+        name: cfg.scope.name.clone(),
+        ordering: Ordering::Fifo,
+        num_entries: DEFAULT_QUEUE_DEPTH,
+    };
+
+    for inst in &cfg.insts {
+        let Inst::Stmt(stmt) = inst;
+        if let ASTNode::VariableDeclaration {
+            typed_identifier,
+            expr: Some(expr),
+            ..
+        } = stmt
+        {
+            let (ASTNode::TypedIdentifier { aql_type, variable }, ASTNode::ConstVal(val)) =
+                (typed_identifier.as_ref(), expr.as_ref())
+            else {
+                continue;
+            };
+
+            if aql_type == "element_ordering" {
+                let Some(ordering) = Ordering::from_name(val) else {
+                    return design!("Unknown element ordering: {}", val);
+                };
+                queue.ordering = ordering;
+            } else if variable == "num_entries" {
+                let Ok(num_entries) = val.parse() else {
+                    return design!("num_entries should be an integer: {}", val);
+                };
+                if num_entries == 0 {
+                    return design!("state_queue {} needs at least one entry.", queue.name);
+                }
+                queue.num_entries = num_entries;
+            }
+        }
+    }
+
+    Ok(queue)
+}
+
+// Every controller becomes its own state machine.
+pub fn convert(node: ASTNode, model: Option<MemoryModel>) -> Result<Vec<StateMachine>, Error> {
+    let mut controllers = Controllers::default();
+    let mut default = None;
+    // Types are global, every controller sees all the enums.
+    let mut types = ConversionTable::new();
+    // So are functions, they are inlined once every structure has its controller.
+    let mut functions = Functions::default();
+
+    let ASTNode::Top(decls) = node else {
+        return design!("Expected a whole design, got {}.", node.to_source()?);
+    };
+    for decl in decls {
+        // should only be structure declaration or internal_func_decl.
+        let ASTNode::Declaration(structure) = decl else {
+            continue;
+        };
+        match *structure {
+            ASTNode::StructureDelcaration {
+                s_type,
+                name,
+                attributes,
+                statement,
+                doc,
+            } => {
+                let owner = get_owner(&attributes)?;
+
+                if s_type == "controller" {
+                    controllers.insert_controller(&name, *statement)?;
+                    continue;
+                }
+
+                let is_entry = s_type == "controller_entry";
+                let structure = OwnedStructure {
+                    s_type,
+                    name: name.clone(),
+                    attributes,
+                    statement: *statement,
+                    doc,
+                };
+
+                if is_entry && owner.is_none() {
+                    // A top-level controller entry starts its own controller.
+                    if default.is_none() {
+                        default = Some(name.clone());
                     }
+                    controllers.group(&name).push(structure);
                 } else {
-                    panic!("Init entry should be a leagal expression.");
+                    controllers.insert(owner, structure);
                 }
             }
+            ASTNode::EnumDeclaration { name, variants } => {
+                types.insert_enum(&name, variants)?;
+            }
+            decl @ ASTNode::InternalFuncDecl { .. } => functions.insert(decl)?,
+            ASTNode::Import { path, .. } | ASTNode::Include(path) => {
+                return design!("\"{}\" isn't loaded, imports are resolved by load.", path);
+            }
+            ASTNode::None | ASTNode::Comment { .. } => {}
+            decl => return design!("{} can't go at the top level.", decl.to_source()?),
+        }
+    }
+
+    let mut controllers = controllers.resolve(default)?;
+    functions.check(&types)?;
+    for (_, structures) in &mut controllers {
+        let mut variables = HashMap::new();
+        for structure in structures.iter() {
+            function::declared(&structure.statement, &types, &mut variables);
+        }
+        for structure in structures.iter_mut() {
+            let statement = mem::replace(&mut structure.statement, ASTNode::None);
+            structure.statement = functions.inline(statement, &types, &variables)?;
         }
     }
+    memory_model::enforce(model, &mut controllers)?;
 
-    cfgs
+    controllers
+        .into_iter()
+        .map(|(_, structures)| convert_controller(structures, &types))
+        .collect()
 }
@@ -0,0 +1,271 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use crate::{
+    ast::ASTNode,
+    cfg::{State, StateMachine, Structure},
+    get_name,
+    interp::Interpreter,
+    utils, Error,
+};
+
+// Input assignments tried per configuration, the rest of the combinations are skipped.
+const MAX_INPUT_COMBINATIONS: usize = 4096;
+
+// Every name and integer literal an expression or statement reads.
+fn visit(node: &ASTNode, names: &mut Vec<String>, constants: &mut Vec<i64>) {
+    match node {
+        ASTNode::BinOp { lhs, rhs, .. } => {
+            visit(lhs, names, constants);
+            visit(rhs, names, constants);
+        }
+        ASTNode::Assignment { expr, .. } => visit(expr, names, constants),
+        ASTNode::VariableDeclaration {
+            expr: Some(expr), ..
+        } => visit(expr, names, constants),
+        ASTNode::ConstVal(val) if val.parse::<i64>().is_ok() => {
+            constants.push(val.parse().unwrap());
+        }
+        _ => {
+            if let Some(name) = get_name(node) {
+                names.push(name);
+            }
+        }
+    }
+}
+
+fn states(cfgs: &StateMachine) -> impl Iterator<Item = &State> {
+    cfgs.cfgs
+        .iter()
+        .filter(|cfg| cfg.scope.is_structure(&Structure::State))
+        .map(|cfg| cfg.as_ref())
+}
+
+// The values worth trying for each input a state reads: 0, 1, all ones and the literals of the
+// machine with their neighbours, so every comparison against a literal can go both ways.
+fn input_candidates(
+    cfgs: &StateMachine,
+    state: &State,
+    constants: &[i64],
+) -> Result<Vec<HashMap<String, u64>>, Error> {
+    let mut names = vec![];
+    for transition in &state.next.trans {
+        for node in transition
+            .guards
+            .iter()
+            .flatten()
+            .chain(transition.actions.iter().flatten())
+        {
+            visit(node, &mut names, &mut vec![]);
+        }
+    }
+
+    let mut ports: Vec<(String, Vec<u64>)> = vec![];
+    for port in &cfgs.inputs {
+        if !names.contains(&port.name) {
+            continue;
+        }
+        let width = utils::width(&port.ty)?;
+        let mask = if width >= 64 {
+            u64::MAX
+        } else {
+            (1 << width) - 1
+        };
+        let mut values = vec![0, 1, mask];
+        for c in constants {
+            for value in [c - 1, *c, c + 1] {
+                values.push(value as u64 & mask);
+            }
+        }
+        values.sort();
+        values.dedup();
+        ports.push((port.name.clone(), values));
+    }
+
+    let mut ret = vec![HashMap::new()];
+    for (name, values) in ports {
+        let mut next = vec![];
+        'outer: for inputs in &ret {
+            for value in &values {
+                if next.len() == MAX_INPUT_COMBINATIONS {
+                    break 'outer;
+                }
+                let mut inputs = inputs.clone();
+                inputs.insert(name.clone(), *value);
+                next.push(inputs);
+            }
+        }
+        ret = next;
+    }
+    Ok(ret)
+}
+
+// A problem found in one state of a controller.
+pub struct Finding {
+    pub state: String,
+    pub message: String,
+}
+
+pub struct Reachability {
+    pub findings: Vec<Finding>,
+    pub configurations: usize,
+    // Exploration stopped at the bound before visiting every configuration.
+    pub bounded: bool,
+}
+
+// Looks for states that can't be reached from `init_entry`, states that get stuck because they
+// have no transition and aren't tagged `complete`, and states that can deadlock. The latter comes
+// from exploring the (state, variables) configurations reachable from reset, up to `bound` of them,
+// with the interpreter. Inputs are free: a configuration deadlocks when no transition can be taken
+// for any of the input values worth trying.
+pub fn reachability(cfgs: &StateMachine, bound: usize) -> Result<Reachability, Error> {
+    let mut findings = vec![];
+
+    let mut reachable = HashSet::new();
+    let mut queue = VecDeque::from([cfgs.entry.clone()]);
+    while let Some(name) = queue.pop_front() {
+        if !reachable.insert(name.clone()) {
+            continue;
+        }
+        if let Some(state) = states(cfgs).find(|s| s.scope.name == name) {
+            for transition in &state.next.trans {
+                queue.push_back(transition.target.clone());
+            }
+        }
+    }
+
+    for state in states(cfgs) {
+        let name = &state.scope.name;
+        for transition in &state.next.trans {
+            let target = &transition.target;
+            if !states(cfgs).any(|s| s.scope.name == *target) {
+                findings.push(Finding {
+                    state: name.clone(),
+                    message: format!(
+                        "state {} transitions to {}, which is not a state of {}",
+                        name, target, cfgs.fsm_name
+                    ),
+                });
+            }
+        }
+        if !reachable.contains(name) {
+            findings.push(Finding {
+                state: name.clone(),
+                message: format!(
+                    "state {} is unreachable from init_entry {}",
+                    name, cfgs.entry
+                ),
+            });
+        }
+        if state.next.trans.is_empty() && !state.terminal {
+            findings.push(Finding {
+                state: name.clone(),
+                message: format!(
+                    "state {} has no outgoing transitions, tag it `complete` if the entry is done there",
+                    name
+                ),
+            });
+        }
+    }
+
+    let mut constants = vec![];
+    for state in states(cfgs) {
+        for transition in &state.next.trans {
+            for node in transition
+                .guards
+                .iter()
+                .flatten()
+                .chain(transition.actions.iter().flatten())
+            {
+                visit(node, &mut vec![], &mut constants);
+            }
+        }
+    }
+    constants.sort();
+    constants.dedup();
+    let candidates: HashMap<&str, Vec<HashMap<String, u64>>> = states(cfgs)
+        .map(|s| {
+            Ok((
+                s.scope.name.as_str(),
+                input_candidates(cfgs, s, &constants)?,
+            ))
+        })
+        .collect::<Result<_, Error>>()?;
+
+    if !states(cfgs).any(|s| s.scope.name == cfgs.entry) {
+        findings.push(Finding {
+            state: cfgs.entry.clone(),
+            message: format!(
+                "init_entry {} is not a state of {}",
+                cfgs.entry, cfgs.fsm_name
+            ),
+        });
+        return Ok(Reachability {
+            findings,
+            configurations: 0,
+            bounded: false,
+        });
+    }
+
+    let reset = Interpreter::new(cfgs)?;
+    let key = |m: &Interpreter| (String::from(m.state()), m.variables());
+    let mut seen = HashSet::from([key(&reset)]);
+    let mut queue = VecDeque::from([reset]);
+    let mut deadlocked = HashSet::new();
+    let mut bounded = false;
+
+    while let Some(machine) = queue.pop_front() {
+        let state = String::from(machine.state());
+        // Stuck states and transitions to missing states are reported above.
+        let Some(cfg) = states(cfgs).find(|s| s.scope.name == state) else {
+            continue;
+        };
+        if cfg.next.trans.is_empty() {
+            continue;
+        }
+
+        let mut enabled = false;
+        for inputs in &candidates[state.as_str()] {
+            let mut next = machine.clone();
+            if next.step(inputs)?.is_none() {
+                continue;
+            }
+            enabled = true;
+            let next_key = key(&next);
+            if seen.contains(&next_key) {
+                continue;
+            }
+            if seen.len() >= bound {
+                bounded = true;
+                continue;
+            }
+            seen.insert(next_key);
+            queue.push_back(next);
+        }
+
+        if !enabled && deadlocked.insert(state.clone()) {
+            let values: Vec<String> = machine
+                .variables()
+                .iter()
+                .map(|(name, value)| format!("{}={}", name, value))
+                .collect();
+            let with = if values.is_empty() {
+                String::new()
+            } else {
+                format!(" with {}", values.join(" "))
+            };
+            findings.push(Finding {
+                message: format!(
+                    "state {} can deadlock, none of its guards can hold{}",
+                    state, with
+                ),
+                state,
+            });
+        }
+    }
+
+    Ok(Reachability {
+        findings,
+        configurations: seen.len(),
+        bounded,
+    })
+}
@@ -0,0 +1,136 @@
+use std::{collections::HashMap, rc::Rc};
+
+use uuid::Uuid;
+
+use crate::{
+    cfg::{ControlFlow, Inst, State, StateMachine, Structure},
+    Error,
+};
+
+// Quotes a label for DOT, lines are left aligned.
+fn escape(label: &str) -> String {
+    let label = label.replace('\\', "\\\\").replace('"', "\\\"");
+    format!("\"{}\"", label.replace('\n', "\\l"))
+}
+
+fn states(cfgs: &StateMachine) -> impl Iterator<Item = &State> {
+    cfgs.cfgs
+        .iter()
+        .filter(|cfg| cfg.scope.is_structure(&Structure::State))
+        .map(|cfg| cfg.as_ref())
+}
+
+// One cluster per controller, states are nodes and transitions are edges labelled
+// `priority: guards / actions`. The entry state is drawn bold with an arrow from a start point,
+// states tagged `complete` are double circled.
+pub fn state_machines(machines: &[StateMachine]) -> Result<String, Error> {
+    let mut ret = String::from("digraph aql {\n  node [shape=box, style=rounded];\n");
+
+    for cfgs in machines {
+        let fsm = &cfgs.fsm_name;
+        ret += &format!("  subgraph \"cluster_{}\" {{\n", fsm);
+        ret += &format!("    label={};\n", escape(fsm));
+        ret += &format!("    \"{}.start\" [shape=point];\n", fsm);
+
+        for state in states(cfgs) {
+            let name = &state.scope.name;
+            let mut label = format!("{}\n", name);
+            for call in &state.calls {
+                label += &format!("{}\n", call.to_source()?);
+            }
+            let mut attributes = vec![format!("label={}", escape(&label))];
+            if *name == cfgs.entry {
+                attributes.push(String::from("penwidth=2"));
+            }
+            if state.terminal {
+                attributes.push(String::from("peripheries=2"));
+            }
+            ret += &format!("    \"{}.{}\" [{}];\n", fsm, name, attributes.join(", "));
+        }
+        ret += &format!("    \"{0}.start\" -> \"{0}.{1}\";\n", fsm, cfgs.entry);
+
+        for state in states(cfgs) {
+            for (i, transition) in state.next.trans.iter().enumerate() {
+                let mut label = format!("{}: {}", i, transition.guard_source()?);
+                let actions = transition.action_source()?;
+                if !actions.is_empty() {
+                    label += &format!(" / {}", actions.join("; "));
+                }
+                ret += &format!(
+                    "    \"{0}.{1}\" -> \"{0}.{2}\" [label={3}];\n",
+                    fsm,
+                    state.scope.name,
+                    transition.target,
+                    escape(&label)
+                );
+            }
+        }
+        ret += "  }\n";
+    }
+
+    ret += "}\n";
+    Ok(ret)
+}
+
+// The blocks of a state as nodes, branches as edges to the true and false successors. Blocks are
+// numbered in visit order so the output doesn't depend on their labels, returns the block's node.
+fn control_flow(
+    block: &Rc<ControlFlow>,
+    prefix: &str,
+    ids: &mut HashMap<Uuid, String>,
+    ret: &mut String,
+) -> Result<String, Error> {
+    if let Some(id) = ids.get(&block.label) {
+        return Ok(id.clone());
+    }
+    let id = format!("{}.b{}", prefix, ids.len());
+    ids.insert(block.label, id.clone());
+
+    let mut label = String::new();
+    for Inst::Stmt(stmt) in &block.insts {
+        label += &format!("{};\n", stmt.to_source()?);
+    }
+    if let Some(cond) = &block.cond {
+        label += &format!("if ({})\n", cond.to_source()?);
+    }
+    *ret += &format!("      \"{}\" [label={}];\n", id, escape(&label));
+
+    for (next, branch) in [(&block.lhs, "true"), (&block.rhs, "false")] {
+        if let Some(next) = next {
+            let next = control_flow(next, prefix, ids, ret)?;
+            let edge = if block.cond.is_some() {
+                format!(" [label=\"{}\"]", branch)
+            } else {
+                String::new()
+            };
+            *ret += &format!("      \"{}\" -> \"{}\"{};\n", id, next, edge);
+        }
+    }
+    Ok(id)
+}
+
+// One cluster per controller and per state, holding the control flow the state's transitions
+// were collected from.
+pub fn control_flows(machines: &[StateMachine]) -> Result<String, Error> {
+    let mut ret = String::from("digraph aql {\n  node [shape=box];\n");
+
+    for cfgs in machines {
+        let fsm = &cfgs.fsm_name;
+        ret += &format!("  subgraph \"cluster_{}\" {{\n", fsm);
+        ret += &format!("    label={};\n", escape(fsm));
+        for state in states(cfgs) {
+            let Some(body) = &state.body else {
+                continue;
+            };
+            let prefix = format!("{}.{}", fsm, state.scope.name);
+            ret += &format!("    subgraph \"cluster_{}\" {{\n", prefix);
+            ret += &format!("      label={};\n", escape(&state.scope.name));
+            control_flow(body, &prefix, &mut HashMap::new(), &mut ret)?;
+            ret += "    }\n";
+        }
+        ret += "  }\n";
+    }
+
+    ret += "}\n";
+    Ok(ret)
+}
@@ -0,0 +1,47 @@
+use std::{fmt, path::PathBuf};
+
+/// Why a design was rejected.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Error {
+    /// The source doesn't follow the AQL grammar. `line` and `column` start at 1.
+    Parse {
+        line: usize,
+        column: usize,
+        message: String,
+    },
+    /// The design parses but can't be compiled, e.g. a missing `init_entry` or an undeclared
+    /// variable.
+    Design(String),
+    /// An `import` or `include` can't be resolved: the file isn't found, the imports form a
+    /// cycle or a file is imported under two namespaces.
+    Import(String),
+    /// An error in a file other than the one being loaded, reached through its imports.
+    File { path: PathBuf, error: Box<Error> },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Parse {
+                line,
+                column,
+                message,
+            } => write!(f, "{}:{}: {}", line, column, message),
+            Error::Design(message) | Error::Import(message) => write!(f, "{}", message),
+            Error::File { path, error } => match error.as_ref() {
+                Error::Parse { .. } => write!(f, "{}:{}", path.display(), error),
+                _ => write!(f, "{}: {}", path.display(), error),
+            },
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+// `Err(Error::Design(..))` with a formatted message, how the passes reject a design.
+macro_rules! design {
+    ($($arg:tt)*) => {
+        Err($crate::Error::Design(format!($($arg)*)))
+    };
+}
+pub(crate) use design;
@@ -0,0 +1,606 @@
+use std::collections::HashMap;
+
+use crate::{
+    ast::{ASTNode, BinVerb},
+    cfg::{self, State, StateMachine, Structure},
+    error::design,
+    get_declaration, get_initial_value, get_name, is_comparison,
+    mlir::index_width,
+    reduce_neg, type_of,
+    utils::{self, ConversionTable},
+    Error,
+};
+
+// A node of the transition system, operators use their BTOR2 names.
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub(crate) enum Node {
+    Input(String),
+    State(String),
+    Const(u64),
+    Op(&'static str, Vec<usize>),
+    // Zero extension and truncation to the width of the node.
+    Uext(usize),
+    Slice(usize),
+}
+
+// A word-level transition system: registers with their initial and next values, free inputs,
+// bad-state properties from `assert` and constraints from `assume`. All the controllers of a
+// design share one system, their names are prefixed with the controller.
+pub struct System {
+    // Nodes are created bottom-up, every operand comes before its users.
+    pub(crate) nodes: Vec<(Node, u32)>,
+    ids: HashMap<(Node, u32), usize>,
+    pub(crate) registers: Vec<(usize, usize, usize)>,
+    pub(crate) bad: Vec<(usize, String)>,
+    pub(crate) constraints: Vec<usize>,
+    // The machine outputs, driven by the requests of the current state.
+    pub(crate) outputs: Vec<(usize, String)>,
+}
+
+impl System {
+    fn node(&mut self, node: Node, width: u32) -> usize {
+        if let Some(id) = self.ids.get(&(node.clone(), width)) {
+            return *id;
+        }
+        self.nodes.push((node.clone(), width));
+        self.ids.insert((node, width), self.nodes.len() - 1);
+        self.nodes.len() - 1
+    }
+
+    pub(crate) fn width(&self, id: usize) -> u32 {
+        self.nodes[id].1
+    }
+
+    fn constant(&mut self, value: u64, width: u32) -> usize {
+        let value = if width >= 64 {
+            value
+        } else {
+            value & ((1 << width) - 1)
+        };
+        self.node(Node::Const(value), width)
+    }
+
+    fn op(&mut self, op: &'static str, args: Vec<usize>, width: u32) -> usize {
+        self.node(Node::Op(op, args), width)
+    }
+
+    fn not(&mut self, a: usize) -> usize {
+        self.op("not", vec![a], 1)
+    }
+
+    fn and(&mut self, a: usize, b: usize) -> usize {
+        self.op("and", vec![a, b], 1)
+    }
+
+    fn eq(&mut self, a: usize, b: usize) -> usize {
+        self.op("eq", vec![a, b], 1)
+    }
+
+    fn ite(&mut self, cond: usize, then: usize, otherwise: usize) -> usize {
+        if then == otherwise {
+            return then;
+        }
+        let width = self.width(then);
+        self.op("ite", vec![cond, then, otherwise], width)
+    }
+
+    fn resize(&mut self, value: usize, width: u32) -> usize {
+        let from = self.width(value);
+        if from == width {
+            value
+        } else if from > width {
+            self.node(Node::Slice(value), width)
+        } else {
+            self.node(Node::Uext(value), width)
+        }
+    }
+}
+
+// The formal counterpart of the `Env` used for lowering guards and actions.
+struct Lowering<'a> {
+    types: &'a ConversionTable,
+    // Machine variables and input ports.
+    values: HashMap<String, (usize, String)>,
+    // Transition-local temporaries.
+    locals: HashMap<String, (usize, String)>,
+}
+
+impl<'a> Lowering<'a> {
+    fn type_of(&self, node: &ASTNode) -> Option<String> {
+        type_of(node, self.types, &|name| {
+            let local = self.locals.get(name).map(|(_, ty)| ty.clone());
+            local.or_else(|| self.values.get(name).map(|(_, ty)| ty.clone()))
+        })
+    }
+
+    fn lower(&self, sys: &mut System, node: &ASTNode, ty: Option<&str>) -> Result<usize, Error> {
+        let value = match node {
+            ASTNode::ConstVal(val) if val.parse::<i64>().is_ok() => {
+                let width = utils::width(ty.unwrap_or("i32"))?;
+                return Ok(sys.constant(val.parse::<i64>().unwrap() as u64, width));
+            }
+            ASTNode::BinOp { verb, lhs, rhs } => {
+                let operand_ty =
+                    self.type_of(lhs)
+                        .or_else(|| self.type_of(rhs))
+                        .unwrap_or(String::from(
+                            ty.filter(|_| !is_comparison(verb)).unwrap_or("i32"),
+                        ));
+                let width = utils::width(&operand_ty)?;
+                let lhs = self.lower(sys, lhs, Some(&operand_ty))?;
+                let rhs = self.lower(sys, rhs, Some(&operand_ty))?;
+
+                if is_comparison(verb) {
+                    let op = match reduce_neg(verb)? {
+                        BinVerb::SmallerThan => "ult",
+                        BinVerb::LargerThan => "ugt",
+                        BinVerb::SmallerOrEqual => "ulte",
+                        BinVerb::LargerOrEqual => "ugte",
+                        BinVerb::Equal => "eq",
+                        BinVerb::NotEqual => "neq",
+                        verb => return design!("{} isn't a comparison.", verb.symbol()),
+                    };
+                    sys.op(op, vec![lhs, rhs], 1)
+                } else if let BinVerb::Divide = verb {
+                    // Division by zero is zero, like in the interpreter.
+                    let zero = sys.constant(0, width);
+                    let by_zero = sys.eq(rhs, zero);
+                    let quotient = sys.op("udiv", vec![lhs, rhs], width);
+                    sys.ite(by_zero, zero, quotient)
+                } else {
+                    let op = match verb {
+                        BinVerb::Plus => "add",
+                        BinVerb::Minus => "sub",
+                        BinVerb::Times => "mul",
+                        BinVerb::And => "and",
+                        BinVerb::Or => "or",
+                        BinVerb::Xor => "xor",
+                        BinVerb::LeftShift => "sll",
+                        BinVerb::RightShift => "srl",
+                        _ => return design!("{} isn't an arithmetic operator.", verb.symbol()),
+                    };
+                    sys.op(op, vec![lhs, rhs], width)
+                }
+            }
+            _ => match get_name(node) {
+                Some(name) => self.lower_name(sys, &name)?,
+                None => {
+                    return design!(
+                        "{} can't be lowered to a transition system yet.",
+                        node.to_source()?
+                    )
+                }
+            },
+        };
+
+        Ok(match ty {
+            Some(ty) => sys.resize(value, utils::width(ty)?),
+            None => value,
+        })
+    }
+
+    fn lower_name(&self, sys: &mut System, name: &str) -> Result<usize, Error> {
+        if let Some((value, _)) = self.locals.get(name) {
+            return Ok(*value);
+        }
+        if let Some((value, _)) = self.values.get(name) {
+            return Ok(*value);
+        }
+        if let Some((enum_name, encoded)) = self.types.constant(name) {
+            let ty = self.types.convert(&enum_name)?.mlir_type();
+            return Ok(sys.constant(encoded as u64, utils::width(&ty)?));
+        }
+        design!("{} is not declared.", name)
+    }
+
+    fn condition(&self, sys: &mut System, node: &ASTNode) -> Result<usize, Error> {
+        let ty = self.type_of(node).unwrap_or(String::from("i32"));
+        let value = self.lower(sys, node, Some(&ty))?;
+        if ty == "i1" {
+            Ok(value)
+        } else {
+            let zero = sys.constant(0, utils::width(&ty)?);
+            Ok(sys.op("neq", vec![value, zero], 1))
+        }
+    }
+}
+
+// Adds one controller to the system. The state register numbers the states in declaration order,
+// and the next state and variables follow the first transition whose guards hold.
+fn insert_machine(sys: &mut System, cfgs: &StateMachine) -> Result<(), Error> {
+    let prefix = &cfgs.fsm_name;
+    let states: Vec<&State> = cfgs
+        .cfgs
+        .iter()
+        .filter(|cfg| cfg.scope.is_structure(&Structure::State))
+        .map(|cfg| cfg.as_ref())
+        .collect();
+    let encode = |name: &str| match states.iter().position(|s| s.scope.name == name) {
+        Some(index) => Ok(index as u64),
+        None => design!("{} is not a state of {}.", name, prefix),
+    };
+    let width = index_width(states.len() as u32);
+    let state = sys.node(Node::State(format!("{}.state", prefix)), width);
+    let init = sys.constant(encode(&cfgs.entry)?, width);
+
+    let mut lowering = Lowering {
+        types: &cfgs.types,
+        values: HashMap::new(),
+        locals: HashMap::new(),
+    };
+    let mut variables = vec![];
+    for cfg in &cfgs.cfgs {
+        if !cfg.scope.is_structure(&Structure::ControllerEntry) {
+            continue;
+        }
+        for inst in &cfg.insts {
+            let cfg::Inst::Stmt(stmt) = inst;
+            if let Some((name, init, ty)) = get_initial_value(stmt, &cfgs.types)? {
+                let width = utils::width(&ty)?;
+                let node = sys.node(Node::State(format!("{}.{}", prefix, name)), width);
+                let value = match init.parse::<i64>() {
+                    Ok(value) => sys.constant(value as u64, width),
+                    Err(_) => return design!("{} is not a valid initial value of {}.", init, name),
+                };
+                lowering.values.insert(name.clone(), (node, ty));
+                variables.push((name, node, value));
+            }
+        }
+    }
+    for port in &cfgs.inputs {
+        let node = sys.node(
+            Node::Input(format!("{}.{}", prefix, port.name)),
+            utils::width(&port.ty)?,
+        );
+        lowering
+            .values
+            .insert(port.name.clone(), (node, port.ty.clone()));
+    }
+
+    let mut next_state = state;
+    let mut next_variables: Vec<usize> = variables.iter().map(|(_, node, _)| *node).collect();
+    let mut outputs: Vec<usize> = cfgs
+        .outputs
+        .iter()
+        .map(|port| Ok(sys.constant(0, utils::width(&port.ty)?)))
+        .collect::<Result<_, Error>>()?;
+
+    for cfg in states.iter().rev() {
+        let encoded = sys.constant(encode(&cfg.scope.name)?, width);
+        let here = sys.eq(state, encoded);
+
+        let mut state_next = state;
+        let mut variables_here: Vec<usize> = variables.iter().map(|(_, node, _)| *node).collect();
+        for transition in cfg.next.trans.iter().rev() {
+            let mut guard = sys.constant(1, 1);
+            for condition in transition.guards.iter().flatten() {
+                let condition = lowering.condition(sys, condition)?;
+                guard = sys.and(guard, condition);
+            }
+
+            // Reads see the values from before the transition, the last assignment wins.
+            lowering.locals.clear();
+            let mut updates: HashMap<String, usize> = HashMap::new();
+            for action in transition.actions.iter().flatten() {
+                if let Some((variable, aql_type, expr)) = get_declaration(action) {
+                    let ty = cfgs.types.convert(&aql_type)?.mlir_type();
+                    let value = match expr {
+                        Some(expr) => lowering.lower(sys, &expr, Some(&ty))?,
+                        None => sys.constant(0, utils::width(&ty)?),
+                    };
+                    lowering.locals.insert(variable, (value, ty));
+                } else if let ASTNode::Assignment { name, expr } = action {
+                    if let Some((_, ty)) = lowering.locals.get(name).cloned() {
+                        let value = lowering.lower(sys, expr, Some(&ty))?;
+                        lowering.locals.insert(name.clone(), (value, ty));
+                    } else if let Some((_, ty)) = lowering.values.get(name).cloned() {
+                        let value = lowering.lower(sys, expr, Some(&ty))?;
+                        updates.insert(name.clone(), value);
+                    } else {
+                        return design!("{} is not declared.", name);
+                    }
+                }
+            }
+
+            let target = sys.constant(encode(&transition.target)?, width);
+            state_next = sys.ite(guard, target, state_next);
+            for (i, (name, node, _)) in variables.iter().enumerate() {
+                let value = updates.get(name).copied().unwrap_or(*node);
+                variables_here[i] = sys.ite(guard, value, variables_here[i]);
+            }
+        }
+
+        next_state = sys.ite(here, state_next, next_state);
+        next_variables = variables_here
+            .into_iter()
+            .zip(next_variables)
+            .map(|(value, otherwise)| sys.ite(here, value, otherwise))
+            .collect();
+
+        lowering.locals.clear();
+        let mut driven = HashMap::new();
+        for call in &cfg.calls {
+            if let Some((unit, method, args)) = cfg::get_request(call) {
+                let prefix = cfg::port_prefix(&unit, &method);
+                driven.insert(format!("{}_valid", prefix), sys.constant(1, 1));
+                for (i, arg) in args.iter().enumerate() {
+                    let name = format!("{}_arg{}", prefix, i);
+                    if let Some(port) = cfgs.outputs.iter().find(|port| port.name == name) {
+                        let value = lowering.lower(sys, arg, Some(&port.ty))?;
+                        driven.insert(name, value);
+                    }
+                }
+            }
+        }
+        for (port, output) in cfgs.outputs.iter().zip(outputs.iter_mut()) {
+            if let Some(value) = driven.get(&port.name) {
+                *output = sys.ite(here, *value, *output);
+            }
+        }
+
+        insert_properties(
+            sys,
+            &lowering,
+            &cfg.properties,
+            Some(here),
+            &cfg.scope.name,
+            prefix,
+        )?;
+    }
+
+    // Properties of the controller entry hold in every state.
+    for cfg in &cfgs.cfgs {
+        if cfg.scope.is_structure(&Structure::ControllerEntry) {
+            insert_properties(
+                sys,
+                &lowering,
+                &cfg.properties,
+                None,
+                &cfg.scope.name,
+                prefix,
+            )?;
+        }
+    }
+
+    sys.registers.push((state, init, next_state));
+    for ((_, node, init), next) in variables.iter().zip(next_variables) {
+        sys.registers.push((*node, *init, next));
+    }
+    for (port, output) in cfgs.outputs.iter().zip(outputs) {
+        sys.outputs
+            .push((output, format!("{}.{}", prefix, port.name)));
+    }
+    Ok(())
+}
+
+fn insert_properties(
+    sys: &mut System,
+    lowering: &Lowering,
+    properties: &[ASTNode],
+    here: Option<usize>,
+    structure: &str,
+    prefix: &str,
+) -> Result<(), Error> {
+    for (i, property) in properties.iter().enumerate() {
+        match property {
+            ASTNode::Assert { expr, .. } => {
+                let holds = lowering.condition(sys, expr)?;
+                let mut bad = sys.not(holds);
+                if let Some(here) = here {
+                    bad = sys.and(here, bad);
+                }
+                let state = here.map(|_| structure);
+                let name = cfg::property_label(prefix, state, i, property);
+                sys.bad.push((bad, name));
+            }
+            ASTNode::Assume { expr, .. } => {
+                let holds = lowering.condition(sys, expr)?;
+                let constraint = match here {
+                    Some(here) => {
+                        let elsewhere = sys.not(here);
+                        sys.op("or", vec![elsewhere, holds], 1)
+                    }
+                    None => holds,
+                };
+                sys.constraints.push(constraint);
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+pub fn build(machines: &[StateMachine]) -> Result<System, Error> {
+    let mut sys = System {
+        nodes: vec![],
+        ids: HashMap::new(),
+        registers: vec![],
+        bad: vec![],
+        constraints: vec![],
+        outputs: vec![],
+    };
+    for cfgs in machines {
+        insert_machine(&mut sys, cfgs)?;
+    }
+    Ok(sys)
+}
+
+// BTOR2, as read by btormc and pono.
+pub fn btor2(sys: &System) -> String {
+    let mut ret = String::new();
+    let mut line = 0;
+    let mut next_id = || {
+        line += 1;
+        line
+    };
+
+    let mut sorts: HashMap<u32, usize> = HashMap::new();
+    let mut widths: Vec<u32> = sys.nodes.iter().map(|(_, width)| *width).collect();
+    widths.sort();
+    widths.dedup();
+    for width in widths {
+        let id = next_id();
+        ret += &format!("{} sort bitvec {}\n", id, width);
+        sorts.insert(width, id);
+    }
+
+    let mut ids: Vec<usize> = vec![];
+    for (node, width) in &sys.nodes {
+        let id = next_id();
+        let sort = sorts[width];
+        let line = match node {
+            Node::Input(name) => format!("{} input {} {}", id, sort, name),
+            Node::State(name) => format!("{} state {} {}", id, sort, name),
+            Node::Const(value) => format!("{} constd {} {}", id, sort, value),
+            Node::Op(op, args) => {
+                let args: Vec<String> = args.iter().map(|a| ids[*a].to_string()).collect();
+                format!("{} {} {} {}", id, op, sort, args.join(" "))
+            }
+            Node::Uext(a) => format!(
+                "{} uext {} {} {}",
+                id,
+                sort,
+                ids[*a],
+                width - sys.nodes[*a].1
+            ),
+            Node::Slice(a) => format!("{} slice {} {} {} 0", id, sort, ids[*a], width - 1),
+        };
+        ret += &line;
+        ret += "\n";
+        ids.push(id);
+    }
+
+    for (register, init, next) in &sys.registers {
+        let sort = sorts[&sys.nodes[*register].1];
+        ret += &format!(
+            "{} init {} {} {}\n",
+            next_id(),
+            sort,
+            ids[*register],
+            ids[*init]
+        );
+        ret += &format!(
+            "{} next {} {} {}\n",
+            next_id(),
+            sort,
+            ids[*register],
+            ids[*next]
+        );
+    }
+    for (output, name) in &sys.outputs {
+        ret += &format!("{} output {} {}\n", next_id(), ids[*output], name);
+    }
+    for constraint in &sys.constraints {
+        ret += &format!("{} constraint {}\n", next_id(), ids[*constraint]);
+    }
+    for (bad, name) in &sys.bad {
+        ret += &format!("{} bad {} {}\n", next_id(), ids[*bad], name);
+    }
+    ret
+}
+
+fn smt_sort(width: u32) -> String {
+    format!("(_ BitVec {})", width)
+}
+
+// SMT-LIB 2 with the VMT annotations (`:next`, `:init`, `:trans`, `:invar-property`), so it is
+// both a plain SMT-LIB script and a transition system for tools reading VMT. Every assert becomes
+// an invariant property, the assumptions constrain every transition.
+pub fn smt2(sys: &System) -> String {
+    let name = |id: usize| match &sys.nodes[id].0 {
+        Node::Input(name) | Node::State(name) => format!("|{}|", name),
+        _ => format!("t{}", id),
+    };
+    let bool_of = |term: String| format!("(= {} #b1)", term);
+    let bit_of = |term: String| format!("(ite {} #b1 #b0)", term);
+
+    let mut ret = String::from("(set-logic QF_BV)\n");
+    for (id, (node, width)) in sys.nodes.iter().enumerate() {
+        let sort = smt_sort(*width);
+        let term = match node {
+            Node::Input(_) | Node::State(_) => {
+                ret += &format!("(declare-fun {} () {})\n", name(id), sort);
+                continue;
+            }
+            Node::Const(value) => format!("(_ bv{} {})", value, width),
+            Node::Op(op, args) => {
+                let args: Vec<String> = args.iter().map(|a| name(*a)).collect();
+                match *op {
+                    "ite" => format!("(ite {} {} {})", bool_of(args[0].clone()), args[1], args[2]),
+                    "not" => format!("(bvnot {})", args[0]),
+                    "eq" => bit_of(format!("(= {} {})", args[0], args[1])),
+                    "neq" => bit_of(format!("(distinct {} {})", args[0], args[1])),
+                    "ult" | "ugt" | "ulte" | "ugte" => {
+                        let op = match *op {
+                            "ult" => "bvult",
+                            "ugt" => "bvugt",
+                            "ulte" => "bvule",
+                            _ => "bvuge",
+                        };
+                        bit_of(format!("({} {} {})", op, args[0], args[1]))
+                    }
+                    op => {
+                        let op = match op {
+                            "add" => "bvadd",
+                            "sub" => "bvsub",
+                            "mul" => "bvmul",
+                            "udiv" => "bvudiv",
+                            "and" => "bvand",
+                            "or" => "bvor",
+                            "xor" => "bvxor",
+                            "sll" => "bvshl",
+                            "srl" => "bvlshr",
+                            _ => unreachable!(),
+                        };
+                        format!("({} {})", op, args.join(" "))
+                    }
+                }
+            }
+            Node::Uext(a) => format!("((_ zero_extend {}) {})", width - sys.nodes[*a].1, name(*a)),
+            Node::Slice(a) => format!("((_ extract {} 0) {})", width - 1, name(*a)),
+        };
+        ret += &format!("(define-fun {} () {} {})\n", name(id), sort, term);
+    }
+
+    let mut init = vec![];
+    let mut trans = vec![];
+    for (i, (register, initial, next)) in sys.registers.iter().enumerate() {
+        let current = name(*register);
+        let next_name = format!("|{}.next|", current.trim_matches('|'));
+        let sort = smt_sort(sys.nodes[*register].1);
+        ret += &format!("(declare-fun {} () {})\n", next_name, sort);
+        ret += &format!(
+            "(define-fun .sv{} () {} (! {} :next {}))\n",
+            i, sort, current, next_name
+        );
+        init.push(format!("(= {} {})", current, name(*initial)));
+        trans.push(format!("(= {} {})", next_name, name(*next)));
+    }
+    for constraint in &sys.constraints {
+        trans.push(bool_of(name(*constraint)));
+    }
+
+    let conjunction = |terms: &[String]| match terms.len() {
+        0 => String::from("true"),
+        1 => terms[0].clone(),
+        _ => format!("(and {})", terms.join(" ")),
+    };
+    ret += &format!(
+        "(define-fun .init () Bool (! {} :init true))\n",
+        conjunction(&init)
+    );
+    ret += &format!(
+        "(define-fun .trans () Bool (! {} :trans true))\n",
+        conjunction(&trans)
+    );
+    for (i, (bad, label)) in sys.bad.iter().enumerate() {
+        ret += &format!("; {}\n", label);
+        ret += &format!(
+            "(define-fun .prop{} () Bool (! (= {} #b0) :invar-property {}))\n",
+            i,
+            name(*bad),
+            i
+        );
+    }
+    ret
+}
@@ -0,0 +1,221 @@
+use crate::{ast::ASTNode, error::design, Error};
+
+const INDENT: &str = "    ";
+
+fn attributes(attributes: &[ASTNode]) -> Result<String, Error> {
+    if attributes.is_empty() {
+        return Ok(String::new());
+    }
+    let attributes = attributes
+        .iter()
+        .map(|attribute| attribute.to_source())
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(format!(" ({})", attributes.join(", ")))
+}
+
+// `///` lines above a declaration, the declaration goes on the line after the last one.
+fn doc(doc: &Option<String>, depth: usize) -> String {
+    let mut ret = String::new();
+    for line in doc.iter().flat_map(|doc| doc.lines()) {
+        ret += format!("/// {}", line).trim_end();
+        ret += "\n";
+        ret += &INDENT.repeat(depth);
+    }
+    ret
+}
+
+// A statement starting at the current indentation. Blocks open on the line of the statement that
+// owns them and close on a line of their own.
+fn statement(node: &ASTNode, depth: usize) -> Result<String, Error> {
+    let ret = match node {
+        ASTNode::StructureDelcaration {
+            s_type,
+            name,
+            attributes: attrs,
+            statement: body,
+            doc: docs,
+        } => format!(
+            "{}{} {}{} {}",
+            doc(docs, depth),
+            s_type,
+            name,
+            attributes(attrs)?,
+            block(body, depth)?
+        ),
+        ASTNode::InternalFuncDecl {
+            typed_identifier,
+            params,
+            body,
+            doc: docs,
+        } => {
+            let params = params
+                .iter()
+                .map(|param| param.to_source())
+                .collect::<Result<Vec<_>, _>>()?;
+            format!(
+                "{}{}({}) {}",
+                doc(docs, depth),
+                typed_identifier.to_source()?,
+                params.join(", "),
+                block(body, depth)?
+            )
+        }
+        ASTNode::Block(_) => block(node, depth)?,
+        ASTNode::Conditional {
+            expr,
+            if_blk,
+            else_blk,
+        } => {
+            let mut ret = format!("if ({}) {}", expr.to_source()?, block(if_blk, depth)?);
+            match else_blk.as_ref() {
+                ASTNode::None => {}
+                // else if chains stay flat.
+                ASTNode::Conditional { .. } => {
+                    ret += &format!(" else {}", statement(else_blk, depth)?);
+                }
+                _ => ret += &format!(" else {}", block(else_blk, depth)?),
+            }
+            ret
+        }
+        ASTNode::Await {
+            call, when_block, ..
+        } => {
+            let mut ret = String::from("await ");
+            if let Some(call) = call {
+                ret += &format!("{} ", call.to_source()?);
+            }
+            ret + &block(when_block, depth)?
+        }
+        ASTNode::When {
+            call,
+            ident,
+            block: body,
+            ..
+        } => format!(
+            "when {} from {} {}",
+            call.to_source()?,
+            ident.to_source()?,
+            block(body, depth)?
+        ),
+        ASTNode::Listen {
+            block: body,
+            catch_block,
+        } => {
+            let mut ret = format!("listen {}", block(body, depth)?);
+            if let ASTNode::Block(catches) = catch_block.as_ref() {
+                for catch in catches {
+                    ret += &format!(" {}", statement(catch, depth)?);
+                }
+            }
+            ret
+        }
+        ASTNode::CatchBlock {
+            qualified_name,
+            idents,
+            source,
+            block: body,
+            ..
+        } => {
+            let idents = idents
+                .iter()
+                .map(|ident| ident.to_source())
+                .collect::<Result<Vec<_>, _>>()?;
+            let mut ret = format!(
+                "handle {}({})",
+                qualified_name.to_source()?,
+                idents.join(", ")
+            );
+            if let Some(source) = source {
+                ret += &format!(" from {}", source.to_source()?);
+            }
+            ret + " " + &block(body, depth)?
+        }
+        ASTNode::Comment { text, .. } => text.clone(),
+        ASTNode::VariableDeclaration { doc: docs, .. } => {
+            format!("{}{};", doc(docs, depth), node.to_source()?)
+        }
+        // The parser leaves labelled statements out.
+        ASTNode::None => {
+            return design!("A statement the parser doesn't support yet was left out.")
+        }
+        _ => format!("{};", node.to_source()?),
+    };
+    Ok(ret)
+}
+
+// Statements that aren't blocks are wrapped in one, so every body reads the same.
+fn block(node: &ASTNode, depth: usize) -> Result<String, Error> {
+    let stmts = match node {
+        ASTNode::Block(stmts) => stmts.as_slice(),
+        ASTNode::None => &[],
+        _ => std::slice::from_ref(node),
+    };
+    if stmts.is_empty() {
+        return Ok(String::from("{}"));
+    }
+
+    let mut ret = String::from("{\n");
+    for stmt in stmts {
+        if let ASTNode::Comment {
+            text,
+            trailing: true,
+        } = stmt
+        {
+            ret.pop();
+            ret += &format!(" {}\n", text);
+            continue;
+        }
+        ret += &INDENT.repeat(depth + 1);
+        ret += &statement(stmt, depth + 1)?;
+        ret += "\n";
+    }
+    ret += &INDENT.repeat(depth);
+    ret += "}";
+    Ok(ret)
+}
+
+// Renders a parsed program as canonical AQL: four space indentation, one statement per line and a
+// blank line between declarations. Comments are kept where the parser put them.
+pub fn format(program: &ASTNode) -> Result<String, Error> {
+    let ASTNode::Top(decls) = program else {
+        return Ok(statement(program, 0)? + "\n");
+    };
+
+    // A comment goes right above the declaration after it, or at the end of the line before it.
+    let mut ret = String::new();
+    let mut glued = false;
+    for decl in decls {
+        let decl = match decl {
+            ASTNode::Declaration(decl) => decl.as_ref(),
+            decl => decl,
+        };
+        let source = match decl {
+            ASTNode::None => continue,
+            ASTNode::Comment {
+                text,
+                trailing: true,
+            } if !ret.is_empty() => {
+                ret += &format!(" {}", text);
+                continue;
+            }
+            ASTNode::Import {
+                path,
+                namespace: Some(namespace),
+            } => format!("import \"{}\" as {};", path, namespace),
+            ASTNode::Import { path, .. } => format!("import \"{}\";", path),
+            ASTNode::Include(path) => format!("include \"{}\";", path),
+            ASTNode::EnumDeclaration { name, variants } => {
+                format!("enum {} {{ {} }}", name, variants.join(", "))
+            }
+            _ => statement(decl, 0)?,
+        };
+        if !ret.is_empty() {
+            ret += if glued { "\n" } else { "\n\n" };
+        }
+        ret += &source;
+        glued = matches!(decl, ASTNode::Comment { .. });
+    }
+
+    ret += "\n";
+    Ok(ret)
+}
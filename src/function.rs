@@ -0,0 +1,416 @@
+use std::collections::HashMap;
+
+use crate::{ast::ASTNode, cfg::substitute, error::design, type_of, utils::ConversionTable, Error};
+
+// A function as the expression it returns. Its locals are replaced by their value, so the
+// expression only reads the parameters.
+struct Function {
+    ty: String,
+    params: Vec<(String, String)>,          // (type, name)
+    locals: Vec<(String, String, ASTNode)>, // (type, name, value)
+    body: ASTNode,
+}
+
+fn typed(node: &ASTNode) -> Result<(String, String), Error> {
+    match node {
+        ASTNode::TypedIdentifier { aql_type, variable } => {
+            Ok((aql_type.trim().to_string(), variable.trim().to_string()))
+        }
+        _ => design!("Expected a typed identifier, got {}.", node.to_source()?),
+    }
+}
+
+// Names an expression reads and functions it calls.
+fn names(node: &ASTNode, reads: &mut Vec<String>, calls: &mut Vec<String>) {
+    match node {
+        ASTNode::BinOp { lhs, rhs, .. } => {
+            names(lhs, reads, calls);
+            names(rhs, reads, calls);
+        }
+        ASTNode::UnuaryOp { term, .. } => names(term, reads, calls),
+        ASTNode::ExprList(exprs) => {
+            for expr in exprs {
+                names(expr, reads, calls);
+            }
+        }
+        ASTNode::Call {
+            qualified_name,
+            list,
+        } => {
+            calls.push(qualified_name.dotted().unwrap_or_default());
+            names(list, reads, calls);
+        }
+        _ => reads.extend(node.name()),
+    }
+}
+
+// User-defined combinational functions, `i32 add(i32 a, i32 b) { return a + b }`. A body declares
+// locals and returns an expression of them and the parameters. Calls are inlined: the call becomes
+// the returned expression with the arguments in place of the parameters.
+#[derive(Default)]
+pub struct Functions {
+    functions: HashMap<String, Function>,
+}
+
+impl Functions {
+    pub fn insert(&mut self, decl: ASTNode) -> Result<(), Error> {
+        let ASTNode::InternalFuncDecl {
+            typed_identifier,
+            params,
+            body,
+            ..
+        } = decl
+        else {
+            return design!("Expected a function, got {}.", decl.to_source()?);
+        };
+        let (ty, name) = typed(&typed_identifier)?;
+        if self.functions.contains_key(&name) {
+            return design!("Function {} is declared twice.", name);
+        }
+        let params = params.iter().map(typed).collect::<Result<Vec<_>, _>>()?;
+        for (i, (_, param)) in params.iter().enumerate() {
+            if params[..i].iter().any(|(_, other)| other == param) {
+                return design!("Function {} has two parameters named {}.", name, param);
+            }
+        }
+
+        let stmts = match *body {
+            ASTNode::Block(stmts) => stmts,
+            stmt => vec![stmt],
+        };
+        let mut temporaries = HashMap::new();
+        let mut locals = vec![];
+        let mut ret = None;
+        for stmt in stmts {
+            match stmt {
+                ASTNode::None | ASTNode::Comment { .. } => continue,
+                _ if ret.is_some() => {
+                    return design!("Function {} has statements after its return.", name)
+                }
+                ASTNode::VariableDeclaration {
+                    typed_identifier,
+                    expr: Some(expr),
+                    ..
+                } => {
+                    let (ty, local) = typed(&typed_identifier)?;
+                    let value = substitute(&expr, &temporaries);
+                    locals.push((ty, local.clone(), value.clone()));
+                    temporaries.insert(local, value);
+                }
+                ASTNode::Assignment { name: local, expr }
+                    if temporaries.contains_key(local.trim()) =>
+                {
+                    let value = substitute(&expr, &temporaries);
+                    temporaries.insert(local.trim().to_string(), value);
+                }
+                ASTNode::Return(expr) => ret = Some(substitute(&expr, &temporaries)),
+                _ => {
+                    return design!(
+                        "Function {} can only declare variables with a value, assign them and \
+                         return.",
+                        name
+                    )
+                }
+            }
+        }
+        let Some(body) = ret else {
+            return design!("Function {} doesn't return a value.", name);
+        };
+
+        let function = Function {
+            ty,
+            params,
+            locals,
+            body,
+        };
+        self.functions.insert(name, function);
+        Ok(())
+    }
+
+    // Types are known and match, functions only read their parameters and don't call themselves.
+    pub fn check(&self, types: &ConversionTable) -> Result<(), Error> {
+        let mut names: Vec<&String> = self.functions.keys().collect();
+        names.sort();
+        for name in names {
+            let function = &self.functions[name];
+            let mlir_type = |ty: &str| match types.get(ty) {
+                Some(aql_type) => Ok(aql_type.mlir_type()),
+                None => design!("Function {} uses the unknown type {}.", name, ty),
+            };
+            let mut params: HashMap<&str, String> = HashMap::new();
+            for (ty, param) in &function.params {
+                params.insert(param, mlir_type(ty)?);
+            }
+            let lookup = |name: &str| params.get(name).cloned();
+
+            for (ty, local, value) in &function.locals {
+                let ty = mlir_type(ty)?;
+                match type_of(value, types, &lookup) {
+                    Some(value_ty) if value_ty != ty => {
+                        return design!(
+                            "Function {} declares {} as {} but gives it a {} value.",
+                            name,
+                            local,
+                            ty,
+                            value_ty
+                        )
+                    }
+                    _ => {}
+                }
+            }
+            let ty = mlir_type(&function.ty)?;
+            match type_of(&function.body, types, &lookup) {
+                Some(value_ty) if value_ty != ty => {
+                    return design!(
+                        "Function {} returns {} but its value is {}.",
+                        name,
+                        ty,
+                        value_ty
+                    )
+                }
+                _ => {}
+            }
+
+            let (mut reads, mut calls) = (vec![], vec![]);
+            self::names(&function.body, &mut reads, &mut calls);
+            for read in reads {
+                if !params.contains_key(read.as_str()) && types.constant(&read).is_none() {
+                    return design!(
+                        "Function {} reads {}, which isn't one of its parameters.",
+                        name,
+                        read
+                    );
+                }
+            }
+            for call in calls {
+                if !self.functions.contains_key(&call) {
+                    return design!("Function {} calls {}, which isn't a function.", name, call);
+                }
+            }
+            self.recursion(name, &mut vec![])?;
+        }
+        Ok(())
+    }
+
+    fn recursion<'a>(&'a self, name: &'a String, stack: &mut Vec<&'a String>) -> Result<(), Error> {
+        if stack.contains(&name) {
+            let mut cycle: Vec<&str> = stack.iter().map(|name| name.as_str()).collect();
+            cycle.push(name);
+            return design!(
+                "Functions are inlined so they can't call themselves: {}.",
+                cycle.join(" -> ")
+            );
+        }
+        stack.push(name);
+        let (mut reads, mut calls) = (vec![], vec![]);
+        names(&self.functions[name].body, &mut reads, &mut calls);
+        for call in &calls {
+            if let Some((callee, _)) = self.functions.get_key_value(call) {
+                self.recursion(callee, stack)?;
+            }
+        }
+        stack.pop();
+        Ok(())
+    }
+
+    // Replaces the calls to functions in a statement, or an expression, by their value. `variables`
+    // has the type of the names the arguments can read.
+    pub fn inline(
+        &self,
+        node: ASTNode,
+        types: &ConversionTable,
+        variables: &HashMap<String, String>,
+    ) -> Result<ASTNode, Error> {
+        let inline = |node: Box<ASTNode>| -> Result<Box<ASTNode>, Error> {
+            Ok(Box::new(self.inline(*node, types, variables)?))
+        };
+
+        let ret = match node {
+            ASTNode::Call {
+                qualified_name,
+                list,
+            } => {
+                let list = inline(list)?;
+                let function = qualified_name
+                    .name()
+                    .and_then(|name| self.functions.get_key_value(&name));
+                let Some((name, function)) = function else {
+                    return Ok(ASTNode::Call {
+                        qualified_name,
+                        list,
+                    });
+                };
+
+                let args = match *list {
+                    ASTNode::ExprList(args) => args,
+                    ASTNode::None => vec![],
+                    arg => vec![arg],
+                };
+                if args.len() != function.params.len() {
+                    return design!(
+                        "Function {} takes {} arguments, {} given.",
+                        name,
+                        function.params.len(),
+                        args.len()
+                    );
+                }
+                let lookup = |name: &str| variables.get(name).cloned();
+                let mut bindings = HashMap::new();
+                for ((ty, param), arg) in function.params.iter().zip(args) {
+                    let ty = types.convert(ty)?.mlir_type();
+                    match type_of(&arg, types, &lookup) {
+                        Some(arg_ty) if arg_ty != ty => {
+                            return design!(
+                                "Function {} takes {} as {}, {} is {}.",
+                                name,
+                                param,
+                                ty,
+                                arg.to_source()?,
+                                arg_ty
+                            )
+                        }
+                        _ => {}
+                    }
+                    bindings.insert(param.clone(), arg);
+                }
+                // Functions it calls are inlined in turn.
+                return self.inline(substitute(&function.body, &bindings), types, variables);
+            }
+            ASTNode::BinOp { verb, lhs, rhs } => ASTNode::BinOp {
+                verb,
+                lhs: inline(lhs)?,
+                rhs: inline(rhs)?,
+            },
+            ASTNode::UnuaryOp { verb, term } => ASTNode::UnuaryOp {
+                verb,
+                term: inline(term)?,
+            },
+            ASTNode::ExprList(exprs) => ASTNode::ExprList(
+                exprs
+                    .into_iter()
+                    .map(|expr| self.inline(expr, types, variables))
+                    .collect::<Result<_, _>>()?,
+            ),
+            ASTNode::Block(stmts) => ASTNode::Block(
+                stmts
+                    .into_iter()
+                    .map(|stmt| self.inline(stmt, types, variables))
+                    .collect::<Result<_, _>>()?,
+            ),
+            ASTNode::Assignment { name, expr } => ASTNode::Assignment {
+                name,
+                expr: inline(expr)?,
+            },
+            ASTNode::VariableDeclaration {
+                typed_identifier,
+                expr,
+                doc,
+            } => ASTNode::VariableDeclaration {
+                typed_identifier,
+                expr: expr.map(inline).transpose()?,
+                doc,
+            },
+            ASTNode::Conditional {
+                expr,
+                if_blk,
+                else_blk,
+            } => ASTNode::Conditional {
+                expr: inline(expr)?,
+                if_blk: inline(if_blk)?,
+                else_blk: inline(else_blk)?,
+            },
+            ASTNode::Assert { expr, always } => ASTNode::Assert {
+                expr: inline(expr)?,
+                always,
+            },
+            ASTNode::Assume { expr, always } => ASTNode::Assume {
+                expr: inline(expr)?,
+                always,
+            },
+            ASTNode::Listen { block, catch_block } => ASTNode::Listen {
+                block: inline(block)?,
+                catch_block: inline(catch_block)?,
+            },
+            ASTNode::CatchBlock {
+                keyword,
+                qualified_name,
+                idents,
+                source,
+                block,
+            } => ASTNode::CatchBlock {
+                keyword,
+                qualified_name,
+                idents,
+                source,
+                block: inline(block)?,
+            },
+            // The event a `when` waits for isn't a call.
+            ASTNode::Await {
+                keyword,
+                call,
+                when_block,
+            } => ASTNode::Await {
+                keyword,
+                call: call.map(inline).transpose()?,
+                when_block: inline(when_block)?,
+            },
+            ASTNode::When {
+                keyword,
+                call,
+                ident,
+                block,
+            } => ASTNode::When {
+                keyword,
+                call,
+                ident,
+                block: inline(block)?,
+            },
+            ASTNode::StructureDelcaration {
+                s_type,
+                name,
+                attributes,
+                statement,
+                doc,
+            } => ASTNode::StructureDelcaration {
+                s_type,
+                name,
+                attributes,
+                statement: inline(statement)?,
+                doc,
+            },
+            ASTNode::Return(_) => {
+                return design!("return is only allowed at the end of a function.")
+            }
+            node => node,
+        };
+        Ok(ret)
+    }
+}
+
+// The type of every variable declared in a structure, for the arguments of the calls in it.
+pub fn declared(node: &ASTNode, types: &ConversionTable, ret: &mut HashMap<String, String>) {
+    match node {
+        ASTNode::VariableDeclaration {
+            typed_identifier, ..
+        } => {
+            if let Ok((ty, name)) = typed(typed_identifier) {
+                if let Some(aql_type) = types.get(&ty) {
+                    ret.insert(name, aql_type.mlir_type());
+                }
+            }
+        }
+        ASTNode::Block(stmts) => {
+            for stmt in stmts {
+                declared(stmt, types, ret);
+            }
+        }
+        ASTNode::StructureDelcaration { statement, .. } => declared(statement, types, ret),
+        ASTNode::Conditional {
+            if_blk, else_blk, ..
+        } => {
+            declared(if_blk, types, ret);
+            declared(else_blk, types, ret);
+        }
+        _ => {}
+    }
+}
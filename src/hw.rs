@@ -0,0 +1,168 @@
+use std::collections::HashMap;
+
+use crate::{
+    ast::ASTNode,
+    cfg::{Inst, StateMachine, Structure},
+    comments,
+    formal::{self, Node},
+    mlir::{self, Builder},
+    Error,
+};
+
+// A controller as a plain hw.module with clock and reset: the state and every variable are
+// `seq.compreg`s, the transitions and outputs are comb logic and assertions stay `verif` ops.
+// There is no fsm dialect left, so it exports to Verilog without `-convert-fsm-to-sv`.
+pub fn module(cfgs: &StateMachine) -> Result<String, Error> {
+    let sys = formal::build(std::slice::from_ref(cfgs))?;
+    let prefix = format!("{}.", cfgs.fsm_name);
+    let ty = |id: usize| format!("i{}", sys.width(id));
+    let mut b = Builder::new();
+
+    let mut values: Vec<String> = vec![];
+    for (node, width) in &sys.nodes {
+        let value = match node {
+            Node::Input(name) => format!("%{}", name.trim_start_matches(&prefix)),
+            Node::State(name) => format!("%reg.{}", name.trim_start_matches(&prefix)),
+            Node::Const(value) => b.constant(*value, *width),
+            Node::Op(op, ids) => {
+                let args: Vec<&str> = ids.iter().map(|a| values[*a].as_str()).collect();
+                match *op {
+                    "ite" => b.mux(args[0], args[1], args[2], &format!("i{}", width)),
+                    "not" => b.not(args[0]),
+                    "eq" | "neq" | "ult" | "ugt" | "ulte" | "ugte" => {
+                        let pred = match *op {
+                            "neq" => "ne",
+                            "ulte" => "ule",
+                            "ugte" => "uge",
+                            op => op,
+                        };
+                        b.icmp(pred, args[0], args[1], &ty(ids[0]))
+                    }
+                    op => {
+                        let op = match op {
+                            "udiv" => "divu",
+                            "sll" => "shl",
+                            "srl" => "shru",
+                            op => op,
+                        };
+                        b.binary(op, args[0], args[1], *width)
+                    }
+                }
+            }
+            Node::Uext(a) => {
+                let zeros = b.constant(0, width - sys.width(*a));
+                let ret = b.fresh();
+                b.push(format!(
+                    "{} = comb.concat {}, {} : i{}, {}",
+                    ret,
+                    zeros,
+                    values[*a],
+                    width - sys.width(*a),
+                    ty(*a)
+                ));
+                ret
+            }
+            Node::Slice(a) => {
+                let ret = b.fresh();
+                b.push(format!(
+                    "{} = comb.extract {} from 0 : ({}) -> i{}",
+                    ret,
+                    values[*a],
+                    ty(*a),
+                    width
+                ));
+                ret
+            }
+        };
+        values.push(value);
+    }
+
+    // The state register lists the documented states with their encoding, variables keep their
+    // own documentation.
+    let mut docs: HashMap<String, Vec<String>> = HashMap::new();
+    let states = cfgs
+        .cfgs
+        .iter()
+        .filter(|cfg| cfg.scope.is_structure(&Structure::State));
+    for (i, cfg) in states.enumerate() {
+        if let Some(doc) = &cfg.doc {
+            let state_docs = docs.entry(String::from("reg.state")).or_default();
+            state_docs.push(format!("// {} = {}:", cfg.scope.name, i));
+            state_docs.extend(comments(Some(doc)));
+        }
+    }
+    for cfg in &cfgs.cfgs {
+        if !cfg.scope.is_structure(&Structure::ControllerEntry) {
+            continue;
+        }
+        for Inst::Stmt(stmt) in &cfg.insts {
+            if let ASTNode::VariableDeclaration {
+                typed_identifier,
+                doc: Some(doc),
+                ..
+            } = stmt
+            {
+                if let ASTNode::TypedIdentifier { variable, .. } = typed_identifier.as_ref() {
+                    docs.insert(format!("reg.{}", variable), comments(Some(doc)));
+                }
+            }
+        }
+    }
+
+    for (register, init, next) in &sys.registers {
+        let name = values[*register].trim_start_matches('%');
+        for comment in docs.get(name).into_iter().flatten() {
+            b.push(comment.clone());
+        }
+        b.register(name, &values[*next], &values[*init], &ty(*register));
+    }
+    for constraint in &sys.constraints {
+        b.push(format!("verif.assume {} : i1", values[*constraint]));
+    }
+    for (bad, label) in &sys.bad {
+        let holds = b.not(&values[*bad]);
+        b.push(format!("verif.assert {} label \"{}\" : i1", holds, label));
+    }
+
+    let mut ports = vec![
+        String::from("in %clk : !seq.clock"),
+        String::from("in %rst : i1"),
+    ];
+    for port in &cfgs.inputs {
+        ports.push(format!("in %{} : {}", port.name, port.ty));
+    }
+    for port in &cfgs.outputs {
+        ports.push(format!("out {} : {}", port.name, port.ty));
+    }
+
+    let mut ret = String::new();
+    for comment in comments(cfgs.doc()) {
+        ret += &format!("{}\n", comment);
+    }
+    ret += &format!(
+        "hw.module @{}({}){} {{\n",
+        mlir::symbol(&cfgs.fsm_name),
+        ports.join(", "),
+        mlir::module_attributes(cfgs.doc())
+    );
+    for op in &b.ops {
+        ret += &format!("\t{}\n", op);
+    }
+    if sys.outputs.is_empty() {
+        ret += "\thw.output\n";
+    } else {
+        let outputs: Vec<&str> = sys
+            .outputs
+            .iter()
+            .map(|(o, _)| values[*o].as_str())
+            .collect();
+        let types: Vec<&str> = cfgs.outputs.iter().map(|port| port.ty.as_str()).collect();
+        ret += &format!(
+            "\thw.output {} : {}\n",
+            outputs.join(", "),
+            types.join(", ")
+        );
+    }
+    ret += "}\n";
+    Ok(ret)
+}
@@ -0,0 +1,583 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    hash::{Hash, Hasher},
+};
+
+use crate::{
+    ast::{ASTNode, BinVerb},
+    cfg::{self, State, StateMachine, Structure},
+    error::design,
+    get_declaration, get_initial_value, get_name, is_comparison,
+    mlir::index_width,
+    reduce_neg, type_of,
+    utils::{self, ConversionTable},
+    Error,
+};
+
+// Values are held in a u64, which covers every integer type AQL has.
+fn mask(value: u64, ty: &str) -> Result<u64, Error> {
+    let width = utils::width(ty)?;
+    if width >= 64 {
+        Ok(value)
+    } else {
+        Ok(value & ((1 << width) - 1))
+    }
+}
+
+fn parse_value(text: &str) -> Option<u64> {
+    let text = text.trim();
+    if let Some(hex) = text.strip_prefix("0x") {
+        u64::from_str_radix(hex, 16).ok()
+    } else if let Some(bin) = text.strip_prefix("0b") {
+        u64::from_str_radix(bin, 2).ok()
+    } else {
+        text.parse::<i64>().ok().map(|value| value as u64)
+    }
+}
+
+// The values visible to one guard, action or output region, the interpreter's counterpart of the
+// `Env` used for lowering. Both have to agree on types, or the trace wouldn't match the hardware.
+struct Frame<'a> {
+    types: &'a ConversionTable,
+    // Machine variables and input ports.
+    values: HashMap<String, (u64, String)>,
+    // Transition-local temporaries.
+    locals: HashMap<String, (u64, String)>,
+}
+
+impl<'a> Frame<'a> {
+    fn type_of(&self, node: &ASTNode) -> Option<String> {
+        type_of(node, self.types, &|name| {
+            let local = self.locals.get(name).map(|(_, ty)| ty.clone());
+            local.or_else(|| self.values.get(name).map(|(_, ty)| ty.clone()))
+        })
+    }
+
+    fn eval(&self, node: &ASTNode, ty: Option<&str>) -> Result<u64, Error> {
+        let value = match node {
+            ASTNode::ConstVal(val) if val.parse::<i64>().is_ok() => {
+                let value = val.parse::<i64>().unwrap() as u64;
+                return mask(value, ty.unwrap_or("i32"));
+            }
+            ASTNode::BinOp { verb, lhs, rhs } => {
+                let operand_ty =
+                    self.type_of(lhs)
+                        .or_else(|| self.type_of(rhs))
+                        .unwrap_or(String::from(
+                            ty.filter(|_| !is_comparison(verb)).unwrap_or("i32"),
+                        ));
+                let lhs = self.eval(lhs, Some(&operand_ty))?;
+                let rhs = self.eval(rhs, Some(&operand_ty))?;
+
+                if is_comparison(verb) {
+                    compare(verb, lhs, rhs)? as u64
+                } else {
+                    mask(arith(verb, lhs, rhs, &operand_ty)?, &operand_ty)?
+                }
+            }
+            _ => match get_name(node) {
+                Some(name) => self.lookup(&name)?,
+                None => return design!("{} can't be simulated yet.", node.to_source()?),
+            },
+        };
+
+        // Widening is a zero extension, which a u64 already is.
+        match ty {
+            Some(ty) => mask(value, ty),
+            None => Ok(value),
+        }
+    }
+
+    fn lookup(&self, name: &str) -> Result<u64, Error> {
+        if let Some((value, _)) = self.locals.get(name) {
+            return Ok(*value);
+        }
+        if let Some((value, _)) = self.values.get(name) {
+            return Ok(*value);
+        }
+        if let Some((_, encoded)) = self.types.constant(name) {
+            return Ok(encoded as u64);
+        }
+        design!("{} is not declared.", name)
+    }
+
+    fn condition(&self, node: &ASTNode) -> Result<bool, Error> {
+        let ty = self.type_of(node).unwrap_or(String::from("i32"));
+        Ok(self.eval(node, Some(&ty))? != 0)
+    }
+}
+
+fn compare(verb: &BinVerb, lhs: u64, rhs: u64) -> Result<bool, Error> {
+    Ok(match reduce_neg(verb)? {
+        BinVerb::SmallerThan => lhs < rhs,
+        BinVerb::LargerThan => lhs > rhs,
+        BinVerb::SmallerOrEqual => lhs <= rhs,
+        BinVerb::LargerOrEqual => lhs >= rhs,
+        BinVerb::Equal => lhs == rhs,
+        BinVerb::NotEqual => lhs != rhs,
+        verb => return design!("{} isn't a comparison.", verb.symbol()),
+    })
+}
+
+fn arith(verb: &BinVerb, lhs: u64, rhs: u64, ty: &str) -> Result<u64, Error> {
+    let width = utils::width(ty)?;
+    let shift = |value: u64| {
+        if rhs >= width as u64 {
+            0
+        } else {
+            value
+        }
+    };
+    Ok(match verb {
+        BinVerb::Plus => lhs.wrapping_add(rhs),
+        BinVerb::Minus => lhs.wrapping_sub(rhs),
+        BinVerb::Times => lhs.wrapping_mul(rhs),
+        // comb.divu leaves division by zero undefined, pick zero.
+        BinVerb::Divide => lhs.checked_div(rhs).unwrap_or(0),
+        BinVerb::And => lhs & rhs,
+        BinVerb::Or => lhs | rhs,
+        BinVerb::Xor => lhs ^ rhs,
+        BinVerb::LeftShift => shift(lhs.wrapping_shl(rhs as u32)),
+        BinVerb::RightShift => shift(lhs.wrapping_shr(rhs as u32)),
+        _ => return design!("{} isn't an arithmetic operator.", verb.symbol()),
+    })
+}
+
+// Runs a `StateMachine` one clock cycle at a time with the semantics of the generated
+// `fsm.machine`: the outputs depend on the current state, the first transition whose guards all
+// hold is taken, and its actions update the variables with the values from before the cycle.
+#[derive(Clone)]
+pub struct Interpreter<'a> {
+    cfgs: &'a StateMachine,
+    state: String,
+    // Machine variables in declaration order, with their type.
+    variables: Vec<(String, String, u64)>,
+}
+
+impl<'a> Interpreter<'a> {
+    pub fn new(cfgs: &'a StateMachine) -> Result<Self, Error> {
+        let mut variables = vec![];
+        for cfg in &cfgs.cfgs {
+            if !cfg.scope.is_structure(&Structure::ControllerEntry) {
+                continue;
+            }
+            for inst in &cfg.insts {
+                let cfg::Inst::Stmt(stmt) = inst;
+                if let Some((name, init, ty)) = get_initial_value(stmt, &cfgs.types)? {
+                    let value = match parse_value(&init) {
+                        Some(value) => mask(value, &ty)?,
+                        None => {
+                            return design!("{} is not a valid initial value of {}.", init, name)
+                        }
+                    };
+                    variables.push((name, ty, value));
+                }
+            }
+        }
+
+        Ok(Interpreter {
+            cfgs,
+            state: cfgs.entry.clone(),
+            variables,
+        })
+    }
+
+    pub fn state(&self) -> &str {
+        &self.state
+    }
+
+    pub fn variables(&self) -> Vec<(String, u64)> {
+        self.variables
+            .iter()
+            .map(|(name, _, value)| (name.clone(), *value))
+            .collect()
+    }
+
+    fn current(&self) -> Result<&'a State, Error> {
+        match self
+            .cfgs
+            .cfgs
+            .iter()
+            .find(|cfg| cfg.scope.is_structure(&Structure::State) && cfg.scope.name == self.state)
+        {
+            Some(cfg) => Ok(cfg),
+            None => design!("{} is not a state of {}.", self.state, self.cfgs.fsm_name),
+        }
+    }
+
+    // Inputs missing from `inputs` are zero.
+    fn frame(&self, inputs: &HashMap<String, u64>) -> Result<Frame<'a>, Error> {
+        let mut values = HashMap::new();
+        for (name, ty, value) in &self.variables {
+            values.insert(name.clone(), (*value, ty.clone()));
+        }
+        for port in &self.cfgs.inputs {
+            let value = inputs.get(&port.name).copied().unwrap_or(0);
+            values.insert(port.name.clone(), (mask(value, &port.ty)?, port.ty.clone()));
+        }
+
+        Ok(Frame {
+            types: &self.cfgs.types,
+            values,
+            locals: HashMap::new(),
+        })
+    }
+
+    // The values of the machine outputs in this cycle, in port order.
+    pub fn outputs(&self, inputs: &HashMap<String, u64>) -> Result<Vec<u64>, Error> {
+        let frame = self.frame(inputs)?;
+        let mut values = HashMap::new();
+
+        for call in &self.current()?.calls {
+            if let Some((unit, method, args)) = cfg::get_request(call) {
+                let prefix = cfg::port_prefix(&unit, &method);
+                values.insert(format!("{}_valid", prefix), 1);
+                for (i, arg) in args.iter().enumerate() {
+                    let name = format!("{}_arg{}", prefix, i);
+                    let port = self.cfgs.outputs.iter().find(|p| p.name == name).unwrap();
+                    values.insert(name, frame.eval(arg, Some(&port.ty))?);
+                }
+            }
+        }
+
+        Ok(self
+            .cfgs
+            .outputs
+            .iter()
+            .map(|port| values.get(&port.name).copied().unwrap_or(0))
+            .collect())
+    }
+
+    // The labels of the properties that don't hold in this cycle.
+    pub fn failures(&self, inputs: &HashMap<String, u64>) -> Result<Vec<String>, Error> {
+        let frame = self.frame(inputs)?;
+        let mut ret = vec![];
+        for (label, property) in cfg::properties(self.cfgs, self.current()?) {
+            if let ASTNode::Assert { expr, .. } | ASTNode::Assume { expr, .. } = property {
+                if !frame.condition(expr)? {
+                    ret.push(label);
+                }
+            }
+        }
+        Ok(ret)
+    }
+
+    // Advances one clock edge, returns the target of the transition taken if any.
+    pub fn step(&mut self, inputs: &HashMap<String, u64>) -> Result<Option<String>, Error> {
+        let mut frame = self.frame(inputs)?;
+        let state = self.current()?;
+
+        let mut taken = None;
+        'transitions: for transition in &state.next.trans {
+            for guard in transition.guards.iter().flatten() {
+                if !frame.condition(guard)? {
+                    continue 'transitions;
+                }
+            }
+            taken = Some(transition);
+            break;
+        }
+        let Some(transition) = taken else {
+            return Ok(None);
+        };
+
+        let mut updates: Vec<(String, u64)> = vec![];
+        for action in transition.actions.iter().flatten() {
+            if let Some((variable, aql_type, expr)) = get_declaration(action) {
+                let ty = self.cfgs.types.convert(&aql_type)?.mlir_type();
+                let value = match expr {
+                    Some(expr) => frame.eval(&expr, Some(&ty))?,
+                    None => 0,
+                };
+                frame.locals.insert(variable, (value, ty));
+            } else if let ASTNode::Assignment { name, expr } = action {
+                if let Some((_, ty)) = frame.locals.get(name).cloned() {
+                    let value = frame.eval(expr, Some(&ty))?;
+                    frame.locals.insert(name.clone(), (value, ty));
+                } else if let Some((_, ty)) = frame.values.get(name).cloned() {
+                    if self.cfgs.inputs.iter().any(|port| port.name == *name) {
+                        return design!("{} is an input and can't be assigned.", name);
+                    }
+                    let value = frame.eval(expr, Some(&ty))?;
+                    match updates.iter_mut().find(|(variable, _)| variable == name) {
+                        Some((_, previous)) => *previous = value,
+                        None => updates.push((name.clone(), value)),
+                    }
+                } else {
+                    return design!("{} is not declared.", name);
+                }
+            }
+        }
+
+        let target = transition.target.clone();
+        for (name, value) in updates {
+            if let Some(variable) = self.variables.iter_mut().find(|(n, _, _)| *n == name) {
+                variable.2 = value;
+            }
+        }
+        self.state = target.clone();
+        Ok(Some(target))
+    }
+}
+
+// Interpreters of the same machine are equal when they are in the same state with the same
+// variables, so an exploration can tell which states it has seen.
+impl PartialEq for Interpreter<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        self.state == other.state && self.variables == other.variables
+    }
+}
+
+impl Eq for Interpreter<'_> {}
+
+impl Hash for Interpreter<'_> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.state.hash(state);
+        self.variables.hash(state);
+    }
+}
+
+// Input values over time. `<cycle> <port>=<value> ...` sets ports from that cycle on, they keep
+// their value until a later line changes them.
+#[derive(Default)]
+pub struct Stimulus {
+    changes: BTreeMap<u64, Vec<(String, u64)>>,
+}
+
+impl Stimulus {
+    pub fn parse(text: &str) -> Result<Self, Error> {
+        let mut changes: BTreeMap<u64, Vec<(String, u64)>> = BTreeMap::new();
+
+        for (i, text) in text.lines().enumerate() {
+            let line = text.split('#').next().unwrap_or_default();
+            // Points at a word of the line, columns start at 1.
+            let malformed = |word: &str, message: String| Error::Parse {
+                line: i + 1,
+                column: line.find(word).unwrap_or_default() + 1,
+                message,
+            };
+            let mut words = line.split_whitespace();
+            let Some(cycle) = words.next() else {
+                continue;
+            };
+            let cycle = match cycle.parse::<u64>() {
+                Ok(number) => number,
+                Err(_) => {
+                    return Err(malformed(
+                        cycle,
+                        format!("{} is not a cycle number.", cycle),
+                    ))
+                }
+            };
+            for word in words {
+                let Some((port, value)) = word.split_once('=') else {
+                    let message = format!("expected <port>=<value>, found {}.", word);
+                    return Err(malformed(word, message));
+                };
+                let Some(value) = parse_value(value) else {
+                    return Err(malformed(word, format!("{} is not a number.", value)));
+                };
+                changes
+                    .entry(cycle)
+                    .or_default()
+                    .push((String::from(port), value));
+            }
+        }
+
+        Ok(Stimulus { changes })
+    }
+
+    pub fn last_cycle(&self) -> Option<u64> {
+        self.changes.keys().next_back().copied()
+    }
+}
+
+// Everything visible in one cycle, before the clock edge ending it.
+pub struct Sample {
+    pub cycle: u64,
+    pub state: String,
+    pub variables: Vec<(String, u64)>,
+    pub inputs: Vec<u64>,
+    pub outputs: Vec<u64>,
+    // Properties that don't hold.
+    pub failures: Vec<String>,
+}
+
+// Runs `cycles` cycles from reset.
+pub fn run(cfgs: &StateMachine, stimulus: &Stimulus, cycles: u64) -> Result<Vec<Sample>, Error> {
+    for (port, _) in stimulus.changes.values().flatten() {
+        if !cfgs.inputs.iter().any(|p| p.name == *port) {
+            let names: Vec<&str> = cfgs.inputs.iter().map(|p| p.name.as_str()).collect();
+            return design!(
+                "{} is not an input of {}, expected one of {}.",
+                port,
+                cfgs.fsm_name,
+                names.join(", ")
+            );
+        }
+    }
+
+    let mut interpreter = Interpreter::new(cfgs)?;
+    let mut inputs: HashMap<String, u64> = HashMap::new();
+    let mut ret = vec![];
+
+    for cycle in 0..cycles {
+        for (port, value) in stimulus.changes.get(&cycle).into_iter().flatten() {
+            inputs.insert(port.clone(), *value);
+        }
+
+        ret.push(Sample {
+            cycle,
+            state: String::from(interpreter.state()),
+            variables: interpreter.variables(),
+            inputs: cfgs
+                .inputs
+                .iter()
+                .map(|p| mask(inputs.get(&p.name).copied().unwrap_or(0), &p.ty))
+                .collect::<Result<_, _>>()?,
+            outputs: interpreter.outputs(&inputs)?,
+            failures: interpreter.failures(&inputs)?,
+        });
+        interpreter.step(&inputs)?;
+    }
+    Ok(ret)
+}
+
+// One line per cycle, ports at zero are left out and failing properties are listed last.
+pub fn trace(cfgs: &StateMachine, samples: &[Sample]) -> String {
+    let ports = |ports: &[cfg::Port], values: &[u64]| {
+        let set: Vec<String> = ports
+            .iter()
+            .zip(values)
+            .filter(|(_, value)| **value != 0)
+            .map(|(port, value)| format!("{}={}", port.name, value))
+            .collect();
+        if set.is_empty() {
+            String::from("-")
+        } else {
+            set.join(" ")
+        }
+    };
+
+    let mut ret = String::new();
+    for sample in samples {
+        let mut line = format!("{:>4} {}", sample.cycle, sample.state);
+        for (name, value) in &sample.variables {
+            line += &format!(" {}={}", name, value);
+        }
+        line += &format!(" | in: {}", ports(&cfgs.inputs, &sample.inputs));
+        line += &format!(" | out: {}", ports(&cfgs.outputs, &sample.outputs));
+        if !sample.failures.is_empty() {
+            line += &format!(" | failed: {}", sample.failures.join(" "));
+        }
+        ret += &line;
+        ret += "\n";
+    }
+    ret
+}
+
+// Short VCD identifiers made of the printable characters `!` to `~`.
+fn vcd_id(mut index: usize) -> String {
+    let mut ret = String::new();
+    loop {
+        ret.push((b'!' + (index % 94) as u8) as char);
+        index /= 94;
+        if index == 0 {
+            return ret;
+        }
+        index -= 1;
+    }
+}
+
+fn vcd_value(bits: &str, id: &str) -> String {
+    if bits.len() == 1 {
+        format!("{}{}", bits, id)
+    } else {
+        format!("b{} {}", bits, id)
+    }
+}
+
+// An IEEE 1364 value change dump of the run, one cycle every 10ns. The state is dumped twice:
+// `state` holds its encoding (states are numbered in declaration order, like the enum the FSM
+// lowering creates) and `state_name` holds the name as ASCII for the viewer's ASCII format.
+pub fn vcd(cfgs: &StateMachine, samples: &[Sample]) -> Result<String, Error> {
+    let states: Vec<&str> = cfgs
+        .cfgs
+        .iter()
+        .filter(|cfg| cfg.scope.is_structure(&Structure::State))
+        .map(|cfg| cfg.scope.name.as_str())
+        .collect();
+    let state_width = index_width(states.len() as u32) as usize;
+    let name_width = 8 * states.iter().map(|s| s.len()).max().unwrap_or(1);
+    let types = cfg::variable_types(cfgs)?;
+    let bits = |value: u64, width: usize| {
+        let mut ret = String::new();
+        for i in (0..width).rev() {
+            ret.push(if i < 64 && (value >> i) & 1 == 1 {
+                '1'
+            } else {
+                '0'
+            });
+        }
+        ret
+    };
+
+    // (kind, name, width) of every signal and its value in each sample, as binary digits.
+    let mut signals: Vec<(&str, String, usize)> = vec![
+        ("reg", String::from("state"), state_width),
+        ("reg", String::from("state_name"), name_width),
+    ];
+    let variables = samples.first().map_or(vec![], |s| s.variables.clone());
+    for (name, _) in &variables {
+        signals.push(("reg", name.clone(), utils::width(&types[name])? as usize));
+    }
+    for port in cfgs.inputs.iter().chain(&cfgs.outputs) {
+        signals.push(("wire", port.name.clone(), utils::width(&port.ty)? as usize));
+    }
+
+    let values: Vec<Vec<String>> = samples
+        .iter()
+        .map(|sample| {
+            let encoded = states.iter().position(|s| *s == sample.state).unwrap();
+            let mut name = String::new();
+            for byte in format!("{:\0>1$}", sample.state, name_width / 8).bytes() {
+                name += &bits(byte as u64, 8);
+            }
+            let mut ret = vec![bits(encoded as u64, state_width), name];
+            let numbers = sample
+                .variables
+                .iter()
+                .map(|(_, value)| *value)
+                .chain(sample.inputs.iter().copied())
+                .chain(sample.outputs.iter().copied());
+            for (value, (_, _, width)) in numbers.zip(&signals[2..]) {
+                ret.push(bits(value, *width));
+            }
+            ret
+        })
+        .collect();
+
+    let mut ret = String::from("$version aql-circt $end\n$timescale 1ns $end\n");
+    ret += &format!("$scope module {} $end\n", cfgs.fsm_name);
+    for (i, (kind, name, width)) in signals.iter().enumerate() {
+        ret += &format!("$var {} {} {} {} $end\n", kind, width, vcd_id(i), name);
+    }
+    ret += "$upscope $end\n$enddefinitions $end\n";
+
+    for (cycle, sample) in values.iter().enumerate() {
+        let changes: Vec<String> = sample
+            .iter()
+            .enumerate()
+            .filter(|(i, value)| cycle == 0 || values[cycle - 1][*i] != **value)
+            .map(|(i, value)| vcd_value(value, &vcd_id(i)))
+            .collect();
+        if cycle == 0 {
+            ret += &format!("#0\n$dumpvars\n{}\n$end\n", changes.join("\n"));
+        } else if !changes.is_empty() {
+            ret += &format!("#{}\n{}\n", cycle * 10, changes.join("\n"));
+        }
+    }
+    // Close the last cycle so viewers show it in full.
+    ret += &format!("#{}\n", samples.len() * 10);
+    Ok(ret)
+}
@@ -0,0 +1,93 @@
+use crate::{
+    ast::ASTNode,
+    cfg::{self, Inst, Port, StateMachine, Structure},
+    Error,
+};
+use serde_json::{json, Value};
+
+fn sources(nodes: &[ASTNode]) -> Result<Value, Error> {
+    nodes
+        .iter()
+        .map(|node| node.to_source().map(Value::from))
+        .collect()
+}
+
+fn ports(ports: &[Port]) -> Value {
+    ports
+        .iter()
+        .map(|port| json!({"name": port.name, "type": port.ty}))
+        .collect()
+}
+
+// Fields keep their order so the output diffs well.
+fn machine(cfgs: &StateMachine) -> Result<Value, Error> {
+    let mut variables = vec![];
+    let mut states = vec![];
+    for cfg in &cfgs.cfgs {
+        if cfg.scope.is_structure(&Structure::ControllerEntry) {
+            for Inst::Stmt(stmt) in &cfg.insts {
+                if let Some((name, init, ty)) = crate::get_initial_value(stmt, &cfgs.types)? {
+                    let init = match init.parse::<i64>() {
+                        Ok(value) => Value::from(value),
+                        Err(_) => Value::from(init),
+                    };
+                    variables.push(json!({"name": name, "type": ty, "init": init}));
+                }
+            }
+        }
+        if !cfg.scope.is_structure(&Structure::State) {
+            continue;
+        }
+
+        let properties = cfg::properties(cfgs, cfg)
+            .into_iter()
+            .map(|(label, property)| Ok(json!({"label": label, "source": property.to_source()?})))
+            .collect::<Result<Vec<_>, Error>>()?;
+        let transitions = cfg
+            .next
+            .trans
+            .iter()
+            .map(|transition| {
+                let guards = transition.guards.as_deref().unwrap_or_default();
+                let actions = transition.actions.as_deref().unwrap_or_default();
+                Ok(json!({
+                    "target": transition.target,
+                    "guards": sources(guards)?,
+                    "actions": sources(actions)?,
+                }))
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+        states.push(json!({
+            "name": cfg.scope.name,
+            "complete": cfg.terminal,
+            "calls": sources(&cfg.calls)?,
+            "properties": properties,
+            "transitions": transitions,
+        }));
+    }
+
+    let queue = cfgs.queue.as_ref().map(|queue| {
+        json!({
+            "name": queue.name,
+            "ordering": format!("{:?}", queue.ordering),
+            "num_entries": queue.num_entries,
+        })
+    });
+
+    Ok(json!({
+        "name": cfgs.fsm_name,
+        "init_entry": cfgs.entry,
+        "state_queue": queue,
+        "inputs": ports(&cfgs.inputs),
+        "outputs": ports(&cfgs.outputs),
+        "variables": variables,
+        "states": states,
+    }))
+}
+
+// The state machines as JSON, one object per controller. Guards, actions, calls and properties
+// are AQL source, transitions are listed in priority order.
+pub fn state_machines(machines: &[StateMachine]) -> Result<String, Error> {
+    let machines = machines.iter().map(machine).collect::<Result<Value, _>>()?;
+    Ok(serde_json::to_string_pretty(&machines).expect("JSON values serialize") + "\n")
+}
@@ -0,0 +1,947 @@
+//! Compiles AQL controller descriptions into CIRCT MLIR.
+//!
+//! A design goes through [`parse`], [`convert`] into one [`StateMachine`] per controller, and
+//! [`generate`]. [`check`] looks for unreachable, stuck and deadlocking states on the way and
+//! [`format`] renders a parsed design back to AQL. Designs that `import` other files are read
+//! with a [`Loader`] instead of [`parse`].
+//!
+//! ```
+//! use aql_circt::{ConvertOptions, GenerateOptions};
+//!
+//! let source = "controller_entry LQ (init_entry: idle) {
+//! i32 count = 0;
+//! }
+//! state idle (controller: LQ) {
+//! count = count + 1;
+//! transition idle;
+//! }
+//! ";
+//! let ast = aql_circt::parse(source)?;
+//! let machines = aql_circt::convert(ast, &ConvertOptions::default())?;
+//! let mlir = aql_circt::generate(&machines, &GenerateOptions::default())?;
+//! assert!(mlir.contains("fsm.machine @LQ"));
+//! # Ok::<(), aql_circt::Error>(())
+//! ```
+
+pub mod ast;
+pub mod cfg;
+pub mod check;
+pub mod dot;
+mod error;
+pub mod formal;
+mod formatter;
+mod function;
+mod hw;
+pub mod interp;
+mod json;
+pub mod litmus;
+mod loader;
+pub mod lsp;
+pub mod memory_model;
+mod mlir;
+pub mod multicore;
+mod parser;
+mod queue;
+mod utils;
+
+use ast::{ASTNode, BinVerb, UniVerb};
+use cfg::Structure;
+use error::design;
+use utils::{AQLType, ConversionTable};
+
+use std::collections::HashMap;
+
+pub use cfg::StateMachine;
+pub use check::Reachability;
+pub use error::Error;
+pub use loader::Loader;
+pub use memory_model::MemoryModel;
+pub use parser::Location;
+
+/// Parses AQL source into its syntax tree.
+pub fn parse(source: &str) -> Result<ASTNode, Error> {
+    parser::parse(source)
+}
+
+/// Parses what it can of AQL source and reports every syntax error in it, in source order. A
+/// statement with an error is left out of the tree, and so is a declaration whose header has one.
+pub fn parse_recovering(source: &str) -> (ASTNode, Vec<Error>) {
+    let (source, errors) = parser::recover(source);
+    let ast = parser::parse(&source).unwrap_or(ASTNode::Top(vec![]));
+    (ast, errors)
+}
+
+/// Joins designs parsed from several files into one, so a state in one file can belong to a
+/// controller declared in another. Declarations keep the order of the files.
+pub fn link(programs: Vec<ASTNode>) -> ASTNode {
+    let mut decls = vec![];
+    for program in programs {
+        match program {
+            ASTNode::Top(program) => decls.extend(program),
+            decl => decls.push(decl),
+        }
+    }
+    ASTNode::Top(decls)
+}
+
+/// Renders a parsed design as canonical AQL source. Fails if the design holds something the
+/// parser left out, rendering it would drop code.
+pub fn format(ast: &ASTNode) -> Result<String, Error> {
+    formatter::format(ast)
+}
+
+/// Formats AQL source, keeping its comments. Formatting a formatted source gives it back as is.
+/// Fails rather than return a source that doesn't parse to the same design.
+pub fn format_source(source: &str) -> Result<String, Error> {
+    let formatted = formatter::format(&parser::parse_with_comments(source)?)?;
+    if parser::parse(&formatted).ok() != Some(parser::parse(source)?) {
+        return design!("fmt can't reproduce this source, it was left as is.");
+    }
+    Ok(formatted)
+}
+
+/// Where every structure of the source is declared, in source order.
+pub fn locations(source: &str) -> Result<Vec<Location>, Error> {
+    parser::locations(source)
+}
+
+/// [`locations`] of every source compiled together, their structures can belong to a controller
+/// of another source.
+pub fn linked_locations(sources: &[&str]) -> Result<Vec<Vec<Location>>, Error> {
+    parser::linked_locations(sources)
+}
+
+/// How the syntax tree is turned into state machines.
+#[derive(Debug, Clone, Default)]
+#[non_exhaustive]
+pub struct ConvertOptions {
+    /// Rewrites the load/store controllers so they enforce this memory consistency model.
+    pub memory_model: Option<MemoryModel>,
+}
+
+/// Builds one state machine per controller of a parsed design.
+pub fn convert(ast: ASTNode, options: &ConvertOptions) -> Result<Vec<StateMachine>, Error> {
+    cfg::convert(ast, options.memory_model)
+}
+
+/// How far [`check`] explores.
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct CheckOptions {
+    /// At most this many (state, variables) configurations are explored per controller.
+    pub bound: usize,
+}
+
+impl Default for CheckOptions {
+    fn default() -> Self {
+        CheckOptions { bound: 10000 }
+    }
+}
+
+/// Reports unreachable, stuck and deadlocking states, one result per machine in order.
+pub fn check(
+    machines: &[StateMachine],
+    options: &CheckOptions,
+) -> Result<Vec<Reachability>, Error> {
+    machines
+        .iter()
+        .map(|cfgs| check::reachability(cfgs, options.bound))
+        .collect()
+}
+
+/// What [`generate`] writes.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Format {
+    /// CIRCT MLIR with the fsm, hw, comb, seq and verif dialects.
+    #[default]
+    Mlir,
+    /// CIRCT MLIR without the fsm dialect, one `hw.module` with clock and reset per controller,
+    /// ready for `-export-verilog`.
+    HwMlir,
+    /// The states, transitions, ports and variables of every machine as text.
+    Cfg,
+    /// The same as [`Format::Cfg`] as JSON.
+    Json,
+    /// A BTOR2 transition system for model checkers.
+    Btor2,
+    /// An SMT-LIB 2 transition system with VMT annotations.
+    Smt2,
+    /// A Graphviz graph of the state machines.
+    Dot,
+    /// A Graphviz graph of the control flow inside every state.
+    DotCfg,
+}
+
+/// How the state machines are written out.
+#[derive(Debug, Clone, Default)]
+#[non_exhaustive]
+pub struct GenerateOptions {
+    pub format: Format,
+    /// Emits a `hw.module` named after the controller entry that instantiates the machine with
+    /// clock and reset. fsm MLIR only, hw MLIR always has one.
+    pub top: bool,
+    /// With `top`, only wraps the controller of this name, the others stay bare machines.
+    pub top_controller: Option<String>,
+    /// Instantiates every controller, and the queue module of a `state_queue`, once per core in a
+    /// shared `hw.module @top`. fsm MLIR only, and not together with `top`.
+    pub cores: Option<u32>,
+}
+
+/// Writes the state machines in the format of `options`.
+pub fn generate(machines: &[StateMachine], options: &GenerateOptions) -> Result<String, Error> {
+    if options.format == Format::HwMlir {
+        if options.cores.is_some() {
+            return Err(Error::Design(String::from(
+                "hw-mlir can't replicate cores yet, use fsm-mlir with --cores",
+            )));
+        }
+        if let Some(cfgs) = machines.iter().find(|cfgs| cfgs.queue.is_some()) {
+            return Err(Error::Design(format!(
+                "hw-mlir can't lower the state_queue of {} yet, use fsm-mlir",
+                cfgs.fsm_name
+            )));
+        }
+    }
+
+    if options.cores.is_some() && options.top {
+        return Err(Error::Design(String::from(
+            "--top wraps a single core, --cores already instantiates every core in hw.module @top",
+        )));
+    }
+
+    if let Some(name) = &options.top_controller {
+        if !machines.iter().any(|cfgs| &cfgs.fsm_name == name) {
+            let names: Vec<&str> = machines.iter().map(|cfgs| cfgs.fsm_name.as_str()).collect();
+            return Err(Error::Design(format!(
+                "--top {} is not a controller, expected one of {}.",
+                name,
+                names.join(", ")
+            )));
+        }
+    }
+
+    if let Format::Mlir | Format::HwMlir = options.format {
+        mlir::distinct_symbols(machines)?;
+    }
+
+    let ret = match options.format {
+        Format::Mlir => generate_mlir(machines, options)?,
+        Format::HwMlir => {
+            let modules = machines
+                .iter()
+                .map(hw::module)
+                .collect::<Result<Vec<_>, _>>()?;
+            wrap_module(&modules.join("\n"))
+        }
+        Format::Cfg => {
+            let machines = machines
+                .iter()
+                .map(|cfgs| cfgs.describe())
+                .collect::<Result<Vec<_>, _>>()?;
+            machines.join("\n")
+        }
+        Format::Json => json::state_machines(machines)?,
+        Format::Btor2 => formal::btor2(&formal::build(machines)?),
+        Format::Smt2 => formal::smt2(&formal::build(machines)?),
+        Format::Dot => dot::state_machines(machines)?,
+        Format::DotCfg => dot::control_flows(machines)?,
+    };
+    Ok(ret)
+}
+
+/// Replicates the controllers for `cores` cores, for [`litmus::testbench`].
+pub fn generate_multicore(
+    machines: &[StateMachine],
+    cores: u32,
+) -> Result<(String, multicore::MultiCore), Error> {
+    mlir::distinct_symbols(machines)?;
+    let design = multicore::generate_multicore(machines, cores)?;
+    Ok((wrap_module(&design.text), design))
+}
+
+// All machines (and their top modules) share one builtin.module.
+fn generate_mlir(machines: &[StateMachine], options: &GenerateOptions) -> Result<String, Error> {
+    let mut body = String::new();
+
+    if let Some(cores) = options.cores {
+        body = multicore::generate_multicore(machines, cores)?.text;
+    } else {
+        for (i, cfgs) in machines.iter().enumerate() {
+            if i != 0 {
+                body += "\n";
+            }
+            body += &generate_controller(cfgs, options)?;
+        }
+    }
+
+    Ok(wrap_module(&body))
+}
+
+// The builtin.module holding everything we generate.
+fn wrap_module(body: &str) -> String {
+    let mut ret = String::from("module {\n");
+    for line in body.lines() {
+        if !line.is_empty() {
+            ret += "\t";
+            ret += line;
+        }
+        ret += "\n";
+    }
+    ret += "}\n";
+    ret
+}
+
+fn generate_controller(cfgs: &StateMachine, options: &GenerateOptions) -> Result<String, Error> {
+    let top = options.top
+        && options
+            .top_controller
+            .as_ref()
+            .is_none_or(|name| name == &cfgs.fsm_name);
+    // The hw.module takes over the controller entry name, so the machine needs its own symbol.
+    let machine_name = if top {
+        format!("{}_fsm", mlir::symbol(&cfgs.fsm_name))
+    } else {
+        mlir::symbol(&cfgs.fsm_name)
+    };
+
+    let mut ret = generate_machine(cfgs, &machine_name)?;
+
+    if top {
+        ret += "\n";
+        ret += &generate_top(cfgs, &machine_name);
+    }
+
+    if let Some(state_queue) = &cfgs.queue {
+        ret += "\n";
+        ret += &queue::generate_queue(
+            cfgs,
+            state_queue,
+            &machine_name,
+            &format!("{}_queue", mlir::symbol(&state_queue.name)),
+        );
+    }
+
+    Ok(ret)
+}
+
+fn generate_machine(cfgs: &StateMachine, machine_name: &str) -> Result<String, Error> {
+    let mut fsm_machine = String::new();
+    for comment in comments(cfgs.doc()) {
+        fsm_machine += &format!("{}\n", comment);
+    }
+    fsm_machine += &format!(
+        "fsm.machine @{}{} attributes {{initialState = \"{}\"}}",
+        machine_name,
+        generate_signature(cfgs),
+        mlir::symbol(&cfgs.entry)
+    );
+
+    fsm_machine += " {\n";
+
+    for cfg in cfgs.cfgs.clone() {
+        // The queue is lowered into its own module below, it doesn't own any machine variables.
+        if cfg.scope.is_structure(&Structure::StateQueue) {
+            continue;
+        }
+        let c = cfg.as_ref();
+        let insts = &c.insts;
+
+        // Only the controller entry owns machine variables, declarations inside states are
+        // temporaries of the transitions using them.
+        if !cfg.scope.is_structure(&Structure::ControllerEntry) {
+            continue;
+        }
+
+        for inst in insts {
+            let cfg::Inst::Stmt(stmt) = inst;
+            if let ASTNode::VariableDeclaration { doc, .. } = stmt {
+                for comment in comments(doc.as_deref()) {
+                    fsm_machine += &format!("\t{}\n", comment);
+                }
+                fsm_machine += "\t";
+                fsm_machine += &generate_decl(stmt, &cfgs.types)?;
+                fsm_machine += "\n";
+            }
+        }
+    }
+
+    for cfg in &cfgs.cfgs {
+        // Transitions only exist in the states, should filter the other structures.
+        if let Structure::State = cfg.scope.label {
+            for comment in comments(cfg.doc.as_deref()) {
+                fsm_machine += &format!("\t{}\n", comment);
+            }
+            let state_name = format!("\tfsm.state @{}", mlir::symbol(&cfg.scope.name));
+            fsm_machine += &state_name;
+            if !cfgs.outputs.is_empty() || !cfg::properties(cfgs, cfg).is_empty() {
+                fsm_machine += &generate_outputs(cfg, cfgs)?;
+            }
+            fsm_machine += " transitions {\n";
+
+            // print transition.
+            for tran in &cfg.next.trans {
+                // guard and actions are all optional.
+                let transition = format!("\t\tfsm.transition @{} ", mlir::symbol(&tran.target));
+                let mut guards = String::from("");
+                let mut actions = String::from("\n");
+
+                if let Some(raw_guards) = tran.guards.as_ref() {
+                    guards = parse_guards(raw_guards, cfgs)?;
+                }
+
+                if let Some(raw_action) = tran.actions.as_ref() {
+                    actions = generate_actions(raw_action, cfgs)?;
+                }
+                fsm_machine += &transition;
+                fsm_machine += &guards;
+                fsm_machine += &actions;
+            }
+            fsm_machine += "\n\t}\n\n";
+        }
+    }
+
+    fsm_machine += "}\n";
+    Ok(fsm_machine)
+}
+
+// (%arg0: i1, %arg1: i1) -> (i1)
+fn generate_signature(cfgs: &StateMachine) -> String {
+    let inputs: Vec<String> = cfgs
+        .inputs
+        .iter()
+        .map(|port| format!("%{}: {}", port.name, port.ty))
+        .collect();
+    let mut ret = format!("({})", inputs.join(", "));
+
+    if !cfgs.outputs.is_empty() {
+        let outputs: Vec<&str> = cfgs.outputs.iter().map(|port| port.ty.as_str()).collect();
+        ret += &format!(" -> ({})", outputs.join(", "));
+    }
+    ret
+}
+
+// The top module only wires clock and reset into the machine and forwards its ports,
+// so the exported Verilog can be dropped into a design without a hand-written wrapper.
+fn generate_top(cfgs: &StateMachine, machine_name: &str) -> String {
+    let module_name = mlir::symbol(&cfgs.fsm_name);
+    let instance = format!("{}_inst", module_name);
+
+    let mut ports = vec![
+        String::from("in %clk : !seq.clock"),
+        String::from("in %rst : i1"),
+    ];
+    for port in &cfgs.inputs {
+        ports.push(format!("in %{} : {}", port.name, port.ty));
+    }
+    for port in &cfgs.outputs {
+        ports.push(format!("out {} : {}", port.name, port.ty));
+    }
+
+    let operands: Vec<String> = cfgs.inputs.iter().map(|p| format!("%{}", p.name)).collect();
+    let output_types: Vec<&str> = cfgs.outputs.iter().map(|p| p.ty.as_str()).collect();
+
+    let mut ret = format!(
+        "hw.module @{}({}){} {{\n",
+        module_name,
+        ports.join(", "),
+        mlir::module_attributes(cfgs.doc())
+    );
+    ret += "\t";
+    ret += &mlir::hw_instance(cfgs, &instance, machine_name, &operands, "%rst");
+    ret += "\n";
+
+    let values: Vec<String> = (0..cfgs.outputs.len())
+        .map(|i| mlir::instance_result(&instance, i, cfgs.outputs.len()))
+        .collect();
+    if values.is_empty() {
+        ret += "\thw.output\n";
+    } else {
+        ret += &format!(
+            "\thw.output {} : {}\n",
+            values.join(", "),
+            output_types.join(", ")
+        );
+    }
+    ret += "}\n";
+    ret
+}
+
+// The values visible while lowering one guard or action region.
+struct Env<'a> {
+    types: &'a ConversionTable,
+    // Machine variables and input ports, every one of them has a single type.
+    variables: HashMap<String, String>,
+    // State-local declarations are transition-local temporaries, bound to an SSA value of the
+    // region instead of becoming registers. Guards never see them, cfg inlines their value.
+    locals: HashMap<String, (String, String)>,
+    ops: Vec<String>,
+    counter: usize,
+}
+
+impl<'a> Env<'a> {
+    fn new(cfgs: &'a StateMachine) -> Result<Self, Error> {
+        let mut variables = cfg::variable_types(cfgs)?;
+        for port in &cfgs.inputs {
+            variables.insert(port.name.clone(), port.ty.clone());
+        }
+
+        Ok(Env {
+            types: &cfgs.types,
+            variables,
+            locals: HashMap::new(),
+            ops: vec![],
+            counter: 0,
+        })
+    }
+
+    fn fresh(&mut self) -> String {
+        let ret = format!("%{}", self.counter);
+        self.counter += 1;
+        ret
+    }
+
+    fn emit(&mut self, op: String) -> String {
+        let ret = self.fresh();
+        self.ops.push(format!("{} = {}", ret, op));
+        ret
+    }
+
+    fn constant(&mut self, value: &str, ty: &str) -> String {
+        self.emit(format!("hw.constant {} : {}", value, ty))
+    }
+
+    fn type_of(&self, node: &ASTNode) -> Option<String> {
+        type_of(node, self.types, &|name| {
+            let local = self.locals.get(name).map(|(_, ty)| ty.clone());
+            local.or_else(|| self.variables.get(name).cloned())
+        })
+    }
+
+    // Lowers an expression into the region, the result has type `ty` when given.
+    fn lower(&mut self, node: &ASTNode, ty: Option<&str>) -> Result<String, Error> {
+        let (value, value_ty) = match node {
+            ASTNode::ConstVal(val) if val.parse::<i64>().is_ok() => {
+                let ty = ty.unwrap_or("i32");
+                return Ok(self.constant(val, ty));
+            }
+            ASTNode::BinOp { verb, lhs, rhs } => {
+                let operand_ty =
+                    self.type_of(lhs)
+                        .or_else(|| self.type_of(rhs))
+                        .unwrap_or(String::from(
+                            ty.filter(|_| !is_comparison(verb)).unwrap_or("i32"),
+                        ));
+                let lhs = self.lower(lhs, Some(&operand_ty))?;
+                let rhs = self.lower(rhs, Some(&operand_ty))?;
+
+                if is_comparison(verb) {
+                    let symbol = get_binverb(verb)?;
+                    let value = self.emit(format!(
+                        "comb.icmp {} {}, {} : {}",
+                        symbol, lhs, rhs, operand_ty
+                    ));
+                    (value, String::from("i1"))
+                } else {
+                    let op = get_arith(verb)?;
+                    let value = self.emit(format!("comb.{} {}, {} : {}", op, lhs, rhs, operand_ty));
+                    (value, operand_ty)
+                }
+            }
+            _ => {
+                let Some(name) = get_name(node) else {
+                    return design!("{} can't be lowered to MLIR yet.", node.to_source()?);
+                };
+                self.lower_name(&name)?
+            }
+        };
+
+        match ty {
+            Some(ty) => self.resize(&value, &value_ty, ty),
+            None => Ok(value),
+        }
+    }
+
+    fn lower_name(&mut self, name: &str) -> Result<(String, String), Error> {
+        if let Some(local) = self.locals.get(name) {
+            return Ok(local.clone());
+        }
+        if let Some(ty) = self.variables.get(name) {
+            return Ok((format!("%{}", name), ty.clone()));
+        }
+        if let Some((enum_name, encoded)) = self.types.constant(name) {
+            let ty = self.types.convert(&enum_name)?.mlir_type();
+            let value = self.constant(&encoded.to_string(), &ty);
+            return Ok((value, ty));
+        }
+        design!("{} is not declared.", name)
+    }
+
+    // Zero-extends or truncates a value so it fits the width it's used with.
+    fn resize(&mut self, value: &str, from: &str, to: &str) -> Result<String, Error> {
+        let from_width = utils::width(from)?;
+        let to_width = utils::width(to)?;
+
+        let ret = if from_width == to_width {
+            String::from(value)
+        } else if from_width > to_width {
+            self.emit(format!(
+                "comb.extract {} from 0 : ({}) -> {}",
+                value, from, to
+            ))
+        } else {
+            let padding_ty = format!("i{}", to_width - from_width);
+            let zero = self.constant("0", &padding_ty);
+            self.emit(format!(
+                "comb.concat {}, {} : {}, {}",
+                zero, value, padding_ty, from
+            ))
+        };
+        Ok(ret)
+    }
+
+    fn lower_condition(&mut self, node: &ASTNode) -> Result<String, Error> {
+        let ty = self.type_of(node).unwrap_or(String::from("i32"));
+        let value = self.lower(node, Some(&ty))?;
+        if ty == "i1" {
+            Ok(value)
+        } else {
+            let zero = self.constant("0", &ty);
+            Ok(self.emit(format!("comb.icmp ne {}, {} : {}", value, zero, ty)))
+        }
+    }
+}
+
+// The type a node has on its own, None for literals which adapt to their use. `lookup` gives the
+// type of the names in scope.
+fn type_of(
+    node: &ASTNode,
+    types: &ConversionTable,
+    lookup: &dyn Fn(&str) -> Option<String>,
+) -> Option<String> {
+    match node {
+        ASTNode::BinOp { verb, lhs, rhs } => {
+            if is_comparison(verb) {
+                Some(String::from("i1"))
+            } else {
+                type_of(lhs, types, lookup).or_else(|| type_of(rhs, types, lookup))
+            }
+        }
+        _ => {
+            let name = get_name(node)?;
+            if let Some(ty) = lookup(&name) {
+                return Some(ty);
+            }
+            let (enum_name, _) = types.constant(&name)?;
+            Some(types.get(&enum_name)?.mlir_type())
+        }
+    }
+}
+
+// Requests are issued for as long as the machine stays in the state making them, every other
+// state drives them to zero.
+fn generate_outputs(state: &cfg::State, cfgs: &StateMachine) -> Result<String, Error> {
+    let mut env = Env::new(cfgs)?;
+    let mut values = HashMap::new();
+
+    for call in &state.calls {
+        if let Some((unit, method, args)) = cfg::get_request(call) {
+            let prefix = cfg::port_prefix(&unit, &method);
+            let valid = env.constant("1", "i1");
+            values.insert(format!("{}_valid", prefix), valid);
+            for (i, arg) in args.iter().enumerate() {
+                let name = format!("{}_arg{}", prefix, i);
+                let ty = match cfgs.outputs.iter().find(|port| port.name == name) {
+                    Some(port) => port.ty.clone(),
+                    None => return design!("{} has no output port {}.", cfgs.fsm_name, name),
+                };
+                let value = env.lower(arg, Some(&ty))?;
+                values.insert(name, value);
+            }
+        }
+    }
+
+    let mut outputs = vec![];
+    for port in &cfgs.outputs {
+        match values.get(&port.name) {
+            Some(value) => outputs.push(value.clone()),
+            None => outputs.push(env.constant("0", &port.ty)),
+        }
+    }
+    let types: Vec<&str> = cfgs.outputs.iter().map(|port| port.ty.as_str()).collect();
+
+    // Properties are checked while the machine is in the state.
+    for (label, property) in cfg::properties(cfgs, state) {
+        let (op, expr) = match property {
+            ASTNode::Assert { expr, .. } => ("verif.assert", expr),
+            ASTNode::Assume { expr, .. } => ("verif.assume", expr),
+            _ => continue,
+        };
+        let value = env.lower_condition(expr)?;
+        env.ops
+            .push(format!("{} {} label \"{}\" : i1", op, value, label));
+    }
+
+    let mut ret = String::from(" output {\n");
+    for op in &env.ops {
+        ret += &format!("\t\t{}\n", op);
+    }
+    if outputs.is_empty() {
+        ret += "\t\tfsm.output\n\t}";
+    } else {
+        ret += &format!(
+            "\t\tfsm.output {} : {}\n\t}}",
+            outputs.join(", "),
+            types.join(", ")
+        );
+    }
+    Ok(ret)
+}
+
+fn generate_actions(actions: &[ASTNode], cfgs: &StateMachine) -> Result<String, Error> {
+    let mut env = Env::new(cfgs)?;
+    // A variable can only be updated once per transition, the last assignment wins.
+    let mut updates: Vec<(String, String)> = vec![];
+
+    for action in actions {
+        if let Some((variable, aql_type, expr)) = get_declaration(action) {
+            let ty = cfgs.types.convert(&aql_type)?.mlir_type();
+            let value = match expr {
+                Some(expr) => env.lower(&expr, Some(&ty))?,
+                None => env.constant("0", &ty),
+            };
+            env.locals.insert(variable, (value, ty));
+        } else if let ASTNode::Assignment { name, expr } = action {
+            if let Some((_, ty)) = env.locals.get(name).cloned() {
+                // Assigning to a temporary just rebinds it.
+                let value = env.lower(expr, Some(&ty))?;
+                env.locals.insert(name.clone(), (value, ty));
+            } else if let Some(ty) = env.variables.get(name).cloned() {
+                let value = env.lower(expr, Some(&ty))?;
+                let update = format!("fsm.update %{}, {} : {}", name, value, ty);
+                match updates.iter_mut().find(|(variable, _)| variable == name) {
+                    Some((_, previous)) => *previous = update,
+                    None => updates.push((name.clone(), update)),
+                }
+            } else {
+                return design!("{} is not declared.", name);
+            }
+        }
+    }
+
+    // Temporaries nobody stores don't need an action region.
+    if updates.is_empty() {
+        return Ok(String::from("\n"));
+    }
+
+    let mut ret = String::from(" action {\n");
+    for op in env
+        .ops
+        .iter()
+        .chain(updates.iter().map(|(_, update)| update))
+    {
+        ret += &format!("\t\t\t{}\n", op);
+    }
+    ret += "\t\t}\n";
+    Ok(ret)
+}
+
+fn parse_guards(guards: &[ASTNode], cfgs: &StateMachine) -> Result<String, Error> {
+    let mut env = Env::new(cfgs)?;
+
+    let mut conditions = vec![];
+    for inst in guards {
+        conditions.push(env.lower_condition(inst)?);
+    }
+
+    let mut ret = String::from("guard {\n");
+    for op in &env.ops {
+        ret += &format!("\t\t\t{}\n", op);
+    }
+    ret += &format!(
+        "\t\t\t%fsm_output = comb.and {} : i1\n",
+        conditions.join(", ")
+    );
+    ret += "\t\t\tfsm.return %fsm_output\n\t\t}";
+    Ok(ret)
+}
+
+// `///` documentation as MLIR comments. CIRCT drops comments when it parses, they are for
+// whoever reads the generated file.
+fn comments(doc: Option<&str>) -> Vec<String> {
+    doc.into_iter()
+        .flat_map(|doc| doc.lines())
+        .map(|line| format!("// {}", line).trim_end().to_string())
+        .collect()
+}
+
+// i32 x = expr; => (x, i32, expr)
+fn get_declaration(node: &ASTNode) -> Option<(String, String, Option<ASTNode>)> {
+    if let ASTNode::VariableDeclaration {
+        typed_identifier,
+        expr,
+        ..
+    } = node
+    {
+        if let ASTNode::TypedIdentifier { aql_type, variable } = typed_identifier.as_ref() {
+            return Some((
+                variable.clone(),
+                aql_type.clone(),
+                expr.as_ref().map(|expr| *expr.clone()),
+            ));
+        }
+    }
+    None
+}
+
+// A plain name, or a literal naming an enum constant.
+fn get_name(node: &ASTNode) -> Option<String> {
+    match node {
+        ASTNode::ConstVal(val) => Some(val.clone()),
+        _ => node.name(),
+    }
+}
+
+fn is_comparison(verb: &BinVerb) -> bool {
+    matches!(
+        verb,
+        BinVerb::SmallerThan
+            | BinVerb::LargerThan
+            | BinVerb::SmallerOrEqual
+            | BinVerb::LargerOrEqual
+            | BinVerb::Equal
+            | BinVerb::NotEqual
+            | BinVerb::Neg(_)
+    )
+}
+
+fn get_arith(verb: &BinVerb) -> Result<String, Error> {
+    let op = match verb {
+        BinVerb::Plus => "add",
+        BinVerb::Minus => "sub",
+        BinVerb::Times => "mul",
+        BinVerb::Divide => "divu",
+        BinVerb::And => "and",
+        BinVerb::Or => "or",
+        BinVerb::Xor => "xor",
+        BinVerb::LeftShift => "shl",
+        BinVerb::RightShift => "shru",
+        _ => return design!("{} isn't an arithmetic operator.", verb.symbol()),
+    };
+    Ok(String::from(op))
+}
+
+fn get_binverb(verb: &BinVerb) -> Result<String, Error> {
+    let symbol = match verb {
+        ast::BinVerb::SmallerThan => String::from("ult"),
+        ast::BinVerb::LargerThan => String::from("ugt"),
+        ast::BinVerb::SmallerOrEqual => String::from("ule"),
+        ast::BinVerb::LargerOrEqual => String::from("uge"),
+        ast::BinVerb::Equal => String::from("eq"),
+        ast::BinVerb::NotEqual => String::from("ne"),
+        ast::BinVerb::Neg(_) => get_binverb(&reduce_neg(verb)?)?,
+        _ => return design!("{} isn't a comparison.", verb.symbol()),
+    };
+
+    Ok(symbol)
+}
+
+fn reduce_neg(verb: &BinVerb) -> Result<BinVerb, Error> {
+    if let BinVerb::Neg(v) = verb {
+        if let BinVerb::Neg(inner_v) = *v.clone() {
+            reduce_neg(&inner_v)
+        } else {
+            let ret = match *v.clone() {
+                BinVerb::SmallerThan => BinVerb::LargerOrEqual,
+                BinVerb::LargerThan => BinVerb::SmallerOrEqual,
+                BinVerb::SmallerOrEqual => BinVerb::LargerThan,
+                BinVerb::LargerOrEqual => BinVerb::SmallerThan,
+                BinVerb::Equal => BinVerb::NotEqual,
+                BinVerb::NotEqual => BinVerb::Equal,
+                v => return design!("{} isn't a comparison, it can't be negated.", v.symbol()),
+            };
+
+            Ok(ret)
+        }
+    } else {
+        Ok(verb.clone())
+    }
+}
+
+// These are all the initilzation process, so should add one indent.
+fn generate_decl(decl: &ASTNode, tbs: &ConversionTable) -> Result<String, Error> {
+    let ret = match get_initial_value(decl, tbs)? {
+        Some((variable, init_value, mlir_type)) => format!(
+            "%{} = fsm.variable \"{}\" {{initValue = {} : {} }} : {}",
+            variable, variable, init_value, mlir_type, mlir_type
+        ),
+        None => String::new(),
+    };
+    Ok(ret)
+}
+
+// i32 x = 3; => (x, 3, i32), enum values are encoded. None when it isn't a declaration, an error
+// when the initializer isn't a constant, registers can only be reset to one.
+fn get_initial_value(
+    decl: &ASTNode,
+    tbs: &ConversionTable,
+) -> Result<Option<(String, String, String)>, Error> {
+    let ASTNode::VariableDeclaration {
+        typed_identifier,
+        expr,
+        ..
+    } = decl
+    else {
+        return Ok(None);
+    };
+    let ASTNode::TypedIdentifier { aql_type, variable } = typed_identifier.as_ref() else {
+        return design!("{} should declare a typed variable.", decl.to_source()?);
+    };
+    let aql_type = tbs.convert(aql_type)?;
+    let mlir_type = aql_type.mlir_type();
+
+    let init_value = match expr {
+        Some(val) => match (&aql_type, val.as_ref()) {
+            (AQLType::Base(_), ASTNode::ConstVal(val)) => val.clone(),
+            (
+                AQLType::Base(_),
+                ASTNode::UnuaryOp {
+                    verb: UniVerb::Minus,
+                    term,
+                },
+            ) if matches!(term.as_ref(), ASTNode::ConstVal(_)) => format!("-{}", term.to_source()?),
+            (AQLType::Enum { .. }, ASTNode::ConstVal(name))
+            | (AQLType::Enum { .. }, ASTNode::Ident(name)) => encode_enum(&aql_type, name)?,
+            (AQLType::Enum { .. }, ASTNode::QualifiedName { names }) if names.len() == 1 => {
+                match &names[0] {
+                    ASTNode::Ident(name) => encode_enum(&aql_type, name)?,
+                    _ => return not_constant(variable, val),
+                }
+            }
+            _ => return not_constant(variable, val),
+        },
+        None => String::from("0"),
+    };
+    Ok(Some((variable.clone(), init_value, mlir_type)))
+}
+
+fn not_constant<T>(variable: &str, value: &ASTNode) -> Result<T, Error> {
+    design!(
+        "{} starts as {}, the initial value of a variable has to be a constant.",
+        variable,
+        value.to_source()?
+    )
+}
+
+fn encode_enum(aql_type: &AQLType, value: &str) -> Result<String, Error> {
+    match (aql_type.encode(value), aql_type) {
+        (Some(encoded), _) => Ok(encoded.to_string()),
+        (None, AQLType::Enum { name, variants }) => design!(
+            "{} is not a value of {}, expected one of {}.",
+            value,
+            name,
+            variants.join(", ")
+        ),
+        (None, AQLType::Base(_)) => design!("{} is not an enum type.", value),
+    }
+}
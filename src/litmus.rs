@@ -0,0 +1,1274 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
+
+use crate::{
+    cfg::StateMachine,
+    error::design,
+    memory_model::MemoryModel,
+    multicore::{MultiCore, Simulation},
+    utils, Error,
+};
+
+// Every location lives on its own cache line in the testbench memory.
+const LINE_BYTES: i64 = 64;
+// Cycles the testbench waits for the design before giving up.
+const TIMEOUT: u32 = 10000;
+// Cycles the testbench gives the store buffers to drain before it reads the memory.
+const DRAIN: u32 = 100;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Op {
+    Load { reg: String, loc: String },
+    Store { loc: String, value: i64 },
+    Fence,
+}
+
+impl Op {
+    fn loc(&self) -> Option<&str> {
+        match self {
+            Op::Load { loc, .. } | Op::Store { loc, .. } => Some(loc),
+            Op::Fence => None,
+        }
+    }
+}
+
+// The final condition: `0:r0=1 /\ (x=2 \/ ~1:r1=0)`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Prop {
+    Eq(String, i64), // `{thread}:{reg}` or a location.
+    And(Box<Prop>, Box<Prop>),
+    Or(Box<Prop>, Box<Prop>),
+    Not(Box<Prop>),
+}
+
+impl Prop {
+    pub fn eval(&self, outcome: &Outcome) -> bool {
+        match self {
+            Prop::Eq(name, value) => outcome.get(name).copied().unwrap_or(0) == *value,
+            Prop::And(lhs, rhs) => lhs.eval(outcome) && rhs.eval(outcome),
+            Prop::Or(lhs, rhs) => lhs.eval(outcome) || rhs.eval(outcome),
+            Prop::Not(prop) => !prop.eval(outcome),
+        }
+    }
+
+    fn names(&self, ret: &mut Vec<String>) {
+        match self {
+            Prop::Eq(name, _) => {
+                if !ret.contains(name) {
+                    ret.push(name.clone());
+                }
+            }
+            Prop::And(lhs, rhs) | Prop::Or(lhs, rhs) => {
+                lhs.names(ret);
+                rhs.names(ret);
+            }
+            Prop::Not(prop) => prop.names(ret),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Quantifier {
+    Exists,
+    NotExists,
+    Forall,
+}
+
+// A litmus test in herd's generic (LISA) syntax:
+//
+//   LISA MP
+//   { x=0; y=0; }
+//    P0       | P1       ;
+//    w[] x 1  | r[] r0 y ;
+//    w[] y 1  | r[] r1 x ;
+//   exists (1:r0=1 /\ 1:r1=0)
+#[derive(Debug, Clone)]
+pub struct Litmus {
+    pub name: String,
+    pub locations: Vec<(String, i64)>,
+    pub registers: Vec<(usize, String, i64)>,
+    pub threads: Vec<Vec<Op>>,
+    pub quantifier: Quantifier,
+    pub condition: Prop,
+}
+
+// Registers are named `{thread}:{reg}`, locations by their name.
+pub type Outcome = BTreeMap<String, i64>;
+
+// A malformed test, columns aren't tracked so errors point at the start of the line.
+fn malformed(line: usize, message: String) -> Error {
+    Error::Parse {
+        line,
+        column: 1,
+        message,
+    }
+}
+
+fn parse_int(text: &str) -> Option<i64> {
+    text.trim().trim_start_matches('$').parse().ok()
+}
+
+// `[x]` and `x` both name location x.
+fn parse_loc(text: &str) -> String {
+    text.trim()
+        .trim_start_matches('[')
+        .trim_end_matches(']')
+        .to_string()
+}
+
+fn parse_op(cell: &str) -> Result<Option<Op>, String> {
+    let tokens: Vec<&str> = cell.split_whitespace().collect();
+    let Some(first) = tokens.first() else {
+        return Ok(None);
+    };
+    // The annotation between the brackets (`r[acq]`, `f[mb]`) doesn't change the semantics here.
+    let kind = first.split('[').next().unwrap_or_default();
+    let ret = match (kind, &tokens[1..]) {
+        ("r", [reg, loc]) => Op::Load {
+            reg: reg.to_string(),
+            loc: parse_loc(loc),
+        },
+        ("w", [loc, value]) => Op::Store {
+            loc: parse_loc(loc),
+            value: parse_int(value).ok_or(format!("{} is not an integer.", value))?,
+        },
+        ("f", []) => Op::Fence,
+        _ => return Err(format!("Unknown litmus instruction: {}", cell.trim())),
+    };
+    Ok(Some(ret))
+}
+
+fn tokenize_condition(text: &str) -> Result<Vec<String>, String> {
+    let mut ret = vec![];
+    let chars: Vec<char> = text.chars().collect();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+        } else if c == '/' && chars.get(i + 1) == Some(&'\\') {
+            ret.push(String::from("/\\"));
+            i += 2;
+        } else if c == '\\' && chars.get(i + 1) == Some(&'/') {
+            ret.push(String::from("\\/"));
+            i += 2;
+        } else if "()~=".contains(c) {
+            ret.push(c.to_string());
+            i += 1;
+        } else {
+            let start = i;
+            while i < chars.len() && (chars[i].is_alphanumeric() || "_:[]$-".contains(chars[i])) {
+                i += 1;
+            }
+            if start == i {
+                return Err(format!("Unexpected {} in the litmus condition.", c));
+            }
+            ret.push(chars[start..i].iter().collect());
+        }
+    }
+    Ok(ret)
+}
+
+// disjunction := conjunction ("\/" conjunction)*
+// conjunction := atom ("/\" atom)*
+// atom        := "(" disjunction ")" | "~" atom | name "=" integer
+struct ConditionParser {
+    tokens: Vec<String>,
+    pos: usize,
+}
+
+impl ConditionParser {
+    fn peek(&self) -> Option<&str> {
+        self.tokens.get(self.pos).map(|t| t.as_str())
+    }
+
+    fn next(&mut self) -> Result<String, String> {
+        let ret = self
+            .tokens
+            .get(self.pos)
+            .cloned()
+            .ok_or(String::from("The litmus condition ends too early."))?;
+        self.pos += 1;
+        Ok(ret)
+    }
+
+    fn disjunction(&mut self) -> Result<Prop, String> {
+        let mut ret = self.conjunction()?;
+        while self.peek() == Some("\\/") {
+            self.next()?;
+            ret = Prop::Or(Box::new(ret), Box::new(self.conjunction()?));
+        }
+        Ok(ret)
+    }
+
+    fn conjunction(&mut self) -> Result<Prop, String> {
+        let mut ret = self.atom()?;
+        while self.peek() == Some("/\\") {
+            self.next()?;
+            ret = Prop::And(Box::new(ret), Box::new(self.atom()?));
+        }
+        Ok(ret)
+    }
+
+    fn atom(&mut self) -> Result<Prop, String> {
+        match self.next()?.as_str() {
+            "(" => {
+                let ret = self.disjunction()?;
+                if self.next()? != ")" {
+                    return Err(String::from("Missing ) in the litmus condition."));
+                }
+                Ok(ret)
+            }
+            "~" => Ok(Prop::Not(Box::new(self.atom()?))),
+            name => {
+                let name = parse_loc(name);
+                if self.next()? != "=" {
+                    return Err(format!(
+                        "Expected = after {} in the litmus condition.",
+                        name
+                    ));
+                }
+                let value = self.next()?;
+                match parse_int(&value) {
+                    Some(value) => Ok(Prop::Eq(name, value)),
+                    None => Err(format!("{} is not an integer.", value)),
+                }
+            }
+        }
+    }
+}
+
+pub fn parse(text: &str) -> Result<Litmus, Error> {
+    let mut lines = text
+        .lines()
+        .enumerate()
+        .map(|(i, line)| (i + 1, line.trim()))
+        .filter(|(_, line)| !line.is_empty());
+
+    let Some((line, header)) = lines.next() else {
+        return Err(malformed(1, String::from("The litmus test is empty.")));
+    };
+    let name = match header.split_whitespace().nth(1) {
+        Some(name) => name.to_string(),
+        None => {
+            return Err(malformed(
+                line,
+                format!("The litmus header should be `LISA <name>`: {}", header),
+            ))
+        }
+    };
+
+    // The optional doc string and the initial state.
+    let mut init = vec![];
+    let mut in_init = false;
+    let mut rest = vec![];
+    for (line, text) in lines.by_ref() {
+        if !in_init && text.starts_with('"') {
+            continue;
+        }
+        if !in_init && text.starts_with('{') {
+            in_init = true;
+        }
+        if in_init {
+            init.push((line, text));
+            if text.contains('}') {
+                break;
+            }
+            continue;
+        }
+        rest.push((line, text));
+        break;
+    }
+    rest.extend(lines);
+
+    let mut locations = vec![];
+    let mut registers = vec![];
+    for (line, text) in init {
+        for entry in text
+            .trim_matches(|c| c == '{' || c == '}' || c == ' ')
+            .split(';')
+        {
+            let Some((lhs, rhs)) = entry.split_once('=') else {
+                continue;
+            };
+            // `int x = 0` declares the type as well.
+            let lhs = lhs.split_whitespace().last().unwrap_or_default();
+            let Some(value) = parse_int(rhs) else {
+                return Err(malformed(
+                    line,
+                    format!("{} is not an integer.", rhs.trim()),
+                ));
+            };
+            match lhs.split_once(':') {
+                Some((thread, reg)) => match parse_int(thread) {
+                    Some(thread) => registers.push((thread as usize, reg.to_string(), value)),
+                    None => return Err(malformed(line, format!("{} is not a thread.", thread))),
+                },
+                None => locations.push((parse_loc(lhs), value)),
+            }
+        }
+    }
+
+    let Some(start) = rest.iter().position(|(_, line)| {
+        line.starts_with("exists") || line.starts_with("~") || line.starts_with("forall")
+    }) else {
+        let line = rest.last().map_or(1, |(line, _)| *line);
+        return Err(malformed(
+            line,
+            String::from("The litmus test has no final condition."),
+        ));
+    };
+    let table = &rest[..start];
+    let condition_line = rest[start].0;
+    let condition: Vec<&str> = rest[start..].iter().map(|(_, text)| *text).collect();
+    let condition = condition.join(" ");
+
+    let mut threads: Vec<Vec<Op>> = vec![];
+    for (row, (line, text)) in table.iter().enumerate() {
+        let cells: Vec<&str> = text.trim_end_matches(';').split('|').collect();
+        if row == 0 {
+            threads = cells.iter().map(|_| vec![]).collect();
+            continue;
+        }
+        for (thread, cell) in cells.iter().enumerate() {
+            if let Some(op) = parse_op(cell).map_err(|message| malformed(*line, message))? {
+                match threads.get_mut(thread) {
+                    Some(ops) => ops.push(op),
+                    None => {
+                        return Err(malformed(
+                            *line,
+                            format!("Row {} has more threads than the header.", row),
+                        ))
+                    }
+                }
+            }
+        }
+    }
+
+    let (quantifier, condition) = if let Some(rest) = condition.strip_prefix("exists") {
+        (Quantifier::Exists, rest)
+    } else if let Some(rest) = condition.strip_prefix("forall") {
+        (Quantifier::Forall, rest)
+    } else {
+        let rest = condition.trim_start_matches('~').trim_start();
+        match rest.strip_prefix("exists") {
+            Some(rest) => (Quantifier::NotExists, rest),
+            None => {
+                return Err(malformed(
+                    condition_line,
+                    String::from(
+                        "The litmus condition should start with exists, ~exists or forall.",
+                    ),
+                ))
+            }
+        }
+    };
+    let condition = tokenize_condition(condition)
+        .and_then(|tokens| {
+            let mut parser = ConditionParser { tokens, pos: 0 };
+            let ret = parser.disjunction()?;
+            match parser.peek() {
+                Some(token) => Err(format!("Unexpected {} in the litmus condition.", token)),
+                None => Ok(ret),
+            }
+        })
+        .map_err(|message| malformed(condition_line, message))?;
+
+    // Locations only used by the program start at 0.
+    for op in threads.iter().flatten() {
+        if let Some(loc) = op.loc() {
+            if !locations.iter().any(|(name, _)| name == loc) {
+                locations.push((loc.to_string(), 0));
+            }
+        }
+    }
+
+    // The testbench reads every name of the condition back from the design.
+    let mut names = vec![];
+    condition.names(&mut names);
+    for used in names {
+        let known = match used.split_once(':') {
+            Some((thread, reg)) => parse_int(thread).is_some_and(|thread| {
+                let thread = thread as usize;
+                registers.iter().any(|(t, r, _)| *t == thread && r == reg)
+                    || threads.get(thread).is_some_and(|ops| {
+                        ops.iter()
+                            .any(|op| matches!(op, Op::Load { reg: r, .. } if r == reg))
+                    })
+            }),
+            None => locations.iter().any(|(loc, _)| *loc == used),
+        };
+        if !known {
+            return Err(malformed(
+                condition_line,
+                format!("{} is not a register or location of the test.", used),
+            ));
+        }
+    }
+
+    Ok(Litmus {
+        name,
+        locations,
+        registers,
+        threads,
+        quantifier,
+        condition,
+    })
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct Machine {
+    done: Vec<Vec<bool>>,
+    regs: Vec<BTreeMap<String, i64>>,
+    mem: BTreeMap<String, i64>,
+    // TSO store buffers, oldest store first.
+    buffers: Vec<VecDeque<(String, i64)>>,
+}
+
+impl Machine {
+    fn new(test: &Litmus) -> Self {
+        let mut regs = vec![BTreeMap::new(); test.threads.len()];
+        for (thread, reg, value) in &test.registers {
+            if let Some(regs) = regs.get_mut(*thread) {
+                regs.insert(reg.clone(), *value);
+            }
+        }
+        Machine {
+            done: test
+                .threads
+                .iter()
+                .map(|ops| vec![false; ops.len()])
+                .collect(),
+            regs,
+            mem: test.locations.iter().cloned().collect(),
+            buffers: vec![VecDeque::new(); test.threads.len()],
+        }
+    }
+
+    fn finished(&self) -> bool {
+        self.done.iter().flatten().all(|done| *done) && self.buffers.iter().all(|b| b.is_empty())
+    }
+
+    fn outcome(&self, test: &Litmus) -> Outcome {
+        let mut ret = Outcome::new();
+        for (thread, regs) in self.regs.iter().enumerate() {
+            for (reg, value) in regs {
+                ret.insert(format!("{}:{}", thread, reg), *value);
+            }
+        }
+        let mut names = vec![];
+        test.condition.names(&mut names);
+        for name in names {
+            if let Some(value) = self.mem.get(&name) {
+                ret.insert(name, *value);
+            }
+        }
+        ret
+    }
+
+    // Instructions of `thread` that may execute now.
+    fn ready(&self, test: &Litmus, model: MemoryModel, thread: usize) -> Vec<usize> {
+        let ops = &test.threads[thread];
+        let done = &self.done[thread];
+        let Some(next) = done.iter().position(|done| !done) else {
+            return vec![];
+        };
+        match model {
+            MemoryModel::Sc | MemoryModel::Tso => vec![next],
+            // Anything may go ahead of older instructions, except across a fence or an access to
+            // the same location.
+            MemoryModel::Rvwmo => (next..ops.len())
+                .filter(|&i| {
+                    !done[i]
+                        && (next..i).all(|j| {
+                            done[j]
+                                || (ops[i] != Op::Fence
+                                    && ops[j] != Op::Fence
+                                    && ops[i].loc() != ops[j].loc())
+                        })
+                })
+                .collect(),
+        }
+    }
+
+    fn execute(&self, test: &Litmus, model: MemoryModel, thread: usize, i: usize) -> Option<Self> {
+        let mut ret = self.clone();
+        let buffer = &mut ret.buffers[thread];
+        match &test.threads[thread][i] {
+            Op::Load { reg, loc } => {
+                // A TSO load sees the youngest store of its own store buffer first.
+                let forwarded = buffer.iter().rev().find(|(l, _)| l == loc).map(|(_, v)| *v);
+                let value = forwarded.unwrap_or(ret.mem.get(loc).copied().unwrap_or(0));
+                ret.regs[thread].insert(reg.clone(), value);
+            }
+            Op::Store { loc, value } => match model {
+                MemoryModel::Tso => buffer.push_back((loc.clone(), *value)),
+                _ => {
+                    ret.mem.insert(loc.clone(), *value);
+                }
+            },
+            Op::Fence => {
+                if !buffer.is_empty() {
+                    return None;
+                }
+            }
+        }
+        ret.done[thread][i] = true;
+        Some(ret)
+    }
+}
+
+fn explore(
+    test: &Litmus,
+    model: MemoryModel,
+    state: Machine,
+    visited: &mut HashSet<Machine>,
+    outcomes: &mut BTreeSet<Outcome>,
+) {
+    if !visited.insert(state.clone()) {
+        return;
+    }
+    if state.finished() {
+        outcomes.insert(state.outcome(test));
+        return;
+    }
+
+    for thread in 0..test.threads.len() {
+        for i in state.ready(test, model, thread) {
+            if let Some(next) = state.execute(test, model, thread, i) {
+                explore(test, model, next, visited, outcomes);
+            }
+        }
+        let mut drained = state.clone();
+        if let Some((loc, value)) = drained.buffers[thread].pop_front() {
+            drained.mem.insert(loc, value);
+            explore(test, model, drained, visited, outcomes);
+        }
+    }
+}
+
+// Every final state the memory model allows, the specification the design is checked against. They
+// are found by running all the interleavings of an operational model: SC executes in program order on a single memory, TSO adds a FIFO store
+// buffer per thread, and RVWMO lets instructions run out of order unless a fence or an access to
+// the same location sits in between.
+pub fn outcomes(test: &Litmus, model: MemoryModel) -> BTreeSet<Outcome> {
+    let mut ret = BTreeSet::new();
+    explore(
+        test,
+        model,
+        Machine::new(test),
+        &mut HashSet::new(),
+        &mut ret,
+    );
+    ret
+}
+
+// The testbench of `testbench` between two clock edges: the program counters, the memory and the
+// registers it keeps, and the signals it drives into the design.
+#[derive(Clone, PartialEq, Eq, Hash)]
+struct Bench {
+    pc: Vec<usize>,
+    mem: Vec<i64>,
+    regs: Vec<Vec<i64>>,
+    loads_left: usize,
+    invalidations: VecDeque<(u64, u64)>,
+    signals: BTreeMap<&'static str, u64>,
+}
+
+impl Bench {
+    fn new(test: &Litmus, registers: &[Vec<String>]) -> Self {
+        let mut regs: Vec<Vec<i64>> = registers.iter().map(|r| vec![0; r.len()]).collect();
+        for (thread, reg, value) in &test.registers {
+            let id = registers[*thread].iter().position(|r| r == reg).unwrap();
+            regs[*thread][id] = *value;
+        }
+        Bench {
+            pc: vec![0; test.threads.len()],
+            mem: test.locations.iter().map(|(_, value)| *value).collect(),
+            regs,
+            loads_left: test
+                .threads
+                .iter()
+                .flatten()
+                .filter(|op| matches!(op, Op::Load { .. }))
+                .count(),
+            invalidations: VecDeque::new(),
+            signals: BTreeMap::new(),
+        }
+    }
+
+    fn issued(&self, test: &Litmus) -> bool {
+        self.pc
+            .iter()
+            .zip(&test.threads)
+            .all(|(pc, ops)| *pc == ops.len())
+    }
+
+    fn outcome(&self, test: &Litmus, registers: &[Vec<String>]) -> Outcome {
+        let mut ret = Outcome::new();
+        for (thread, regs) in registers.iter().enumerate() {
+            for (id, reg) in regs.iter().enumerate() {
+                ret.insert(format!("{}:{}", thread, reg), self.regs[thread][id]);
+            }
+        }
+        let mut names = vec![];
+        test.condition.names(&mut names);
+        for (i, (loc, _)) in test.locations.iter().enumerate() {
+            if names.contains(loc) {
+                ret.insert(loc.clone(), self.mem[i]);
+            }
+        }
+        ret
+    }
+
+    // The location an address of the design points at.
+    fn location(&self, test: &Litmus, addr: u64) -> Result<usize, Error> {
+        let loc = addr / LINE_BYTES as u64;
+        match (loc as usize) < self.mem.len() {
+            true => Ok(loc as usize),
+            false => design!(
+                "{}: the design accessed address {}, which isn't a location of the test.",
+                test.name,
+                addr
+            ),
+        }
+    }
+
+    // One clock edge of the testbench and the design, `pick` is the core that gets its next
+    // instruction, if any. The testbench sees the design's outputs from before the edge and the design
+    // sees the signals the testbench drove at the previous one.
+    fn edge<'a>(
+        &self,
+        test: &Litmus,
+        design: &Simulation<'a>,
+        pick: Option<usize>,
+    ) -> Result<(Bench, Simulation<'a>), Error> {
+        let inputs: HashMap<String, u64> = self
+            .signals
+            .iter()
+            .map(|(name, value)| (name.to_string(), *value))
+            .collect();
+        let mut design = design.clone();
+        let outputs = design.step(&inputs)?;
+        let output = |name: &str| outputs.get(name).copied().unwrap_or(0);
+        // Data is an `int` in the testbench.
+        let int = |value: u64| value as i32 as i64;
+        let mut next = self.clone();
+        for valid in [
+            "MemSys_load_response_valid",
+            "MemSys_invalidate_valid",
+            "Core_issue_load_valid",
+            "Core_issue_store_valid",
+            "Core_issue_fence_valid",
+        ] {
+            next.signals.insert(valid, 0);
+        }
+
+        if output("MemSys_store_valid") == 1 {
+            let addr = output("MemSys_store_arg0");
+            let loc = next.location(test, addr)?;
+            next.mem[loc] = int(output("MemSys_store_arg1"));
+            for core in 0..test.threads.len() as u64 {
+                if core != output("MemSys_store_core") {
+                    next.invalidations.push_back((core, addr));
+                }
+            }
+        }
+        if output("MemSys_load_valid") == 1 {
+            let loc = next.location(test, output("MemSys_load_arg0"))?;
+            next.signals.insert("MemSys_load_response_valid", 1);
+            next.signals
+                .insert("MemSys_load_response_core", output("MemSys_load_core"));
+            next.signals
+                .insert("MemSys_load_response_arg0", next.mem[loc] as u64);
+        }
+        if let Some((core, line)) = next.invalidations.pop_front() {
+            next.signals.insert("MemSys_invalidate_valid", 1);
+            next.signals.insert("MemSys_invalidate_core", core);
+            next.signals.insert("MemSys_invalidate_arg0", line);
+        }
+        if output("Core_load_done_valid") == 1 {
+            let core = output("Core_load_done_core") as usize;
+            let id = output("Core_load_done_arg0") as usize;
+            match next.regs.get_mut(core).and_then(|regs| regs.get_mut(id)) {
+                Some(reg) => *reg = int(output("Core_load_done_arg1")),
+                None => {
+                    return design!(
+                        "{}: core {} retired a load to register {}, which the thread doesn't have.",
+                        test.name,
+                        core,
+                        id
+                    )
+                }
+            }
+            next.loads_left = next.loads_left.saturating_sub(1);
+        }
+
+        if let Some(core) = pick {
+            let loc_addr = |loc: &str| {
+                let loc = test.locations.iter().position(|(l, _)| l == loc).unwrap();
+                loc as u64 * LINE_BYTES as u64
+            };
+            let tag = core as u64;
+            match &test.threads[core][self.pc[core]] {
+                Op::Load { reg, loc } => {
+                    let registers = register_ids(test);
+                    let id = registers[core].iter().position(|r| r == reg).unwrap();
+                    next.signals.insert("Core_issue_load_valid", 1);
+                    next.signals.insert("Core_issue_load_core", tag);
+                    next.signals.insert("Core_issue_load_arg0", loc_addr(loc));
+                    next.signals.insert("Core_issue_load_arg1", id as u64);
+                }
+                Op::Store { loc, value } => {
+                    next.signals.insert("Core_issue_store_valid", 1);
+                    next.signals.insert("Core_issue_store_core", tag);
+                    next.signals.insert("Core_issue_store_arg0", loc_addr(loc));
+                    next.signals.insert("Core_issue_store_arg1", *value as u64);
+                }
+                Op::Fence => {
+                    next.signals.insert("Core_issue_fence_valid", 1);
+                    next.signals.insert("Core_issue_fence_core", tag);
+                }
+            }
+            next.pc[core] += 1;
+        }
+
+        Ok((next, design))
+    }
+
+    // The rest of the run once every instruction is issued, it doesn't depend on any choice.
+    // `None` if the design never retires all the loads.
+    fn finish(
+        mut self,
+        test: &Litmus,
+        mut design: Simulation,
+        registers: &[Vec<String>],
+    ) -> Result<Option<Outcome>, Error> {
+        let mut seen = HashSet::new();
+        while self.loads_left != 0 {
+            if seen.len() == TIMEOUT as usize || !seen.insert((self.clone(), design.clone())) {
+                return Ok(None);
+            }
+            (self, design) = self.edge(test, &design, None)?;
+        }
+        // Nothing changes anymore once the design and the testbench are idle.
+        for _ in 0..DRAIN {
+            let (bench, next) = self.edge(test, &design, None)?;
+            if bench == self && next == design {
+                break;
+            }
+            (self, design) = (bench, next);
+        }
+        Ok(Some(self.outcome(test, registers)))
+    }
+}
+
+// The outcomes of a design in every interleaving the testbench can pick.
+pub struct Reachable {
+    pub outcomes: BTreeSet<Outcome>,
+    // Some interleavings never finish: the design dropped an instruction it wasn't ready for, or
+    // never retired a load.
+    pub unfinished: bool,
+}
+
+// Runs the test on the design compiled for one core per thread, with the interpreter in place of
+// the simulator: the testbench of `testbench` gives one core or none its next instruction every
+// cycle, and every choice is explored.
+pub fn reachable(test: &Litmus, machines: &[StateMachine]) -> Result<Reachable, Error> {
+    let cores = test.threads.len();
+    let registers = register_ids(test);
+    let design = Simulation::new(machines, cores as u32)?;
+    let mut ret = Reachable {
+        outcomes: BTreeSet::new(),
+        unfinished: false,
+    };
+    let mut visited = HashSet::new();
+    let mut stack = vec![(Bench::new(test, &registers), design)];
+
+    while let Some((bench, design)) = stack.pop() {
+        if !visited.insert((bench.clone(), design.clone())) {
+            continue;
+        }
+        if bench.issued(test) {
+            match bench.finish(test, design, &registers)? {
+                Some(outcome) => {
+                    ret.outcomes.insert(outcome);
+                }
+                None => ret.unfinished = true,
+            }
+            continue;
+        }
+        // A core that has issued its whole thread gets nothing when it's picked, like a cycle
+        // without an instruction.
+        let picks = (0..cores).filter(|core| bench.pc[*core] < test.threads[*core].len());
+        for pick in picks.map(Some).chain([None]) {
+            stack.push(bench.edge(test, &design, pick)?);
+        }
+    }
+    Ok(ret)
+}
+
+fn format_outcome(outcome: &Outcome) -> String {
+    outcome
+        .iter()
+        .map(|(name, value)| format!("{}={};", name, value))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+// herd-like summary of what the design does: the outcomes it reaches, the ones `model` forbids
+// among them, and whether the final condition is observable.
+pub fn report(test: &Litmus, model: MemoryModel, design: &Reachable) -> String {
+    let allowed = outcomes(test, model);
+    let reached = &design.outcomes;
+    let forbidden: Vec<&Outcome> = reached.difference(&allowed).collect();
+
+    let mark = |outcome: &Outcome| {
+        if test.condition.eval(outcome) {
+            " *"
+        } else {
+            ""
+        }
+    };
+    let mut ret = format!("Test {} under {}\n", test.name, model.name());
+    ret += &format!("Reachable outcomes ({}):\n", reached.len());
+    for outcome in reached {
+        ret += &format!("  {}{}\n", format_outcome(outcome), mark(outcome));
+    }
+    ret += &format!("Forbidden by {} ({}):\n", model.name(), forbidden.len());
+    for outcome in &forbidden {
+        ret += &format!("  {}{}\n", format_outcome(outcome), mark(outcome));
+    }
+    if design.unfinished {
+        ret += "Some interleavings don't finish: the design drops an instruction or never retires a load.\n";
+    }
+
+    let positive = reached.iter().filter(|o| test.condition.eval(o)).count();
+    let negative = reached.len() - positive;
+    let observation = match (positive, negative) {
+        (0, _) => "Never",
+        (_, 0) => "Always",
+        _ => "Sometimes",
+    };
+    let holds = match test.quantifier {
+        Quantifier::Exists => positive > 0,
+        Quantifier::NotExists => positive == 0,
+        Quantifier::Forall => negative == 0,
+    };
+    ret += &format!(
+        "Condition {}: {}\n",
+        if holds { "Ok" } else { "No" },
+        format_condition(test)
+    );
+    ret += &format!(
+        "Observation {} {} {} {}\n",
+        test.name, observation, positive, negative
+    );
+    ret
+}
+
+fn format_prop(prop: &Prop) -> String {
+    // Conjunctions bind tighter than disjunctions, only a disjunction inside one needs parentheses.
+    let operand = |prop: &Prop, parent: &Prop| match (prop, parent) {
+        (Prop::Or(..), Prop::And(..)) => format!("({})", format_prop(prop)),
+        _ => format_prop(prop),
+    };
+    match prop {
+        Prop::Eq(name, value) => format!("{}={}", name, value),
+        Prop::And(lhs, rhs) => format!("{} /\\ {}", operand(lhs, prop), operand(rhs, prop)),
+        Prop::Or(lhs, rhs) => format!("{} \\/ {}", operand(lhs, prop), operand(rhs, prop)),
+        Prop::Not(inner) => match inner.as_ref() {
+            Prop::Eq(..) => format!("~{}", format_prop(inner)),
+            _ => format!("~({})", format_prop(inner)),
+        },
+    }
+}
+
+fn format_condition(test: &Litmus) -> String {
+    let quantifier = match test.quantifier {
+        Quantifier::Exists => "exists",
+        Quantifier::NotExists => "~exists",
+        Quantifier::Forall => "forall",
+    };
+    format!("{} ({})", quantifier, format_prop(&test.condition))
+}
+
+// Registers are numbered per thread in order of appearance.
+fn register_ids(test: &Litmus) -> Vec<Vec<String>> {
+    let mut ret: Vec<Vec<String>> = vec![vec![]; test.threads.len()];
+    for (thread, reg, _) in &test.registers {
+        if *thread < ret.len() && !ret[*thread].contains(reg) {
+            ret[*thread].push(reg.clone());
+        }
+    }
+    for (thread, ops) in test.threads.iter().enumerate() {
+        for op in ops {
+            if let Op::Load { reg, .. } = op {
+                if !ret[thread].contains(reg) {
+                    ret[thread].push(reg.clone());
+                }
+            }
+        }
+    }
+    ret
+}
+
+// The SystemVerilog expression reading `name` at the end of the test.
+fn sv_value(test: &Litmus, registers: &[Vec<String>], name: &str) -> String {
+    if let Some((thread, reg)) = name.split_once(':') {
+        let thread = parse_int(thread).unwrap_or_default() as usize;
+        let id = registers
+            .get(thread)
+            .and_then(|regs| regs.iter().position(|r| r == reg))
+            .unwrap_or_else(|| panic!("{} is not a register of the test.", name));
+        return format!("regs[{}][{}]", thread, id);
+    }
+    let loc = test
+        .locations
+        .iter()
+        .position(|(loc, _)| loc == name)
+        .unwrap_or_else(|| panic!("{} is not a location of the test.", name));
+    format!("mem[{}]", loc)
+}
+
+fn sv_prop(test: &Litmus, registers: &[Vec<String>], prop: &Prop) -> String {
+    match prop {
+        Prop::Eq(name, value) => format!("{} == {}", sv_value(test, registers, name), value),
+        Prop::And(lhs, rhs) => format!(
+            "({}) && ({})",
+            sv_prop(test, registers, lhs),
+            sv_prop(test, registers, rhs)
+        ),
+        Prop::Or(lhs, rhs) => format!(
+            "({}) || ({})",
+            sv_prop(test, registers, lhs),
+            sv_prop(test, registers, rhs)
+        ),
+        Prop::Not(prop) => format!("!({})", sv_prop(test, registers, prop)),
+    }
+}
+
+// Signals of the harness protocol with their default widths, the design's own width wins when it
+// has the port.
+//
+//   Core.issue_load(addr, reg), Core.issue_store(addr, value), Core.issue_fence()
+//       events the testbench raises, at most one instruction per cycle, tagged with the core.
+//   Core.load_done(reg, value)
+//       the request the design makes when a load retires.
+//   MemSys.load(addr) -> MemSys.load_response(value), MemSys.store(addr, value)
+//       served by the testbench memory, which invalidates the line of a store in every other
+//       core with MemSys.invalidate(line).
+const HARNESS_INPUTS: &[(&str, u32)] = &[
+    ("Core_issue_load_valid", 1),
+    ("Core_issue_load_core", 0),
+    ("Core_issue_load_arg0", 32),
+    ("Core_issue_load_arg1", 32),
+    ("Core_issue_store_valid", 1),
+    ("Core_issue_store_core", 0),
+    ("Core_issue_store_arg0", 32),
+    ("Core_issue_store_arg1", 32),
+    ("Core_issue_fence_valid", 1),
+    ("Core_issue_fence_core", 0),
+    ("MemSys_load_response_valid", 1),
+    ("MemSys_load_response_core", 0),
+    ("MemSys_load_response_arg0", 32),
+    ("MemSys_invalidate_valid", 1),
+    ("MemSys_invalidate_core", 0),
+    ("MemSys_invalidate_arg0", 32),
+];
+
+const HARNESS_OUTPUTS: &[(&str, u32)] = &[
+    ("Core_load_done_valid", 1),
+    ("Core_load_done_core", 0),
+    ("Core_load_done_arg0", 32),
+    ("Core_load_done_arg1", 32),
+    ("MemSys_load_valid", 1),
+    ("MemSys_load_core", 0),
+    ("MemSys_load_arg0", 32),
+    ("MemSys_store_valid", 1),
+    ("MemSys_store_core", 0),
+    ("MemSys_store_arg0", 32),
+    ("MemSys_store_arg1", 32),
+];
+
+fn sv_type(width: u32) -> String {
+    match width {
+        1 => String::from("logic"),
+        width => format!("logic [{}:0]", width - 1),
+    }
+}
+
+// SystemVerilog array literal: '{1, 2, 3}
+fn sv_array<T: ToString>(values: impl IntoIterator<Item = T>) -> String {
+    let values: Vec<String> = values.into_iter().map(|v| v.to_string()).collect();
+    format!("'{{{}}}", values.join(", "))
+}
+
+// A self-checking testbench around `hw.module @top`: it feeds every core its thread of the test
+// in a random interleaving, plays the memory system, and checks the final registers and memory
+// against the outcomes `model` allows.
+pub fn testbench(test: &Litmus, model: MemoryModel, design: &MultiCore) -> Result<String, Error> {
+    let cores = test.threads.len();
+    let tag_width = crate::mlir::index_width(cores as u32);
+    let registers = register_ids(test);
+    let max_regs = registers.iter().map(|r| r.len()).max().unwrap_or(0).max(1);
+    let max_len = test
+        .threads
+        .iter()
+        .map(|t| t.len())
+        .max()
+        .unwrap_or(0)
+        .max(1);
+    let loads = test
+        .threads
+        .iter()
+        .flatten()
+        .filter(|op| matches!(op, Op::Load { .. }))
+        .count();
+    let total: usize = test.threads.iter().map(|t| t.len()).sum();
+    let loc_index = |loc: &str| test.locations.iter().position(|(l, _)| l == loc).unwrap();
+
+    // Program rows: kind (0 load, 1 store, 2 fence), location, register or stored value.
+    let mut kinds = vec![];
+    let mut locs = vec![];
+    let mut args = vec![];
+    for (thread, ops) in test.threads.iter().enumerate() {
+        let mut k = vec![0; max_len];
+        let mut l = vec![0; max_len];
+        let mut a = vec![0; max_len];
+        for (i, op) in ops.iter().enumerate() {
+            match op {
+                Op::Load { reg, loc } => {
+                    l[i] = loc_index(loc) as i64;
+                    a[i] = registers[thread].iter().position(|r| r == reg).unwrap() as i64;
+                }
+                Op::Store { loc, value } => {
+                    k[i] = 1;
+                    l[i] = loc_index(loc) as i64;
+                    a[i] = *value;
+                }
+                Op::Fence => k[i] = 2,
+            }
+        }
+        kinds.push(sv_array(k));
+        locs.push(sv_array(l));
+        args.push(sv_array(a));
+    }
+
+    let mut ret = format!(
+        "// Litmus test {} under {}, generated by aql-circt.\n// {}\n",
+        test.name,
+        model.name(),
+        format_condition(test)
+    );
+    ret += &format!("module litmus_{}_tb;\n", test.name);
+    ret += &format!("\tlocalparam int CORES = {};\n", cores);
+    ret += &format!("\tlocalparam int LOCS = {};\n", test.locations.len());
+    ret += &format!("\tlocalparam int LINE_BYTES = {};\n", LINE_BYTES);
+    ret += "\tlocalparam int LINE_OFFSET = 0;\n";
+    ret += &format!("\tlocalparam int TIMEOUT = {};\n\n", TIMEOUT);
+    ret += "\tlogic clk = 1'b0;\n\tlogic rst = 1'b1;\n\talways #5 clk = ~clk;\n\n";
+
+    // Harness signals, sized like the design's ports.
+    let design_width = |name: &str, default: u32| {
+        design
+            .inputs
+            .iter()
+            .chain(design.outputs.iter())
+            .find(|(port, _)| port == name)
+            .map_or(
+                Ok(if default == 0 { tag_width } else { default }),
+                |(_, ty)| utils::width(ty),
+            )
+    };
+    for (name, default) in HARNESS_INPUTS.iter().chain(HARNESS_OUTPUTS) {
+        ret += &format!("\t{} {};\n", sv_type(design_width(name, *default)?), name);
+    }
+    // Outputs the design doesn't have stay idle.
+    for (name, _) in HARNESS_OUTPUTS {
+        if !design.outputs.iter().any(|(port, _)| port == name) {
+            ret += &format!("\tassign {} = '0;\n", name);
+        }
+    }
+
+    let mut connections = vec![String::from(".clk(clk)"), String::from(".rst(rst)")];
+    for (name, _) in &design.inputs {
+        if HARNESS_INPUTS.iter().any(|(n, _)| n == name) {
+            connections.push(format!(".{}({})", name, name));
+        } else {
+            connections.push(format!(".{}('0)", name));
+        }
+    }
+    for (name, _) in &design.outputs {
+        if HARNESS_OUTPUTS.iter().any(|(n, _)| n == name) {
+            connections.push(format!(".{}({})", name, name));
+        } else {
+            connections.push(format!(".{}()", name));
+        }
+    }
+    ret += &format!(
+        "\n\ttop dut (\n\t\t{}\n\t);\n\n",
+        connections.join(",\n\t\t")
+    );
+
+    ret += &format!(
+        "\tint len [CORES] = {};\n",
+        sv_array(test.threads.iter().map(|t| t.len()))
+    );
+    ret += &format!("\tint kind [CORES][{}] = {};\n", max_len, sv_array(kinds));
+    ret += &format!("\tint loc [CORES][{}] = {};\n", max_len, sv_array(locs));
+    ret += &format!("\tint arg [CORES][{}] = {};\n", max_len, sv_array(args));
+    ret += "\tint mem [LOCS];\n";
+    ret += &format!("\tint regs [CORES][{}];\n", max_regs);
+    ret += "\tint pc [CORES];\n\tint issued;\n\tint loads_left;\n";
+    ret += "\tint inv_core [$];\n\tint inv_line [$];\n\n";
+
+    // Memory system and retirement.
+    ret += "\talways @(posedge clk) begin\n";
+    ret += "\t\tMemSys_load_response_valid <= 1'b0;\n";
+    ret += "\t\tMemSys_invalidate_valid <= 1'b0;\n";
+    ret += "\t\tif (!rst) begin\n";
+    ret += "\t\t\tif (MemSys_store_valid) begin\n";
+    ret += "\t\t\t\tmem[MemSys_store_arg0 / LINE_BYTES] = MemSys_store_arg1;\n";
+    ret += "\t\t\t\tfor (int c = 0; c < CORES; c++) begin\n";
+    ret += "\t\t\t\t\tif (c != MemSys_store_core) begin\n";
+    ret += "\t\t\t\t\t\tinv_core.push_back(c);\n";
+    ret += "\t\t\t\t\t\tinv_line.push_back(MemSys_store_arg0 >> LINE_OFFSET);\n";
+    ret += "\t\t\t\t\tend\n\t\t\t\tend\n\t\t\tend\n";
+    ret += "\t\t\tif (MemSys_load_valid) begin\n";
+    ret += "\t\t\t\tMemSys_load_response_valid <= 1'b1;\n";
+    ret += "\t\t\t\tMemSys_load_response_core <= MemSys_load_core;\n";
+    ret += "\t\t\t\tMemSys_load_response_arg0 <= mem[MemSys_load_arg0 / LINE_BYTES];\n";
+    ret += "\t\t\tend\n";
+    ret += "\t\t\tif (inv_core.size() != 0) begin\n";
+    ret += "\t\t\t\tMemSys_invalidate_valid <= 1'b1;\n";
+    ret += "\t\t\t\tMemSys_invalidate_core <= inv_core.pop_front();\n";
+    ret += "\t\t\t\tMemSys_invalidate_arg0 <= inv_line.pop_front();\n";
+    ret += "\t\t\tend\n";
+    ret += "\t\t\tif (Core_load_done_valid) begin\n";
+    ret += "\t\t\t\tregs[Core_load_done_core][Core_load_done_arg0] = Core_load_done_arg1;\n";
+    ret += "\t\t\t\tloads_left--;\n";
+    ret += "\t\t\tend\n\t\tend\n\tend\n\n";
+
+    // Instruction issue, one random core per cycle or none when `c` is CORES.
+    ret += "\talways @(posedge clk) begin\n";
+    ret += "\t\tint c;\n";
+    ret += "\t\tCore_issue_load_valid <= 1'b0;\n";
+    ret += "\t\tCore_issue_store_valid <= 1'b0;\n";
+    ret += "\t\tCore_issue_fence_valid <= 1'b0;\n";
+    ret += "\t\tc = $urandom_range(CORES);\n";
+    ret += "\t\tif (!rst && c < CORES && pc[c] < len[c]) begin\n";
+    ret += "\t\t\tcase (kind[c][pc[c]])\n";
+    ret += "\t\t\t\t0: begin\n";
+    ret += "\t\t\t\t\tCore_issue_load_valid <= 1'b1;\n";
+    ret += "\t\t\t\t\tCore_issue_load_core <= c;\n";
+    ret += "\t\t\t\t\tCore_issue_load_arg0 <= loc[c][pc[c]] * LINE_BYTES;\n";
+    ret += "\t\t\t\t\tCore_issue_load_arg1 <= arg[c][pc[c]];\n";
+    ret += "\t\t\t\tend\n";
+    ret += "\t\t\t\t1: begin\n";
+    ret += "\t\t\t\t\tCore_issue_store_valid <= 1'b1;\n";
+    ret += "\t\t\t\t\tCore_issue_store_core <= c;\n";
+    ret += "\t\t\t\t\tCore_issue_store_arg0 <= loc[c][pc[c]] * LINE_BYTES;\n";
+    ret += "\t\t\t\t\tCore_issue_store_arg1 <= arg[c][pc[c]];\n";
+    ret += "\t\t\t\tend\n";
+    ret += "\t\t\t\tdefault: begin\n";
+    ret += "\t\t\t\t\tCore_issue_fence_valid <= 1'b1;\n";
+    ret += "\t\t\t\t\tCore_issue_fence_core <= c;\n";
+    ret += "\t\t\t\tend\n";
+    ret += "\t\t\tendcase\n";
+    ret += "\t\t\tpc[c]++;\n";
+    ret += "\t\t\tissued++;\n";
+    ret += "\t\tend\n\tend\n\n";
+
+    let allowed = outcomes(test, model);
+    let checks: Vec<String> = allowed
+        .iter()
+        .map(|outcome| {
+            let terms: Vec<String> = outcome
+                .iter()
+                .map(|(name, value)| format!("{} == {}", sv_value(test, &registers, name), value))
+                .collect();
+            if terms.is_empty() {
+                String::from("1")
+            } else {
+                format!("({})", terms.join(" && "))
+            }
+        })
+        .collect();
+    ret += &format!(
+        "\t// The {} outcomes {} allows.\n",
+        allowed.len(),
+        model.name()
+    );
+    ret += "\tfunction automatic bit allowed();\n";
+    let checks = if checks.is_empty() {
+        String::from("0")
+    } else {
+        checks.join("\n\t\t\t|| ")
+    };
+    ret += &format!("\t\treturn {};\n", checks);
+    ret += "\tendfunction\n\n";
+    ret += "\tfunction automatic bit condition();\n";
+    ret += &format!(
+        "\t\treturn {};\n",
+        sv_prop(test, &registers, &test.condition)
+    );
+    ret += "\tendfunction\n\n";
+
+    let mut outcome = vec![];
+    let names: BTreeSet<&String> = allowed.iter().flat_map(|o| o.keys()).collect();
+    for name in names {
+        outcome.push((format!("{}=%0d;", name), sv_value(test, &registers, name)));
+    }
+    let formats: Vec<&str> = outcome.iter().map(|(f, _)| f.as_str()).collect();
+    let values: Vec<&str> = outcome.iter().map(|(_, v)| v.as_str()).collect();
+
+    ret += "\tinitial begin\n";
+    for (i, (_, value)) in test.locations.iter().enumerate() {
+        ret += &format!("\t\tmem[{}] = {};\n", i, value);
+    }
+    for (thread, reg, value) in &test.registers {
+        let id = registers[*thread].iter().position(|r| r == reg).unwrap();
+        ret += &format!("\t\tregs[{}][{}] = {};\n", thread, id, value);
+    }
+    ret += "\t\tpc = '{default: 0};\n";
+    ret += "\t\tissued = 0;\n";
+    ret += &format!("\t\tloads_left = {};\n", loads);
+    ret += "\t\trepeat (4) @(posedge clk);\n";
+    ret += "\t\trst = 1'b0;\n";
+    ret += "\t\tfork\n";
+    ret += &format!("\t\t\twait (issued == {} && loads_left == 0);\n", total);
+    ret += "\t\t\trepeat (TIMEOUT) @(posedge clk);\n";
+    ret += "\t\tjoin_any\n";
+    ret += "\t\tdisable fork;\n";
+    ret += &format!("\t\tif (issued != {} || loads_left != 0)\n", total);
+    ret += &format!(
+        "\t\t\t$fatal(1, \"{}: the design didn't finish in %0d cycles.\", TIMEOUT);\n",
+        test.name
+    );
+    ret += "\t\t// Let the store buffers drain.\n";
+    ret += &format!("\t\trepeat ({}) @(posedge clk);\n", DRAIN);
+    if !values.is_empty() {
+        ret += &format!(
+            "\t\t$display(\"{}: {}\", {});\n",
+            test.name,
+            formats.join(" "),
+            values.join(", ")
+        );
+    }
+    ret += "\t\tif (!allowed())\n";
+    ret += &format!(
+        "\t\t\t$error(\"{}: outcome forbidden by {}.\");\n",
+        test.name,
+        model.name()
+    );
+    ret += "\t\tif (condition())\n";
+    ret += &format!(
+        "\t\t\t$display(\"{}: {} is observed.\");\n",
+        test.name,
+        format_prop(&test.condition).replace('\\', "\\\\")
+    );
+    ret += "\t\t$finish;\n";
+    ret += "\tend\n";
+    ret += "endmodule\n";
+    Ok(ret)
+}
@@ -0,0 +1,513 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fs, iter, mem,
+    path::{Path, PathBuf},
+};
+
+use crate::{ast::ASTNode, error::Error, parser};
+
+/// Reads AQL files and resolves their `import` and `include` declarations.
+///
+/// `import "lsq.aql" as lsq;` loads the file under the namespace `lsq`: its structures are named
+/// `lsq.ReadyToIssue`, ... and other files refer to them by those names. `include "common.aql";`
+/// merges the file's declarations in as if they were written in place. Imported files are looked
+/// up next to the file importing them, then in each search path, and are read once however many
+/// files import them.
+pub struct Loader {
+    search_paths: Vec<PathBuf>,
+    // Every file read so far with its namespace, by canonical path.
+    loaded: HashMap<PathBuf, Option<String>>,
+    // The files being read, canonical and as found, to report cycles.
+    stack: Vec<(PathBuf, PathBuf)>,
+}
+
+// A file and the files it includes, which share its namespace.
+#[derive(Default)]
+struct Unit {
+    decls: Vec<ASTNode>,
+    aliases: HashSet<String>,
+    included: HashSet<PathBuf>,
+    // The declarations of the files it imports.
+    imported: Vec<ASTNode>,
+}
+
+fn canonical(path: &Path) -> PathBuf {
+    fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+// Errors are reported against the innermost file they come from.
+fn within(path: &Path, error: Error) -> Error {
+    match error {
+        Error::File { .. } => error,
+        error => Error::File {
+            path: path.to_path_buf(),
+            error: Box::new(error),
+        },
+    }
+}
+
+fn is_ident(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars.next().is_some_and(|c| c.is_ascii_alphabetic())
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+fn declared(node: &ASTNode, names: &mut HashSet<String>) {
+    match node {
+        ASTNode::Declaration(decl) => declared(decl, names),
+        ASTNode::StructureDelcaration {
+            name, statement, ..
+        } => {
+            names.insert(name.clone());
+            declared(statement, names);
+        }
+        ASTNode::Block(stmts) => stmts.iter().for_each(|stmt| declared(stmt, names)),
+        _ => {}
+    }
+}
+
+fn owner(attributes: &[ASTNode]) -> bool {
+    attributes.iter().any(
+        |attribute| matches!(attribute, ASTNode::Attribute { name, .. } if name == "controller"),
+    )
+}
+
+// A state without a controller belongs to the file's own controller entry, not to whichever entry
+// comes first once the files are put together.
+fn adopt(decls: &mut [ASTNode]) {
+    let mut default = None;
+    for decl in decls.iter() {
+        if let ASTNode::Declaration(decl) = decl {
+            if let ASTNode::StructureDelcaration {
+                s_type,
+                name,
+                attributes,
+                ..
+            } = decl.as_ref()
+            {
+                if s_type == "controller_entry" && !owner(attributes) {
+                    default = Some(name.clone());
+                    break;
+                }
+            }
+        }
+    }
+    let Some(default) = default else {
+        return;
+    };
+
+    for decl in decls.iter_mut() {
+        if let ASTNode::Declaration(decl) = decl {
+            if let ASTNode::StructureDelcaration {
+                s_type, attributes, ..
+            } = decl.as_mut()
+            {
+                if s_type != "controller" && s_type != "controller_entry" && !owner(attributes) {
+                    attributes.push(ASTNode::Attribute {
+                        name: String::from("controller"),
+                        value: Some(Box::new(ASTNode::QualifiedName {
+                            names: vec![ASTNode::Ident(default.clone())],
+                        })),
+                    });
+                }
+            }
+        }
+    }
+}
+
+// Puts the names a file declares in its namespace. Names qualified with an import's namespace are
+// already what they refer to, other names are left alone.
+struct Scope<'a> {
+    prefix: Option<&'a str>,
+    locals: &'a HashSet<String>,
+    aliases: &'a HashSet<String>,
+}
+
+impl Scope<'_> {
+    fn name(&self, name: &str) -> String {
+        match self.prefix {
+            Some(prefix) if self.locals.contains(name) => format!("{}.{}", prefix, name),
+            _ => name.to_string(),
+        }
+    }
+
+    fn ident(&self, node: Box<ASTNode>) -> Box<ASTNode> {
+        match node.dotted() {
+            Some(name) => Box::new(ASTNode::Ident(self.name(&name))),
+            None => node,
+        }
+    }
+
+    fn qualified(&self, node: Box<ASTNode>) -> Box<ASTNode> {
+        match node.dotted() {
+            Some(name) => Box::new(ASTNode::QualifiedName {
+                names: vec![ASTNode::Ident(self.name(&name))],
+            }),
+            None => node,
+        }
+    }
+
+    // Renames in place, keeping the allocation.
+    fn boxed(&self, mut node: Box<ASTNode>) -> Box<ASTNode> {
+        *node = self.node(mem::replace(&mut node, ASTNode::None));
+        node
+    }
+
+    // `lsq.LQ.alloc()` and, inside lsq, `LQ.alloc()` both call the unit `lsq.LQ`.
+    fn call(&self, qualified_name: Box<ASTNode>) -> Box<ASTNode> {
+        let ASTNode::QualifiedName { names } = qualified_name.as_ref() else {
+            return qualified_name;
+        };
+        let Some(parts) = names
+            .iter()
+            .map(|name| name.name())
+            .collect::<Option<Vec<String>>>()
+        else {
+            return qualified_name;
+        };
+        let (method, unit) = match parts.as_slice() {
+            [unit, method] => (method, self.name(unit)),
+            [alias, .., method] if self.aliases.contains(alias) => {
+                (method, parts[..parts.len() - 1].join("."))
+            }
+            _ => return qualified_name,
+        };
+        Box::new(ASTNode::QualifiedName {
+            names: vec![ASTNode::Ident(unit), ASTNode::Ident(method.clone())],
+        })
+    }
+
+    fn node(&self, node: ASTNode) -> ASTNode {
+        match node {
+            ASTNode::Declaration(decl) => ASTNode::Declaration(self.boxed(decl)),
+            ASTNode::StructureDelcaration {
+                s_type,
+                name,
+                attributes,
+                statement,
+                doc,
+            } => ASTNode::StructureDelcaration {
+                s_type,
+                name: self.name(&name),
+                attributes: attributes.into_iter().map(|a| self.node(a)).collect(),
+                statement: self.boxed(statement),
+                doc,
+            },
+            ASTNode::Attribute {
+                name,
+                value: Some(value),
+            } if name == "controller" => ASTNode::Attribute {
+                name,
+                value: Some(self.qualified(value)),
+            },
+            ASTNode::Block(stmts) => {
+                ASTNode::Block(stmts.into_iter().map(|stmt| self.node(stmt)).collect())
+            }
+            ASTNode::ExprList(exprs) => {
+                ASTNode::ExprList(exprs.into_iter().map(|expr| self.node(expr)).collect())
+            }
+            ASTNode::Conditional {
+                expr,
+                if_blk,
+                else_blk,
+            } => ASTNode::Conditional {
+                expr: self.boxed(expr),
+                if_blk: self.boxed(if_blk),
+                else_blk: self.boxed(else_blk),
+            },
+            ASTNode::Listen { block, catch_block } => ASTNode::Listen {
+                block: self.boxed(block),
+                catch_block: self.boxed(catch_block),
+            },
+            ASTNode::CatchBlock {
+                keyword,
+                qualified_name,
+                idents,
+                source,
+                block,
+            } => ASTNode::CatchBlock {
+                keyword,
+                qualified_name,
+                idents,
+                source: source.map(|source| self.ident(source)),
+                block: self.boxed(block),
+            },
+            ASTNode::Await {
+                keyword,
+                call,
+                when_block,
+            } => ASTNode::Await {
+                keyword,
+                call: call.map(|call| self.boxed(call)),
+                when_block: self.boxed(when_block),
+            },
+            ASTNode::When {
+                keyword,
+                call,
+                ident,
+                block,
+            } => ASTNode::When {
+                keyword,
+                call: self.boxed(call),
+                ident: self.ident(ident),
+                block: self.boxed(block),
+            },
+            ASTNode::Transition { action, ident } => ASTNode::Transition {
+                action,
+                ident: self.ident(ident),
+            },
+            ASTNode::Assignment { name, expr } if name.trim() == "init_entry" => {
+                ASTNode::Assignment {
+                    name,
+                    expr: self.qualified(expr),
+                }
+            }
+            ASTNode::Assignment { name, expr } => ASTNode::Assignment {
+                name,
+                expr: self.boxed(expr),
+            },
+            ASTNode::VariableDeclaration {
+                typed_identifier,
+                expr,
+                doc,
+            } => ASTNode::VariableDeclaration {
+                typed_identifier,
+                expr: expr.map(|expr| self.boxed(expr)),
+                doc,
+            },
+            ASTNode::Assert { expr, always } => ASTNode::Assert {
+                expr: self.boxed(expr),
+                always,
+            },
+            ASTNode::Assume { expr, always } => ASTNode::Assume {
+                expr: self.boxed(expr),
+                always,
+            },
+            ASTNode::BinOp { verb, lhs, rhs } => ASTNode::BinOp {
+                verb,
+                lhs: self.boxed(lhs),
+                rhs: self.boxed(rhs),
+            },
+            ASTNode::UnuaryOp { verb, term } => ASTNode::UnuaryOp {
+                verb,
+                term: self.boxed(term),
+            },
+            ASTNode::Call {
+                qualified_name,
+                list,
+            } => ASTNode::Call {
+                qualified_name: self.call(qualified_name),
+                list: self.boxed(list),
+            },
+            node => node,
+        }
+    }
+}
+
+impl Loader {
+    /// A loader looking for imported files next to the file importing them, then in
+    /// `search_paths` in order.
+    pub fn new(search_paths: Vec<PathBuf>) -> Self {
+        Loader {
+            search_paths,
+            loaded: HashMap::new(),
+            stack: vec![],
+        }
+    }
+
+    /// Reads and loads the file at `path`.
+    pub fn load(&mut self, path: &Path) -> Result<ASTNode, Error> {
+        let source = read(path)?;
+        self.load_source(&source, Some(path))
+    }
+
+    /// Loads a file's source together with everything it imports, as one program whose
+    /// declarations can be converted. `path` is where the source was read from, imports of a
+    /// source without one are looked up in the current directory first. A file loaded before,
+    /// directly or as an import, gives an empty program.
+    pub fn load_source(&mut self, source: &str, path: Option<&Path>) -> Result<ASTNode, Error> {
+        if let Some(path) = path {
+            match self.loaded.get(&canonical(path)) {
+                Some(None) => return Ok(ASTNode::Top(vec![])),
+                Some(Some(namespace)) => {
+                    return Err(Error::Import(format!(
+                        "already imported as {}, it can't also be loaded without a namespace",
+                        namespace
+                    )));
+                }
+                None => {
+                    self.loaded.insert(canonical(path), None);
+                }
+            }
+        }
+        self.unit(source, path, None).map(ASTNode::Top)
+    }
+
+    fn unit(
+        &mut self,
+        source: &str,
+        path: Option<&Path>,
+        namespace: Option<&str>,
+    ) -> Result<Vec<ASTNode>, Error> {
+        let mut unit = Unit::default();
+        self.expand(source, path, namespace, &mut unit)?;
+
+        if namespace.is_some() {
+            adopt(&mut unit.decls);
+        }
+        let mut locals = HashSet::new();
+        for decl in &unit.decls {
+            declared(decl, &mut locals);
+        }
+        let scope = Scope {
+            prefix: namespace,
+            locals: &locals,
+            aliases: &unit.aliases,
+        };
+        // The file's own declarations come first, so its controller entry stays the default.
+        let mut ret: Vec<ASTNode> = unit.decls.into_iter().map(|d| scope.node(d)).collect();
+        ret.extend(unit.imported);
+        Ok(ret)
+    }
+
+    fn expand(
+        &mut self,
+        source: &str,
+        path: Option<&Path>,
+        namespace: Option<&str>,
+        unit: &mut Unit,
+    ) -> Result<(), Error> {
+        if let Some(path) = path {
+            self.stack.push((canonical(path), path.to_path_buf()));
+        }
+        let ret = self.declarations(source, path, namespace, unit);
+        if path.is_some() {
+            self.stack.pop();
+        }
+        ret
+    }
+
+    fn declarations(
+        &mut self,
+        source: &str,
+        path: Option<&Path>,
+        namespace: Option<&str>,
+        unit: &mut Unit,
+    ) -> Result<(), Error> {
+        let ASTNode::Top(decls) = parser::parse(source)? else {
+            return Ok(());
+        };
+        for decl in decls {
+            let ASTNode::Declaration(inner) = &decl else {
+                unit.decls.push(decl);
+                continue;
+            };
+            match inner.as_ref() {
+                ASTNode::Include(target) => {
+                    let file = self.find(target, path)?;
+                    let canonical = self.acyclic(&file)?;
+                    if unit.included.insert(canonical) {
+                        let source = read(&file)?;
+                        self.expand(&source, Some(&file), namespace, unit)
+                            .map_err(|error| within(&file, error))?;
+                    }
+                }
+                ASTNode::Import {
+                    path: target,
+                    namespace: alias,
+                } => {
+                    let file = self.find(target, path)?;
+                    let alias = match alias {
+                        Some(alias) => alias.clone(),
+                        None => file
+                            .file_stem()
+                            .and_then(|stem| stem.to_str())
+                            .filter(|stem| is_ident(stem))
+                            .map(String::from)
+                            .ok_or_else(|| {
+                                Error::Import(format!(
+                                    "\"{}\" needs a namespace, import it with `as`",
+                                    target
+                                ))
+                            })?,
+                    };
+                    let canonical = self.acyclic(&file)?;
+                    unit.aliases.insert(alias.clone());
+                    match self.loaded.get(&canonical) {
+                        Some(Some(namespace)) if *namespace == alias => {}
+                        Some(Some(namespace)) => {
+                            return Err(Error::Import(format!(
+                                "\"{}\" is imported as {} and as {}",
+                                target, namespace, alias
+                            )));
+                        }
+                        Some(None) => {
+                            return Err(Error::Import(format!(
+                                "\"{}\" is imported as {} and also loaded without a namespace",
+                                target, alias
+                            )));
+                        }
+                        None => {
+                            self.loaded.insert(canonical, Some(alias.clone()));
+                            let source = read(&file)?;
+                            let decls = self
+                                .unit(&source, Some(&file), Some(&alias))
+                                .map_err(|error| within(&file, error))?;
+                            unit.imported.extend(decls);
+                        }
+                    }
+                }
+                _ => unit.decls.push(decl),
+            }
+        }
+        Ok(())
+    }
+
+    // The file `target` names, from the directory of the file importing it or a search path.
+    fn find(&self, target: &str, importer: Option<&Path>) -> Result<PathBuf, Error> {
+        let dir = importer
+            .and_then(|path| path.parent())
+            .map(Path::to_path_buf)
+            .unwrap_or_default();
+        let mut tried = vec![];
+        for dir in iter::once(dir).chain(self.search_paths.iter().cloned()) {
+            let path = dir.join(target);
+            if path.is_file() {
+                return Ok(path);
+            }
+            let dir = match dir.as_os_str().is_empty() {
+                true => String::from("."),
+                false => dir.display().to_string(),
+            };
+            if !tried.contains(&dir) {
+                tried.push(dir);
+            }
+        }
+        Err(Error::Import(format!(
+            "can't find \"{}\", looked in {}",
+            target,
+            tried.join(", ")
+        )))
+    }
+
+    fn acyclic(&self, file: &Path) -> Result<PathBuf, Error> {
+        let canonical = canonical(file);
+        let Some(start) = self.stack.iter().position(|(path, _)| *path == canonical) else {
+            return Ok(canonical);
+        };
+        let mut cycle: Vec<String> = self.stack[start..]
+            .iter()
+            .map(|(_, shown)| shown.display().to_string())
+            .collect();
+        cycle.push(file.display().to_string());
+        Err(Error::Import(format!(
+            "import cycle: {}",
+            cycle.join(" -> ")
+        )))
+    }
+}
+
+fn read(path: &Path) -> Result<String, Error> {
+    fs::read_to_string(path)
+        .map_err(|error| Error::Import(format!("{}: {}", path.display(), error)))
+}
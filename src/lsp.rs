@@ -0,0 +1,458 @@
+//! A language server for AQL over stdio.
+//!
+//! It publishes the parser's and the checker's diagnostics, goes to the declaration of states and
+//! variables, shows a variable's type on hover, completes state names and lists the structures of
+//! a document, also those with syntax errors. Documents are synced whole, positions are counted
+//! in UTF-16 code units.
+
+use crate::{
+    ast::ASTNode,
+    parser::{self, Location, Variable},
+    utils::{AQLType, ConversionTable},
+    CheckOptions, ConvertOptions, Error, Loader,
+};
+use serde_json::{json, Value};
+use std::{
+    collections::HashMap,
+    io::{self, BufRead, Write},
+    path::PathBuf,
+};
+
+// https://microsoft.github.io/language-server-protocol/specifications/lsp/3.17/specification/
+const METHOD_NOT_FOUND: i32 = -32601;
+const PARSE_ERROR: i32 = -32700;
+const ERROR: usize = 1;
+const WARNING: usize = 2;
+
+// `Content-Length: n` and a blank line, then n bytes of JSON. None at the end of the input.
+fn read_message(input: &mut impl BufRead) -> io::Result<Option<String>> {
+    let mut length = None;
+    loop {
+        let mut line = String::new();
+        if input.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                length = value.trim().parse().ok();
+            }
+        }
+    }
+
+    let length = length.ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            "message without a Content-Length",
+        )
+    })?;
+    let mut body = vec![0; length];
+    input.read_exact(&mut body)?;
+    String::from_utf8(body)
+        .map(Some)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+fn send(output: &mut impl Write, message: Value) -> io::Result<()> {
+    let body = message.to_string();
+    write!(output, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+    output.flush()
+}
+
+fn notification(method: &str, params: Value) -> Value {
+    json!({"jsonrpc": "2.0", "method": method, "params": params})
+}
+
+fn error(id: Value, code: i32, message: &str) -> Value {
+    json!({
+        "jsonrpc": "2.0",
+        "id": id,
+        "error": {"code": code, "message": message},
+    })
+}
+
+// The protocol counts characters in UTF-16 code units, the parser in chars.
+fn utf16(text: &str, line: usize, column: usize) -> usize {
+    let line = text.lines().nth(line).unwrap_or("");
+    line.chars().take(column).map(char::len_utf16).sum()
+}
+
+// The chars before the `units`th UTF-16 code unit of a line, a unit inside a surrogate pair
+// counts its char.
+fn chars(text: &str, line: usize, units: usize) -> usize {
+    let line = text.lines().nth(line).unwrap_or("");
+    let mut seen = 0;
+    line.chars()
+        .take_while(|c| {
+            seen += c.len_utf16();
+            seen <= units
+        })
+        .count()
+}
+
+// The parser counts lines and columns from 1, the protocol from 0.
+fn range(text: &str, line: usize, column: usize, length: usize) -> Value {
+    let line = line.saturating_sub(1);
+    let position = |column: usize| json!({"line": line, "character": utf16(text, line, column)});
+    let column = column.saturating_sub(1);
+    json!({"start": position(column), "end": position(column + length)})
+}
+
+fn location(uri: &str, text: &str, line: usize, column: usize, name: &str) -> Value {
+    json!({"uri": uri, "range": range(text, line, column, name.chars().count())})
+}
+
+// `file:///a/b%20c.aql`, other schemes have no path and their imports are looked up in the
+// search paths only.
+fn file_path(uri: &str) -> Option<PathBuf> {
+    let path = uri.strip_prefix("file://")?;
+    let mut bytes = vec![];
+    let mut rest = path.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        let escaped = match tail {
+            [high, low, ..] if byte == b'%' => std::str::from_utf8(&[*high, *low])
+                .ok()
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok()),
+            _ => None,
+        };
+        match escaped {
+            Some(escaped) => {
+                bytes.push(escaped);
+                rest = &tail[2..];
+            }
+            None => {
+                bytes.push(byte);
+                rest = tail;
+            }
+        }
+    }
+    String::from_utf8(bytes).ok().map(PathBuf::from)
+}
+
+fn is_ident(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_'
+}
+
+// The identifier under the cursor, the part of `LQ.count` the cursor is on.
+fn word_at(text: &str, line: usize, character: usize) -> Option<String> {
+    let chars: Vec<char> = text.lines().nth(line)?.chars().collect();
+    let mut start = character.min(chars.len());
+    let mut end = start;
+    while start > 0 && is_ident(chars[start - 1]) {
+        start -= 1;
+    }
+    while end < chars.len() && is_ident(chars[end]) {
+        end += 1;
+    }
+    match start < end {
+        true => Some(chars[start..end].iter().collect()),
+        false => None,
+    }
+}
+
+// The declaration a name refers to at the cursor: the one in the innermost structure around it,
+// or else the controller entry's, which every state sees.
+fn variable(text: &str, name: &str, cursor: (usize, usize)) -> Option<Variable> {
+    let mut variables: Vec<Variable> = parser::variables(text)
+        .ok()?
+        .into_iter()
+        .filter(|variable| variable.name == name)
+        .collect();
+    let index = variables
+        .iter()
+        .enumerate()
+        .filter(|(_, variable)| variable.scope.0 <= cursor && cursor <= variable.scope.1)
+        .max_by_key(|(_, variable)| variable.scope.0)
+        .map(|(index, _)| index)
+        .or_else(|| {
+            variables
+                .iter()
+                .position(|variable| variable.s_type == "controller_entry")
+        })
+        .or((!variables.is_empty()).then_some(0))?;
+    Some(variables.swap_remove(index))
+}
+
+fn structure(text: &str, name: &str) -> Option<Location> {
+    parser::locations(text)
+        .ok()?
+        .into_iter()
+        .find(|location| location.name == name)
+}
+
+// The `///` documentation of a structure.
+fn doc(node: &ASTNode, name: &str) -> Option<String> {
+    match node {
+        ASTNode::Top(nodes) | ASTNode::Block(nodes) => {
+            nodes.iter().find_map(|node| doc(node, name))
+        }
+        ASTNode::Declaration(node) => doc(node, name),
+        ASTNode::StructureDelcaration {
+            name: declared,
+            doc: Some(doc),
+            ..
+        } if declared == name => Some(doc.clone()),
+        ASTNode::StructureDelcaration { statement, .. } => doc(statement, name),
+        _ => None,
+    }
+}
+
+// What a declared type is lowered to, with the enums of the document.
+fn lowered(ast: &ASTNode, aql_type: &str) -> Option<AQLType> {
+    let mut types = ConversionTable::new();
+    if let ASTNode::Top(decls) = ast {
+        for decl in decls {
+            if let ASTNode::Declaration(decl) = decl {
+                if let ASTNode::EnumDeclaration { name, variants } = decl.as_ref() {
+                    let variants = variants.clone();
+                    // A duplicate enum is reported by the diagnostics, not here.
+                    let _ = types.insert_enum(name, variants);
+                }
+            }
+        }
+    }
+    types.get(aql_type)
+}
+
+fn hover(text: &str, line: usize, character: usize) -> Value {
+    let Some(name) = word_at(text, line, character) else {
+        return Value::Null;
+    };
+    let ast = parser::parse(text).unwrap_or(ASTNode::None);
+
+    let value = if let Some(location) = structure(text, &name) {
+        let mut value = format!("```aql\n{} {}\n```", location.s_type, name);
+        if let Some(doc) = doc(&ast, &name) {
+            value += &format!("\n\n{}", doc);
+        }
+        value
+    } else if let Some(variable) = variable(text, &name, (line + 1, character + 1)) {
+        let mut value = format!("```aql\n{} {}\n```", variable.aql_type, name);
+        match lowered(&ast, &variable.aql_type) {
+            Some(AQLType::Base(ty)) => value += &format!("\n\n`{}`", ty),
+            Some(ty) => {
+                if let AQLType::Enum { name, variants } = &ty {
+                    let variants = variants.join(", ");
+                    let mlir_type = ty.mlir_type();
+                    value += &format!("\n\nenum {} {{ {} }}, `{}`", name, variants, mlir_type);
+                }
+            }
+            None => {}
+        }
+        value
+    } else {
+        return Value::Null;
+    };
+
+    json!({"contents": {"kind": "markdown", "value": value}})
+}
+
+fn definition(uri: &str, text: &str, line: usize, character: usize) -> Value {
+    let Some(name) = word_at(text, line, character) else {
+        return Value::Null;
+    };
+    if let Some(location) = structure(text, &name) {
+        return self::location(uri, text, location.line, location.column, &name);
+    }
+    match variable(text, &name, (line + 1, character + 1)) {
+        Some(variable) => self::location(uri, text, variable.line, variable.column, &name),
+        None => Value::Null,
+    }
+}
+
+fn completion(text: &str) -> Value {
+    const ENUM_MEMBER: usize = 20;
+    let locations = parser::locations(text).unwrap_or_default();
+    locations
+        .into_iter()
+        .filter(|location| location.s_type == "state")
+        .map(|location| json!({"label": location.name, "kind": ENUM_MEMBER, "detail": "state"}))
+        .collect()
+}
+
+fn symbols(uri: &str, text: &str) -> Value {
+    const CLASS: usize = 5;
+    const ARRAY: usize = 18;
+    const ENUM_MEMBER: usize = 22;
+    let locations = parser::locations(text).unwrap_or_default();
+    locations
+        .into_iter()
+        .map(|l| {
+            let kind = match l.s_type.as_str() {
+                "state" => ENUM_MEMBER,
+                "state_queue" => ARRAY,
+                _ => CLASS,
+            };
+            json!({
+                "name": l.name,
+                "detail": l.s_type,
+                "kind": kind,
+                "location": location(uri, text, l.line, l.column, &l.name),
+            })
+        })
+        .collect()
+}
+
+fn diagnostic(text: &str, line: usize, column: usize, severity: usize, message: &str) -> Value {
+    json!({
+        "range": range(text, line, column, 1),
+        "severity": severity,
+        "source": "aql-circt",
+        "message": message,
+    })
+}
+
+// Syntax errors, then design errors, then what the checker finds. Errors without a place in the
+// document go on its first line.
+fn diagnostics(uri: &str, text: &str, search_paths: &[PathBuf]) -> Value {
+    let path = file_path(uri);
+    let mut loader = Loader::new(search_paths.to_vec());
+    let machines = loader
+        .load_source(text, path.as_deref())
+        .and_then(|ast| crate::convert(ast, &ConvertOptions::default()));
+    let ret: Vec<Value> = match machines {
+        Err(Error::Parse { .. }) => parser::recover(text)
+            .1
+            .into_iter()
+            .filter_map(|error| match error {
+                Error::Parse {
+                    line,
+                    column,
+                    message,
+                } => Some(diagnostic(text, line, column, ERROR, &message)),
+                _ => None,
+            })
+            .collect(),
+        Err(error) => vec![diagnostic(text, 1, 1, ERROR, &error.to_string())],
+        Ok(machines) => {
+            let locations = parser::locations(text).unwrap_or_default();
+            let results = crate::check(&machines, &CheckOptions::default()).unwrap_or_default();
+            machines
+                .iter()
+                .zip(&results)
+                .flat_map(|(cfgs, result)| result.findings.iter().map(move |f| (cfgs, f)))
+                .map(|(cfgs, finding)| {
+                    let (line, column) = locations
+                        .iter()
+                        .find(|l| {
+                            l.s_type == "state"
+                                && l.controller == cfgs.fsm_name
+                                && l.name == finding.state
+                        })
+                        .map_or((1, 1), |l| (l.line, l.column));
+                    diagnostic(text, line, column, WARNING, &finding.message)
+                })
+                .collect()
+        }
+    };
+    json!({"uri": uri, "diagnostics": ret})
+}
+
+struct Server {
+    search_paths: Vec<PathBuf>,
+    documents: HashMap<String, String>,
+}
+
+impl Server {
+    // Notifications only change the documents, each change is answered with its diagnostics.
+    fn notify(&mut self, method: &str, params: &Value) -> Option<Value> {
+        let uri = params["textDocument"]["uri"].as_str()?.to_string();
+        let text = match method {
+            "textDocument/didOpen" => params["textDocument"]["text"].as_str()?,
+            "textDocument/didChange" => {
+                params["contentChanges"].as_array()?.last()?["text"].as_str()?
+            }
+            "textDocument/didClose" => {
+                self.documents.remove(&uri);
+                let params = json!({"uri": uri, "diagnostics": []});
+                return Some(notification("textDocument/publishDiagnostics", params));
+            }
+            _ => return None,
+        };
+        let params = diagnostics(&uri, text, &self.search_paths);
+        self.documents.insert(uri, text.to_string());
+        Some(notification("textDocument/publishDiagnostics", params))
+    }
+
+    // None for a method the server doesn't know.
+    fn request(&self, method: &str, params: &Value) -> Option<Value> {
+        let uri = params["textDocument"]["uri"].as_str().unwrap_or("");
+        // What parses of the document, so a syntax error doesn't hide the rest of it.
+        let text = &parser::recover(self.documents.get(uri).map_or("", String::as_str)).0;
+        let position = &params["position"];
+        let line = position["line"].as_u64().unwrap_or(0) as usize;
+        let character = position["character"].as_u64().unwrap_or(0) as usize;
+        let character = chars(text, line, character);
+
+        let ret = match method {
+            // Positions are in UTF-16, the encoding every client supports.
+            "initialize" => json!({
+                "capabilities": {
+                    "positionEncoding": "utf-16",
+                    "textDocumentSync": 1,
+                    "definitionProvider": true,
+                    "hoverProvider": true,
+                    "completionProvider": {},
+                    "documentSymbolProvider": true,
+                },
+                "serverInfo": {"name": "aql-circt", "version": env!("CARGO_PKG_VERSION")},
+            }),
+            "shutdown" => Value::Null,
+            "textDocument/definition" => definition(uri, text, line, character),
+            "textDocument/hover" => hover(text, line, character),
+            "textDocument/completion" => completion(text),
+            "textDocument/documentSymbol" => symbols(uri, text),
+            _ => return None,
+        };
+        Some(ret)
+    }
+}
+
+/// Answers the language server protocol messages read from `input` until the client sends
+/// `exit` or closes it. Imports are looked up next to the document, then in `search_paths`.
+pub fn serve(
+    mut input: impl BufRead,
+    mut output: impl Write,
+    search_paths: Vec<PathBuf>,
+) -> io::Result<()> {
+    let mut server = Server {
+        search_paths,
+        documents: HashMap::new(),
+    };
+
+    while let Some(message) = read_message(&mut input)? {
+        let Ok(message) = serde_json::from_str::<Value>(&message) else {
+            send(&mut output, error(Value::Null, PARSE_ERROR, "invalid JSON"))?;
+            continue;
+        };
+        let method = message["method"].as_str().unwrap_or("");
+        let params = &message["params"];
+        let id = &message["id"];
+
+        match (method, id) {
+            ("exit", _) => return Ok(()),
+            // The client's answer to a request, the server sends none.
+            ("", _) => {}
+            (_, Value::Null) => {
+                if let Some(reply) = server.notify(method, params) {
+                    send(&mut output, reply)?;
+                }
+            }
+            _ => {
+                let reply = match server.request(method, params) {
+                    Some(result) => json!({"jsonrpc": "2.0", "id": id, "result": result}),
+                    None => error(
+                        id.clone(),
+                        METHOD_NOT_FOUND,
+                        &format!("unknown method {}", method),
+                    ),
+                };
+                send(&mut output, reply)?;
+            }
+        }
+    }
+    Ok(())
+}
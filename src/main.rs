@@ -1,421 +1,538 @@
-mod ast;
-mod cfg;
-mod parser;
-mod utils;
-
-use ast::{ASTNode, BinVerb};
-use cfg::{StateMachine, Structure};
-
-use crate::parser::parse;
-use clap::{arg, command, value_parser};
+use aql_circt::{
+    ast::ASTNode, interp, litmus, CheckOptions, ConvertOptions, Error, Format, GenerateOptions,
+    Loader, Location, MemoryModel, StateMachine,
+};
+use clap::{arg, command, value_parser, ArgAction, ArgGroup, ArgMatches, Command};
 use std::{
     fs::{self, File},
-    io::Write,
-    path::PathBuf,
+    io::{self, Read, Write},
+    path::{Path, PathBuf},
+    process,
 };
 
-fn main() {
-    let matches = command!()
+// The stages `--emit` can stop the pipeline at, `mlir` is short for `fsm-mlir`.
+const STAGES: [&str; 10] = [
+    "ast", "cfg", "json", "dot", "dot-cfg", "fsm-mlir", "mlir", "hw-mlir", "btor2", "smt2",
+];
+
+fn memory_model_arg() -> clap::Arg {
+    arg!(
+        --"memory-model" <MODEL> "Enforces a memory consistency model on the load/store controllers."
+    )
+    .value_parser(["sc", "tso", "rvwmo"])
+}
+
+fn import_path_arg() -> clap::Arg {
+    arg!(
+        -I --"import-path" <DIR> "Adds a directory to look for imported files in, after the importing file's own."
+    )
+    .action(ArgAction::Append)
+    .value_parser(value_parser!(PathBuf))
+}
+
+// Shared by `build` and the bare `aql-circt -i ...` invocation.
+fn build_args(command: Command) -> Command {
+    command
         .arg(
             arg!(
-                -i --input <FILE> "Sets a input file."
+                -i --input <FILE>... "Sets the input files, compiled together. `-` reads stdin and a directory stands for the .aql files in it."
             )
+            .num_args(1..)
             .required(true)
             .value_parser(value_parser!(PathBuf)),
         )
         .arg(
             arg!(
-                -o --output <FILE> "Sets a output file."
+                -o --output <FILE> "Sets a output file, `-` or none prints the output."
             )
-            .required(true)
             .value_parser(value_parser!(PathBuf)),
         )
-        .get_matches();
+        .arg(arg!(
+            --top [CONTROLLER] "Wraps the generated machines, or only CONTROLLER, in a top-level hw.module with clock and reset."
+        ))
+        .arg(
+            arg!(
+                --cores <N> "Replicates the controllers for N cores behind a shared memory system."
+            )
+            .value_parser(value_parser!(u32).range(1..)),
+        )
+        .arg(memory_model_arg())
+        .arg(import_path_arg())
+        .arg(
+            arg!(
+                --emit <STAGE> "Stops the pipeline at STAGE and writes what it produced."
+            )
+            .value_parser(STAGES)
+            .default_value("fsm-mlir"),
+        )
+}
 
-    if let Some(file) = matches.get_one::<PathBuf>("input") {
-        let file_text = fs::read_to_string(file).unwrap();
-        let ast = parse(&file_text);
-        let graph = cfg::convert(ast);
-        let output = generate(graph);
+fn main() {
+    let matches = build_args(command!())
+        .subcommand_negates_reqs(true)
+        .subcommand(build_args(
+            Command::new("build").about("Compiles a design, to MLIR by default."),
+        ))
+        .subcommand(
+            Command::new("fmt")
+                .about("Prints a design as canonical AQL source.")
+                .arg(
+                    arg!(
+                        -i --input <FILE> "Sets a input file, `-` reads stdin."
+                    )
+                    .required(true)
+                    .value_parser(value_parser!(PathBuf)),
+                )
+                .arg(
+                    arg!(
+                        -o --output <FILE> "Sets a output file, `-` or none prints the source."
+                    )
+                    .value_parser(value_parser!(PathBuf)),
+                )
+                .arg(
+                    arg!(
+                        --check "Prints nothing and fails if the input isn't formatted."
+                    )
+                    .conflicts_with("output"),
+                ),
+        )
+        .subcommand(
+            Command::new("litmus")
+                .about("Runs a litmus test on a design in every interleaving and emits a testbench for it.")
+                .arg(
+                    arg!(<TEST> "A litmus test in herd's LISA format.")
+                        .value_parser(value_parser!(PathBuf)),
+                )
+                .arg(
+                    arg!(
+                        -i --input <FILE> "The AQL design to check, replicated for every thread of the test."
+                    )
+                    .required(true)
+                    .value_parser(value_parser!(PathBuf)),
+                )
+                .arg(
+                    arg!(
+                        -o --output <FILE> "Writes the SystemVerilog testbench."
+                    )
+                    .value_parser(value_parser!(PathBuf)),
+                )
+                .arg(
+                    arg!(
+                        --mlir <FILE> "Writes the compiled multi-core design."
+                    )
+                    .value_parser(value_parser!(PathBuf)),
+                )
+                .arg(
+                    arg!(
+                        --"memory-model" <MODEL> "The memory model the outcomes are checked against."
+                    )
+                    .value_parser(["sc", "tso", "rvwmo"])
+                    .default_value("sc"),
+                )
+                .arg(import_path_arg()),
+        )
+        .subcommand(
+            Command::new("check")
+                .about("Analyses the controllers of a design.")
+                .arg(
+                    arg!(
+                        -i --input <FILE>... "Sets the input files, compiled together. `-` reads stdin and a directory stands for the .aql files in it."
+                    )
+                    .num_args(1..)
+                    .required(true)
+                    .value_parser(value_parser!(PathBuf)),
+                )
+                .arg(arg!(
+                    --reachability "Reports unreachable, stuck and deadlocking states."
+                ))
+                .group(ArgGroup::new("mode").args(["reachability"]).required(true))
+                .arg(
+                    arg!(
+                        --bound <N> "Explores at most N (state, variables) configurations per controller."
+                    )
+                    .value_parser(value_parser!(usize))
+                    .default_value("10000"),
+                )
+                .arg(memory_model_arg())
+                .arg(import_path_arg()),
+        )
+        .subcommand(
+            Command::new("sim")
+                .about("Runs a controller cycle by cycle and prints its state and variables.")
+                .arg(
+                    arg!(
+                        -i --input <FILE>... "Sets the input files, compiled together. `-` reads stdin and a directory stands for the .aql files in it."
+                    )
+                    .num_args(1..)
+                    .required(true)
+                    .value_parser(value_parser!(PathBuf)),
+                )
+                .arg(
+                    arg!(
+                        -s --stimulus <FILE> "Input port values, one `<cycle> <port>=<value> ...` line per change."
+                    )
+                    .value_parser(value_parser!(PathBuf)),
+                )
+                .arg(arg!(
+                    -c --controller <NAME> "The controller to run, the first one by default."
+                ))
+                .arg(
+                    arg!(
+                        --vcd <FILE> "Writes the run as a VCD waveform."
+                    )
+                    .value_parser(value_parser!(PathBuf)),
+                )
+                .arg(
+                    arg!(
+                        --cycles <N> "Number of cycles to run, by default through the last stimulus line and at least 16."
+                    )
+                    .value_parser(value_parser!(u64)),
+                )
+                .arg(memory_model_arg())
+                .arg(import_path_arg()),
+        )
+        .subcommand(
+            Command::new("lsp")
+                .about("Runs a language server on stdin and stdout for editors.")
+                .arg(import_path_arg()),
+        )
+        .get_matches();
 
-        if let Some(output_path) = matches.get_one::<PathBuf>("output") {
-            let mut file = File::create(output_path).unwrap();
+    let result = match matches.subcommand() {
+        Some(("build", matches)) => run_build(matches),
+        Some(("fmt", matches)) => run_fmt(matches),
+        Some(("litmus", matches)) => run_litmus(matches),
+        Some(("check", matches)) => run_check(matches),
+        Some(("sim", matches)) => run_sim(matches),
+        Some(("lsp", matches)) => run_lsp(matches),
+        _ => run_build(&matches),
+    };
 
-            file.write_all(output.as_bytes()).unwrap();
-        }
+    if let Err(message) = result {
+        eprintln!("error: {}", message);
+        process::exit(1);
     }
 }
 
-fn generate(cfgs: StateMachine) -> String {
-    let cfg_vec = cfgs.cfgs;
+fn is_std(path: &Path) -> bool {
+    path.as_os_str() == "-"
+}
 
-    let mut fsm_machine = format!(
-        "fsm.machine @{}(%arg0: i1, %arg1: i1) attributes {{initialState = \"{}\"}}",
-        cfgs.fsm_name, cfgs.entry
-    );
+fn name(path: &Path) -> String {
+    if is_std(path) {
+        String::from("<stdin>")
+    } else {
+        path.display().to_string()
+    }
+}
 
-    fsm_machine += " {\n";
+fn read(path: &Path) -> Result<String, String> {
+    if is_std(path) {
+        let mut source = String::new();
+        return io::stdin()
+            .read_to_string(&mut source)
+            .map(|_| source)
+            .map_err(|e| format!("<stdin>: {}", e));
+    }
+    fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))
+}
 
-    for cfg in cfg_vec.clone() {
-        let c = cfg.as_ref();
-        let insts = &c.insts;
+fn write(path: &Path, text: &str) -> Result<(), String> {
+    if is_std(path) {
+        print!("{}", text);
+        return Ok(());
+    }
+    File::create(path)
+        .and_then(|mut file| file.write_all(text.as_bytes()))
+        .map_err(|e| format!("{}: {}", path.display(), e))
+}
 
-        for inst in insts {
-            match inst {
-                cfg::Inst::Stmt(stmt) => {
-                    fsm_machine += "\t";
-                    fsm_machine += &generate_decl(stmt);
-                    fsm_machine += "\n";
-                }
-            }
-        }
+// file:line:column: message for syntax errors, file: message otherwise.
+fn located(path: &Path, error: Error) -> String {
+    match error {
+        Error::Parse { .. } => format!("{}:{}", name(path), error),
+        Error::File { .. } => error.to_string(),
+        _ => format!("{}: {}", name(path), error),
     }
+}
+
+fn memory_model(matches: &ArgMatches) -> Option<MemoryModel> {
+    matches
+        .get_one::<String>("memory-model")
+        .and_then(|model| MemoryModel::from_name(model))
+}
 
-    for cfg in cfg_vec {
-        // Transitions only exist in the states, should filter the other structures.
-        if let Structure::State = cfg.scope.label {
-            let state_name = format!("\tfsm.state @{} transitions {{\n", &cfg.scope.name);
-            fsm_machine += &state_name;
-
-            // print transition.
-            for tran in &cfg.next.trans {
-                // guard and actions are all optional.
-                let transition = format!("\t\tfsm.transition @{} ", tran.target);
-                let mut guards = String::from("");
-                let mut actions = String::from("\n");
-
-                match tran.guards.as_ref() {
-                    Some(raw_guards) => {
-                        guards = parse_guards(raw_guards);
-                    }
-                    None => (),
-                }
-
-                match tran.actions.as_ref() {
-                    Some(raw_action) => {
-                        actions = generate_actions(raw_action);
-                    }
-                    None => (),
-                }
-                fsm_machine += &transition;
-                fsm_machine += &guards;
-                fsm_machine += &actions;
+// Every input file with its source, in the order given. Directories stand for the .aql files in
+// them, sorted by name.
+fn sources(paths: &[&PathBuf]) -> Result<Vec<(PathBuf, String)>, String> {
+    let mut ret = vec![];
+    for path in paths {
+        if !is_std(path) && path.is_dir() {
+            let entries = fs::read_dir(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+            let mut files: Vec<PathBuf> = entries
+                .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                .filter(|file| file.extension().is_some_and(|ext| ext == "aql"))
+                .collect();
+            if files.is_empty() {
+                return Err(format!("{}: no .aql files", path.display()));
+            }
+            files.sort();
+            for file in files {
+                let source = read(&file)?;
+                ret.push((file, source));
             }
-            fsm_machine += "\n\t}\n\n";
+        } else {
+            ret.push((PathBuf::from(path), read(path)?));
         }
     }
+    Ok(ret)
+}
 
-    fsm_machine += "}\n";
-    fsm_machine
+fn input_sources(matches: &ArgMatches) -> Result<Vec<(PathBuf, String)>, String> {
+    let paths: Vec<&PathBuf> = matches.get_many::<PathBuf>("input").unwrap().collect();
+    sources(&paths)
 }
 
-fn generate_actions(actions: &Vec<ASTNode>) -> String {
-    let mut ret = String::from(" action {\n");
-    for action in actions {
-        let mut act = format!("\t\t\tfsm.update ");
-        if let ASTNode::Assignment { name, expr } = action {
-            let rhs = generate_stmt(expr.as_ref());
-            act += &format!("%{}, {} : i32\n", name, rhs);
-        }
-        ret += &act;
-    }
-    ret += "\t\t}\n";
-    ret
+// The `-I` directories in the order given, imports not found next to their file are looked up there.
+fn search_paths(matches: &ArgMatches) -> Vec<PathBuf> {
+    matches
+        .get_many::<PathBuf>("import-path")
+        .map(|paths| paths.cloned().collect())
+        .unwrap_or_default()
 }
 
-fn parse_guards(guards: &Vec<ASTNode>) -> String {
-    let mut ret = String::from("\t\t\t%fsm_output = comb.and");
-
-    let mut conditions = vec![];
-    for (i, inst) in guards.into_iter().enumerate() {
-        if let ASTNode::BinOp { verb, lhs, rhs } = inst {
-            let symbol = get_binverb(&verb);
-            let lhs = generate_stmt(lhs);
-            let rhs = generate_stmt(rhs);
-            let cond = format!("\t\t\t%{} = comb.icmp {} {}, {} : i32", i, symbol, lhs, rhs);
-
-            if i == guards.len() - 1 {
-                ret += &format!(" %{}", i);
-            } else {
-                ret += &format!(" %{},", i);
-            }
-            conditions.push(cond);
-        }
+// Each input with the files it imports, then linked together.
+fn parse(sources: &[(PathBuf, String)], search_paths: Vec<PathBuf>) -> Result<ASTNode, String> {
+    let mut loader = Loader::new(search_paths);
+    let mut programs = vec![];
+    for (path, source) in sources {
+        let file = (!is_std(path)).then_some(path.as_path());
+        let program = loader.load_source(source, file);
+        programs.push(program.map_err(|e| syntax_errors(path, source, e))?);
     }
+    Ok(aql_circt::link(programs))
+}
 
-    ret += " : i1\n";
-    let return_stmt = String::from("\t\t\tfsm.return %fsm_output\n\t\t}");
-    let mut guards = String::from("guard {\n");
-    for cond in &conditions {
-        guards += cond;
-        guards += "\n";
+// A syntax error comes with all the others in its file, one per line.
+fn syntax_errors(path: &Path, source: &str, error: Error) -> String {
+    let Error::Parse { .. } = error else {
+        return located(path, error);
+    };
+    let errors: Vec<String> = aql_circt::parse_recovering(source)
+        .1
+        .into_iter()
+        .map(|e| located(path, e))
+        .collect();
+    match errors.is_empty() {
+        true => located(path, error),
+        false => errors.join("\nerror: "),
     }
-
-    guards += &ret;
-    guards += &return_stmt;
-    guards
 }
 
-fn get_binverb(verb: &BinVerb) -> String {
-    let symbol = match verb {
-        ast::BinVerb::SmallerThan => String::from("ult"),
-        ast::BinVerb::LargerThan => String::from("ugt"),
-        ast::BinVerb::SmallerOrEqual => String::from("ule"),
-        ast::BinVerb::LargerOrEqual => String::from("uge"),
-        ast::BinVerb::Equal => String::from("eq"),
-        ast::BinVerb::NotEqual => String::from("ne"),
-        ast::BinVerb::Neg(_) => get_binverb(&reduce_neg(&verb)),
-        _ => todo!("Unsupported binary operation: {:?}", verb),
-    };
+// Problems with the design as a whole name the file when there is only one.
+fn design_error(sources: &[(PathBuf, String)], error: Error) -> String {
+    match sources {
+        [(path, _)] => located(path, error),
+        _ => error.to_string(),
+    }
+}
 
-    symbol
+fn load(
+    sources: &[(PathBuf, String)],
+    search_paths: Vec<PathBuf>,
+    model: Option<MemoryModel>,
+) -> Result<Vec<StateMachine>, String> {
+    let ast = parse(sources, search_paths)?;
+    let mut options = ConvertOptions::default();
+    options.memory_model = model;
+    aql_circt::convert(ast, &options).map_err(|e| design_error(sources, e))
 }
 
-fn reduce_neg(verb: &BinVerb) -> BinVerb {
-    if let BinVerb::Neg(v) = verb {
-        if let BinVerb::Neg(inner_v) = *v.clone() {
-            return reduce_neg(&inner_v);
-        } else {
-            let ret = match *v.clone() {
-                BinVerb::SmallerThan => BinVerb::LargerOrEqual,
-                BinVerb::LargerThan => BinVerb::SmallerOrEqual,
-                BinVerb::SmallerOrEqual => BinVerb::LargerThan,
-                BinVerb::LargerOrEqual => BinVerb::SmallerThan,
-                BinVerb::Equal => BinVerb::NotEqual,
-                BinVerb::NotEqual => BinVerb::Equal,
-                _ => todo!("Unsupported binary operation: {:?}", v),
-            };
-
-            return ret;
+// The output file, or stdout without one.
+fn emit(matches: &ArgMatches, text: &str) -> Result<(), String> {
+    match matches.get_one::<PathBuf>("output") {
+        Some(path) => write(path, text),
+        None => {
+            print!("{}", text);
+            Ok(())
         }
-    } else {
-        return verb.clone();
     }
 }
 
-// These are all the initilzation process, so should add one indent.
-fn generate_decl(decl: &ASTNode) -> String {
-    let mut ret = String::new();
-    let tbs = utils::ConversionTable::new();
-
-    if let ASTNode::VariableDeclaration {
-        typed_identifier,
-        expr,
-    } = decl
-    {
-        if let ASTNode::TypedIdentifier { aql_type, variable } = *typed_identifier.clone() {
-            let aql_type = tbs.convert(&aql_type);
-
-            match expr {
-                Some(val) => {
-                    // println!("Expr: {:?}", *val);
-                    // ret = format!("fsm.variable \"{}\" {{initValue = {} : {} }} : {}", variable, init_value, aql_type, aql_type);
-                    if let ASTNode::ConstVal(mut val) = *val.clone() {
-                        let aql_type = match aql_type {
-                            utils::AQLType::Base(t) => t,
-                            utils::AQLType::Ordering => {
-                                if val == "FIFO" {
-                                    val = String::from("0");
-                                } else if variable == "Hash" {
-                                    val = String::from("1");
-                                } else {
-                                    val = String::from("2");
-                                }
-
-                                String::from("i32")
-                            }
-                        };
-                        ret = format!(
-                            "%{} = fsm.variable \"{}\" {{initValue = {} : {} }} : {}",
-                            variable, variable, val, aql_type, aql_type
-                        );
-                    }
-                }
-                None => {
-                    let aql_type = match aql_type {
-                        utils::AQLType::Base(t) => t,
-                        utils::AQLType::Ordering => String::from("i32"),
-                    };
-                    ret = format!(
-                        "%{} = fsm.variable \"{}\" {{initValue = 0 : {} }} : {}",
-                        variable, variable, aql_type, aql_type
-                    );
-                }
-            }
-        } else {
-            panic!("invalid grammar.")
+fn run_build(matches: &ArgMatches) -> Result<(), String> {
+    let mut options = GenerateOptions::default();
+    options.top = matches.contains_id("top");
+    options.top_controller = matches.get_one::<String>("top").cloned();
+    options.cores = matches.get_one::<u32>("cores").copied();
+    options.format = match matches.get_one::<String>("emit").unwrap().as_str() {
+        "ast" => {
+            let ast = parse(&input_sources(matches)?, search_paths(matches))?;
+            return emit(matches, &format!("{:#?}\n", ast));
         }
+        "cfg" => Format::Cfg,
+        "json" => Format::Json,
+        "dot" => Format::Dot,
+        "dot-cfg" => Format::DotCfg,
+        "hw-mlir" => Format::HwMlir,
+        "btor2" => Format::Btor2,
+        "smt2" => Format::Smt2,
+        _ => Format::Mlir,
     };
-    ret
+
+    let sources = input_sources(matches)?;
+    let machines = load(&sources, search_paths(matches), memory_model(matches))?;
+    let output = aql_circt::generate(&machines, &options).map_err(|e| design_error(&sources, e))?;
+    emit(matches, &output)
 }
 
-fn generate_stmt(node: &ASTNode) -> String {
-    let ret = match node {
-        ASTNode::Ident(var) => String::from("%") + var,
-        ASTNode::QualifiedName { names } => {
-            let mut ret = String::from("%");
-            for (i, name) in names.iter().enumerate() {
-                match name {
-                    ASTNode::Ident(ident) => {
-                        ret = ret + ident;
-                        if i != names.len() - 1 {
-                            ret += ".";
-                        }
-                    }
-                    _ => {}
-                }
-            }
-            ret
-        }
-        _ => todo!("Unimplemented statement."),
-    };
-    ret
+fn run_fmt(matches: &ArgMatches) -> Result<(), String> {
+    let file = matches.get_one::<PathBuf>("input").unwrap();
+    let source = read(file)?;
+    let formatted =
+        aql_circt::format_source(&source).map_err(|e| syntax_errors(file, &source, e))?;
+    if !matches.get_flag("check") {
+        return emit(matches, &formatted);
+    }
+    match formatted == source {
+        true => Ok(()),
+        false => Err(format!(
+            "{}: not formatted, run `aql-circt fmt`",
+            name(file)
+        )),
+    }
 }
 
-/*
-fn print_assign(assign: ASTNode) -> String {
-    let mut ret = String::from("fsm.variable");
-    if let ASTNode::Assignment {name, expr} = assign {
-        if let ASTNode::TypedIdentifier { aql_type, variable } = *epxr {
-            ret = format!("fsm.variable \"{}\" {{initValue = 0 : {} }} : {}", variable, aql_type, aql_type);
-        }
-    } else {
-        panic!("invalid grammar.")
+fn run_litmus(matches: &ArgMatches) -> Result<(), String> {
+    let test_path = matches.get_one::<PathBuf>("TEST").unwrap();
+    let test = litmus::parse(&read(test_path)?).map_err(|e| located(test_path, e))?;
+    let model = memory_model(matches).unwrap();
+
+    let file = matches.get_one::<PathBuf>("input").unwrap();
+    let sources = sources(&[file])?;
+    let machines = load(&sources, search_paths(matches), Some(model))?;
+    let (mlir, design) = aql_circt::generate_multicore(&machines, test.threads.len() as u32)
+        .map_err(|e| design_error(&sources, e))?;
+
+    if let Some(path) = matches.get_one::<PathBuf>("mlir") {
+        write(path, &mlir)?;
+    }
+    if let Some(path) = matches.get_one::<PathBuf>("output") {
+        write(
+            path,
+            &litmus::testbench(&test, model, &design).map_err(|e| design_error(&sources, e))?,
+        )?;
     }
 
-    ret
+    let reachable = litmus::reachable(&test, &machines).map_err(|e| design_error(&sources, e))?;
+    print!("{}", litmus::report(&test, model, &reachable));
+    if !reachable
+        .outcomes
+        .is_subset(&litmus::outcomes(&test, model))
+    {
+        return Err(format!(
+            "{}: the design reaches outcomes {} forbids.",
+            test.name,
+            model.name()
+        ));
+    }
+    Ok(())
 }
-*/
-
-/*
-fn pretty_print(tree: ASTNode, output: &mut Vec<String>, indent: u8, env: &mut HashMap<String, Option<String>>) -> String {
-    // Top level structure is the declaration.
-    match tree {
-        ASTNode::Top(nodes) => {
-            output.push(String::from("fsm.machine @LoadInst(%arg0: i1, %arg1: i1) attributes {initialState = \"ReadyToDispatch\"} {\n"));
-            for node in nodes {
-                pretty_print(node, output, indent, env);
-            }
-            output.push(String::from("}\n"));
-        },
-        ASTNode::Integer(_) => todo!(),
-        ASTNode::Decimal(_) => todo!(),
-        ASTNode::Str(_) => todo!(),
-        ASTNode::Ident(ident) => {
-            output.push(ident + " ");
-        },
-        ASTNode::ConstVal(_) => todo!(),
-        ASTNode::QualifiedName { names } => {
-            for (i, name) in names.iter().enumerate() {
-                match name {
-                    ASTNode::Ident(ident) => {
-                        output.push(String::from(ident));
-                        if i != names.len() - 1 {
-                            output.push(".".to_string());
-                        }
-                    }
-                    _ => {
-
-                    }
-                }
-            }
-        },
-        ASTNode::Declaration(decl) => {
-            // top-level structure, machine, load instruction.
-            pretty_print(*decl, output, indent + 1, env);
-        },
-        ASTNode::Transition { action, ident } => {
-            output.push(String::from("\t".repeat(indent.into())));
-            output.push(String::from("fsm.transition @"));
-            if let ASTNode::Ident(next_state) = *ident {
-                output.push(next_state);
-            }
-            output.push(";\n".to_string());
-        },
-        ASTNode::StructureDelcaration { s_type, statement, name } => {
-            output.push(String::from("\t".repeat(indent.into())));
 
-            if s_type == String::from("state") {
-                output.push(String::from("fsm.state") + " ");
-                output.push(String::from("@") + name.as_str() + " transitions ");
-            }
-            if s_type == String::from("controller_entry") {
-                // a series of initilizations.
+fn run_check(matches: &ArgMatches) -> Result<(), String> {
+    let sources = input_sources(matches)?;
+    let machines = load(&sources, search_paths(matches), memory_model(matches))?;
+    let texts: Vec<&str> = sources.iter().map(|(_, source)| source.as_str()).collect();
+    let linked = aql_circt::linked_locations(&texts).map_err(|e| design_error(&sources, e))?;
+    let locations: Vec<(&PathBuf, Location)> = sources
+        .iter()
+        .zip(linked)
+        .flat_map(|((path, _), locations)| locations.into_iter().map(move |l| (path, l)))
+        .collect();
+    let mut options = CheckOptions::default();
+    options.bound = *matches.get_one::<usize>("bound").unwrap();
+    let results = aql_circt::check(&machines, &options).map_err(|e| design_error(&sources, e))?;
+
+    let mut found = false;
+    for (cfgs, result) in machines.iter().zip(&results) {
+        for finding in &result.findings {
+            // States added by the memory model have no location of their own.
+            let location = locations.iter().find(|(_, l)| {
+                l.s_type == "state" && l.controller == cfgs.fsm_name && l.name == finding.state
+            });
+            match location {
+                Some((path, l)) => println!(
+                    "{}:{}:{}: warning: {}",
+                    name(path),
+                    l.line,
+                    l.column,
+                    finding.message
+                ),
+                None => println!("warning: {}", finding.message),
             }
+        }
+        found |= !result.findings.is_empty();
+
+        print!(
+            "{}: {} configurations explored",
+            cfgs.fsm_name, result.configurations
+        );
+        if result.bounded {
+            print!(", stopped at --bound so deadlocks past it are missed");
+        }
+        println!();
+    }
 
-            pretty_print(*statement, output, indent, env);
-        },
-        ASTNode::InternalFuncDecl(_) => todo!(),
-        ASTNode::CatchBlock { keyword, qualified_name, idents, block } => {
-            output.push(String::from("\t".repeat(indent.into())));
-            output.push("handle ".to_string() + &keyword + " ");
-            pretty_print(*qualified_name, output, indent, env);
-            for ident in idents {
-                pretty_print(ident, output, indent, env);
-            }
-            pretty_print(*block, output, indent, env);
-        },
-        ASTNode::Block(stmts) => {
-            output.push(String::from("{\n"));
+    if found {
+        process::exit(1);
+    }
+    Ok(())
+}
 
-            for stmt in stmts {
-                pretty_print(stmt, output, indent+1, env);
-            }
+fn run_lsp(matches: &ArgMatches) -> Result<(), String> {
+    aql_circt::lsp::serve(
+        io::stdin().lock(),
+        io::stdout().lock(),
+        search_paths(matches),
+    )
+    .map_err(|e| format!("lsp: {}", e))
+}
 
-            output.push("\t".repeat(indent.into()) + "}\n");
-        },
-        ASTNode::Expr(expr) => {
-            match expr {
-                ast::Expr::UnuaryOp { verb, term } => todo!(),
-                ast::Expr::BinOp { verb, lhs, rhs } => todo!(),
-                ast::Expr::List(_) => todo!(),
-                ast::Expr::DSLTerm => {
-                },
-            }
-        },
-        ASTNode::Listen { block, catch_block } => {
-            output.push("\t".repeat(indent.into()) + "listen ");
-            pretty_print(*block, output, indent, env);
-            output.push(String::from(" "));
-            pretty_print(*catch_block, output, indent, env);
-        },
-        ASTNode::Call { qualified_name, list } => {
-            output.push("\t".repeat(indent.into()));
-            pretty_print(*qualified_name, output, indent, env);
-            pretty_print(*list, output, indent, env);
-            output.push(";\n".to_string());
-        },
-        ASTNode::ExprList(list) => {
-            output.push("(".to_string());
-            for expr in list {
-                pretty_print(expr, output, indent, env);
-                output.push(", ".to_string());
+fn run_sim(matches: &ArgMatches) -> Result<(), String> {
+    let sources = input_sources(matches)?;
+    let machines = load(&sources, search_paths(matches), memory_model(matches))?;
+
+    let cfgs = match matches.get_one::<String>("controller") {
+        Some(name) => match machines.iter().find(|m| m.fsm_name == *name) {
+            Some(cfgs) => cfgs,
+            None => {
+                let names: Vec<&str> = machines.iter().map(|m| m.fsm_name.as_str()).collect();
+                return Err(format!(
+                    "{} is not a controller, expected one of {}.",
+                    name,
+                    names.join(", ")
+                ));
             }
-            output.push(")".to_string());
         },
-        ASTNode::None => {},
-        ASTNode::Await { keyword, call, when_block } => {
-            // ignore function call now.
-            // ignore await keyword.
-            pretty_print(*when_block, output, indent, env);
-        },
-        ASTNode::When { keyword, call, ident, block } => {
-            // igore function call.
-
-            // ignore the block.
-            match *block {
-                ASTNode::Block(blk) => {
-                    for stmt in blk {
-                        pretty_print(stmt, output, indent, env);
-                    }
-                },
-                _ => {
-
-                }
-            }
+        None => match machines.first() {
+            Some(cfgs) => cfgs,
+            None => return Err(String::from("the design has no controller.")),
         },
+    };
+
+    let stimulus = match matches.get_one::<PathBuf>("stimulus") {
+        Some(path) => interp::Stimulus::parse(&read(path)?).map_err(|e| located(path, e))?,
+        None => interp::Stimulus::default(),
+    };
+    let cycles = match matches.get_one::<u64>("cycles") {
+        Some(cycles) => *cycles,
+        None => stimulus.last_cycle().map_or(0, |cycle| cycle + 1).max(16),
+    };
 
+    let samples = interp::run(cfgs, &stimulus, cycles).map_err(|e| design_error(&sources, e))?;
+    print!("{}", interp::trace(cfgs, &samples));
+    if let Some(path) = matches.get_one::<PathBuf>("vcd") {
+        let vcd = interp::vcd(cfgs, &samples).map_err(|e| design_error(&sources, e))?;
+        write(path, &vcd)?;
     }
-    String::new()
+    Ok(())
 }
-*/
@@ -0,0 +1,491 @@
+use crate::{
+    ast::{ASTNode, BinVerb},
+    cfg::{take_properties, OwnedStructure},
+    error::design,
+    utils::Ordering,
+    Error,
+};
+
+// The memory unit requests go to, unless the controller entry names another one with `memory:`.
+const MEMORY_UNIT: &str = "MemSys";
+// The field a load queue entry compares against invalidated lines, unless the entry names another
+// one with `address:`.
+const ADDRESS_FIELD: &str = "phys_addr";
+const INVALIDATED_LINE: &str = "invalidated_line";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MemoryModel {
+    Sc,
+    Tso,
+    Rvwmo,
+}
+
+impl MemoryModel {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "sc" => Some(MemoryModel::Sc),
+            "tso" => Some(MemoryModel::Tso),
+            "rvwmo" => Some(MemoryModel::Rvwmo),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            MemoryModel::Sc => "SC",
+            MemoryModel::Tso => "TSO",
+            MemoryModel::Rvwmo => "RVWMO",
+        }
+    }
+
+    // load -> load: a performed load is replayed when its line is invalidated.
+    fn orders_loads(self) -> bool {
+        matches!(self, MemoryModel::Sc | MemoryModel::Tso)
+    }
+
+    // store -> store: stores leave the store buffer in program order.
+    fn orders_stores(self) -> bool {
+        matches!(self, MemoryModel::Sc | MemoryModel::Tso)
+    }
+
+    // store -> load: loads wait for the store buffer to drain.
+    fn orders_store_load(self) -> bool {
+        matches!(self, MemoryModel::Sc)
+    }
+
+    // Under SC every access is already ordered, fences have nothing left to do.
+    fn needs_fences(self) -> bool {
+        matches!(self, MemoryModel::Tso | MemoryModel::Rvwmo)
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Role {
+    LoadQueue,
+    StoreBuffer,
+    Other,
+}
+
+fn attribute(attributes: &[ASTNode], key: &str) -> Option<Option<String>> {
+    attributes.iter().find_map(|attribute| match attribute {
+        ASTNode::Attribute { name, value } if name == key => {
+            Some(value.as_ref().and_then(|value| match value.as_ref() {
+                ASTNode::ConstVal(value) => Some(value.clone()),
+                value => value.name(),
+            }))
+        }
+        _ => None,
+    })
+}
+
+fn entry(structures: &[OwnedStructure]) -> Option<&OwnedStructure> {
+    structures.iter().find(|s| s.s_type == "controller_entry")
+}
+
+// controller_entry LQ (role: load_queue) { ... }
+fn role(structures: &[OwnedStructure]) -> Result<Role, Error> {
+    let role = entry(structures).and_then(|entry| attribute(&entry.attributes, "role"));
+    match role {
+        None => Ok(Role::Other),
+        Some(Some(role)) if role == "load_queue" => Ok(Role::LoadQueue),
+        Some(Some(role)) if role == "store_buffer" => Ok(Role::StoreBuffer),
+        Some(role) => design!(
+            "Unknown controller role: {}, expected load_queue or store_buffer.",
+            role.unwrap_or_default()
+        ),
+    }
+}
+
+fn statements(node: &ASTNode) -> &[ASTNode] {
+    match node {
+        ASTNode::Block(stmts) => stmts,
+        _ => std::slice::from_ref(node),
+    }
+}
+
+fn init_entry(entry: &OwnedStructure) -> Option<String> {
+    statements(&entry.statement)
+        .iter()
+        .find_map(|stmt| match stmt {
+            ASTNode::Assignment { name, expr } if name == "init_entry" => expr.name(),
+            _ => None,
+        })
+}
+
+fn declares(entry: &OwnedStructure, field: &str) -> bool {
+    statements(&entry.statement).iter().any(|stmt| {
+        matches!(
+            stmt,
+            ASTNode::VariableDeclaration { typed_identifier, .. }
+                if matches!(typed_identifier.as_ref(), ASTNode::TypedIdentifier { variable, .. } if variable == field)
+        )
+    })
+}
+
+// Whether a state makes any request to `unit`.
+fn requests(node: &ASTNode, unit: &str) -> bool {
+    match node {
+        ASTNode::Block(stmts) => stmts.iter().any(|stmt| requests(stmt, unit)),
+        ASTNode::Conditional {
+            if_blk, else_blk, ..
+        } => requests(if_blk, unit) || requests(else_blk, unit),
+        ASTNode::Listen { block, catch_block } => {
+            requests(block, unit) || requests(catch_block, unit)
+        }
+        ASTNode::CatchBlock { block, .. } | ASTNode::When { block, .. } => requests(block, unit),
+        ASTNode::Await {
+            call, when_block, ..
+        } => call.as_ref().is_some_and(|call| requests(call, unit)) || requests(when_block, unit),
+        ASTNode::Call { qualified_name, .. } => match qualified_name.as_ref() {
+            ASTNode::QualifiedName { names } => {
+                names.len() > 1 && names[0].name().as_deref() == Some(unit)
+            }
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+// Redirects every transition (and the init_entry) to `from` into `to`.
+fn retarget(node: ASTNode, from: &str, to: &str) -> ASTNode {
+    let boxed = |node: Box<ASTNode>| Box::new(retarget(*node, from, to));
+    match node {
+        ASTNode::Block(stmts) => {
+            ASTNode::Block(stmts.into_iter().map(|s| retarget(s, from, to)).collect())
+        }
+        ASTNode::Transition { action, ident } if ident.name().as_deref() == Some(from) => {
+            ASTNode::Transition {
+                action,
+                ident: Box::new(ASTNode::Ident(String::from(to))),
+            }
+        }
+        ASTNode::Assignment { name, expr }
+            if name == "init_entry" && expr.name().as_deref() == Some(from) =>
+        {
+            ASTNode::Assignment {
+                name,
+                expr: Box::new(ASTNode::QualifiedName {
+                    names: vec![ASTNode::Ident(String::from(to))],
+                }),
+            }
+        }
+        ASTNode::Conditional {
+            expr,
+            if_blk,
+            else_blk,
+        } => ASTNode::Conditional {
+            expr,
+            if_blk: boxed(if_blk),
+            else_blk: boxed(else_blk),
+        },
+        ASTNode::Listen { block, catch_block } => ASTNode::Listen {
+            block: boxed(block),
+            catch_block: boxed(catch_block),
+        },
+        ASTNode::CatchBlock {
+            keyword,
+            qualified_name,
+            idents,
+            source,
+            block,
+        } => ASTNode::CatchBlock {
+            keyword,
+            qualified_name,
+            idents,
+            source,
+            block: boxed(block),
+        },
+        ASTNode::Await {
+            keyword,
+            call,
+            when_block,
+        } => ASTNode::Await {
+            keyword,
+            call,
+            when_block: boxed(when_block),
+        },
+        ASTNode::When {
+            keyword,
+            call,
+            ident,
+            block,
+        } => ASTNode::When {
+            keyword,
+            call,
+            ident,
+            block: boxed(block),
+        },
+        node => node,
+    }
+}
+
+fn transition(target: &str) -> ASTNode {
+    ASTNode::Transition {
+        action: String::from("transition"),
+        ident: Box::new(ASTNode::Ident(String::from(target))),
+    }
+}
+
+// state {target}_await_{event} {
+//   await {
+//     when {event}() from {source} { transition {target}; }
+//   }
+// }
+fn wait_state(target: &str, event: &str, source: &str) -> OwnedStructure {
+    let when = ASTNode::When {
+        keyword: String::from("when"),
+        call: Box::new(ASTNode::Call {
+            qualified_name: Box::new(ASTNode::QualifiedName {
+                names: vec![ASTNode::Ident(String::from(event))],
+            }),
+            list: Box::new(ASTNode::ExprList(vec![])),
+        }),
+        ident: Box::new(ASTNode::Ident(String::from(source))),
+        block: Box::new(ASTNode::Block(vec![transition(target)])),
+    };
+
+    OwnedStructure {
+        s_type: String::from("state"),
+        name: format!("{}_await_{}", target, event),
+        attributes: vec![],
+        doc: None,
+        statement: ASTNode::Block(vec![ASTNode::Await {
+            keyword: String::from("await"),
+            call: None,
+            when_block: Box::new(ASTNode::Block(vec![when])),
+        }]),
+    }
+}
+
+// Where a performed load goes when its line is invalidated, configured on the controller entry:
+//
+//   controller_entry LQ (address: vaddr, replay: lq_replay, line_offset: 6) { ... }
+struct Squash {
+    memory: String,
+    address: String,
+    replay: String,
+    // Low address bits dropped to get the line address.
+    line_offset: Option<String>,
+}
+
+impl Squash {
+    fn from_entry(controller: &str, entry: &OwnedStructure, memory: &str) -> Result<Self, Error> {
+        let address = attribute(&entry.attributes, "address")
+            .flatten()
+            .unwrap_or(String::from(ADDRESS_FIELD));
+        if !declares(entry, &address) {
+            return design!(
+                "{} needs a {} field to be squashed by invalidations.",
+                controller,
+                address
+            );
+        }
+        let replay = attribute(&entry.attributes, "replay")
+            .flatten()
+            .or_else(|| init_entry(entry));
+        let Some(replay) = replay else {
+            return design!("{} has no init_entry to replay from.", controller);
+        };
+        let line_offset = attribute(&entry.attributes, "line_offset").flatten();
+
+        Ok(Squash {
+            memory: String::from(memory),
+            address,
+            replay,
+            line_offset,
+        })
+    }
+
+    // listen { body } handle invalidate(invalidated_line) from {memory} {
+    //   if (invalidated_line == {address} >> {line_offset}) { transition {replay}; }
+    //   body
+    // }
+    fn listener(&self, body: ASTNode) -> ASTNode {
+        // Properties stay at the top of the state, they hold whether or not the event fires.
+        let (mut ret, body) = take_properties(body);
+        let address = ASTNode::Ident(self.address.clone());
+        let line = match &self.line_offset {
+            Some(offset) => ASTNode::BinOp {
+                verb: BinVerb::RightShift,
+                lhs: Box::new(address),
+                rhs: Box::new(ASTNode::ConstVal(offset.clone())),
+            },
+            None => address,
+        };
+        let squash = ASTNode::Conditional {
+            expr: Box::new(ASTNode::BinOp {
+                verb: BinVerb::Equal,
+                lhs: Box::new(ASTNode::Ident(String::from(INVALIDATED_LINE))),
+                rhs: Box::new(line),
+            }),
+            if_blk: Box::new(ASTNode::Block(vec![transition(&self.replay)])),
+            else_blk: Box::new(ASTNode::None),
+        };
+        let mut handler = vec![squash];
+        handler.extend(statements(&body).iter().cloned());
+
+        let listen = ASTNode::Listen {
+            block: Box::new(body),
+            catch_block: Box::new(ASTNode::Block(vec![ASTNode::CatchBlock {
+                keyword: String::from("handle"),
+                qualified_name: Box::new(ASTNode::QualifiedName {
+                    names: vec![ASTNode::Ident(String::from("invalidate"))],
+                }),
+                idents: vec![ASTNode::Ident(String::from(INVALIDATED_LINE))],
+                source: Some(Box::new(ASTNode::Ident(self.memory.clone()))),
+                block: Box::new(ASTNode::Block(handler)),
+            }])),
+        };
+        ret.push(listen);
+        ASTNode::Block(ret)
+    }
+}
+
+// The ordering declared by a state_queue, FIFO unless it says otherwise.
+fn queue_ordering(queue: &OwnedStructure) -> Ordering {
+    statements(&queue.statement)
+        .iter()
+        .find_map(|stmt| match stmt {
+            ASTNode::VariableDeclaration {
+                typed_identifier,
+                expr: Some(expr),
+                ..
+            } => match (typed_identifier.as_ref(), expr.as_ref()) {
+                (ASTNode::TypedIdentifier { aql_type, .. }, ASTNode::ConstVal(value))
+                    if aql_type == "element_ordering" =>
+                {
+                    Ordering::from_name(value)
+                }
+                _ => None,
+            },
+            _ => None,
+        })
+        .unwrap_or(Ordering::Fifo)
+}
+
+fn is_performed(structure: &OwnedStructure) -> bool {
+    attribute(&structure.attributes, "performed").is_some()
+}
+
+// Rewrites the controllers so the generated machines follow `model`. Everything is expressed as
+// plain AQL states and transitions, controllers take part through the `role` attribute of their
+// entry:
+//
+//   - states tagged `performed` listen for `invalidate(line)` from the memory unit and go to the
+//     replay state when `line` matches their address field (see `Squash`). Under SC/TSO a
+//     load_queue without any tagged state treats every state after the init_entry as performed.
+//   - load_queue under SC: states requesting the memory unit first wait in
+//     `{state}_await_drained` for `drained()` from the store buffer.
+//   - store_buffer under SC/TSO: the queue must be FIFO, and states requesting the memory unit
+//     first wait in `{state}_await_head` until the queue reports the entry as its `head()`.
+//   - states with the `fence` attribute under TSO/RVWMO wait for `drained()` from the store
+//     buffer.
+pub fn enforce(
+    model: Option<MemoryModel>,
+    controllers: &mut [(String, Vec<OwnedStructure>)],
+) -> Result<(), Error> {
+    let mut store_buffer = None;
+    for (name, structures) in controllers.iter() {
+        if role(structures)? == Role::StoreBuffer && store_buffer.is_none() {
+            store_buffer = Some(name.clone());
+        }
+    }
+
+    for (name, structures) in controllers.iter_mut() {
+        let role = role(structures)?;
+        let Some(entry) = entry(structures) else {
+            continue;
+        };
+        let memory = attribute(&entry.attributes, "memory")
+            .flatten()
+            .unwrap_or(String::from(MEMORY_UNIT));
+        let init = init_entry(entry);
+        let tagged = structures.iter().any(is_performed);
+        let untagged_loads =
+            !tagged && role == Role::LoadQueue && model.is_some_and(|m| m.orders_loads());
+        let squash = if tagged || untagged_loads {
+            Some(Squash::from_entry(name, entry, &memory)?)
+        } else {
+            None
+        };
+        if let Some(squash) = &squash {
+            if !structures
+                .iter()
+                .any(|s| s.s_type == "state" && s.name == squash.replay)
+            {
+                return design!(
+                    "{} replays into {}, which is not one of its states.",
+                    name,
+                    squash.replay
+                );
+            }
+        }
+        let queue = structures.iter().find(|s| s.s_type == "state_queue");
+
+        if let (Some(model), Some(queue)) = (model, queue) {
+            let ordering = queue_ordering(queue);
+            if role == Role::StoreBuffer && model.orders_stores() && ordering != Ordering::Fifo {
+                return design!(
+                    "{:?} needs store buffer {} to drain in order, but its queue is {:?}.",
+                    model,
+                    name,
+                    ordering
+                );
+            }
+        }
+        let has_queue = queue.is_some();
+
+        // (state, event, source) the state has to wait for before it is entered.
+        let mut gates: Vec<(String, &str, String)> = vec![];
+
+        for structure in structures.iter_mut() {
+            if structure.s_type != "state" {
+                continue;
+            }
+
+            let performed = if tagged {
+                is_performed(structure)
+            } else {
+                untagged_loads && init.as_ref() != Some(&structure.name)
+            };
+            if let (true, Some(squash)) = (performed, &squash) {
+                let body = std::mem::replace(&mut structure.statement, ASTNode::None);
+                structure.statement = squash.listener(body);
+            }
+
+            let Some(model) = model else {
+                continue;
+            };
+            let issues = requests(&structure.statement, &memory);
+            let fence = attribute(&structure.attributes, "fence").is_some();
+            if fence && model.needs_fences() && store_buffer.is_none() {
+                return design!(
+                    "{} is a fence, but no controller has the store_buffer role for it to wait for \
+                     under {}.",
+                    structure.name,
+                    model.name()
+                );
+            }
+            if let Some(store_buffer) = &store_buffer {
+                if (role == Role::LoadQueue && model.orders_store_load() && issues)
+                    || (fence && model.needs_fences())
+                {
+                    gates.push((structure.name.clone(), "drained", store_buffer.clone()));
+                }
+            }
+            if role == Role::StoreBuffer && model.orders_stores() && has_queue && issues {
+                gates.push((structure.name.clone(), "head", name.clone()));
+            }
+        }
+
+        for (state, event, source) in gates {
+            let gate = wait_state(&state, event, &source);
+            for structure in structures.iter_mut() {
+                let statement = std::mem::replace(&mut structure.statement, ASTNode::None);
+                structure.statement = retarget(statement, &state, &gate.name);
+            }
+            structures.push(gate);
+        }
+    }
+    Ok(())
+}
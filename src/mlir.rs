@@ -0,0 +1,192 @@
+use std::collections::HashMap;
+
+use crate::{cfg::StateMachine, error::design, Error};
+
+// Collects the ops of a generated hw.module and hands out SSA names.
+pub struct Builder {
+    pub ops: Vec<String>,
+    constants: HashMap<(u64, u32), String>,
+    counter: usize,
+}
+
+impl Builder {
+    pub fn new() -> Self {
+        Builder {
+            ops: vec![],
+            constants: HashMap::new(),
+            counter: 0,
+        }
+    }
+
+    pub fn fresh(&mut self) -> String {
+        self.counter += 1;
+        format!("%t{}", self.counter)
+    }
+
+    pub fn push(&mut self, op: String) {
+        self.ops.push(op);
+    }
+
+    pub fn constant(&mut self, value: u64, width: u32) -> String {
+        if let Some(name) = self.constants.get(&(value, width)) {
+            return name.clone();
+        }
+        let name = format!("%c{}_i{}", value, width);
+        self.push(format!("{} = hw.constant {} : i{}", name, value, width));
+        self.constants.insert((value, width), name.clone());
+        name
+    }
+
+    pub fn binary(&mut self, op: &str, lhs: &str, rhs: &str, width: u32) -> String {
+        let ret = self.fresh();
+        self.push(format!(
+            "{} = comb.{} {}, {} : i{}",
+            ret, op, lhs, rhs, width
+        ));
+        ret
+    }
+
+    pub fn icmp(&mut self, pred: &str, lhs: &str, rhs: &str, ty: &str) -> String {
+        let ret = self.fresh();
+        self.push(format!(
+            "{} = comb.icmp {} {}, {} : {}",
+            ret, pred, lhs, rhs, ty
+        ));
+        ret
+    }
+
+    pub fn not(&mut self, value: &str) -> String {
+        let one = self.constant(1, 1);
+        self.binary("xor", value, &one, 1)
+    }
+
+    pub fn mux(&mut self, cond: &str, if_true: &str, if_false: &str, ty: &str) -> String {
+        let ret = self.fresh();
+        self.push(format!(
+            "{} = comb.mux {}, {}, {} : {}",
+            ret, cond, if_true, if_false, ty
+        ));
+        ret
+    }
+
+    // Registers are declared by name so they can be read before their next value exists.
+    pub fn register(&mut self, name: &str, next: &str, init: &str, ty: &str) {
+        self.push(format!(
+            "%{} = seq.compreg {}, %clk reset %rst, {} : {}",
+            name, next, init, ty
+        ));
+    }
+}
+
+// Imported names are namespaced, `lsq.LQ`, but MLIR port names can't hold a dot and symbols with
+// one read as nested references, so they become `lsq_LQ`.
+pub fn symbol(name: &str) -> String {
+    name.replace('.', "_")
+}
+
+// The documentation of a hw.module as its `comment`, which ExportVerilog writes above the
+// Verilog module. `//` lines are dropped when CIRCT parses the MLIR.
+pub fn module_attributes(doc: Option<&str>) -> String {
+    match doc {
+        Some(doc) => {
+            let escaped = doc
+                .replace('\\', "\\\\")
+                .replace('"', "\\\"")
+                .replace('\n', "\\n");
+            format!(" attributes {{comment = \"{}\"}}", escaped)
+        }
+        None => String::new(),
+    }
+}
+
+// `lsq.LQ` and `lsq_LQ` would be the same symbol.
+pub fn distinct_symbols(machines: &[StateMachine]) -> Result<(), Error> {
+    let mut symbols: HashMap<String, &str> = HashMap::new();
+    for cfgs in machines {
+        if let Some(other) = symbols.insert(symbol(&cfgs.fsm_name), &cfgs.fsm_name) {
+            return design!(
+                "{} and {} are both @{} in MLIR, rename one of them.",
+                other,
+                cfgs.fsm_name,
+                symbol(&cfgs.fsm_name)
+            );
+        }
+    }
+    Ok(())
+}
+
+pub fn index_width(num_entries: u32) -> u32 {
+    // ceil(log2(n)), but at least one bit.
+    let mut width = 1;
+    while (1u64 << width) < num_entries as u64 {
+        width += 1;
+    }
+    width
+}
+
+// The SSA value of the `index`th result of an instance.
+pub fn instance_result(instance: &str, index: usize, len: usize) -> String {
+    if len == 1 {
+        format!("%{}", instance)
+    } else {
+        format!("%{}#{}", instance, index)
+    }
+}
+
+// %inst:2 = fsm.hw_instance "inst" @machine(%a, %b), clock %clk, reset %rst : (i1, i32) -> (i1, i32)
+pub fn hw_instance(
+    cfgs: &StateMachine,
+    instance: &str,
+    machine_name: &str,
+    operands: &[String],
+    reset: &str,
+) -> String {
+    let input_types: Vec<&str> = cfgs.inputs.iter().map(|p| p.ty.as_str()).collect();
+    let output_types: Vec<&str> = cfgs.outputs.iter().map(|p| p.ty.as_str()).collect();
+    let results = match cfgs.outputs.len() {
+        0 => String::new(),
+        1 => format!("%{} = ", instance),
+        len => format!("%{}:{} = ", instance, len),
+    };
+
+    format!(
+        "{}fsm.hw_instance \"{}\" @{}({}), clock %clk, reset {} : ({}) -> ({})",
+        results,
+        instance,
+        machine_name,
+        operands.join(", "),
+        reset,
+        input_types.join(", "),
+        output_types.join(", ")
+    )
+}
+
+// %inst:2 = hw.instance "inst" @module(clk: %clk: !seq.clock, a: %a: i1) -> (x: i1, y: i32)
+pub fn module_instance(
+    instance: &str,
+    module_name: &str,
+    inputs: &[(String, String, String)],
+    outputs: &[(String, String)],
+) -> String {
+    let results = match outputs.len() {
+        0 => String::new(),
+        1 => format!("%{} = ", instance),
+        len => format!("%{}:{} = ", instance, len),
+    };
+    let inputs: Vec<String> = inputs
+        .iter()
+        .map(|(name, value, ty)| format!("{}: {}: {}", name, value, ty))
+        .collect();
+    let outputs: Vec<String> = outputs
+        .iter()
+        .map(|(name, ty)| format!("{}: {}", name, ty))
+        .collect();
+    format!(
+        "{}hw.instance \"{}\" @{}({}) -> ({})",
+        results,
+        instance,
+        module_name,
+        inputs.join(", "),
+        outputs.join(", ")
+    )
+}
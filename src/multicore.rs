@@ -0,0 +1,747 @@
+use std::{
+    collections::HashMap,
+    hash::{Hash, Hasher},
+    rc::Rc,
+};
+
+use crate::{
+    cfg::{self, Port, StateMachine},
+    error::design,
+    generate_machine,
+    interp::Interpreter,
+    mlir::{hw_instance, index_width, instance_result, module_instance, symbol, Builder},
+    queue, utils, Error,
+};
+
+// A controller of one core, or one entry of its state_queue.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Unit {
+    pub core: u32,
+    pub machine: usize,
+    pub entry: Option<u32>,
+}
+
+// Where an input of a controller gets its value.
+#[derive(Debug, Clone)]
+pub enum Source {
+    // Output `j` of controller `i` of the same core.
+    Output(usize, usize),
+    // An event of another unit: the valid bit of `{unit}_{event}` for this core, or one of its
+    // arguments, which are broadcast to every core.
+    Event(String, Option<usize>),
+    // The `head()` a state_queue raises for its own entries.
+    Head,
+    // An input of the top.
+    Port(String),
+}
+
+// One unit's request on a shared channel: the output holding its valid bit, the outputs holding
+// its arguments, indexed by position, and the events of the called unit that answer it.
+pub struct Request {
+    pub unit: Unit,
+    pub valid: usize,
+    pub args: Vec<(usize, usize)>,
+    pub responses: Vec<String>,
+}
+
+// How `hw.module @top` connects the cores, shared by the generated MLIR and the interpreter.
+pub struct Routing {
+    pub cores: u32,
+    // The source of every input, by (core, controller, input).
+    pub sources: HashMap<(u32, usize, usize), Source>,
+    // Requests sharing a channel by `{unit}_{method}`, in arbitration order.
+    pub requests: Vec<(String, Vec<Request>)>,
+    // The type of every argument of a channel.
+    pub arg_types: HashMap<String, Vec<(usize, String)>>,
+    // Inputs of the top besides clock and reset.
+    pub inputs: Vec<(String, String)>,
+    // Outputs of the top that forward an output of a unit as is: (name, unit, output, type). The
+    // outputs of a state_queue unit without an entry are the ones of its module.
+    pub exported: Vec<(String, Unit, usize, String)>,
+}
+
+fn machine_name(cfgs: &StateMachine, core: u32) -> String {
+    format!("{}_core{}", symbol(&cfgs.fsm_name), core)
+}
+
+fn entries(cfgs: &StateMachine) -> Vec<Option<u32>> {
+    match &cfgs.queue {
+        Some(queue) => (0..queue.num_entries).map(Some).collect(),
+        None => vec![None],
+    }
+}
+
+// The input of `callee` that `caller` drives through `port`: a request `Callee.method(..)` of the
+// caller is the event `method(..) from Caller` of the callee.
+fn find_receiver(callee: &StateMachine, caller: &str, port: &Port) -> Option<usize> {
+    let channel = port.channel.as_ref()?;
+    callee.inputs.iter().position(|input| {
+        input.channel.as_ref().is_some_and(|c| {
+            c.unit.as_deref() == Some(caller) && c.method == channel.method && c.arg == channel.arg
+        })
+    })
+}
+
+impl Routing {
+    // Connects the copies of `machines`:
+    //
+    //   - Calls between controllers of the design stay inside their core.
+    //   - Calls to any other unit (e.g. `MemSys.*`) share one request channel per method. Each
+    //     request carries the index of the core that made it in `{unit}_{method}_core`. Lower
+    //     cores win the arbitration, a request that loses is held until it is granted.
+    //   - Events from any other unit come back on `{unit}_{event}_valid` and are only delivered to
+    //     the core named by `{unit}_{event}_core`.
+    //
+    // Ports that can't be routed are exported per core as `core{c}_{controller}_{port}`, with the
+    // entry appended for the entries of a state_queue, which can't share a controller's input.
+    pub fn new(machines: &[StateMachine], cores: u32) -> Result<Self, Error> {
+        let tag_ty = format!("i{}", index_width(cores));
+        let mut sources = HashMap::new();
+        let mut requests: Vec<(String, Vec<Request>)> = vec![];
+        let mut inputs: Vec<(String, String)> = vec![];
+        let mut exported = vec![];
+        let find_machine = |unit: &str| machines.iter().position(|m| m.fsm_name == unit);
+
+        for core in 0..cores {
+            for (i, cfgs) in machines.iter().enumerate() {
+                if let Some(state_queue) = &cfgs.queue {
+                    let unit = Unit {
+                        core,
+                        machine: i,
+                        entry: None,
+                    };
+                    let (_, outputs) = queue::ports(cfgs, state_queue);
+                    for (j, (name, ty)) in outputs.into_iter().enumerate() {
+                        if j == queue::STATUS_OUTPUTS {
+                            break;
+                        }
+                        let name = format!("core{}_{}_{}", core, symbol(&cfgs.fsm_name), name);
+                        exported.push((name, unit, j, ty));
+                    }
+                }
+
+                for entry in entries(cfgs) {
+                    let unit = Unit {
+                        core,
+                        machine: i,
+                        entry,
+                    };
+                    let exported_name = |port: &Port| match entry {
+                        Some(entry) => {
+                            format!(
+                                "core{}_{}_{}_{}",
+                                core,
+                                symbol(&cfgs.fsm_name),
+                                port.name,
+                                entry
+                            )
+                        }
+                        None => format!("core{}_{}_{}", core, symbol(&cfgs.fsm_name), port.name),
+                    };
+
+                    for (j, port) in cfgs.outputs.iter().enumerate() {
+                        let channel = port.channel.as_ref();
+                        let callee = channel.and_then(|c| c.unit.as_deref());
+
+                        if let Some(callee) = callee.and_then(find_machine) {
+                            match find_receiver(&machines[callee], &cfgs.fsm_name, port) {
+                                Some(k) if entry.is_none() => {
+                                    sources.insert((core, callee, k), Source::Output(i, j));
+                                }
+                                _ => exported.push((exported_name(port), unit, j, port.ty.clone())),
+                            }
+                            continue;
+                        }
+
+                        let (Some(callee), Some(channel)) = (callee, channel) else {
+                            exported.push((exported_name(port), unit, j, port.ty.clone()));
+                            continue;
+                        };
+                        let prefix = cfg::port_prefix(callee, &channel.method);
+                        let index = match requests.iter().position(|(p, _)| *p == prefix) {
+                            Some(index) => index,
+                            None => {
+                                requests.push((prefix, vec![]));
+                                requests.len() - 1
+                            }
+                        };
+                        let group = &mut requests[index].1;
+                        // A unit makes a single request per method, so every valid opens a new one.
+                        match channel.arg {
+                            None => group.push(Request {
+                                unit,
+                                valid: j,
+                                args: vec![],
+                                responses: cfgs
+                                    .inputs
+                                    .iter()
+                                    .filter_map(|input| input.channel.as_ref())
+                                    .filter(|c| {
+                                        c.unit.as_deref() == Some(callee) && c.arg.is_none()
+                                    })
+                                    .map(|c| cfg::port_prefix(callee, &c.method))
+                                    .collect(),
+                            }),
+                            Some(k) => group
+                                .last_mut()
+                                .expect("Arguments follow their valid bit.")
+                                .args
+                                .push((k, j)),
+                        }
+                    }
+                }
+            }
+        }
+
+        // Everything not driven by another controller comes from outside of the top module.
+        for core in 0..cores {
+            for (i, cfgs) in machines.iter().enumerate() {
+                if let Some(state_queue) = &cfgs.queue {
+                    // `alloc`, `alloc_key`, `free` and `search_key` come first.
+                    let (controls, _) = queue::ports(cfgs, state_queue);
+                    for (name, ty) in controls.into_iter().take(queue::CONTROL_INPUTS) {
+                        inputs.push((
+                            format!("core{}_{}_{}", core, symbol(&cfgs.fsm_name), name),
+                            ty,
+                        ));
+                    }
+                }
+
+                for (k, port) in cfgs.inputs.iter().enumerate() {
+                    if sources.contains_key(&(core, i, k)) {
+                        continue;
+                    }
+                    if cfgs.queue.as_ref().is_some_and(|q| queue::is_head(q, port)) {
+                        sources.insert((core, i, k), Source::Head);
+                        continue;
+                    }
+                    let channel = port.channel.as_ref();
+                    let unit = channel.and_then(|c| c.unit.as_deref());
+                    let source = match (unit, channel) {
+                        (Some(unit), Some(channel)) if find_machine(unit).is_none() => {
+                            let prefix = cfg::port_prefix(unit, &channel.method);
+                            let valid = format!("{}_valid", prefix);
+                            if !inputs.iter().any(|(name, _)| *name == valid) {
+                                inputs.push((valid, String::from("i1")));
+                                inputs.push((format!("{}_core", prefix), tag_ty.clone()));
+                            }
+                            // Arguments are broadcast, only the valid bit is demultiplexed.
+                            if let Some(arg) = channel.arg {
+                                let name = format!("{}_arg{}", prefix, arg);
+                                if !inputs.iter().any(|(n, _)| *n == name) {
+                                    inputs.push((name, port.ty.clone()));
+                                }
+                            }
+                            Source::Event(prefix, channel.arg)
+                        }
+                        _ => {
+                            let name =
+                                format!("core{}_{}_{}", core, symbol(&cfgs.fsm_name), port.name);
+                            inputs.push((name.clone(), port.ty.clone()));
+                            Source::Port(name)
+                        }
+                    };
+                    sources.insert((core, i, k), source);
+                }
+            }
+        }
+
+        let mut arg_types = HashMap::new();
+        for (prefix, group) in &requests {
+            let mut types: Vec<(usize, String)> = vec![];
+            for request in group {
+                let cfgs = &machines[request.unit.machine];
+                for (k, j) in &request.args {
+                    let ty = &cfgs.outputs[*j].ty;
+                    match types.iter().find(|(a, _)| a == k) {
+                        Some((_, other)) if other != ty => {
+                            return design!(
+                                "{}_arg{} is passed as both {} and {}.",
+                                prefix,
+                                k,
+                                other,
+                                ty
+                            )
+                        }
+                        Some(_) => {}
+                        None => types.push((*k, ty.clone())),
+                    }
+                }
+            }
+            types.sort();
+            arg_types.insert(prefix.clone(), types);
+        }
+
+        Ok(Routing {
+            cores,
+            sources,
+            requests,
+            arg_types,
+            inputs,
+            exported,
+        })
+    }
+}
+
+// The generated machines and top module, with the ports of the top so harnesses can wire it up.
+pub struct MultiCore {
+    pub text: String,
+    pub inputs: Vec<(String, String)>,
+    pub outputs: Vec<(String, String)>,
+}
+
+fn queue_module_name(cfgs: &StateMachine, core: u32) -> String {
+    format!("{}_queue_core{}", symbol(&cfgs.fsm_name), core)
+}
+
+fn instance_name(cfgs: &StateMachine, core: u32) -> String {
+    match cfgs.queue {
+        Some(_) => format!("{}_inst", queue_module_name(cfgs, core)),
+        None => format!("{}_inst", machine_name(cfgs, core)),
+    }
+}
+
+// The SSA value of output `j` of a unit, see `Routing::exported` for state_queues.
+fn output_value(machines: &[StateMachine], unit: Unit, j: usize) -> String {
+    let cfgs = &machines[unit.machine];
+    let instance = instance_name(cfgs, unit.core);
+    match &cfgs.queue {
+        Some(state_queue) => {
+            let len = queue::STATUS_OUTPUTS + state_queue.num_entries as usize * cfgs.outputs.len();
+            let index = match unit.entry {
+                Some(entry) => queue::STATUS_OUTPUTS + entry as usize * cfgs.outputs.len() + j,
+                None => j,
+            };
+            instance_result(&instance, index, len)
+        }
+        None => instance_result(&instance, j, cfgs.outputs.len()),
+    }
+}
+
+// Replicates every controller for `cores` cores and connects them in `hw.module @top` as
+// `Routing` describes. A state_queue gets its queue module per core, its `alloc`, `free` and keys
+// come from the top and its status goes out of it.
+//
+// A unit's request is made once, in the first cycle its valid bit is set or as soon as it wins the
+// arbitration, and not again until the valid bit drops or an event of the called unit reaches the
+// core. Machine outputs hold while the machine stays in a state, so this keeps a core waiting for
+// the response from requesting again, and lets a state that loops on the response request again.
+pub fn generate_multicore(machines: &[StateMachine], cores: u32) -> Result<MultiCore, Error> {
+    let routing = Routing::new(machines, cores)?;
+    let tag_width = index_width(cores);
+    let tag_ty = format!("i{}", tag_width);
+    let mut b = Builder::new();
+    let mut outputs: Vec<(String, String, String)> = routing
+        .exported
+        .iter()
+        .map(|(name, unit, j, ty)| (name.clone(), output_value(machines, *unit, *j), ty.clone()))
+        .collect();
+
+    let mut operands: HashMap<(u32, usize, usize), String> = HashMap::new();
+    let mut selected: HashMap<(String, u32), String> = HashMap::new();
+    for core in 0..cores {
+        for (i, cfgs) in machines.iter().enumerate() {
+            for k in 0..cfgs.inputs.len() {
+                let value = match &routing.sources[&(core, i, k)] {
+                    Source::Output(m, j) => {
+                        let unit = Unit {
+                            core,
+                            machine: *m,
+                            entry: None,
+                        };
+                        output_value(machines, unit, *j)
+                    }
+                    Source::Event(prefix, None) => {
+                        let key = (prefix.clone(), core);
+                        if !selected.contains_key(&key) {
+                            let tag = b.constant(core as u64, tag_width);
+                            let here = b.icmp("eq", &format!("%{}_core", prefix), &tag, &tag_ty);
+                            let valid = b.binary("and", &format!("%{}_valid", prefix), &here, 1);
+                            selected.insert(key.clone(), valid);
+                        }
+                        selected[&key].clone()
+                    }
+                    Source::Event(prefix, Some(arg)) => format!("%{}_arg{}", prefix, arg),
+                    Source::Head => continue,
+                    Source::Port(name) => format!("%{}", name),
+                };
+                operands.insert((core, i, k), value);
+            }
+        }
+    }
+
+    // Fixed priority arbitration, lower cores first.
+    for (prefix, group) in &routing.requests {
+        let mut taken = b.constant(0, 1);
+        let mut grants = vec![];
+        for (i, request) in group.iter().enumerate() {
+            let valid = output_value(machines, request.unit, request.valid);
+            let pending = format!("%{}_pending_{}", prefix, i);
+            let granted = format!("%{}_granted_{}", prefix, i);
+            let not_granted = b.not(&granted);
+            let fresh = b.binary("and", &valid, &not_granted, 1);
+            let req = b.binary("or", &fresh, &pending, 1);
+            let not_taken = b.not(&taken);
+            let grant = b.binary("and", &req, &not_taken, 1);
+            taken = b.binary("or", &taken, &req, 1);
+
+            let not_grant = b.not(&grant);
+            let pending_next = b.binary("and", &req, &not_grant, 1);
+            let served = b.binary("or", &granted, &grant, 1);
+            let mut answered = b.constant(0, 1);
+            for response in &request.responses {
+                let event = &selected[&(response.clone(), request.unit.core)];
+                answered = b.binary("or", &answered, event, 1);
+            }
+            let waiting = b.not(&answered);
+            let served = b.binary("and", &served, &waiting, 1);
+            let granted_next = b.binary("and", &valid, &served, 1);
+            let false_ = b.constant(0, 1);
+            b.register(&pending[1..], &pending_next, &false_, "i1");
+            b.register(&granted[1..], &granted_next, &false_, "i1");
+
+            // A new request replaces the one still waiting.
+            let cfgs = &machines[request.unit.machine];
+            let mut args = vec![];
+            for (k, j) in &request.args {
+                let ty = &cfgs.outputs[*j].ty;
+                let value = output_value(machines, request.unit, *j);
+                let saved = format!("{}_arg{}_saved_{}", prefix, k, i);
+                let current = b.mux(&fresh, &value, &format!("%{}", saved), ty);
+                let zero = b.constant(0, utils::width(ty)?);
+                b.register(&saved, &current, &zero, ty);
+                args.push((*k, current));
+            }
+            grants.push((grant, request.unit.core, args));
+        }
+
+        let mut core = b.constant(0, tag_width);
+        for (grant, c, _) in grants.iter().rev() {
+            let tag = b.constant(*c as u64, tag_width);
+            core = b.mux(grant, &tag, &core, &tag_ty);
+        }
+        outputs.push((format!("{}_valid", prefix), taken, String::from("i1")));
+        outputs.push((format!("{}_core", prefix), core, tag_ty.clone()));
+
+        for (k, ty) in &routing.arg_types[prefix] {
+            let mut value = b.constant(0, utils::width(ty)?);
+            for (grant, _, args) in grants.iter().rev() {
+                if let Some((_, arg)) = args.iter().find(|(a, _)| a == k) {
+                    value = b.mux(grant, arg, &value, ty);
+                }
+            }
+            outputs.push((format!("{}_arg{}", prefix, k), value, ty.clone()));
+        }
+    }
+
+    let mut ret = String::new();
+    for core in 0..cores {
+        for cfgs in machines {
+            let name = machine_name(cfgs, core);
+            ret += &generate_machine(cfgs, &name)?;
+            ret += "\n";
+            if let Some(state_queue) = &cfgs.queue {
+                let module_name = queue_module_name(cfgs, core);
+                ret += &queue::generate_queue(cfgs, state_queue, &name, &module_name);
+                ret += "\n";
+            }
+        }
+    }
+
+    let mut ports = vec![
+        String::from("in %clk : !seq.clock"),
+        String::from("in %rst : i1"),
+    ];
+    for (name, ty) in &routing.inputs {
+        ports.push(format!("in %{} : {}", name, ty));
+    }
+    for (name, _, ty) in &outputs {
+        ports.push(format!("out {} : {}", name, ty));
+    }
+
+    ret += &format!("hw.module @top({}) {{\n", ports.join(", "));
+    for core in 0..cores {
+        for (i, cfgs) in machines.iter().enumerate() {
+            let instance = instance_name(cfgs, core);
+            ret += "\t";
+            match &cfgs.queue {
+                Some(state_queue) => {
+                    let (inputs, outputs) = queue::ports(cfgs, state_queue);
+                    let mut connections = vec![
+                        (
+                            String::from("clk"),
+                            String::from("%clk"),
+                            String::from("!seq.clock"),
+                        ),
+                        (
+                            String::from("rst"),
+                            String::from("%rst"),
+                            String::from("i1"),
+                        ),
+                    ];
+                    for (name, ty) in inputs {
+                        let value = match cfgs.inputs.iter().position(|p| p.name == name) {
+                            Some(k) => operands[&(core, i, k)].clone(),
+                            None => format!("%core{}_{}_{}", core, symbol(&cfgs.fsm_name), name),
+                        };
+                        connections.push((name, value, ty));
+                    }
+                    let module_name = queue_module_name(cfgs, core);
+                    ret += &module_instance(&instance, &module_name, &connections, &outputs);
+                }
+                None => {
+                    let args: Vec<String> = (0..cfgs.inputs.len())
+                        .map(|k| operands[&(core, i, k)].clone())
+                        .collect();
+                    let name = machine_name(cfgs, core);
+                    ret += &hw_instance(cfgs, &instance, &name, &args, "%rst");
+                }
+            }
+            ret += "\n";
+        }
+    }
+    for op in &b.ops {
+        ret += &format!("\t{}\n", op);
+    }
+
+    if outputs.is_empty() {
+        ret += "\thw.output\n";
+    } else {
+        let values: Vec<&str> = outputs.iter().map(|(_, v, _)| v.as_str()).collect();
+        let types: Vec<&str> = outputs.iter().map(|(_, _, t)| t.as_str()).collect();
+        ret += &format!("\thw.output {} : {}\n", values.join(", "), types.join(", "));
+    }
+    ret += "}\n";
+
+    Ok(MultiCore {
+        text: ret,
+        inputs: routing.inputs,
+        outputs: outputs
+            .into_iter()
+            .map(|(name, _, ty)| (name, ty))
+            .collect(),
+    })
+}
+
+// The arbitration state of every request, by channel.
+#[derive(Clone, PartialEq, Eq, Hash)]
+struct Arbitration {
+    pending: Vec<Vec<bool>>,
+    granted: Vec<Vec<bool>>,
+    saved: Vec<Vec<Vec<u64>>>,
+}
+
+// The registers of the top.
+#[derive(Clone, PartialEq, Eq, Hash)]
+struct Registers<'a> {
+    // By core, then controller.
+    machines: Vec<Interpreter<'a>>,
+    arbitration: Arbitration,
+}
+
+// The values of one cycle, before the clock edge ending it.
+struct Cycle {
+    inputs: Vec<HashMap<String, u64>>,
+    outputs: HashMap<String, u64>,
+    next: Arbitration,
+}
+
+// `hw.module @top` of `generate_multicore` run by the interpreter: one `Interpreter` per
+// controller and core, connected and arbitrated like the generated MLIR. Inputs and outputs are
+// the ports of the top by name, a missing input is zero.
+#[derive(Clone)]
+pub struct Simulation<'a> {
+    machines: &'a [StateMachine],
+    routing: Rc<Routing>,
+    registers: Registers<'a>,
+}
+
+impl<'a> Simulation<'a> {
+    pub fn new(machines: &'a [StateMachine], cores: u32) -> Result<Self, Error> {
+        if let Some(cfgs) = machines.iter().find(|cfgs| cfgs.queue.is_some()) {
+            return design!(
+                "The interpreter can't run the state_queue of {} yet.",
+                cfgs.fsm_name
+            );
+        }
+        let routing = Routing::new(machines, cores)?;
+        let mut interpreters = vec![];
+        for _ in 0..cores {
+            for cfgs in machines {
+                interpreters.push(Interpreter::new(cfgs)?);
+            }
+        }
+        let arbitration = Arbitration {
+            pending: routing
+                .requests
+                .iter()
+                .map(|(_, group)| vec![false; group.len()])
+                .collect(),
+            granted: routing
+                .requests
+                .iter()
+                .map(|(_, group)| vec![false; group.len()])
+                .collect(),
+            saved: routing
+                .requests
+                .iter()
+                .map(|(_, group)| group.iter().map(|r| vec![0; r.args.len()]).collect())
+                .collect(),
+        };
+        let registers = Registers {
+            machines: interpreters,
+            arbitration,
+        };
+        Ok(Simulation {
+            machines,
+            routing: Rc::new(routing),
+            registers,
+        })
+    }
+
+    fn evaluate(&self, inputs: &HashMap<String, u64>) -> Result<Cycle, Error> {
+        let input = |name: &str| inputs.get(name).copied().unwrap_or(0);
+        let n = self.machines.len();
+        let cores = self.routing.cores as usize;
+        let mut machine_inputs = vec![HashMap::new(); cores * n];
+        let mut outputs: Vec<Vec<u64>> = self
+            .machines
+            .iter()
+            .cycle()
+            .take(cores * n)
+            .map(|cfgs| vec![0; cfgs.outputs.len()])
+            .collect();
+
+        // Controllers of a core drive each other's inputs, every pass settles at least one more.
+        for _ in 0..=n {
+            let mut settled = true;
+            for (index, interpreter) in self.registers.machines.iter().enumerate() {
+                let (core, i) = (index / n, index % n);
+                let cfgs = &self.machines[i];
+                let mut values = HashMap::new();
+                for (k, port) in cfgs.inputs.iter().enumerate() {
+                    let value = match &self.routing.sources[&(core as u32, i, k)] {
+                        Source::Output(m, j) => outputs[core * n + m][*j],
+                        Source::Event(prefix, None) => {
+                            let here = input(&format!("{}_core", prefix)) == core as u64;
+                            (input(&format!("{}_valid", prefix)) == 1 && here) as u64
+                        }
+                        Source::Event(prefix, Some(arg)) => {
+                            input(&format!("{}_arg{}", prefix, arg))
+                        }
+                        Source::Head => 0,
+                        Source::Port(name) => input(name),
+                    };
+                    values.insert(port.name.clone(), value);
+                }
+                let values_out = interpreter.outputs(&values)?;
+                if values_out != outputs[index] {
+                    settled = false;
+                    outputs[index] = values_out;
+                }
+                machine_inputs[index] = values;
+            }
+            if settled {
+                break;
+            }
+        }
+
+        let output = |unit: Unit, j: usize| outputs[unit.core as usize * n + unit.machine][j];
+        let event = |prefix: &str, core: u32| {
+            input(&format!("{}_valid", prefix)) == 1
+                && input(&format!("{}_core", prefix)) == core as u64
+        };
+        let mut top: HashMap<String, u64> = self
+            .routing
+            .exported
+            .iter()
+            .map(|(name, unit, j, _)| (name.clone(), output(*unit, *j)))
+            .collect();
+        let (mut pending, mut granted, mut saved) = (vec![], vec![], vec![]);
+
+        // Fixed priority arbitration, lower cores first, like the generated top.
+        for (g, (prefix, group)) in self.routing.requests.iter().enumerate() {
+            let mut taken = false;
+            let mut winner: Option<(u32, Vec<(usize, u64)>)> = None;
+            let (mut pending_next, mut granted_next, mut saved_next) = (vec![], vec![], vec![]);
+            for (r, request) in group.iter().enumerate() {
+                let valid = output(request.unit, request.valid) == 1;
+                let fresh = valid && !self.registers.arbitration.granted[g][r];
+                let req = fresh || self.registers.arbitration.pending[g][r];
+                let grant = req && !taken;
+                taken |= req;
+
+                let answered = request
+                    .responses
+                    .iter()
+                    .any(|response| event(response, request.unit.core));
+                pending_next.push(req && !grant);
+                granted_next.push(
+                    valid && (self.registers.arbitration.granted[g][r] || grant) && !answered,
+                );
+
+                // A new request replaces the one still waiting.
+                let args: Vec<u64> = request
+                    .args
+                    .iter()
+                    .enumerate()
+                    .map(|(a, (_, j))| match fresh {
+                        true => output(request.unit, *j),
+                        false => self.registers.arbitration.saved[g][r][a],
+                    })
+                    .collect();
+                if grant {
+                    let positions = request.args.iter().map(|(k, _)| *k);
+                    winner = Some((request.unit.core, positions.zip(args.clone()).collect()));
+                }
+                saved_next.push(args);
+            }
+
+            top.insert(format!("{}_valid", prefix), taken as u64);
+            let (core, args) = winner.unwrap_or_default();
+            top.insert(format!("{}_core", prefix), core as u64);
+            for (k, _) in &self.routing.arg_types[prefix] {
+                let value = args.iter().find(|(a, _)| a == k).map_or(0, |(_, v)| *v);
+                top.insert(format!("{}_arg{}", prefix, k), value);
+            }
+            pending.push(pending_next);
+            granted.push(granted_next);
+            saved.push(saved_next);
+        }
+
+        Ok(Cycle {
+            inputs: machine_inputs,
+            outputs: top,
+            next: Arbitration {
+                pending,
+                granted,
+                saved,
+            },
+        })
+    }
+
+    // Advances one clock edge and returns the outputs of the top in the cycle before it.
+    pub fn step(&mut self, inputs: &HashMap<String, u64>) -> Result<HashMap<String, u64>, Error> {
+        let cycle = self.evaluate(inputs)?;
+        for (interpreter, inputs) in self.registers.machines.iter_mut().zip(&cycle.inputs) {
+            interpreter.step(inputs)?;
+        }
+        self.registers.arbitration = cycle.next;
+        Ok(cycle.outputs)
+    }
+}
+
+// Simulations of the same design are equal when all their registers are.
+impl PartialEq for Simulation<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        self.registers == other.registers
+    }
+}
+
+impl Eq for Simulation<'_> {}
+
+impl Hash for Simulation<'_> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.registers.hash(state);
+    }
+}
@@ -1,47 +1,829 @@
-use crate::ast::{self, ASTNode, BinVerb};
-use pest::Parser;
+use crate::{
+    ast::{self, ASTNode, BinVerb, UniVerb},
+    error::Error,
+};
+use pest::{
+    error::{InputLocation, LineColLocation},
+    iterators::{Pair, Pairs},
+    Parser,
+};
 use pest_derive::Parser;
 
 #[derive(Parser)]
 #[grammar = "aql.pest"]
 pub struct AQLParser;
 
-pub fn parse(source: &str) -> ASTNode {
+fn syntax_error(error: pest::error::Error<Rule>) -> Error {
+    let (line, column) = match error.line_col {
+        LineColLocation::Pos(pos) | LineColLocation::Span(pos, _) => pos,
+    };
+    Error::Parse {
+        line,
+        column,
+        message: error.variant.message().to_string(),
+    }
+}
+
+// The source without its `//` and `/* */` comments, newlines stay, even those inside a comment.
+fn uncommented(text: &str) -> String {
+    let mut ret = String::new();
+    let mut rest = text;
+    while let Some(start) = rest.find("//").into_iter().chain(rest.find("/*")).min() {
+        ret += &rest[..start];
+        rest = &rest[start..];
+        let end = match rest.starts_with("/*") {
+            true => rest.find("*/").map_or(rest.len(), |end| end + 2),
+            false => rest.find('\n').unwrap_or(rest.len()),
+        };
+        ret.extend(rest[..end].chars().filter(|c| *c == '\n'));
+        rest = &rest[end..];
+    }
+    ret + rest
+}
+
+// The end of the last token with a pair of its own. A rule's span also covers the whitespace and
+// comments skipped after it when it ends with something optional.
+fn token_end(pair: &Pair<Rule>) -> usize {
+    pair.clone()
+        .into_inner()
+        .flatten()
+        .filter(|inner| inner.clone().into_inner().next().is_none())
+        .map(|inner| inner.as_span().end())
+        .max()
+        .unwrap_or(pair.as_span().end())
+}
+
+// Statements of a block, and declarations, that share a line need a `;` between them unless the
+// first one ends with a block. The grammar treats newlines as whitespace, so it can't tell.
+fn separated(pairs: Pairs<Rule>) -> Result<(), (usize, Error)> {
+    let mut previous: Option<Pair<Rule>> = None;
+    for pair in pairs {
+        if pair.as_rule() == Rule::EOI {
+            continue;
+        }
+        if let Some(previous) = &previous {
+            // The closing `}`, `)` or `;` of the previous statement and whatever was skipped.
+            let between = &pair.get_input()[token_end(previous)..pair.as_span().start()];
+            let between = uncommented(between);
+            if !between.contains([';', '\n', '}']) {
+                let (line, column) = pair.line_col();
+                let error = Error::Parse {
+                    line,
+                    column,
+                    message: String::from("expected `;` or a newline before this statement"),
+                };
+                return Err((pair.as_span().start(), error));
+            }
+        }
+        previous = Some(pair);
+    }
+    Ok(())
+}
+
+// A `//` or `/* */` comment and the line it starts on.
+pub struct Comment {
+    start: usize,
+    end: usize,
+    line: usize,
+    text: String,
+}
+
+// The comments of a source that parsed, in order. Strings and import paths can hold `//` without
+// starting one.
+fn scan_comments(source: &str) -> Vec<Comment> {
+    let bytes = source.as_bytes();
+    let mut ret = vec![];
+    let mut line = 1;
+    let mut i = 0;
+    while i < bytes.len() {
+        let start = i;
+        match (bytes[i], bytes.get(i + 1)) {
+            (b'\n', _) => {
+                line += 1;
+                i += 1;
+                continue;
+            }
+            (b'\'', _) => {
+                i += 1;
+                while i < bytes.len() {
+                    match (bytes[i], bytes.get(i + 1)) {
+                        (b'\'', Some(b'\'')) => i += 2,
+                        (b'\'', _) => break,
+                        _ => i += 1,
+                    }
+                }
+            }
+            (b'"', _) => i += source[i + 1..].find('"').map_or(bytes.len(), |end| end + 1),
+            (b'/', Some(b'/')) => i += source[i..].find('\n').unwrap_or(bytes.len() - i),
+            (b'/', Some(b'*')) => {
+                i += source[i..]
+                    .find("*/")
+                    .map_or(bytes.len() - i, |end| end + 2)
+            }
+            _ => {}
+        }
+        if i == start {
+            i += 1;
+            continue;
+        }
+        let text = &source[start..i.min(bytes.len())];
+        if bytes[start] == b'/' {
+            ret.push(Comment {
+                start,
+                end: i,
+                line,
+                text: text.trim_end().to_string(),
+            });
+        } else {
+            i += 1; // the closing quote.
+        }
+        line += text.matches('\n').count();
+    }
+    ret
+}
+
+// Where an item's last token ends, a rule's span can run over the whitespace and comments after it.
+fn item_end(pair: &Pair<Rule>, comments: &[Comment]) -> usize {
+    let source = pair.get_input();
+    let mut end = pair.as_span().end();
+    loop {
+        end = source[..end].trim_end().len();
+        match comments.iter().find(|c| c.start < end && end <= c.end) {
+            Some(comment) => end = comment.start,
+            None => return end,
+        }
+    }
+}
+
+fn line_of(pair: &Pair<Rule>, pos: usize) -> usize {
+    pest::Position::new(pair.get_input(), pos).map_or(0, |pos| pos.line_col().0)
+}
+
+fn comment(comment: &Comment, trailing: bool) -> ASTNode {
+    ASTNode::Comment {
+        text: comment.text.clone(),
+        trailing,
+    }
+}
+
+fn is_doc(comment: &ASTNode) -> bool {
+    matches!(comment, ASTNode::Comment { text, .. } if text.starts_with("///") && !text.starts_with("////"))
+}
+
+fn has_doc(node: &ASTNode) -> bool {
+    match node {
+        ASTNode::Declaration(decl) => has_doc(decl),
+        ASTNode::StructureDelcaration { doc, .. }
+        | ASTNode::VariableDeclaration { doc, .. }
+        | ASTNode::InternalFuncDecl { doc, .. } => doc.is_some(),
+        _ => false,
+    }
+}
+
+// Puts the comments of a list of statements, or declarations, between them. A comment on the line a
+// statement ends on trails it, comments inside a statement but outside the blocks nested in it go
+// above it. `///` lines already taken as a declaration's documentation are left out.
+fn interleave(
+    items: Vec<(Pair<Rule>, ASTNode)>,
+    (start, end): (usize, usize),
+    comments: &[Comment],
+) -> Vec<ASTNode> {
+    let mut ret = vec![];
+    if comments.is_empty() {
+        ret.extend(items.into_iter().map(|(_, node)| node));
+        return ret;
+    }
+    let within =
+        |comment: &&Comment, from: usize, to: usize| comment.start >= from && comment.start < to;
+
+    let mut cursor = start;
+    let mut last_line = None;
+    for (pair, node) in items {
+        let span = pair.as_span();
+        let mut leading = vec![];
+        for c in comments.iter().filter(|c| within(c, cursor, span.start())) {
+            match Some(c.line) == last_line {
+                true => ret.push(comment(c, true)),
+                false => leading.push((c.line, comment(c, false))),
+            }
+        }
+        if has_doc(&node) {
+            let mut line = pair.line_col().0;
+            while leading
+                .last()
+                .is_some_and(|(l, c)| *l + 1 == line && is_doc(c))
+            {
+                leading.pop();
+                line -= 1;
+            }
+        }
+
+        let last = item_end(&pair, comments);
+        let nested: Vec<(usize, usize)> = std::iter::once(pair.clone())
+            .chain(pair.clone().into_inner().flatten())
+            .filter(|inner| matches!(inner.as_rule(), Rule::block | Rule::await_block))
+            .map(|inner| (inner.as_span().start(), inner.as_span().end()))
+            .collect();
+        for c in comments.iter().filter(|c| within(c, span.start(), last)) {
+            if !nested.iter().any(|(from, to)| within(&c, *from, *to)) {
+                leading.push((c.line, comment(c, false)));
+            }
+        }
+
+        ret.extend(leading.into_iter().map(|(_, c)| c));
+        ret.push(node);
+        cursor = last;
+        last_line = Some(line_of(&pair, last));
+    }
+    for c in comments.iter().filter(|c| within(c, cursor, end)) {
+        ret.push(comment(c, Some(c.line) == last_line));
+    }
+    ret
+}
+
+pub fn parse(source: &str) -> Result<ASTNode, Error> {
+    program(source, false)
+}
+
+// Like `parse`, with the comments kept as `ASTNode::Comment` statements for the formatter.
+pub fn parse_with_comments(source: &str) -> Result<ASTNode, Error> {
+    program(source, true)
+}
+
+// The grammar, then the separators. Errors come with their offset in the source.
+fn syntax(source: &str) -> Result<Pairs<'_, Rule>, (usize, Error)> {
+    let pairs = AQLParser::parse(Rule::program, source).map_err(|error| {
+        let offset = match error.location {
+            InputLocation::Pos(offset) | InputLocation::Span((offset, _)) => offset,
+        };
+        (offset, syntax_error(error))
+    })?;
+    separated(pairs.clone())?;
+    for block in pairs.clone().flatten() {
+        if block.as_rule() == Rule::block {
+            separated(block.into_inner())?;
+        }
+    }
+    Ok(pairs)
+}
+
+const KEYWORDS: [&str; 8] = [
+    "controller_entry",
+    "controller_control_flow",
+    "controller",
+    "state_queue",
+    "state",
+    "enum",
+    "import",
+    "include",
+];
+
+// Each byte of a source in one pass: whether it is code rather than a comment, string or path, and
+// how many blocks are open before it.
+struct Scan {
+    code: Vec<bool>,
+    depths: Vec<usize>,
+}
+
+impl Scan {
+    fn new(source: &str) -> Self {
+        let bytes = source.as_bytes();
+        let mut comments = scan_comments(source).into_iter().peekable();
+        let mut code = vec![false; bytes.len()];
+        let mut depths = vec![0; bytes.len() + 1];
+        let mut depth = 0usize;
+        let mut quote = None;
+        for (i, byte) in bytes.iter().enumerate() {
+            depths[i] = depth;
+            while comments.next_if(|c| c.end <= i).is_some() {}
+            if comments.peek().is_some_and(|c| c.start <= i) {
+                continue;
+            }
+            // Quotes count as code, so a string ends a line like a name does.
+            match (quote, byte) {
+                (Some(q), _) if q == *byte => quote = None,
+                (Some(_), _) => continue,
+                (None, b'\'' | b'"') => quote = Some(*byte),
+                (None, b'{') => depth += 1,
+                (None, b'}') => depth = depth.saturating_sub(1),
+                _ => {}
+            }
+            code[i] = true;
+        }
+        depths[bytes.len()] = depth;
+        Scan { code, depths }
+    }
+
+    fn is_token(&self, source: &str, i: usize) -> bool {
+        self.code[i] && !source.as_bytes()[i].is_ascii_whitespace()
+    }
+
+    // A newline ends a statement unless the expression goes on: the line ends with an operator,
+    // `=`, `,` or `(`, or the next one starts with an operator, `)`, `else` or `handle`.
+    fn ends_statement(&self, source: &str, newline: usize) -> bool {
+        let bytes = source.as_bytes();
+        let before = (0..newline).rev().find(|i| self.is_token(source, *i));
+        if before.is_some_and(|i| b"=+-*/&|^<>!~,.(".contains(&bytes[i])) {
+            return false;
+        }
+        let after = (newline..bytes.len())
+            .find(|i| self.is_token(source, *i))
+            .map_or("", |i| &source[i..]);
+        let word = after.split(|c: char| !(c.is_ascii_alphanumeric() || c == '_'));
+        let word = word.into_iter().next().unwrap_or("");
+        !(after.starts_with(['=', '+', '-', '*', '/', '&', '|', '^', '<', '>', '.', ')'])
+            || after.starts_with("!=")
+            || word == "else"
+            || word == "handle")
+    }
+}
+
+// The top-level declarations, each from the line its keyword starts on to the line the next one
+// does. Whatever comes before the first one counts as one too.
+fn declarations(source: &str, scan: &Scan) -> Vec<(usize, usize)> {
+    let bytes = source.as_bytes();
+    let mut starts = vec![0];
+    let mut parens = 0i32;
+    for i in 0..bytes.len() {
+        if scan.depths[i] > 0 || !scan.code[i] {
+            parens = 0;
+            continue;
+        }
+        match bytes[i] {
+            b'(' => parens += 1,
+            b')' => parens -= 1,
+            _ => {}
+        }
+        if bytes[i] != b'\n' || parens > 0 || !scan.code.get(i + 1).unwrap_or(&false) {
+            continue;
+        }
+        let line = source[i + 1..].trim_start_matches([' ', '\t']);
+        let word = line.split(|c: char| !(c.is_ascii_alphanumeric() || c == '_'));
+        if KEYWORDS.contains(&word.into_iter().next().unwrap_or("")) {
+            starts.push(i + 1);
+        }
+    }
+    let ends = starts.iter().skip(1).copied().chain([source.len()]);
+    starts.iter().copied().zip(ends).collect()
+}
+
+// The statement around an offset in a block: from where the one before it ends to its `;`, the
+// newline ending it or the `}` closing the block, outside the parentheses and blocks it opens.
+fn statement_span(source: &str, scan: &Scan, offset: usize) -> (usize, usize) {
+    let bytes = source.as_bytes();
+    let depth = scan.depths[offset];
+    let block = (0..offset)
+        .rev()
+        .find(|i| scan.depths[*i] < depth)
+        .map_or(0, |i| i + 1);
+    let mut start = block;
+    let mut parens = 0i32;
+    let mut end = block;
+    while end < bytes.len() {
+        if !scan.code[end] || scan.depths[end] != depth {
+            end += 1;
+            continue;
+        }
+        let boundary = match bytes[end] {
+            b'(' => {
+                parens += 1;
+                false
+            }
+            b')' => {
+                parens -= 1;
+                false
+            }
+            b'}' if end >= offset => break,
+            b';' => parens <= 0,
+            b'\n' => parens <= 0 && scan.ends_statement(source, end),
+            _ => false,
+        };
+        end += 1;
+        if boundary && end <= offset {
+            start = end;
+            parens = 0;
+        } else if boundary {
+            if bytes[end - 1] == b'\n' {
+                end -= 1;
+            }
+            break;
+        }
+    }
+    (start, end)
+}
+
+// Spaces over a part of the source byte by byte, keeping its newlines so what follows keeps its
+// offsets. The part starts and ends between characters.
+fn blank(source: &mut String, start: usize, end: usize) {
+    let mut bytes = std::mem::take(source).into_bytes();
+    for byte in &mut bytes[start..end] {
+        if *byte != b'\n' {
+            *byte = b' ';
+        }
+    }
+    *source = String::from_utf8(bytes).expect("whole characters are blanked");
+}
+
+// Blanks what doesn't parse in a declaration until the rest does. Returns it with the `}` it was
+// missing appended, and the errors with their offset in it.
+fn recover_declaration(source: &str) -> (String, Vec<(usize, Error)>) {
+    let mut text = source.to_string();
+    let mut errors = vec![];
+    while let Err((offset, mut error)) = syntax(&text) {
+        let scan = Scan::new(&text);
+        let depth = scan.depths[offset];
+        if depth > 0 && offset >= text.trim_end().len() {
+            if let Error::Parse { message, .. } = &mut error {
+                *message = String::from("expected `}` before the end of the file");
+            }
+            errors.push((offset, error));
+            text += "\n";
+            text += &"}".repeat(depth);
+            continue;
+        }
+        errors.push((offset, error));
+        let (start, end) = match depth {
+            0 => (0, text.len()),
+            _ => statement_span(&text, &scan, offset),
+        };
+        if text[start..end].trim().is_empty() {
+            // Nothing left to skip, give up on the whole declaration.
+            let len = text.len();
+            blank(&mut text, 0, len);
+            break;
+        }
+        blank(&mut text, start, end);
+    }
+    (text, errors)
+}
+
+// Parses what it can of a source and reports every syntax error. A statement that doesn't parse is
+// blanked out up to its `;`, the newline ending it or `}`, a declaration whose header doesn't parse
+// up to the next declaration, and blocks left open are closed at the end. Only the declaration an
+// error is in gets parsed again. Returns the source that parses in the end, lines and columns are
+// those of the original.
+pub fn recover(source: &str) -> (String, Vec<Error>) {
+    if syntax(source).is_ok() {
+        return (source.to_string(), vec![]);
+    }
+    let lines: Vec<usize> = std::iter::once(0)
+        .chain(source.match_indices('\n').map(|(i, _)| i + 1))
+        .collect();
+    let mut text = String::with_capacity(source.len());
+    let mut errors = vec![];
+    for (start, end) in declarations(source, &Scan::new(source)) {
+        let declaration = &source[start..end];
+        let (recovered, found) = recover_declaration(declaration);
+        // Blanked characters become one space each, so columns stay those of the original.
+        for (i, c) in declaration.char_indices() {
+            text.push(match recovered.as_bytes()[i] {
+                b' ' => ' ',
+                _ => c,
+            });
+        }
+        text += &recovered[declaration.len()..];
+
+        for (offset, error) in found {
+            let mut offset = (start + offset).min(source.len());
+            while !source.is_char_boundary(offset) {
+                offset -= 1;
+            }
+            let line = lines.partition_point(|start| *start <= offset);
+            let column = source[lines[line - 1]..offset].chars().count() + 1;
+            errors.push(match error {
+                Error::Parse { message, .. } => Error::Parse {
+                    line,
+                    column,
+                    message,
+                },
+                error => error,
+            });
+        }
+    }
+    errors.sort_by_key(|error| match error {
+        Error::Parse { line, column, .. } => (*line, *column),
+        _ => (0, 0),
+    });
+    errors.dedup();
+    (text, errors)
+}
+
+fn program(source: &str, keep_comments: bool) -> Result<ASTNode, Error> {
     // top-level parser
-    let pairs = AQLParser::parse(Rule::program, source).unwrap();
+    let pairs = syntax(source).map_err(|(_, error)| error)?;
+    let comments = match keep_comments {
+        true => scan_comments(source),
+        false => vec![],
+    };
+    let mut decls = vec![];
+    for pair in pairs.clone() {
+        if pair.as_rule() == Rule::declaration {
+            let decl = ASTNode::Declaration(Box::new(parse_decl(pair.clone(), &comments)));
+            decls.push((pair, decl));
+        }
+    }
+    let ends = (0, source.len());
+    Ok(ASTNode::Top(interleave(decls, ends, &comments)))
+}
+
+/// Where a structure is declared in the source.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Location {
+    /// `state`, `controller`, `controller_entry`, ...
+    pub s_type: String,
+    pub name: String,
+    /// The controller owning the structure, by the same rules as [`crate::convert`]. Empty when
+    /// none does.
+    pub controller: String,
+    /// Line and column of the name, both start at 1.
+    pub line: usize,
+    pub column: usize,
+}
+
+pub fn locations(source: &str) -> Result<Vec<Location>, Error> {
+    Ok(linked_locations(&[source])?.remove(0))
+}
+
+// Files compiled together share their controllers, a file of states can belong to the
+// controller entry of another one.
+pub fn linked_locations(sources: &[&str]) -> Result<Vec<Vec<Location>>, Error> {
+    let mut declared = vec![];
+    for source in sources {
+        declared.push(declared_structures(source)?);
+    }
+    let controllers: Vec<String> = declared
+        .iter()
+        .flatten()
+        .filter(|d| !d.location.controller.is_empty())
+        .map(|d| d.location.controller.clone())
+        .collect();
+    let default = declared
+        .iter()
+        .flatten()
+        .find(|d| d.top_entry)
+        .map(|d| d.location.name.clone());
+
+    // Like `convert`, structures without an owner join the controller sharing their name,
+    // otherwise the first top-level controller entry.
+    let resolve = |declared: Declared| {
+        let mut location = declared.location;
+        if declared.unowned {
+            if controllers.contains(&location.name) {
+                location.controller = location.name.clone();
+            } else if let Some(default) = &default {
+                location.controller = default.clone();
+            }
+        }
+        location
+    };
+    Ok(declared
+        .into_iter()
+        .map(|declared| declared.into_iter().map(resolve).collect())
+        .collect())
+}
+
+// A structure, and whether it names no owner or starts a controller of its own.
+struct Declared {
+    location: Location,
+    unowned: bool,
+    top_entry: bool,
+}
+
+fn declared_structures(source: &str) -> Result<Vec<Declared>, Error> {
+    let pairs = AQLParser::parse(Rule::program, source).map_err(syntax_error)?;
     let mut ret = vec![];
-    for pair in pairs {
+    // The `controller` block being walked and where it ends.
+    let mut block: Option<(String, usize)> = None;
+    for pair in pairs.flatten() {
+        if pair.as_rule() != Rule::structure_declaration {
+            continue;
+        }
+        let start = pair.as_span().start();
+        let end = pair.as_span().end();
+        let mut pairs = pair.into_inner();
+        let s_type = pairs.next().unwrap().as_str().to_string();
+        let ident = pairs.next().unwrap();
+        let name = ident.as_str().to_string();
+        let attribute = pairs
+            .find(|pair| pair.as_rule() == Rule::attributes)
+            .into_iter()
+            .flat_map(|attributes| attributes.into_inner())
+            .find_map(|attribute| {
+                let mut pairs = attribute.into_inner();
+                (pairs.next()?.as_str() == "controller").then_some(())?;
+                let value: String = pairs.next()?.as_str().split_whitespace().collect();
+                Some(value)
+            });
+
+        block = block.filter(|(_, block_end)| start < *block_end);
+        let (controller, unowned, top_entry) = match (&block, attribute) {
+            (Some((controller, _)), _) => (controller.clone(), false, false),
+            (None, _) if s_type == "controller" => {
+                block = Some((name.clone(), end));
+                (name.clone(), false, false)
+            }
+            (None, Some(owner)) => (owner, false, false),
+            (None, None) if s_type == "controller_entry" => (name.clone(), false, true),
+            (None, None) => (String::new(), true, false),
+        };
+
+        let (line, column) = ident.line_col();
+        ret.push(Declared {
+            location: Location {
+                s_type,
+                name,
+                controller,
+                line,
+                column,
+            },
+            unowned,
+            top_entry,
+        });
+    }
+    Ok(ret)
+}
+
+// A variable declaration and the structure it is declared in, for the language server.
+pub struct Variable {
+    pub aql_type: String,
+    pub name: String,
+    pub line: usize,
+    pub column: usize,
+    // `controller_entry`, `state`, ..., empty for a function argument or a top-level variable.
+    pub s_type: String,
+    // Where the structure starts and ends.
+    pub scope: ((usize, usize), (usize, usize)),
+}
+
+pub fn variables(source: &str) -> Result<Vec<Variable>, Error> {
+    fn walk(
+        pair: Pair<Rule>,
+        s_type: &str,
+        scope: ((usize, usize), (usize, usize)),
+        ret: &mut Vec<Variable>,
+    ) {
         match pair.as_rule() {
-            Rule::declaration => {
-                ret.push(ASTNode::Declaration(Box::new(parse_decl(pair))));
+            Rule::structure_declaration => {
+                let span = pair.as_span();
+                let scope = (span.start_pos().line_col(), span.end_pos().line_col());
+                let s_type = pair
+                    .clone()
+                    .into_inner()
+                    .next()
+                    .unwrap()
+                    .as_str()
+                    .to_string();
+                for inner in pair.into_inner() {
+                    walk(inner, &s_type, scope, ret);
+                }
+            }
+            Rule::variable_declaration => {
+                let mut idents = pair.into_inner().next().unwrap().into_inner();
+                let aql_type = idents.next().unwrap().as_str().to_string();
+                let ident = idents.next().unwrap();
+                let (line, column) = ident.line_col();
+                ret.push(Variable {
+                    aql_type,
+                    name: ident.as_str().to_string(),
+                    line,
+                    column,
+                    s_type: s_type.to_string(),
+                    scope,
+                });
+            }
+            _ => {
+                for inner in pair.into_inner() {
+                    walk(inner, s_type, scope, ret);
+                }
             }
-            _ => {}
         }
     }
-    ASTNode::Top(ret)
+
+    let pairs = AQLParser::parse(Rule::program, source).map_err(syntax_error)?;
+    let mut ret = vec![];
+    for pair in pairs {
+        walk(pair, "", ((1, 1), (1, 1)), &mut ret);
+    }
+    Ok(ret)
 }
 
-fn parse_decl(pair: pest::iterators::Pair<Rule>) -> ASTNode {
+fn parse_decl(pair: pest::iterators::Pair<Rule>, comments: &[Comment]) -> ASTNode {
     let pair = pair.into_inner().next().unwrap();
-    let ret = match pair.as_rule() {
-        Rule::structure_declaration => {
+    match pair.as_rule() {
+        Rule::enum_declaration => {
             let mut pairs = pair.into_inner();
+            let name = pairs.next().unwrap().as_str().to_string();
+            let variants = pairs.map(|variant| variant.as_str().to_string()).collect();
 
-            let s_type = pairs.next().unwrap().as_str().to_string();
-            let ident = pairs.next().unwrap();
-            let stmt = pairs.next().unwrap();
+            ASTNode::EnumDeclaration { name, variants }
+        }
+        Rule::import_declaration => {
+            let mut pairs = pair.into_inner().skip(1);
+            let path = unquote(pairs.next().unwrap());
+            let namespace = pairs.nth(1).map(|ident| ident.as_str().to_string());
 
-            let structure_declaration = ast::ASTNode::StructureDelcaration {
-                s_type,
-                name: ident.as_str().to_string(),
-                statement: Box::new(parse_state(stmt)),
-            };
-            structure_declaration
+            ASTNode::Import { path, namespace }
+        }
+        Rule::include_declaration => ASTNode::Include(unquote(pair.into_inner().nth(1).unwrap())),
+        Rule::structure_declaration => parse_structure(pair, comments),
+        Rule::internal_func_decl => {
+            let doc = doc(&pair);
+            let mut pairs = pair.into_inner();
+            let typed_identifier = Box::new(parse_typed_identifier(pairs.next().unwrap()));
+            let params = pairs
+                .next()
+                .unwrap()
+                .into_inner()
+                .map(parse_typed_identifier)
+                .collect();
+            let body = Box::new(parse_state(pairs.next().unwrap(), comments));
+
+            ASTNode::InternalFuncDecl {
+                typed_identifier,
+                params,
+                body,
+                doc,
+            }
         }
-        Rule::internal_func_decl => ast::ASTNode::None,
         _ => ast::ASTNode::None,
-    };
-    ret
+    }
+}
+
+fn unquote(path: pest::iterators::Pair<Rule>) -> String {
+    let path = path.as_str();
+    path[1..path.len() - 1].to_string()
+}
+
+// `a.b.c` as a single name, without the whitespace the grammar allows around the dots.
+fn dotted(pair: pest::iterators::Pair<Rule>) -> String {
+    let names: Vec<&str> = pair.into_inner().map(|ident| ident.as_str()).collect();
+    names.join(".")
+}
+
+// The `///` lines right above a declaration, without the slashes.
+fn doc(pair: &pest::iterators::Pair<Rule>) -> Option<String> {
+    let before = &pair.get_input()[..pair.as_span().start()];
+    let mut lines = before.rsplit('\n');
+    // Only a declaration starting its line can have one.
+    if !lines.next().unwrap_or_default().trim().is_empty() {
+        return None;
+    }
+    let mut docs = vec![];
+    for line in lines {
+        let line = line.trim();
+        match line.strip_prefix("///") {
+            Some(text) if !text.starts_with('/') => {
+                docs.push(text.strip_prefix(' ').unwrap_or(text).trim_end());
+            }
+            _ => break,
+        }
+    }
+    if docs.is_empty() {
+        return None;
+    }
+    docs.reverse();
+    Some(docs.join("\n"))
+}
+
+fn parse_structure(pair: pest::iterators::Pair<Rule>, comments: &[Comment]) -> ASTNode {
+    let doc = doc(&pair);
+    let mut pairs = pair.into_inner();
+
+    let s_type = pairs.next().unwrap().as_str().to_string();
+    let ident = pairs.next().unwrap();
+    let mut attributes = vec![];
+
+    // attributes are optional.
+    let mut stmt = pairs.next().unwrap();
+    if let Rule::attributes = stmt.as_rule() {
+        for attribute in stmt.into_inner() {
+            attributes.push(parse_attribute(attribute));
+        }
+        stmt = pairs.next().unwrap();
+    }
+
+    ast::ASTNode::StructureDelcaration {
+        s_type,
+        name: ident.as_str().to_string(),
+        attributes,
+        statement: Box::new(parse_state(stmt, comments)),
+        doc,
+    }
+}
+
+fn parse_attribute(pair: pest::iterators::Pair<Rule>) -> ASTNode {
+    let mut pairs = pair.into_inner();
+    let name = pairs.next().unwrap().as_str().to_string();
+
+    let value = pairs.next().map(|value| match value.as_rule() {
+        Rule::constval => {
+            let constval = value.into_inner().next().unwrap();
+            Box::new(ASTNode::ConstVal(constval.as_str().to_string()))
+        }
+        _ => Box::new(parse_qualified_name(value)),
+    });
+
+    ASTNode::Attribute { name, value }
 }
 
 fn parse_typed_identifier(pair: pest::iterators::Pair<Rule>) -> ASTNode {
@@ -54,17 +836,34 @@ fn parse_typed_identifier(pair: pest::iterators::Pair<Rule>) -> ASTNode {
     ASTNode::TypedIdentifier { aql_type, variable }
 }
 
-fn parse_state(pair: pest::iterators::Pair<Rule>) -> ASTNode {
+fn parse_state(pair: pest::iterators::Pair<Rule>, comments: &[Comment]) -> ASTNode {
     let mut pairs = pair.clone().into_inner();
     match pair.as_rule() {
+        Rule::structure_declaration => {
+            return parse_structure(pair, comments);
+        }
         Rule::labeled_statement => {}
         Rule::dsl_transition => {
             let action = pairs.next().unwrap().as_str().to_string();
-            let ident = Box::new(ASTNode::Ident(pairs.next().unwrap().as_str().to_string()));
+            let ident = Box::new(ASTNode::Ident(dotted(pairs.next().unwrap())));
 
             return ASTNode::Transition { action, ident };
         }
+        Rule::property => {
+            let keyword = pairs.next().unwrap().as_str();
+            let mut expr = pairs.next().unwrap();
+            let always = expr.as_rule() == Rule::always;
+            if always {
+                expr = pairs.next().unwrap();
+            }
+            let expr = Box::new(parse_expr(expr));
+            if keyword == "assert" {
+                return ASTNode::Assert { expr, always };
+            }
+            return ASTNode::Assume { expr, always };
+        }
         Rule::variable_declaration => {
+            let doc = doc(&pair);
             let typed_identifier = pairs.next().unwrap();
             let typed_identifier = Box::new(parse_typed_identifier(typed_identifier));
             if let Some(expr_raw) = pairs.next() {
@@ -72,30 +871,35 @@ fn parse_state(pair: pest::iterators::Pair<Rule>) -> ASTNode {
                 return ASTNode::VariableDeclaration {
                     typed_identifier,
                     expr,
+                    doc,
                 };
             } else {
                 return ASTNode::VariableDeclaration {
                     typed_identifier,
                     expr: None,
+                    doc,
                 };
             }
         }
         Rule::assignment => {
-            let name = pairs.next().unwrap().as_str().to_string();
+            let name = pairs.next().unwrap().as_str().trim().to_string();
 
             let expr = pairs.next().unwrap();
             let expr = Box::new(parse_expr(expr));
             return ASTNode::Assignment { name, expr };
         }
         Rule::conditional => {
-            let condition = Box::new(parse_conditon(pairs.next().unwrap()));
-            let if_blk = Box::new(parse_state(pairs.next().unwrap()));
+            let condition = pairs.next().unwrap();
+            let condition = Box::new(match condition.as_rule() {
+                Rule::unuaryop_keyword => parse_unary(condition, pairs.next().unwrap()),
+                _ => parse_conditon(condition),
+            });
+            let if_blk = Box::new(parse_state(pairs.next().unwrap(), comments));
             let mut else_blk = Box::new(ASTNode::None);
 
             // else block is optional.
-            let next_pair = pairs.next();
-            if next_pair.is_some() {
-                else_blk = Box::new(parse_state(next_pair.unwrap()));
+            if let Some(next_pair) = pairs.next() {
+                else_blk = Box::new(parse_state(next_pair, comments));
             }
 
             return ASTNode::Conditional {
@@ -108,23 +912,25 @@ fn parse_state(pair: pest::iterators::Pair<Rule>) -> ASTNode {
             let mut stmts = vec![];
 
             for pair in pairs {
-                stmts.push(parse_state(pair));
+                stmts.push((pair.clone(), parse_state(pair, comments)));
             }
 
-            return ASTNode::Block(stmts);
+            let span = (pair.as_span().start(), pair.as_span().end());
+            return ASTNode::Block(interleave(stmts, span, comments));
         }
         Rule::await_block => {
             let keyword = String::from("await");
+            let span = (pair.as_span().start(), pair.as_span().end());
             let mut call = None;
-            let mut when_block = Box::new(ASTNode::None);
+            let mut whens = vec![];
 
             for pair in pairs {
                 match pair.as_rule() {
                     Rule::call => {
-                        call = Some(Box::new(parse_dsl(pair)));
+                        call = Some(Box::new(parse_call(pair)));
                     }
                     Rule::when_block => {
-                        when_block = Box::new(parse_when(pair));
+                        whens.push((pair.clone(), parse_when(pair, comments)));
                     }
                     _ => {}
                 }
@@ -132,18 +938,22 @@ fn parse_state(pair: pest::iterators::Pair<Rule>) -> ASTNode {
             return ASTNode::Await {
                 keyword,
                 call,
-                when_block,
+                when_block: Box::new(ASTNode::Block(interleave(whens, span, comments))),
             };
         }
 
         Rule::listen_handle => {
-            let block = Box::new(parse_state(pairs.next().unwrap()));
-            let catch_block = Box::new(parse_catch(pairs.next().unwrap()));
+            let block = Box::new(parse_state(pairs.next().unwrap(), comments));
+            // A listen can have several handlers.
+            let catches = pairs.map(|pair| parse_catch(pair, comments)).collect();
+            let catch_block = Box::new(ASTNode::Block(catches));
 
             return ASTNode::Listen { block, catch_block };
         }
 
-        Rule::return_stmt => {}
+        Rule::return_stmt => {
+            return ASTNode::Return(Box::new(parse_expr(pairs.nth(1).unwrap())));
+        }
 
         Rule::expr => {
             return parse_expr(pair);
@@ -154,11 +964,11 @@ fn parse_state(pair: pest::iterators::Pair<Rule>) -> ASTNode {
     ASTNode::None
 }
 
-fn parse_when(pair: pest::iterators::Pair<Rule>) -> ASTNode {
+fn parse_when(pair: pest::iterators::Pair<Rule>, comments: &[Comment]) -> ASTNode {
     let mut pairs = pair.into_inner();
-    let call = Box::new(parse_dsl(pairs.next().unwrap()));
-    let ident = Box::new(ASTNode::Ident(pairs.next().unwrap().as_str().to_string()));
-    let block = Box::new(parse_state(pairs.next().unwrap()));
+    let call = Box::new(parse_call(pairs.next().unwrap()));
+    let ident = Box::new(ASTNode::Ident(dotted(pairs.next().unwrap())));
+    let block = Box::new(parse_state(pairs.next().unwrap(), comments));
     ASTNode::When {
         keyword: String::from("when"),
         call,
@@ -174,15 +984,30 @@ fn parse_conditon(pair: pest::iterators::Pair<Rule>) -> ASTNode {
     ASTNode::None
 }
 
+// `unuaryop` is silent, its keyword and term show up in the rule using it.
+fn parse_unary(keyword: pest::iterators::Pair<Rule>, term: pest::iterators::Pair<Rule>) -> ASTNode {
+    let verb = match keyword.as_str() {
+        "!" => UniVerb::Not,
+        "~" => UniVerb::Tiled,
+        _ => UniVerb::Minus,
+    };
+    ASTNode::UnuaryOp {
+        verb,
+        term: Box::new(parse_dsl(term)),
+    }
+}
+
 fn parse_expr(pair: pest::iterators::Pair<Rule>) -> ASTNode {
-    let pair = pair.into_inner().next().unwrap();
-    // println!("{:?}", pair.as_rule());
+    let mut pairs = pair.into_inner();
+    let pair = pairs.next().unwrap();
     match pair.as_rule() {
         Rule::dsl_term => {
             return parse_dsl(pair);
         }
 
-        Rule::unuaryop => {}
+        Rule::unuaryop_keyword => {
+            return parse_unary(pair, pairs.next().unwrap());
+        }
 
         Rule::binop => {
             return parse_binop(pair);
@@ -244,27 +1069,18 @@ fn parse_binop(pair: pest::iterators::Pair<Rule>) -> ASTNode {
 }
 
 fn parse_dsl(pair: pest::iterators::Pair<Rule>) -> ASTNode {
-    let pair = pair.into_inner().next().unwrap();
+    let mut pairs = pair.into_inner();
+    let pair = pairs.next().unwrap();
     match pair.as_rule() {
+        // `(expr)`, the tree keeps the grouping so the parentheses aren't needed.
+        Rule::expr => {
+            return parse_expr(pair);
+        }
+        Rule::unuaryop_keyword => {
+            return parse_unary(pair, pairs.next().unwrap());
+        }
         Rule::call => {
-            let mut pairs = pair.into_inner();
-            let qualified_name_raw = pairs.next().unwrap();
-            let list_raw = pairs.next().unwrap();
-
-            let qualified_name = Box::new(parse_qualified_name(qualified_name_raw));
-            let mut args_list = vec![];
-
-            for expr in list_raw.into_inner() {
-                // println!("{:?}", expr.as_rule());
-                args_list.push(parse_expr(expr));
-            }
-
-            let list = Box::new(ASTNode::ExprList(args_list));
-
-            return ASTNode::Call {
-                qualified_name,
-                list,
-            };
+            return parse_call(pair);
         }
         Rule::ident => {
             return ASTNode::Ident(pair.as_str().to_string());
@@ -281,23 +1097,44 @@ fn parse_dsl(pair: pest::iterators::Pair<Rule>) -> ASTNode {
     ASTNode::None
 }
 
-fn parse_catch(pair: pest::iterators::Pair<Rule>) -> ASTNode {
+fn parse_call(pair: pest::iterators::Pair<Rule>) -> ASTNode {
+    let mut pairs = pair.into_inner();
+    let qualified_name_raw = pairs.next().unwrap();
+    let list_raw = pairs.next().unwrap();
+
+    let qualified_name = Box::new(parse_qualified_name(qualified_name_raw));
+    let mut args_list = vec![];
+
+    for expr in list_raw.into_inner() {
+        args_list.push(parse_expr(expr));
+    }
+
+    let list = Box::new(ASTNode::ExprList(args_list));
+
+    ASTNode::Call {
+        qualified_name,
+        list,
+    }
+}
+
+fn parse_catch(pair: pest::iterators::Pair<Rule>, comments: &[Comment]) -> ASTNode {
     let mut pairs = pair.into_inner();
     let mut idents = vec![];
 
-    let keyword = pairs.next().unwrap().as_str().to_string();
+    let keyword = String::from("handle");
     let qualified_name = Box::new(parse_qualified_name(pairs.next().unwrap()));
+    let mut source = None;
     let mut block = Box::new(ASTNode::None);
 
     for pair in pairs {
         match pair.as_rule() {
             Rule::ident => idents.push(ASTNode::Ident(pair.as_str().to_string())),
-            Rule::statement => {
-                block = Box::new(parse_state(pair));
+            Rule::catch_source => {
+                let name = dotted(pair.into_inner().next().unwrap());
+                source = Some(Box::new(ASTNode::Ident(name)));
             }
             _ => {
-                // Left or right quota.
-                // Do nothing.
+                block = Box::new(parse_state(pair, comments));
             }
         }
     }
@@ -305,6 +1142,7 @@ fn parse_catch(pair: pest::iterators::Pair<Rule>) -> ASTNode {
         keyword,
         qualified_name,
         idents,
+        source,
         block,
     }
 }
@@ -0,0 +1,261 @@
+use crate::{
+    cfg::{Port, StateMachine, StateQueue},
+    mlir::{hw_instance, index_width, instance_result, Builder},
+    utils::Ordering,
+};
+
+// Width of the allocation key used by search and by Hash frees.
+const KEY_TYPE: &str = "i32";
+
+fn count_width(num_entries: u32) -> u32 {
+    // enough bits to hold `num_entries` itself.
+    32 - num_entries.leading_zeros()
+}
+
+// p == n - 1 ? 0 : p + 1
+fn wrap_increment(b: &mut Builder, ptr: &str, num_entries: u32, width: u32) -> String {
+    let one = b.constant(1, width);
+    let zero = b.constant(0, width);
+    let last = b.constant(num_entries as u64 - 1, width);
+    let inc = b.binary("add", ptr, &one, width);
+    let at_end = b.icmp("eq", ptr, &last, &format!("i{}", width));
+    b.mux(&at_end, &zero, &inc, &format!("i{}", width))
+}
+
+// Index of the first set bit, entries earlier in the list win.
+fn priority_encode(b: &mut Builder, bits: &[String], width: u32) -> String {
+    let ty = format!("i{}", width);
+    let mut ret = b.constant(0, width);
+    for (i, bit) in bits.iter().enumerate().rev() {
+        let index = b.constant(i as u64, width);
+        ret = b.mux(bit, &index, &ret, &ty);
+    }
+    ret
+}
+
+fn or_reduce(b: &mut Builder, bits: &[String]) -> String {
+    let mut ret = b.constant(0, 1);
+    for bit in bits {
+        ret = b.binary("or", &ret, bit, 1);
+    }
+    ret
+}
+
+// `head() from {queue}` is raised by the queue itself for the slot `free` would release.
+pub fn is_head(queue: &StateQueue, port: &Port) -> bool {
+    port.channel.as_ref().is_some_and(|c| {
+        c.unit.as_deref() == Some(queue.name.as_str()) && c.method == "head" && c.arg.is_none()
+    })
+}
+
+// (name, type) of every port.
+type Ports = Vec<(String, String)>;
+
+// The inputs and outputs of the queue module besides clock and reset. The other machine inputs are
+// broadcast to every entry, and every entry has its own outputs.
+pub fn ports(cfgs: &StateMachine, queue: &StateQueue) -> (Ports, Ports) {
+    let idx_ty = format!("i{}", index_width(queue.num_entries));
+    let mut inputs = vec![
+        (String::from("alloc"), String::from("i1")),
+        (String::from("alloc_key"), String::from(KEY_TYPE)),
+        (String::from("free"), String::from("i1")),
+        (String::from("search_key"), String::from(KEY_TYPE)),
+    ];
+    for port in cfgs.inputs.iter().filter(|p| !is_head(queue, p)) {
+        inputs.push((port.name.clone(), port.ty.clone()));
+    }
+
+    let mut outputs = vec![
+        (String::from("full"), String::from("i1")),
+        (String::from("empty"), String::from("i1")),
+        (String::from("alloc_idx"), idx_ty.clone()),
+        (String::from("free_idx"), idx_ty.clone()),
+        (String::from("search_hit"), String::from("i1")),
+        (String::from("search_idx"), idx_ty),
+    ];
+    for i in 0..queue.num_entries {
+        for port in &cfgs.outputs {
+            outputs.push((format!("{}_{}", port.name, i), port.ty.clone()));
+        }
+    }
+    (inputs, outputs)
+}
+
+// The control inputs ahead of the entries' own, and the status outputs ahead of theirs.
+pub const CONTROL_INPUTS: usize = 4;
+pub const STATUS_OUTPUTS: usize = 6;
+
+// Lowers a state_queue into an hw.module holding `num_entries` copies of the controller entry
+// machine. Allocating a slot resets its machine to `init_entry`; the ordering decides which slot
+// `alloc` and `free` pick:
+//
+//   FIFO:  alloc at the tail pointer, free at the head pointer.
+//   Stack: alloc on top of the stack, free pops the top.
+//   Hash:  alloc into the first free slot, free the slot whose key matches `search_key`.
+//
+// Every slot remembers the `alloc_key` it was allocated with so it can be found with
+// `search_key` regardless of the ordering.
+pub fn generate_queue(
+    cfgs: &StateMachine,
+    queue: &StateQueue,
+    machine_name: &str,
+    module_name: &str,
+) -> String {
+    let n = queue.num_entries;
+    let w = index_width(n);
+    let cw = count_width(n);
+    let idx_ty = format!("i{}", w);
+    let count_ty = format!("i{}", cw);
+    let mut b = Builder::new();
+
+    let valids: Vec<String> = (0..n).map(|i| format!("%valid_{}", i)).collect();
+    let keys: Vec<String> = (0..n).map(|i| format!("%key_{}", i)).collect();
+
+    // Search is shared by every ordering.
+    let mut matches = vec![];
+    for i in 0..n as usize {
+        let eq = b.icmp("eq", &keys[i], "%search_key", KEY_TYPE);
+        matches.push(b.binary("and", &valids[i], &eq, 1));
+    }
+    let search_hit = or_reduce(&mut b, &matches);
+    let search_idx = priority_encode(&mut b, &matches, w);
+
+    let count_n = b.constant(n as u64, cw);
+    let count_zero = b.constant(0, cw);
+    let full = b.icmp("eq", "%count", &count_n, &count_ty);
+    let empty = b.icmp("eq", "%count", &count_zero, &count_ty);
+    let not_full = b.not(&full);
+    let not_empty = b.not(&empty);
+
+    let mut alloc_fire = b.binary("and", "%alloc", &not_full, 1);
+    let free_fire;
+    let alloc_idx;
+    let free_idx;
+
+    match queue.ordering {
+        Ordering::Fifo => {
+            free_fire = b.binary("and", "%free", &not_empty, 1);
+            alloc_idx = String::from("%tail");
+            free_idx = String::from("%head");
+
+            let tail_inc = wrap_increment(&mut b, "%tail", n, w);
+            let tail_next = b.mux(&alloc_fire, &tail_inc, "%tail", &idx_ty);
+            let head_inc = wrap_increment(&mut b, "%head", n, w);
+            let head_next = b.mux(&free_fire, &head_inc, "%head", &idx_ty);
+            let zero = b.constant(0, w);
+            b.register("tail", &tail_next, &zero, &idx_ty);
+            b.register("head", &head_next, &zero, &idx_ty);
+        }
+        Ordering::Stack => {
+            // A push and a pop in the same cycle would race on the top slot, the pop wins.
+            free_fire = b.binary("and", "%free", &not_empty, 1);
+            let not_free = b.not("%free");
+            alloc_fire = b.binary("and", &alloc_fire, &not_free, 1);
+
+            let top = if cw > w {
+                let ret = b.fresh();
+                b.push(format!(
+                    "{} = comb.extract %count from 0 : ({}) -> {}",
+                    ret, count_ty, idx_ty
+                ));
+                ret
+            } else {
+                String::from("%count")
+            };
+            let one = b.constant(1, w);
+            alloc_idx = top.clone();
+            free_idx = b.binary("sub", &top, &one, w);
+        }
+        Ordering::Hash => {
+            free_fire = b.binary("and", "%free", &search_hit, 1);
+            let free_slots: Vec<String> = valids.iter().map(|v| b.not(v)).collect();
+            alloc_idx = priority_encode(&mut b, &free_slots, w);
+            free_idx = search_idx.clone();
+        }
+    }
+
+    // count + alloc - free
+    let one = b.constant(1, cw);
+    let inc = b.binary("add", "%count", &one, cw);
+    let dec = b.binary("sub", "%count", &one, cw);
+    let not_free_fire = b.not(&free_fire);
+    let not_alloc_fire = b.not(&alloc_fire);
+    let only_alloc = b.binary("and", &alloc_fire, &not_free_fire, 1);
+    let only_free = b.binary("and", &free_fire, &not_alloc_fire, 1);
+    let count_dec = b.mux(&only_free, &dec, "%count", &count_ty);
+    let count_next = b.mux(&only_alloc, &inc, &count_dec, &count_ty);
+    b.register("count", &count_next, &count_zero, &count_ty);
+
+    let mut entry_outputs = vec![];
+
+    for i in 0..n as usize {
+        let index = b.constant(i as u64, w);
+        let alloc_here = b.icmp("eq", &alloc_idx, &index, &idx_ty);
+        let free_here = b.icmp("eq", &free_idx, &index, &idx_ty);
+        let alloc_i = b.binary("and", &alloc_fire, &alloc_here, 1);
+        let free_i = b.binary("and", &free_fire, &free_here, 1);
+
+        let not_free_i = b.not(&free_i);
+        let keep = b.binary("and", &valids[i], &not_free_i, 1);
+        let valid_next = b.binary("or", &alloc_i, &keep, 1);
+        let key_next = b.mux(&alloc_i, "%alloc_key", &keys[i], KEY_TYPE);
+        let false_ = b.constant(0, 1);
+        let key_zero = b.constant(0, 32);
+        b.register(&format!("valid_{}", i), &valid_next, &false_, "i1");
+        b.register(&format!("key_{}", i), &key_next, &key_zero, KEY_TYPE);
+
+        // Allocation restarts the entry machine from its init_entry state.
+        let entry_rst = b.binary("or", "%rst", &alloc_i, 1);
+        let instance = format!("entry_{}", i);
+        let head = b.binary("and", &valids[i], &free_here, 1);
+        let operands: Vec<String> = cfgs
+            .inputs
+            .iter()
+            .map(|p| {
+                if is_head(queue, p) {
+                    head.clone()
+                } else {
+                    format!("%{}", p.name)
+                }
+            })
+            .collect();
+        b.push(hw_instance(
+            cfgs,
+            &instance,
+            machine_name,
+            &operands,
+            &entry_rst,
+        ));
+        for j in 0..cfgs.outputs.len() {
+            entry_outputs.push(instance_result(&instance, j, cfgs.outputs.len()));
+        }
+    }
+
+    let (inputs, outputs) = ports(cfgs, queue);
+    let mut ports = vec![
+        String::from("in %clk : !seq.clock"),
+        String::from("in %rst : i1"),
+    ];
+    for (name, ty) in &inputs {
+        ports.push(format!("in %{} : {}", name, ty));
+    }
+    for (name, ty) in &outputs {
+        ports.push(format!("out {} : {}", name, ty));
+    }
+    let mut values = vec![full, empty, alloc_idx, free_idx, search_hit, search_idx];
+    values.extend(entry_outputs);
+
+    let mut ret = format!(
+        "// {:?} queue with {} entries of @{}.\n",
+        queue.ordering, n, machine_name
+    );
+    ret += &format!("hw.module @{}({}) {{\n", module_name, ports.join(", "));
+    for op in &b.ops {
+        ret += &format!("\t{}\n", op);
+    }
+
+    let types: Vec<&str> = outputs.iter().map(|(_, ty)| ty.as_str()).collect();
+    ret += &format!("\thw.output {} : {}\n", values.join(", "), types.join(", "));
+    ret += "}\n";
+    ret
+}
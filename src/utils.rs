@@ -1,16 +1,64 @@
 use std::collections::HashMap;
 
-#[derive(Debug, Clone)]
+use crate::{error::design, Error};
+
+// The width of an MLIR integer type, every type the passes produce is an iN.
+pub fn width(ty: &str) -> Result<u32, Error> {
+    match ty.trim().strip_prefix('i').map(str::parse) {
+        Some(Ok(width)) if width > 0 => Ok(width),
+        _ => design!("{} is not an integer type.", ty.trim()),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub enum AQLType {
     Base(String),
-    Ordering,
+    // Named constants encoded by their position, `element_ordering` is one of them.
+    Enum { name: String, variants: Vec<String> },
+}
+
+impl AQLType {
+    // The MLIR integer type holding a value of this type.
+    pub fn mlir_type(&self) -> String {
+        match self {
+            AQLType::Base(t) => t.clone(),
+            AQLType::Enum { variants, .. } => {
+                // ceil(log2(n)), but at least one bit.
+                let mut width = 1;
+                while (1usize << width) < variants.len() {
+                    width += 1;
+                }
+                format!("i{}", width)
+            }
+        }
+    }
+
+    // The value encoding the named constant, None if it isn't part of the enum.
+    pub fn encode(&self, value: &str) -> Option<usize> {
+        match self {
+            AQLType::Base(_) => None,
+            AQLType::Enum { variants, .. } => variants.iter().position(|v| v == value.trim()),
+        }
+    }
 }
 
-#[derive(Clone)]
+// How the entries of a state_queue are allocated and freed.
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum Ordering {
-    FIFO(String),
-    HASH(String),
-    STACK(String),
+    Fifo,
+    Hash,
+    Stack,
+}
+
+impl Ordering {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.trim() {
+            "FIFO" => Some(Ordering::Fifo),
+            "Hash" => Some(Ordering::Hash),
+            "Stack" => Some(Ordering::Stack),
+            _ => None,
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -26,13 +74,58 @@ impl ConversionTable {
         tbs.insert(String::from("int"), AQLType::Base(String::from("i32")));
         tbs.insert(String::from("i32"), AQLType::Base(String::from("i32")));
         tbs.insert(String::from("i64"), AQLType::Base(String::from("i64")));
-        tbs.insert(String::from("element_ordering"), AQLType::Ordering);
+        tbs.insert(
+            String::from("element_ordering"),
+            AQLType::Enum {
+                name: String::from("element_ordering"),
+                variants: vec![
+                    String::from("FIFO"),
+                    String::from("Hash"),
+                    String::from("Stack"),
+                ],
+            },
+        );
 
         ConversionTable { tbs }
     }
 
-    pub fn convert(&self, t: &String) -> AQLType {
-        let key = t.trim();
-        self.tbs[key].clone()
+    pub fn convert(&self, t: &str) -> Result<AQLType, Error> {
+        match self.get(t) {
+            Some(aql_type) => Ok(aql_type),
+            None => design!("Unknown type: {}", t.trim()),
+        }
+    }
+
+    pub fn get(&self, t: &str) -> Option<AQLType> {
+        self.tbs.get(t.trim()).cloned()
+    }
+
+    // enum mem_op_kind { Load, Store }
+    pub fn insert_enum(&mut self, name: &str, variants: Vec<String>) -> Result<(), Error> {
+        if self.tbs.contains_key(name) {
+            return design!("Type {} is declared twice.", name);
+        }
+        for variant in &variants {
+            if let Some((other, _)) = self.constant(variant) {
+                return design!("{} is already a value of {}.", variant, other);
+            }
+        }
+
+        let aql_type = AQLType::Enum {
+            name: String::from(name),
+            variants,
+        };
+        self.tbs.insert(String::from(name), aql_type);
+        Ok(())
+    }
+
+    // Look up a named constant among all enum types, returns the enum and the encoded value.
+    pub fn constant(&self, value: &str) -> Option<(String, usize)> {
+        for (name, aql_type) in &self.tbs {
+            if let Some(encoded) = aql_type.encode(value) {
+                return Some((name.clone(), encoded));
+            }
+        }
+        None
     }
 }
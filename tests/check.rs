@@ -0,0 +1,156 @@
+// Runs the reachability check on tests/check/design.aql, which has no findings, and on variants of
+// it that each break one thing.
+
+mod common;
+
+use aql_circt::{CheckOptions, ConvertOptions};
+use common::read;
+
+// The (state, message) of each finding, the number of configurations and whether it hit the bound.
+fn check(source: &str, bound: usize) -> (Vec<(String, String)>, usize, bool) {
+    let ast = aql_circt::parse(source).unwrap();
+    let machines = aql_circt::convert(ast, &ConvertOptions::default()).unwrap();
+    let mut options = CheckOptions::default();
+    options.bound = bound;
+    let mut results = aql_circt::check(&machines, &options).unwrap();
+    assert_eq!(results.len(), 1);
+    let result = results.remove(0);
+    let findings = result
+        .findings
+        .into_iter()
+        .map(|f| (f.state, f.message))
+        .collect();
+    (findings, result.configurations, result.bounded)
+}
+
+fn findings(source: &str) -> Vec<(String, String)> {
+    check(source, CheckOptions::default().bound).0
+}
+
+fn finding(state: &str, message: &str) -> (String, String) {
+    (String::from(state), String::from(message))
+}
+
+// Idle and Counting alternate until count is 3, then Done.
+#[test]
+fn clean_design_has_no_findings() {
+    assert_eq!(check(&read("check/design.aql"), 10000), (vec![], 7, false));
+}
+
+#[test]
+fn unreachable_states_are_reported() {
+    let source =
+        read("check/design.aql") + "\nstate Orphan (controller: LQ) {\n    transition Idle\n}\n";
+    assert_eq!(
+        findings(&source),
+        [finding(
+            "Orphan",
+            "state Orphan is unreachable from init_entry Idle"
+        )]
+    );
+}
+
+#[test]
+fn stuck_states_need_complete() {
+    let source = read("check/design.aql").replace("(controller: LQ, complete)", "(controller: LQ)");
+    assert_eq!(
+        findings(&source),
+        [finding(
+            "Done",
+            "state Done has no outgoing transitions, tag it `complete` if the entry is done there"
+        )]
+    );
+}
+
+#[test]
+fn missing_targets_are_reported() {
+    let source = read("check/design.aql").replace("transition Done", "transition Finished");
+    assert_eq!(
+        findings(&source),
+        [
+            finding(
+                "Counting",
+                "state Counting transitions to Finished, which is not a state of LQ"
+            ),
+            finding("Done", "state Done is unreachable from init_entry Idle"),
+        ]
+    );
+}
+
+#[test]
+fn missing_init_entry_stops_the_exploration() {
+    let source = read("check/design.aql").replace("init_entry = Idle", "init_entry = Start");
+    let (findings, configurations, bounded) = check(&source, 10000);
+    assert_eq!(
+        findings.last(),
+        Some(&finding("Start", "init_entry Start is not a state of LQ"))
+    );
+    assert_eq!(findings.len(), 4);
+    assert_eq!((configurations, bounded), (0, false));
+}
+
+// With count == 2 neither guard of Counting holds, whatever the inputs.
+#[test]
+fn deadlocks_print_the_variables() {
+    let source = read("check/design.aql").replace("count < 3", "count < 2");
+    assert_eq!(
+        check(&source, 10000),
+        (
+            vec![finding(
+                "Counting",
+                "state Counting can deadlock, none of its guards can hold with count=2"
+            )],
+            4,
+            false
+        )
+    );
+}
+
+// The deadlock is past the bound, so it isn't found.
+#[test]
+fn exploration_stops_at_the_bound() {
+    assert_eq!(check(&read("check/design.aql"), 3), (vec![], 3, true));
+    let source = read("check/design.aql").replace("count < 3", "count < 2");
+    assert_eq!(check(&source, 3), (vec![], 3, true));
+}
+
+// States are owned like `convert` owns them: by their controller block, their attribute, their
+// name, or else the first top-level controller entry, which can be in another file.
+#[test]
+fn locations_know_their_controller() {
+    let controllers = "controller LQ {\n\
+                       controller_entry LQ { init_entry = Idle }\n\
+                       state Idle { transition Idle }\n\
+                       }\n\
+                       controller_entry SB { init_entry = Idle }\n\
+                       state Idle (controller: SB) { transition Idle }\n\
+                       state_queue LQ { element_ordering order = FIFO }\n";
+    let states = "state Busy { transition Busy }\n";
+    let located: Vec<Vec<(String, String, usize)>> =
+        aql_circt::linked_locations(&[controllers, states])
+            .unwrap()
+            .into_iter()
+            .map(|locations| {
+                locations
+                    .into_iter()
+                    .map(|l| (l.controller, l.name, l.line))
+                    .collect()
+            })
+            .collect();
+    let location =
+        |controller: &str, name: &str, line| (String::from(controller), String::from(name), line);
+    assert_eq!(
+        located,
+        [
+            vec![
+                location("LQ", "LQ", 1),
+                location("LQ", "LQ", 2),
+                location("LQ", "Idle", 3),
+                location("SB", "SB", 5),
+                location("SB", "Idle", 6),
+                location("LQ", "LQ", 7),
+            ],
+            vec![location("SB", "Busy", 1)],
+        ]
+    );
+}
@@ -0,0 +1,144 @@
+// Runs the binary on tests/cli the way a shell would: from stdin to stdout, on a directory and on
+// several files compiled together.
+
+mod common;
+
+use std::{
+    io::Write,
+    path::PathBuf,
+    process::{Command, Stdio},
+};
+
+use aql_circt::{ConvertOptions, GenerateOptions};
+use common::read;
+
+// Exit status, stdout and stderr of the compiler run in tests/cli.
+fn run(args: &[&str], stdin: &str) -> (bool, String, String) {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_aql-circt"))
+        .args(args)
+        .current_dir(PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/cli"))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(stdin.as_bytes())
+        .unwrap();
+    let output = child.wait_with_output().unwrap();
+    let text = |bytes: Vec<u8>| String::from_utf8(bytes).unwrap();
+    (
+        output.status.success(),
+        text(output.stdout),
+        text(output.stderr),
+    )
+}
+
+// The controller and its states in one source, which the library compiles like the CLI does.
+fn joined() -> String {
+    read("cli/split/a_controller.aql") + &read("cli/split/b_states.aql")
+}
+
+fn expected() -> String {
+    let ast = aql_circt::parse(&joined()).unwrap();
+    let machines = aql_circt::convert(ast, &ConvertOptions::default()).unwrap();
+    aql_circt::generate(&machines, &GenerateOptions::default()).unwrap()
+}
+
+#[test]
+fn stdin_to_stdout() {
+    assert_eq!(
+        run(&["-i", "-", "-o", "-"], &joined()),
+        (true, expected(), String::new())
+    );
+    assert_eq!(
+        run(&["build", "-i", "-"], &joined()),
+        (true, expected(), String::new())
+    );
+
+    let (ok, formatted, _) = run(&["fmt", "-i", "-"], &read("cli/split/b_states.aql"));
+    assert!(ok);
+    assert!(formatted.starts_with("state Idle (controller: LQ) {\n    count = count + 1;\n"));
+
+    // Errors name stdin as the file.
+    let (ok, stdout, stderr) = run(
+        &["-i", "-"],
+        "state Idle (controller: LQ) { count = = 1 }\n",
+    );
+    assert!(!ok && stdout.is_empty());
+    assert_eq!(stderr, "error: <stdin>:1:39: expected expr\n");
+}
+
+// The .aql files of the directory, sorted by name, and nothing else in it.
+#[test]
+fn directories_stand_for_their_files() {
+    assert_eq!(run(&["-i", "split"], ""), (true, expected(), String::new()));
+
+    let (ok, _, stderr) = run(&["-i", "missing"], "");
+    assert!(!ok);
+    assert!(stderr.starts_with("error: missing: "), "{}", stderr);
+}
+
+// States can name a controller from another file, in any order, and errors point into their file.
+#[test]
+fn inputs_are_compiled_together() {
+    let files = ["split/b_states.aql", "split/a_controller.aql"];
+    let (ok, mlir, _) = run(&["-i", files[0], files[1]], "");
+    assert!(ok);
+    assert!(mlir.contains("fsm.machine @LQ() attributes {initialState = \"Idle\"}"));
+    assert!(mlir.contains("fsm.state @Idle") && mlir.contains("fsm.state @Busy"));
+
+    let (ok, mlir, _) = run(
+        &["-i", "split/a_controller.aql", "-", "-o", "-"],
+        &read("cli/split/b_states.aql"),
+    );
+    assert_eq!((ok, mlir), (true, expected()));
+
+    let (ok, stdout, stderr) = run(&["-i", "split", "broken.aql"], "");
+    assert!(!ok && stdout.is_empty());
+    assert_eq!(stderr, "error: broken.aql:2:13: expected expr\n");
+
+    // Alone, the states have no controller.
+    let (ok, _, stderr) = run(&["-i", files[0]], "");
+    assert!(!ok);
+    assert!(
+        stderr.starts_with("error: split/b_states.aql: "),
+        "{}",
+        stderr
+    );
+}
+
+// included.aql includes the litmus design, which is only found through -I.
+#[test]
+fn litmus_searches_the_import_paths() {
+    let args = ["litmus", "../litmus/MP.litmus", "-i", "included.aql"];
+    let (ok, _, stderr) = run(&args, "");
+    assert!(!ok);
+    assert!(
+        stderr.starts_with("error: included.aql: can't find \"in_order.aql\""),
+        "{}",
+        stderr
+    );
+
+    let (ok, report, _) = run(&[&args[..], &["-I", "../litmus"]].concat(), "");
+    assert!(ok);
+    assert!(report.starts_with("Test MP under SC\n"), "{}", report);
+}
+
+// Both controllers have an unreachable Orphan, each warning points at its own.
+#[test]
+fn findings_point_at_their_state() {
+    let (ok, report, _) = run(&["check", "--reachability", "-i", "orphans.aql"], "");
+    assert!(!ok);
+    let warnings: Vec<&str> = report.lines().filter(|l| l.contains("warning")).collect();
+    assert_eq!(
+        warnings,
+        [
+            "orphans.aql:8:11: warning: state Orphan is unreachable from init_entry Idle",
+            "orphans.aql:21:7: warning: state Orphan is unreachable from init_entry Idle",
+        ]
+    );
+}
@@ -0,0 +1,11 @@
+// Helpers shared by the integration tests. The snapshots next to the designs are the expected
+// output byte for byte, after a deliberate change to the output regenerate them with the stage the
+// test emits and review the diff.
+
+use std::{fs, path::PathBuf};
+
+// A file under tests/, e.g. `read("hw/design.aql")`.
+pub fn read(path: &str) -> String {
+    let root = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests");
+    fs::read_to_string(root.join(path)).unwrap()
+}
@@ -0,0 +1,52 @@
+// `///` documentation of tests/docs/design.aql in the generated MLIR, `--top` compared with the
+// snapshot next to it.
+
+mod common;
+
+use aql_circt::{ConvertOptions, Error, Format, GenerateOptions};
+use common::read;
+
+fn generate(format: Format, top: bool) -> Result<String, Error> {
+    let ast = aql_circt::parse(&read("docs/design.aql"))?;
+    let machines = aql_circt::convert(ast, &ConvertOptions::default())?;
+    let mut options = GenerateOptions::default();
+    options.format = format;
+    options.top = top;
+    aql_circt::generate(&machines, &options)
+}
+
+#[test]
+fn docs_match_the_snapshot() {
+    assert_eq!(
+        generate(Format::Mlir, true).unwrap(),
+        read("docs/design.mlir")
+    );
+}
+
+// The machine, its states and variables get `//` lines, which CIRCT drops.
+#[test]
+fn docs_are_comments_above_their_op() {
+    let mlir = generate(Format::Mlir, false).unwrap();
+    assert!(mlir.contains(
+        "\t// Counts the loads of the core.\n\t// Wraps at \"max\".\n\tfsm.machine @LQ("
+    ));
+    assert!(mlir.contains("\t\t// Loads seen so far.\n\t\t%count = fsm.variable \"count\""));
+    assert!(mlir.contains("\t\t// Waits for the next load.\n\t\tfsm.state @Idle "));
+}
+
+// The doc of the controller entry is also the `comment` of its hw.module, which reaches the
+// Verilog.
+#[test]
+fn modules_keep_their_doc_as_a_comment() {
+    let comment =
+        "attributes {comment = \"Counts the loads of the core.\\nWraps at \\\"max\\\".\"} {";
+    for (format, top) in [(Format::Mlir, true), (Format::HwMlir, false)] {
+        let mlir = generate(format, top).unwrap();
+        let module = mlir
+            .lines()
+            .find(|line| line.contains("hw.module @LQ("))
+            .unwrap();
+        assert!(module.ends_with(comment), "{:?}: {}", format, module);
+    }
+    assert!(!generate(Format::Mlir, false).unwrap().contains("comment ="));
+}
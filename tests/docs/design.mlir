@@ -0,0 +1,28 @@
+module {
+	// Counts the loads of the core.
+	// Wraps at "max".
+	fsm.machine @LQ_fsm(%Core_load_valid: i1, %Core_load_a: i32) attributes {initialState = "Idle"} {
+		// Loads seen so far.
+		%count = fsm.variable "count" {initValue = 0 : i32 } : i32
+		// Waits for the next load.
+		fsm.state @Idle transitions {
+			fsm.transition @Idle guard {
+				%0 = hw.constant 1 : i1
+				%1 = comb.icmp eq %Core_load_valid, %0 : i1
+				%fsm_output = comb.and %1 : i1
+				fsm.return %fsm_output
+			} action {
+				%0 = hw.constant 1 : i32
+				%1 = comb.add %count, %0 : i32
+				fsm.update %count, %1 : i32
+			}
+
+		}
+
+	}
+
+	hw.module @LQ(in %clk : !seq.clock, in %rst : i1, in %Core_load_valid : i1, in %Core_load_a : i32) attributes {comment = "Counts the loads of the core.\nWraps at \"max\"."} {
+		fsm.hw_instance "LQ_inst" @LQ_fsm(%Core_load_valid, %Core_load_a), clock %clk, reset %rst : (i1, i32) -> ()
+		hw.output
+	}
+}
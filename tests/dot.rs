@@ -0,0 +1,33 @@
+// The DOT exports of tests/dot/design.aql, compared with the snapshots next to it.
+
+mod common;
+
+use aql_circt::{ConvertOptions, Format, GenerateOptions};
+use common::read;
+
+fn emit(format: Format) -> String {
+    let ast = aql_circt::parse(&read("dot/design.aql")).unwrap();
+    let machines = aql_circt::convert(ast, &ConvertOptions::default()).unwrap();
+    let mut options = GenerateOptions::default();
+    options.format = format;
+    aql_circt::generate(&machines, &options).unwrap()
+}
+
+#[test]
+fn states_match_the_snapshot() {
+    assert_eq!(emit(Format::Dot), read("dot/states.dot"));
+}
+
+#[test]
+fn control_flows_match_the_snapshot() {
+    assert_eq!(emit(Format::DotCfg), read("dot/control_flows.dot"));
+}
+
+// Labels can hold any statement, so every node has a source, and it reads back as the same tree.
+#[test]
+fn every_node_has_a_source() {
+    let source = read("dot/design.aql");
+    let ast = aql_circt::parse(&source).unwrap();
+    let one_line = ast.to_source().unwrap();
+    assert_eq!(aql_circt::parse(&one_line).unwrap(), ast, "{}", one_line);
+}
@@ -0,0 +1,78 @@
+// Enums are encoded by position in the smallest integer type that holds them, the encoding is the
+// same in every controller of the design.
+
+use aql_circt::{ConvertOptions, Error, GenerateOptions, StateMachine};
+
+fn convert(enums: &str, kind: &str) -> Result<Vec<StateMachine>, Error> {
+    let source = format!(
+        "{}\n\
+         controller_entry LQ {{\n\
+         init_entry = Idle\n\
+         mem_op kind = {}\n\
+         }}\n\
+         state Idle (controller: LQ) {{ transition Idle }}\n",
+        enums, kind
+    );
+    aql_circt::convert(aql_circt::parse(&source)?, &ConvertOptions::default())
+}
+
+#[test]
+fn variants_are_encoded_by_position() {
+    let machines = convert(
+        "enum mem_op { Load, Store, Fence }\nenum one { Only }\nenum five { A, B, C, D, E }",
+        "Store",
+    )
+    .unwrap();
+    let types = &machines[0].types;
+    let width = |name: &str| types.convert(name).unwrap().mlir_type();
+    assert_eq!(width("mem_op"), "i2");
+    assert_eq!(width("one"), "i1");
+    assert_eq!(width("five"), "i3");
+    assert_eq!(width("element_ordering"), "i2");
+
+    assert_eq!(types.constant("Load"), Some((String::from("mem_op"), 0)));
+    assert_eq!(types.constant("Fence"), Some((String::from("mem_op"), 2)));
+    assert_eq!(types.constant("E"), Some((String::from("five"), 4)));
+    assert_eq!(
+        types.constant("Stack"),
+        Some((String::from("element_ordering"), 2))
+    );
+    assert_eq!(types.constant("Flush"), None);
+
+    let mlir = aql_circt::generate(&machines, &GenerateOptions::default()).unwrap();
+    assert!(mlir.contains("%kind = fsm.variable \"kind\" {initValue = 1 : i2 } : i2"));
+}
+
+#[test]
+fn variants_must_be_declared_once() {
+    let mut types = convert("enum mem_op { Load, Store }", "Load").unwrap()[0]
+        .types
+        .clone();
+    assert_eq!(
+        types.insert_enum("mem_op", vec![String::from("Flush")]),
+        Err(Error::Design(String::from(
+            "Type mem_op is declared twice."
+        )))
+    );
+    assert_eq!(
+        types.insert_enum("kind", vec![String::from("Store")]),
+        Err(Error::Design(String::from(
+            "Store is already a value of mem_op."
+        )))
+    );
+    types
+        .insert_enum("kind", vec![String::from("Flush")])
+        .unwrap();
+    assert_eq!(types.constant("Flush"), Some((String::from("kind"), 0)));
+}
+
+#[test]
+fn unknown_variants_are_rejected() {
+    assert_eq!(
+        convert("enum mem_op { Load, Store, Fence }", "Flush")
+            .and_then(|machines| aql_circt::generate(&machines, &GenerateOptions::default())),
+        Err(Error::Design(String::from(
+            "Flush is not a value of mem_op, expected one of Load, Store, Fence."
+        )))
+    );
+}
@@ -0,0 +1,157 @@
+// Designs that can't be compiled come back as errors from every pass, nothing panics.
+
+use aql_circt::{CheckOptions, ConvertOptions, Error, Format, GenerateOptions};
+
+const CONTROLLER: &str = "controller_entry LQ {\n\
+                          init_entry = Idle\n\
+                          i32 count = 0\n\
+                          }\n";
+
+fn design_error(source: &str, format: Format) -> String {
+    let source = format!("{}{}", CONTROLLER, source);
+    let ret = aql_circt::parse(&source)
+        .and_then(|ast| aql_circt::convert(ast, &ConvertOptions::default()))
+        .and_then(|machines| {
+            aql_circt::check(&machines, &CheckOptions::default())?;
+            let mut options = GenerateOptions::default();
+            options.format = format;
+            aql_circt::generate(&machines, &options)
+        });
+    match ret {
+        Err(Error::Design(message)) => message,
+        ret => panic!("expected a design error, got {:?}", ret),
+    }
+}
+
+#[test]
+fn passes_return_errors() {
+    let cases = [
+        (
+            "state Idle (controller: LQ) { u7 x = 0\n transition Idle }\n",
+            "Unknown type: u7",
+        ),
+        (
+            "state Idle (controller: LQ) { count = missing\n transition Idle }\n",
+            "missing is not declared.",
+        ),
+    ];
+    for (source, expected) in cases {
+        for format in [Format::Mlir, Format::Btor2, Format::Smt2, Format::HwMlir] {
+            let message = design_error(source, format);
+            assert!(message.contains(expected), "{:?}: {}", format, message);
+        }
+    }
+
+    // The fsm dialect leaves a transition to a missing state to the verifier, `check` reports it.
+    let missing = "state Idle (controller: LQ) { transition Busy }\n";
+    for format in [Format::Btor2, Format::Smt2, Format::HwMlir] {
+        let message = design_error(missing, format);
+        assert_eq!(message, "Busy is not a state of LQ.", "{:?}", format);
+    }
+}
+
+// The else branch takes the negated condition, only a comparison has one.
+#[test]
+fn else_needs_a_comparison() {
+    let source = "state Idle (controller: LQ) {\n\
+                  if (count & 1) { transition Idle } else { transition Busy }\n\
+                  }\n\
+                  state Busy (controller: LQ) { transition Idle }\n";
+    let formats = [
+        Format::Mlir,
+        Format::HwMlir,
+        Format::Cfg,
+        Format::Json,
+        Format::Dot,
+        Format::DotCfg,
+    ];
+    for format in formats {
+        assert_eq!(
+            design_error(source, format),
+            "if (count & 1) has an else branch, its condition should be a comparison.",
+            "{:?}",
+            format
+        );
+    }
+}
+
+#[test]
+fn misplaced_statements_name_their_structure() {
+    let cases = [
+        (
+            "controller LQ { count = 1 }\n",
+            "controller LQ: count = 1 can't go in a controller, only structures and `assert always` \
+             can.",
+        ),
+        (
+            "state Idle (controller: LQ) { count; transition Idle }\n",
+            "state Idle: count can't go in a state.",
+        ),
+        (
+            "state Idle (controller: LQ) { if (count > 0) { assert(count > 0) }\n transition Idle }\n",
+            "state Idle: assert and assume only go at the top level of a state.",
+        ),
+    ];
+    for (source, expected) in cases {
+        assert_eq!(design_error(source, Format::Mlir), expected);
+    }
+
+    let entry = "controller_entry LQ { init_entry = Idle\n if (1 > 0) { init_entry = Idle } }\n";
+    let error = aql_circt::parse(entry)
+        .and_then(|ast| aql_circt::convert(ast, &ConvertOptions::default()))
+        .err();
+    assert_eq!(
+        error,
+        Some(Error::Design(String::from(
+            "controller_entry LQ: if (1 > 0) { init_entry = Idle; } can't go in a controller_entry."
+        )))
+    );
+}
+
+#[test]
+fn stimulus_errors_are_located() {
+    let error = aql_circt::interp::Stimulus::parse("0 valid=1\n\n4 valid=x\n").err();
+    assert_eq!(
+        error,
+        Some(Error::Parse {
+            line: 3,
+            column: 3,
+            message: String::from("x is not a number."),
+        })
+    );
+}
+
+#[test]
+fn litmus_errors_are_located() {
+    let error = aql_circt::litmus::parse("LISA MP\n{ x=0; }\n P0 ;\n w[] x 1 ;\nexists (0:r0=1)\n");
+    assert_eq!(
+        error.err(),
+        Some(Error::Parse {
+            line: 5,
+            column: 1,
+            message: String::from("0:r0 is not a register or location of the test."),
+        })
+    );
+}
+
+// Ports built by hand can have any type, widths are only known for iN.
+#[test]
+fn widths_need_integer_types() {
+    let source = format!(
+        "{}state Idle (controller: LQ) {{ MemSys.issue(count)\n transition Idle }}\n",
+        CONTROLLER
+    );
+    let ast = aql_circt::parse(&source).unwrap();
+    let mut machines = aql_circt::convert(ast, &ConvertOptions::default()).unwrap();
+    machines[0].outputs[1].ty = String::from("f32");
+    let expected = Error::Design(String::from("f32 is not an integer type."));
+    for format in [Format::Btor2, Format::HwMlir] {
+        let mut options = GenerateOptions::default();
+        options.format = format;
+        let ret = aql_circt::generate(&machines, &options);
+        assert_eq!(ret.err(), Some(expected.clone()), "{:?}", format);
+    }
+    let stimulus = aql_circt::interp::Stimulus::parse("").unwrap();
+    let run = aql_circt::interp::run(&machines[0], &stimulus, 1);
+    assert_eq!(run.err(), Some(expected));
+}
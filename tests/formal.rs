@@ -0,0 +1,245 @@
+// Emits BTOR2 and SMT-LIB for tests/formal/design.aql and compares them with the snapshots next
+// to it. The BTOR2 is also run against the interpreter.
+
+mod common;
+
+use std::collections::{BTreeMap, HashMap};
+
+use aql_circt::{cfg::StateMachine, interp::Interpreter, ConvertOptions, Format, GenerateOptions};
+use common::read;
+
+fn machines() -> Vec<StateMachine> {
+    let ast = aql_circt::parse(&read("formal/design.aql")).unwrap();
+    aql_circt::convert(ast, &ConvertOptions::default()).unwrap()
+}
+
+fn emit(format: Format) -> String {
+    let mut options = GenerateOptions::default();
+    options.format = format;
+    aql_circt::generate(&machines(), &options).unwrap()
+}
+
+#[test]
+fn btor2_matches_the_snapshot() {
+    assert_eq!(emit(Format::Btor2), read("formal/design.btor2"));
+}
+
+#[test]
+fn smt2_matches_the_snapshot() {
+    assert_eq!(emit(Format::Smt2), read("formal/design.smt2"));
+}
+
+// Every term is defined before it's used and every parenthesis is closed on its own line.
+#[test]
+fn smt2_is_well_formed() {
+    let smt2 = emit(Format::Smt2);
+    let mut defined = vec![];
+    for line in smt2.lines().filter(|l| !l.starts_with(';')) {
+        assert_eq!(
+            line.matches('(').count(),
+            line.matches(')').count(),
+            "{}",
+            line
+        );
+        let tokens: Vec<&str> = line
+            .split(|c: char| c.is_whitespace() || c == '(' || c == ')')
+            .filter(|t| !t.is_empty())
+            .collect();
+        let name = match tokens[..] {
+            ["declare-fun" | "define-fun", name, ..] => Some(name),
+            _ => None,
+        };
+        for token in &tokens {
+            let term = (token.starts_with('t') && token[1..].parse::<u32>().is_ok())
+                || token.starts_with('|');
+            if term && Some(*token) != name {
+                assert!(defined.contains(token), "{} is used before {}", token, line);
+            }
+        }
+        defined.extend(name);
+    }
+    assert!(smt2.contains(":invar-property 0"));
+}
+
+// A BTOR2 node: operator, width and operands, or the name of an input or a state.
+struct Line {
+    op: String,
+    width: u32,
+    args: Vec<u64>,
+    name: String,
+}
+
+struct Btor2 {
+    lines: BTreeMap<u64, Line>,
+    init: HashMap<u64, u64>,
+    next: HashMap<u64, u64>,
+    bad: Vec<(u64, String)>,
+    constraints: Vec<u64>,
+}
+
+fn mask(value: u64, width: u32) -> u64 {
+    if width >= 64 {
+        value
+    } else {
+        value & ((1 << width) - 1)
+    }
+}
+
+impl Btor2 {
+    fn parse(text: &str) -> Self {
+        let mut sorts = HashMap::new();
+        let mut ret = Btor2 {
+            lines: BTreeMap::new(),
+            init: HashMap::new(),
+            next: HashMap::new(),
+            bad: vec![],
+            constraints: vec![],
+        };
+        for line in text.lines() {
+            let tokens: Vec<&str> = line.split_whitespace().collect();
+            let id: u64 = tokens[0].parse().unwrap();
+            let number = |i: usize| tokens[i].parse::<u64>().unwrap();
+            match tokens[1] {
+                "sort" => {
+                    sorts.insert(id, number(3) as u32);
+                }
+                "init" => {
+                    ret.init.insert(number(3), number(4));
+                }
+                "next" => {
+                    ret.next.insert(number(3), number(4));
+                }
+                "bad" => ret.bad.push((number(2), tokens[3].to_string())),
+                "constraint" => ret.constraints.push(number(2)),
+                "output" => {}
+                op => {
+                    let (args, name) = match op {
+                        "input" | "state" => (vec![], tokens[3].to_string()),
+                        _ => (
+                            tokens[3..].iter().map(|t| t.parse().unwrap()).collect(),
+                            String::new(),
+                        ),
+                    };
+                    let line = Line {
+                        op: op.to_string(),
+                        width: sorts[&number(2)],
+                        args,
+                        name,
+                    };
+                    ret.lines.insert(id, line);
+                }
+            }
+        }
+        ret
+    }
+
+    fn eval(&self, id: u64, values: &mut HashMap<u64, u64>) -> u64 {
+        if let Some(value) = values.get(&id) {
+            return *value;
+        }
+        let line = &self.lines[&id];
+        let mut arg = |i: usize| self.eval(line.args[i], values);
+        let value = match line.op.as_str() {
+            "constd" => line.args[0],
+            "not" => !arg(0),
+            "eq" => (arg(0) == arg(1)) as u64,
+            "neq" => (arg(0) != arg(1)) as u64,
+            "ult" => (arg(0) < arg(1)) as u64,
+            "ugt" => (arg(0) > arg(1)) as u64,
+            "ulte" => (arg(0) <= arg(1)) as u64,
+            "ugte" => (arg(0) >= arg(1)) as u64,
+            "and" => arg(0) & arg(1),
+            "or" => arg(0) | arg(1),
+            "xor" => arg(0) ^ arg(1),
+            "add" => arg(0).wrapping_add(arg(1)),
+            "sub" => arg(0).wrapping_sub(arg(1)),
+            "mul" => arg(0).wrapping_mul(arg(1)),
+            "udiv" => arg(0).checked_div(arg(1)).unwrap_or(u64::MAX),
+            "sll" => arg(0).checked_shl(arg(1) as u32).unwrap_or(0),
+            "srl" => arg(0).checked_shr(arg(1) as u32).unwrap_or(0),
+            "ite" => match arg(0) {
+                1 => arg(1),
+                _ => arg(2),
+            },
+            "uext" | "slice" => arg(0),
+            op => panic!("{} isn't evaluated", op),
+        };
+        let value = mask(value, line.width);
+        values.insert(id, value);
+        value
+    }
+
+    fn named(&self, op: &str) -> Vec<(u64, &str)> {
+        let lines = self.lines.iter().filter(|(_, line)| line.op == op);
+        lines.map(|(id, line)| (*id, line.name.as_str())).collect()
+    }
+}
+
+// A cheap deterministic stimulus, so the run doesn't need a random number crate.
+fn stimulus(cycle: u64) -> HashMap<String, u64> {
+    let x = cycle
+        .wrapping_mul(6364136223846793005)
+        .wrapping_add(1442695040888963407)
+        >> 33;
+    HashMap::from([
+        (String::from("Core_load_valid"), x & 1),
+        (String::from("Core_load_a"), (x >> 1) % 120),
+        (String::from("MemSys_ready_valid"), (x >> 8) & 1),
+        (String::from("MemSys_ready_data"), x >> 9),
+    ])
+}
+
+// The transition system and the interpreter agree on the variables, the failing asserts and the
+// violated assumptions.
+#[test]
+fn btor2_follows_the_interpreter() {
+    let machines = machines();
+    let btor2 = Btor2::parse(&emit(Format::Btor2));
+    let mut interpreter = Interpreter::new(&machines[0]).unwrap();
+
+    let mut state: HashMap<u64, u64> = HashMap::new();
+    for (id, _) in btor2.named("state") {
+        let init = btor2.init[&id];
+        state.insert(id, btor2.eval(init, &mut HashMap::new()));
+    }
+
+    for cycle in 0..200 {
+        let inputs = stimulus(cycle);
+        let mut values = state.clone();
+        for (id, name) in btor2.named("input") {
+            values.insert(id, inputs[name.trim_start_matches("LQ.")]);
+        }
+
+        for (name, value) in interpreter.variables() {
+            let (id, _) = btor2
+                .named("state")
+                .into_iter()
+                .find(|(_, n)| *n == format!("LQ.{}", name))
+                .unwrap();
+            assert_eq!(state[&id], value, "{} in cycle {}", name, cycle);
+        }
+        let failures = interpreter.failures(&inputs).unwrap();
+        let (assumptions, asserts): (Vec<String>, Vec<String>) = failures
+            .into_iter()
+            .partition(|name| name.contains(".assume"));
+        let bad: Vec<String> = btor2
+            .bad
+            .iter()
+            .filter(|(id, _)| btor2.eval(*id, &mut values) == 1)
+            .map(|(_, name)| name.clone())
+            .collect();
+        assert_eq!(bad, asserts, "cycle {}", cycle);
+        let violated = btor2
+            .constraints
+            .iter()
+            .filter(|id| btor2.eval(**id, &mut values) == 0)
+            .count();
+        assert_eq!(violated, assumptions.len(), "cycle {}", cycle);
+
+        state = state
+            .keys()
+            .map(|id| (*id, btor2.eval(btor2.next[id], &mut values)))
+            .collect();
+        interpreter.step(&inputs).unwrap();
+    }
+}
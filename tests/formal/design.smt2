@@ -0,0 +1,79 @@
+(set-logic QF_BV)
+(declare-fun |LQ.state| () (_ BitVec 1))
+(define-fun t1 () (_ BitVec 1) (_ bv0 1))
+(declare-fun |LQ.addr| () (_ BitVec 32))
+(define-fun t3 () (_ BitVec 32) (_ bv0 32))
+(declare-fun |LQ.sum| () (_ BitVec 32))
+(define-fun t5 () (_ BitVec 32) (_ bv3 32))
+(declare-fun |LQ.small| () (_ BitVec 64))
+(define-fun t7 () (_ BitVec 64) (_ bv0 64))
+(declare-fun |LQ.seen| () (_ BitVec 1))
+(declare-fun |LQ.Core_load_valid| () (_ BitVec 1))
+(declare-fun |LQ.Core_load_a| () (_ BitVec 32))
+(declare-fun |LQ.MemSys_ready_valid| () (_ BitVec 1))
+(declare-fun |LQ.MemSys_ready_data| () (_ BitVec 32))
+(define-fun t13 () (_ BitVec 1) (_ bv1 1))
+(define-fun t14 () (_ BitVec 1) (ite (= |LQ.state| t13) #b1 #b0))
+(define-fun t15 () (_ BitVec 1) (ite (= |LQ.MemSys_ready_valid| t13) #b1 #b0))
+(define-fun t16 () (_ BitVec 1) (bvand t13 t15))
+(define-fun t17 () (_ BitVec 32) (bvadd |LQ.sum| |LQ.MemSys_ready_data|))
+(define-fun t18 () (_ BitVec 32) (_ bv2 32))
+(define-fun t19 () (_ BitVec 32) (bvmul t17 t18))
+(define-fun t20 () (_ BitVec 32) ((_ extract 31 0) |LQ.small|))
+(define-fun t21 () (_ BitVec 1) (ite (= t20 t3) #b1 #b0))
+(define-fun t22 () (_ BitVec 32) (bvudiv |LQ.addr| t20))
+(define-fun t23 () (_ BitVec 32) (ite (= t21 #b1) t3 t22))
+(define-fun t24 () (_ BitVec 32) (bvsub t19 t23))
+(define-fun t25 () (_ BitVec 32) (_ bv255 32))
+(define-fun t26 () (_ BitVec 32) (bvand |LQ.addr| t25))
+(define-fun t27 () (_ BitVec 32) (_ bv1 32))
+(define-fun t28 () (_ BitVec 32) (bvxor |LQ.MemSys_ready_data| t27))
+(define-fun t29 () (_ BitVec 32) (bvor t26 t28))
+(define-fun t30 () (_ BitVec 64) (_ bv1 64))
+(define-fun t31 () (_ BitVec 64) (bvlshr |LQ.small| t30))
+(define-fun t32 () (_ BitVec 1) (ite (= t16 #b1) t1 |LQ.state|))
+(define-fun t33 () (_ BitVec 32) (ite (= t16 #b1) t29 |LQ.addr|))
+(define-fun t34 () (_ BitVec 32) (ite (= t16 #b1) t24 |LQ.sum|))
+(define-fun t35 () (_ BitVec 64) (ite (= t16 #b1) t31 |LQ.small|))
+(define-fun t36 () (_ BitVec 1) (ite (= t14 #b1) t32 |LQ.state|))
+(define-fun t37 () (_ BitVec 32) (ite (= t14 #b1) t33 |LQ.addr|))
+(define-fun t38 () (_ BitVec 32) (ite (= t14 #b1) t34 |LQ.sum|))
+(define-fun t39 () (_ BitVec 64) (ite (= t14 #b1) t35 |LQ.small|))
+(define-fun t40 () (_ BitVec 32) (bvshl |LQ.addr| t18))
+(define-fun t41 () (_ BitVec 1) (ite (= t14 #b1) t13 t1))
+(define-fun t42 () (_ BitVec 32) (ite (= t14 #b1) t40 t3))
+(define-fun t43 () (_ BitVec 64) (_ bv50 64))
+(define-fun t44 () (_ BitVec 1) (ite (bvult |LQ.small| t43) #b1 #b0))
+(define-fun t45 () (_ BitVec 1) (bvnot t44))
+(define-fun t46 () (_ BitVec 1) (bvand t14 t45))
+(define-fun t47 () (_ BitVec 1) (ite (= |LQ.state| t1) #b1 #b0))
+(define-fun t48 () (_ BitVec 1) (ite (= |LQ.Core_load_valid| t13) #b1 #b0))
+(define-fun t49 () (_ BitVec 1) (bvand t13 t48))
+(define-fun t50 () (_ BitVec 64) ((_ zero_extend 32) |LQ.Core_load_a|))
+(define-fun t51 () (_ BitVec 1) (ite (= t49 #b1) t13 |LQ.state|))
+(define-fun t52 () (_ BitVec 32) (ite (= t49 #b1) |LQ.Core_load_a| |LQ.addr|))
+(define-fun t53 () (_ BitVec 64) (ite (= t49 #b1) t50 |LQ.small|))
+(define-fun t54 () (_ BitVec 1) (ite (= t49 #b1) t13 |LQ.seen|))
+(define-fun t55 () (_ BitVec 1) (ite (= t47 #b1) t51 t36))
+(define-fun t56 () (_ BitVec 32) (ite (= t47 #b1) t52 t37))
+(define-fun t57 () (_ BitVec 32) (ite (= t47 #b1) |LQ.sum| t38))
+(define-fun t58 () (_ BitVec 64) (ite (= t47 #b1) t53 t39))
+(define-fun t59 () (_ BitVec 1) (ite (= t47 #b1) t54 |LQ.seen|))
+(define-fun t60 () (_ BitVec 32) (_ bv100 32))
+(define-fun t61 () (_ BitVec 1) (ite (bvult |LQ.addr| t60) #b1 #b0))
+(define-fun t62 () (_ BitVec 1) (bvnot t47))
+(define-fun t63 () (_ BitVec 1) (bvor t62 t61))
+(declare-fun |LQ.state.next| () (_ BitVec 1))
+(define-fun .sv0 () (_ BitVec 1) (! |LQ.state| :next |LQ.state.next|))
+(declare-fun |LQ.addr.next| () (_ BitVec 32))
+(define-fun .sv1 () (_ BitVec 32) (! |LQ.addr| :next |LQ.addr.next|))
+(declare-fun |LQ.sum.next| () (_ BitVec 32))
+(define-fun .sv2 () (_ BitVec 32) (! |LQ.sum| :next |LQ.sum.next|))
+(declare-fun |LQ.small.next| () (_ BitVec 64))
+(define-fun .sv3 () (_ BitVec 64) (! |LQ.small| :next |LQ.small.next|))
+(declare-fun |LQ.seen.next| () (_ BitVec 1))
+(define-fun .sv4 () (_ BitVec 1) (! |LQ.seen| :next |LQ.seen.next|))
+(define-fun .init () Bool (! (and (= |LQ.state| t1) (= |LQ.addr| t3) (= |LQ.sum| t5) (= |LQ.small| t7) (= |LQ.seen| t1)) :init true))
+(define-fun .trans () Bool (! (and (= |LQ.state.next| t55) (= |LQ.addr.next| t56) (= |LQ.sum.next| t57) (= |LQ.small.next| t58) (= |LQ.seen.next| t59) (= t63 #b1)) :trans true))
+; LQ.Busy.assert0
+(define-fun .prop0 () Bool (! (= t46 #b0) :invar-property 0))
@@ -0,0 +1,118 @@
+// `fmt` must keep a design as it is: formatting changes the layout and nothing else, and a formatted
+// file formats to itself.
+
+use std::{fs, path::PathBuf};
+
+fn sources() -> Vec<(PathBuf, String)> {
+    let root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    let mut paths = vec![root.join("example.aql"), root.join("ReadyToIssue.aql")];
+    let mut corpus: Vec<PathBuf> = fs::read_dir(root.join("tests/corpus"))
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "aql"))
+        .collect();
+    corpus.sort();
+    paths.extend(corpus);
+    paths
+        .into_iter()
+        .map(|path| {
+            let source = fs::read_to_string(&path).unwrap();
+            (path, source)
+        })
+        .collect()
+}
+
+#[test]
+fn formatting_keeps_the_tree() {
+    for (path, source) in sources() {
+        let ast = aql_circt::parse(&source).unwrap();
+        let formatted = aql_circt::format(&ast).unwrap();
+        let reparsed = aql_circt::parse(&formatted)
+            .unwrap_or_else(|error| panic!("{}: {}\n{}", path.display(), error, formatted));
+        assert_eq!(reparsed, ast, "{}", path.display());
+
+        let formatted = aql_circt::format_source(&source).unwrap();
+        let reparsed = aql_circt::parse(&formatted)
+            .unwrap_or_else(|error| panic!("{}: {}\n{}", path.display(), error, formatted));
+        assert_eq!(reparsed, ast, "{}", path.display());
+    }
+}
+
+#[test]
+fn formatting_is_idempotent() {
+    for (path, source) in sources() {
+        let formatted = aql_circt::format_source(&source).unwrap();
+        assert_eq!(
+            aql_circt::format_source(&formatted).unwrap(),
+            formatted,
+            "{}",
+            path.display()
+        );
+    }
+}
+
+#[test]
+fn comments_are_kept() {
+    let source = "// LQ\n\
+                  controller_entry LQ { // opens\n\
+                  init_entry = A // the first\n\
+                  /* a\n   block */ i32 x = 1 + /* inside */ 2\n\
+                  }\n\
+                  /// A.\n\
+                  state A (controller: LQ) { transition A }\n";
+    let expected = "// LQ\n\
+                    controller_entry LQ {\n    \
+                        // opens\n    \
+                        init_entry = A; // the first\n    \
+                        /* a\n   block */\n    \
+                        /* inside */\n    \
+                        i32 x = 1 + 2;\n\
+                    }\n\
+                    \n\
+                    /// A.\n\
+                    state A (controller: LQ) {\n    \
+                        transition A;\n\
+                    }\n";
+    assert_eq!(aql_circt::format_source(source).unwrap(), expected);
+}
+
+#[test]
+fn comment_markers_in_strings() {
+    let source =
+        "import \"a//b.aql\"\ncontroller_entry LQ { init_entry = A; string s = '/* no' }\n";
+    let formatted = aql_circt::format_source(source).unwrap();
+    assert!(!formatted.contains("\n/*"), "{}", formatted);
+    assert_eq!(
+        aql_circt::parse(&formatted).unwrap(),
+        aql_circt::parse(source).unwrap()
+    );
+}
+
+#[test]
+fn grouped_and_unary_terms_are_kept() {
+    let source = "controller_entry LQ { init_entry = A\n i32 x = 0 }\n\
+                  state A (controller: LQ) {\n\
+                  x = (x - 1) - 2\n\
+                  x = x - (1 - 2)\n\
+                  if (!(x > 1)) { x = 0 }\n\
+                  x = ~x\n\
+                  x = -(x + 1)\n\
+                  transition A\n\
+                  }\n";
+    let formatted = aql_circt::format_source(source).unwrap();
+    assert!(formatted.contains("x = (x - 1) - 2;"), "{}", formatted);
+    assert!(formatted.contains("if (!(x > 1)) {"), "{}", formatted);
+    assert!(formatted.contains("x = ~x;"), "{}", formatted);
+    assert_eq!(
+        aql_circt::parse(&formatted).unwrap(),
+        aql_circt::parse(source).unwrap()
+    );
+}
+
+// What the parser leaves out can't be written back, so fmt refuses the file instead of dropping it.
+#[test]
+fn unsupported_code_is_not_dropped() {
+    let source = "controller_entry LQ { init_entry = A\n i32 x = 0 }\n\
+                  state A (controller: LQ) { x = prev<x>\n transition A }\n";
+    assert!(aql_circt::format_source(source).is_err());
+}
@@ -0,0 +1,116 @@
+// Functions are inlined where they're called: the call becomes the returned expression with the
+// arguments in place of the parameters.
+
+use aql_circt::{ConvertOptions, Error, GenerateOptions};
+
+const CONTROLLER: &str = "controller_entry LQ {\n\
+                          init_entry = Idle\n\
+                          i32 count = 0\n\
+                          i64 wide = 0\n\
+                          }\n";
+
+fn build(source: &str) -> Result<String, Error> {
+    let source = format!("{}{}", CONTROLLER, source);
+    let ast = aql_circt::parse(&source)?;
+    let machines = aql_circt::convert(ast, &ConvertOptions::default())?;
+    aql_circt::generate(&machines, &GenerateOptions::default())
+}
+
+fn design_error(source: &str) -> String {
+    match build(source) {
+        Err(Error::Design(message)) => message,
+        ret => panic!("expected a design error, got {:?}", ret),
+    }
+}
+
+#[test]
+fn calls_are_inlined() {
+    let mlir = build(
+        "/// Adds one to the sum.\n\
+         i32 add(i32 a, i32 b) {\n\
+         i32 s = a + b\n\
+         return s + 1\n\
+         }\n\
+         i32 twice(i32 v) { return add(v, v) }\n\
+         state Idle (controller: LQ) {\n\
+         count = add(count, 2)\n\
+         transition Busy\n\
+         }\n\
+         state Busy (controller: LQ) {\n\
+         count = twice(count)\n\
+         transition Idle\n\
+         }\n",
+    )
+    .unwrap();
+    // `count + 2 + 1` and, through `twice`, `count + count + 1`: the grammar can't write either.
+    assert!(mlir.contains("%1 = comb.add %count, %0 : i32"), "{}", mlir);
+    assert!(mlir.contains("%3 = comb.add %1, %2 : i32"), "{}", mlir);
+    assert!(
+        mlir.contains("%0 = comb.add %count, %count : i32"),
+        "{}",
+        mlir
+    );
+    assert!(mlir.contains("%2 = comb.add %0, %1 : i32"), "{}", mlir);
+    assert!(!mlir.contains("add("), "{}", mlir);
+}
+
+#[test]
+fn functions_are_checked() {
+    let state = "state Idle (controller: LQ) { transition Idle }\n";
+    let cases = [
+        (
+            "i32 f(i32 a) { return a }\n\
+             state Idle (controller: LQ) { count = f(count, 1)\n transition Idle }\n",
+            "Function f takes 1 arguments, 2 given.",
+        ),
+        (
+            "i32 f(i32 a) { return a }\n\
+             state Idle (controller: LQ) { count = f(wide)\n transition Idle }\n",
+            "Function f takes a as i32, wide is i64.",
+        ),
+        (
+            &format!("i64 f(i32 a) {{ return a }}\n{}", state),
+            "Function f returns i64 but its value is i32.",
+        ),
+        (
+            &format!("i32 f(i32 a) {{ return a + count }}\n{}", state),
+            "Function f reads count, which isn't one of its parameters.",
+        ),
+        (
+            &format!(
+                "i32 f(i32 a) {{ return g(a) }}\ni32 g(i32 a) {{ return f(a) }}\n{}",
+                state
+            ),
+            "Functions are inlined so they can't call themselves: f -> g -> f.",
+        ),
+        (
+            &format!("i32 f(i32 a) {{ i32 b = a }}\n{}", state),
+            "Function f doesn't return a value.",
+        ),
+        (
+            "state Idle (controller: LQ) { return count\n transition Idle }\n",
+            "return is only allowed at the end of a function.",
+        ),
+    ];
+    for (source, expected) in cases {
+        assert_eq!(design_error(source), expected, "{}", source);
+    }
+}
+
+#[test]
+fn functions_format() {
+    let source = "/// Adds one.\ni32 inc(i32 a, i32 b) { i32 s = a + b\nreturn s + 1 }\n";
+    let formatted = aql_circt::format_source(source).unwrap();
+    assert_eq!(
+        formatted,
+        "/// Adds one.\n\
+         i32 inc(i32 a, i32 b) {\n    \
+         i32 s = a + b;\n    \
+         return s + 1;\n\
+         }\n"
+    );
+    assert_eq!(
+        aql_circt::parse(&formatted).unwrap(),
+        aql_circt::parse(source).unwrap()
+    );
+}
@@ -0,0 +1,185 @@
+// The files in tests/corpus are one design written in different layouts, they must all parse to
+// the same tree.
+
+use std::{fs, path::PathBuf};
+
+use aql_circt::{ConvertOptions, Error};
+
+fn corpus() -> Vec<(PathBuf, String)> {
+    let dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/corpus");
+    let mut paths: Vec<PathBuf> = fs::read_dir(dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "aql"))
+        .collect();
+    paths.sort();
+    paths
+        .into_iter()
+        .map(|path| {
+            let source = fs::read_to_string(&path).unwrap();
+            (path, source)
+        })
+        .collect()
+}
+
+fn canonical() -> String {
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/corpus/canonical.aql");
+    fs::read_to_string(path).unwrap()
+}
+
+#[test]
+fn layouts_parse_alike() {
+    let expected = aql_circt::parse(&canonical()).unwrap();
+    for (path, source) in corpus() {
+        let ast = aql_circt::parse(&source)
+            .unwrap_or_else(|error| panic!("{}: {}", path.display(), error));
+        assert_eq!(ast, expected, "{}", path.display());
+    }
+}
+
+#[test]
+fn crlf_line_endings() {
+    let expected = aql_circt::parse(&canonical()).unwrap();
+    for (path, source) in corpus() {
+        let source = source.replace('\n', "\r\n");
+        let ast = aql_circt::parse(&source)
+            .unwrap_or_else(|error| panic!("{}: {}", path.display(), error));
+        assert_eq!(ast, expected, "{}", path.display());
+    }
+}
+
+#[test]
+fn canonical_compiles() {
+    let ast = aql_circt::parse(&canonical()).unwrap();
+    let machines = aql_circt::convert(ast, &ConvertOptions::default()).unwrap();
+    assert_eq!(machines.len(), 1);
+}
+
+#[test]
+fn statements_on_one_line_need_a_separator() {
+    let source = "controller_entry LQ {\n    init_entry = A\n    i32 x = 0\n}\n\
+                  state A (controller: LQ) { x = 1 transition A }\n";
+    match aql_circt::parse(source) {
+        Err(Error::Parse { line, column, .. }) => assert_eq!((line, column), (5, 34)),
+        ret => panic!("expected a parse error, got {:?}", ret),
+    }
+
+    // A comment doesn't separate statements, a newline inside one does.
+    let source = "state A { x = 1 /* ; */ transition A }";
+    assert!(matches!(
+        aql_circt::parse(source),
+        Err(Error::Parse { column: 25, .. })
+    ));
+    let source = "state A { x = 1 /*\n*/ transition A }";
+    assert!(aql_circt::parse(source).is_ok());
+}
+
+#[test]
+fn declarations_on_one_line_need_a_separator() {
+    assert!(aql_circt::parse("enum A { X } enum B { Y }").is_ok());
+    assert!(aql_circt::parse("import \"a.aql\"; import \"b.aql\"").is_ok());
+    assert!(matches!(
+        aql_circt::parse("import \"a.aql\" import \"b.aql\""),
+        Err(Error::Parse {
+            line: 1,
+            column: 16,
+            ..
+        })
+    ));
+}
+
+#[test]
+fn every_syntax_error_is_reported() {
+    let source = "controller_entry LQ {\n\
+                  \x20   init_entry = Idle\n\
+                  \x20   i32 count = = 0\n\
+                  }\n\
+                  state Idle (controller: LQ) {\n\
+                  \x20   if (count > ) { count = 0 }\n\
+                  \x20   transition Busy\n\
+                  }\n\
+                  state (controller: LQ) {\n\
+                  \x20   transition Idle\n\
+                  }\n\
+                  state Busy (controller: LQ) {\n\
+                  \x20   count = 1 transition Idle\n\
+                  \x20   transition Idle\n";
+    let (ast, errors) = aql_circt::parse_recovering(source);
+    let positions: Vec<(usize, usize)> = errors
+        .iter()
+        .map(|error| match error {
+            Error::Parse { line, column, .. } => (*line, *column),
+            error => panic!("{:?}", error),
+        })
+        .collect();
+    assert_eq!(positions, [(3, 17), (6, 17), (9, 7), (13, 15), (15, 1)]);
+    assert_eq!(aql_circt::parse(source).unwrap_err(), errors[0]);
+
+    // What parsed is kept, less the statements and the declaration with errors.
+    let expected = "controller_entry LQ {\n    init_entry = Idle\n}\n\
+                    state Idle (controller: LQ) {\n    transition Busy\n}\n\
+                    state Busy (controller: LQ) {\n    transition Idle\n}\n";
+    assert_eq!(ast, aql_circt::parse(expected).unwrap());
+
+    let (ast, errors) = aql_circt::parse_recovering(&canonical());
+    assert!(errors.is_empty());
+    assert_eq!(ast, aql_circt::parse(&canonical()).unwrap());
+}
+
+// A statement going on over lines is skipped as a whole, not line by line.
+#[test]
+fn recovery_skips_multi_line_statements() {
+    let source = "controller_entry LQ {\n\
+                  \x20   init_entry = Idle\n\
+                  \x20   i32 x = 0\n\
+                  }\n\
+                  state Idle (controller: LQ) {\n\
+                  \x20   x = x +\n\
+                  \x20       + 1\n\
+                  \x20   x = x +\n\
+                  \x20       1\n\
+                  \x20   if (x >\n\
+                  \x20       ) { x = 0 }\n\
+                  \x20   x = x\n\
+                  \x20       * 2\n\
+                  \x20   transition Idle\n\
+                  }\n";
+    let (ast, errors) = aql_circt::parse_recovering(source);
+    let positions: Vec<(usize, usize)> = errors
+        .iter()
+        .map(|error| match error {
+            Error::Parse { line, column, .. } => (*line, *column),
+            error => panic!("{:?}", error),
+        })
+        .collect();
+    assert_eq!(positions, [(7, 9), (11, 9)]);
+
+    let expected = "controller_entry LQ {\n    init_entry = Idle\n    i32 x = 0\n}\n\
+                    state Idle (controller: LQ) {\n\
+                    \x20   x = x + 1\n    x = x * 2\n    transition Idle\n\
+                    }\n";
+    assert_eq!(ast, aql_circt::parse(expected).unwrap());
+}
+
+// Each error only has its own declaration parsed again.
+#[test]
+fn recovery_scales_with_the_errors() {
+    let mut source =
+        String::from("controller_entry LQ {\n    init_entry = Idle\n    i32 x = 0\n}\n");
+    for i in 0..400 {
+        source += &format!(
+            "state S{} (controller: LQ) {{\n    x = = {}\n    transition Idle\n}}\n",
+            i, i
+        );
+    }
+    let (_, errors) = aql_circt::parse_recovering(&source);
+    assert_eq!(errors.len(), 400);
+    assert_eq!(
+        errors.last(),
+        Some(&Error::Parse {
+            line: 1602,
+            column: 9,
+            message: String::from("expected expr"),
+        })
+    );
+}
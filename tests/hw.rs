@@ -0,0 +1,98 @@
+// `--emit hw-mlir` on tests/hw/design.aql, compared with the snapshot next to it.
+
+mod common;
+
+use aql_circt::{ConvertOptions, Error, Format, GenerateOptions};
+use common::read;
+
+fn generate(source: &str, cores: Option<u32>) -> Result<String, Error> {
+    let ast = aql_circt::parse(source)?;
+    let machines = aql_circt::convert(ast, &ConvertOptions::default())?;
+    let mut options = GenerateOptions::default();
+    options.format = Format::HwMlir;
+    options.cores = cores;
+    aql_circt::generate(&machines, &options)
+}
+
+#[test]
+fn module_matches_the_snapshot() {
+    assert_eq!(
+        generate(&read("hw/design.aql"), None).unwrap(),
+        read("hw/design.mlir")
+    );
+}
+
+// The module is a graph region, registers can be used before they are defined but every value
+// has to be defined somewhere.
+#[test]
+fn every_value_is_defined() {
+    let mlir = generate(&read("hw/design.aql"), None).unwrap();
+    let mut defined = vec![];
+    let mut used = vec![];
+    for line in mlir.lines().map(str::trim) {
+        let values = line
+            .split(|c: char| !(c.is_alphanumeric() || c == '%' || c == '_' || c == '.'))
+            .filter(|token| token.starts_with('%'));
+        if line.starts_with("hw.module") {
+            defined.extend(values);
+        } else if let Some((result, rest)) = line.split_once(" = ") {
+            defined.push(result);
+            used.extend(rest.split([' ', ',']).filter(|t| t.starts_with('%')));
+        } else {
+            used.extend(values);
+        }
+    }
+    for value in used {
+        assert!(defined.contains(&value), "{} is not defined", value);
+    }
+}
+
+// One register per variable and the state, with the documentation above it.
+#[test]
+fn registers_keep_their_documentation() {
+    let mlir = generate(&read("hw/design.aql"), None).unwrap();
+    let registers: Vec<&str> = mlir
+        .lines()
+        .filter(|line| line.contains("seq.compreg"))
+        .map(|line| line.trim().split(' ').next().unwrap())
+        .collect();
+    assert_eq!(
+        registers,
+        ["%reg.state", "%reg.addr", "%reg.wide", "%reg.busy"]
+    );
+    assert!(mlir.contains("\t\t// The address being loaded.\n\t\t%reg.addr = seq.compreg"));
+    assert!(mlir.contains("\t\t// Idle = 0:\n\t\t// Waits for the core.\n"));
+    assert!(mlir.contains("\t// Loads one address at a time.\n\thw.module @LQ("));
+}
+
+#[test]
+fn cores_are_rejected() {
+    assert_eq!(
+        generate(&read("hw/design.aql"), Some(2)).err(),
+        Some(Error::Design(String::from(
+            "hw-mlir can't replicate cores yet, use fsm-mlir with --cores"
+        )))
+    );
+}
+
+#[test]
+fn state_queues_are_rejected() {
+    let source = read("hw/design.aql")
+        + "\nstate_queue LQ {\n    element_ordering order = FIFO\n    int num_entries = 2\n}\n";
+    assert_eq!(
+        generate(&source, None).err(),
+        Some(Error::Design(String::from(
+            "hw-mlir can't lower the state_queue of LQ yet, use fsm-mlir"
+        )))
+    );
+}
+
+// A field of a user enum is as wide as the enum needs, its values are the variants' positions.
+#[test]
+fn enum_fields_are_encoded() {
+    let mlir = generate(&read("hw/enum.aql"), None).unwrap();
+    assert!(mlir.contains("out MemSys_issue_arg0 : i2, out MemSys_issue_arg1 : i32"));
+    assert!(mlir.contains("%reg.mem_op_kind = seq.compreg %t10, %clk reset %rst, %c0_i2 : i2"));
+    assert!(mlir.contains("%t5 = comb.icmp eq %reg.mem_op_kind, %c1_i2 : i2"));
+    assert!(mlir.contains("%t8 = comb.mux %t6, %c2_i2, %reg.mem_op_kind : i2"));
+}
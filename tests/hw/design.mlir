@@ -0,0 +1,63 @@
+module {
+	// Loads one address at a time.
+	hw.module @LQ(in %clk : !seq.clock, in %rst : i1, in %Core_load_valid : i1, in %Core_load_a : i32, in %MemSys_ready_valid : i1, in %MemSys_ready_data : i32, out MemSys_load_valid : i1, out MemSys_load_arg0 : i32) attributes {comment = "Loads one address at a time."} {
+		%c0_i1 = hw.constant 0 : i1
+		%c0_i32 = hw.constant 0 : i32
+		%c0_i64 = hw.constant 0 : i64
+		%c1_i1 = hw.constant 1 : i1
+		%t1 = comb.icmp eq %reg.state, %c1_i1 : i1
+		%t2 = comb.icmp eq %MemSys_ready_valid, %c1_i1 : i1
+		%t3 = comb.and %c1_i1, %t2 : i1
+		%c255_i32 = hw.constant 255 : i32
+		%t4 = comb.and %reg.addr, %c255_i32 : i32
+		%c1_i32 = hw.constant 1 : i32
+		%t5 = comb.xor %MemSys_ready_data, %c1_i32 : i32
+		%t6 = comb.or %t4, %t5 : i32
+		%c2_i64 = hw.constant 2 : i64
+		%t7 = comb.icmp eq %c2_i64, %c0_i64 : i64
+		%t8 = comb.divu %reg.wide, %c2_i64 : i64
+		%t9 = comb.mux %t7, %c0_i64, %t8 : i64
+		%t10 = comb.mux %t3, %c0_i1, %reg.state : i1
+		%t11 = comb.mux %t3, %t6, %reg.addr : i32
+		%t12 = comb.mux %t3, %t9, %reg.wide : i64
+		%t13 = comb.mux %t3, %c0_i1, %reg.busy : i1
+		%t14 = comb.mux %t1, %t10, %reg.state : i1
+		%t15 = comb.mux %t1, %t11, %reg.addr : i32
+		%t16 = comb.mux %t1, %t12, %reg.wide : i64
+		%t17 = comb.mux %t1, %t13, %reg.busy : i1
+		%c2_i32 = hw.constant 2 : i32
+		%t18 = comb.shl %reg.addr, %c2_i32 : i32
+		%t19 = comb.mux %t1, %c1_i1, %c0_i1 : i1
+		%t20 = comb.mux %t1, %t18, %c0_i32 : i32
+		%t21 = comb.icmp eq %reg.busy, %c1_i1 : i1
+		%t22 = comb.xor %t21, %c1_i1 : i1
+		%t23 = comb.and %t1, %t22 : i1
+		%t24 = comb.icmp eq %reg.state, %c0_i1 : i1
+		%t25 = comb.icmp eq %Core_load_valid, %c1_i1 : i1
+		%t26 = comb.and %c1_i1, %t25 : i1
+		%t27 = comb.concat %c0_i32, %Core_load_a : i32, i32
+		%t28 = comb.mux %t26, %c1_i1, %reg.state : i1
+		%t29 = comb.mux %t26, %Core_load_a, %reg.addr : i32
+		%t30 = comb.mux %t26, %t27, %reg.wide : i64
+		%t31 = comb.mux %t26, %c1_i1, %reg.busy : i1
+		%t32 = comb.mux %t24, %t28, %t14 : i1
+		%t33 = comb.mux %t24, %t29, %t15 : i32
+		%t34 = comb.mux %t24, %t30, %t16 : i64
+		%t35 = comb.mux %t24, %t31, %t17 : i1
+		%c100_i32 = hw.constant 100 : i32
+		%t36 = comb.icmp ult %reg.addr, %c100_i32 : i32
+		%t37 = comb.xor %t24, %c1_i1 : i1
+		%t38 = comb.or %t37, %t36 : i1
+		// Idle = 0:
+		// Waits for the core.
+		%reg.state = seq.compreg %t32, %clk reset %rst, %c0_i1 : i1
+		// The address being loaded.
+		%reg.addr = seq.compreg %t33, %clk reset %rst, %c0_i32 : i32
+		%reg.wide = seq.compreg %t34, %clk reset %rst, %c0_i64 : i64
+		%reg.busy = seq.compreg %t35, %clk reset %rst, %c0_i1 : i1
+		verif.assume %t38 : i1
+		%t39 = comb.xor %t23, %c1_i1 : i1
+		verif.assert %t39 label "LQ.Busy.assert0" : i1
+		hw.output %t19, %t20 : i1, i32
+	}
+}
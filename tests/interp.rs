@@ -0,0 +1,89 @@
+// Runs tests/interp/design.aql with the stimulus next to it for 6 cycles and compares the trace
+// and the VCD with the snapshots.
+
+mod common;
+
+use std::collections::HashMap;
+
+use aql_circt::{
+    cfg::StateMachine,
+    interp::{self, Interpreter, Stimulus},
+    ConvertOptions, Error,
+};
+use common::read;
+
+fn machine() -> StateMachine {
+    let ast = aql_circt::parse(&read("interp/design.aql")).unwrap();
+    let mut machines = aql_circt::convert(ast, &ConvertOptions::default()).unwrap();
+    machines.remove(0)
+}
+
+#[test]
+fn trace_matches_the_snapshot() {
+    let machine = machine();
+    let stimulus = Stimulus::parse(&read("interp/load.stim")).unwrap();
+    let samples = interp::run(&machine, &stimulus, 6).unwrap();
+    assert_eq!(interp::trace(&machine, &samples), read("interp/trace.txt"));
+}
+
+#[test]
+fn vcd_matches_the_snapshot() {
+    let machine = machine();
+    let stimulus = Stimulus::parse(&read("interp/load.stim")).unwrap();
+    let samples = interp::run(&machine, &stimulus, 6).unwrap();
+    assert_eq!(
+        interp::vcd(&machine, &samples).unwrap(),
+        read("interp/run.vcd")
+    );
+}
+
+// Actions see the values from before the clock edge, and a missing input is zero.
+#[test]
+fn steps_one_edge_at_a_time() {
+    let machine = machine();
+    let mut interpreter = Interpreter::new(&machine).unwrap();
+    let inputs = HashMap::new();
+
+    assert_eq!(
+        interpreter.step(&inputs).unwrap(),
+        Some(String::from("Busy"))
+    );
+    assert_eq!(
+        interpreter.variables(),
+        [
+            (String::from("count"), 5),
+            (String::from("other"), 0),
+            (String::from("done"), 0),
+        ]
+    );
+    assert_eq!(interpreter.outputs(&inputs).unwrap(), [1, 6]);
+    assert_eq!(interpreter.failures(&inputs).unwrap(), ["LQ.Busy.assert0"]);
+
+    // Waits for the response.
+    assert_eq!(interpreter.step(&inputs).unwrap(), None);
+    assert_eq!(interpreter.state(), "Busy");
+
+    let inputs = HashMap::from([
+        (String::from("MemSys_ready_valid"), 1),
+        (String::from("MemSys_ready_data"), 42),
+    ]);
+    assert_eq!(
+        interpreter.step(&inputs).unwrap(),
+        Some(String::from("Idle"))
+    );
+    // Division by zero gives zero, like the formal backends.
+    assert_eq!(interpreter.variables()[0], (String::from("count"), 0));
+}
+
+#[test]
+fn unknown_ports_are_errors() {
+    let stimulus = Stimulus::parse("0 MemSys_ready=1\n").unwrap();
+    let error = interp::run(&machine(), &stimulus, 1).err();
+    assert_eq!(
+        error,
+        Some(Error::Design(String::from(
+            "MemSys_ready is not an input of LQ, expected one of MemSys_ready_valid, \
+             MemSys_ready_data."
+        )))
+    );
+}
@@ -0,0 +1,115 @@
+// Runs the litmus tests in tests/litmus on the designs next to them and compares the outcomes the
+// interpreter reaches with the ones each memory model allows.
+
+mod common;
+
+use aql_circt::{
+    litmus::{self, Litmus, Reachable},
+    ConvertOptions, MemoryModel, StateMachine,
+};
+use common::read;
+
+const MODELS: [MemoryModel; 3] = [MemoryModel::Sc, MemoryModel::Tso, MemoryModel::Rvwmo];
+
+fn test(name: &str) -> Litmus {
+    litmus::parse(&read(&format!("litmus/{}.litmus", name))).unwrap()
+}
+
+fn machines(design: &str) -> Vec<StateMachine> {
+    let ast = aql_circt::parse(&read(&format!("litmus/{}", design))).unwrap();
+    aql_circt::convert(ast, &ConvertOptions::default()).unwrap()
+}
+
+fn reachable(name: &str, design: &str) -> (Litmus, Reachable) {
+    let test = test(name);
+    let reachable = litmus::reachable(&test, &machines(design)).unwrap();
+    (test, reachable)
+}
+
+// One instruction at a time is sequentially consistent, so every model allows what it does and it
+// does everything SC allows.
+fn assert_in_order(name: &str) {
+    let (test, reachable) = reachable(name, "in_order.aql");
+    assert_eq!(reachable.outcomes, litmus::outcomes(&test, MemoryModel::Sc));
+    for model in MODELS {
+        let report = litmus::report(&test, model, &reachable);
+        assert!(
+            report.contains(&format!("Forbidden by {} (0)", model.name())),
+            "{}",
+            report
+        );
+    }
+}
+
+#[test]
+fn in_order_mp() {
+    assert_in_order("MP");
+}
+
+#[test]
+fn in_order_sb() {
+    assert_in_order("SB");
+}
+
+#[test]
+fn in_order_lb() {
+    assert_in_order("LB");
+}
+
+// Only the load overtaking the buffered store of SB is relaxed, TSO and RVWMO allow it.
+#[test]
+fn store_buffer_sb() {
+    let (test, reachable) = reachable("SB", "store_buffer.aql");
+    let relaxed = litmus::outcomes(&test, MemoryModel::Tso);
+    assert_eq!(reachable.outcomes, relaxed);
+
+    let sc = litmus::outcomes(&test, MemoryModel::Sc);
+    let forbidden: Vec<_> = reachable.outcomes.difference(&sc).collect();
+    assert_eq!(forbidden.len(), 1);
+    assert!(test.condition.eval(forbidden[0]));
+
+    let report = litmus::report(&test, MemoryModel::Sc, &reachable);
+    assert!(report.contains("Forbidden by SC (1)"), "{}", report);
+    assert!(
+        report.contains("Observation SB Sometimes 1 3"),
+        "{}",
+        report
+    );
+    for model in [MemoryModel::Tso, MemoryModel::Rvwmo] {
+        assert!(reachable
+            .outcomes
+            .is_subset(&litmus::outcomes(&test, model)));
+    }
+}
+
+// Stores still reach the memory in order and loads in order, MP and LB stay SC.
+#[test]
+fn store_buffer_mp_and_lb() {
+    for name in ["MP", "LB"] {
+        let (test, reachable) = reachable(name, "store_buffer.aql");
+        for model in MODELS {
+            assert!(
+                reachable
+                    .outcomes
+                    .is_subset(&litmus::outcomes(&test, model)),
+                "{} under {}",
+                name,
+                model.name()
+            );
+        }
+        assert!(!reachable.outcomes.iter().any(|o| test.condition.eval(o)));
+    }
+}
+
+// The testbench hands out an instruction while the design is busy in some interleavings.
+#[test]
+fn dropped_instructions_are_reported() {
+    let (test, reachable) = reachable("MP", "in_order.aql");
+    assert!(reachable.unfinished);
+    let report = litmus::report(&test, MemoryModel::Sc, &reachable);
+    assert!(
+        report.contains("Some interleavings don't finish"),
+        "{}",
+        report
+    );
+}
@@ -0,0 +1,131 @@
+// Loads tests/loader/top.aql, which imports lsq.aql, imports mem.aql from the lib/ search path
+// through both of them and includes common.aql, then checks the names and the MLIR they lower to.
+
+use std::path::PathBuf;
+
+use aql_circt::{ConvertOptions, Error, GenerateOptions, Loader, StateMachine};
+
+fn root() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/loader")
+}
+
+fn load(name: &str, search_paths: Vec<PathBuf>) -> Result<Vec<StateMachine>, Error> {
+    let ast = Loader::new(search_paths).load(&root().join(name))?;
+    aql_circt::convert(ast, &ConvertOptions::default())
+}
+
+fn top() -> Vec<StateMachine> {
+    load("top.aql", vec![root().join("lib")]).unwrap()
+}
+
+// The importing file's own declarations come first, then each import once.
+#[test]
+fn imports_are_namespaced() {
+    let machines = top();
+    let names: Vec<&str> = machines.iter().map(|m| m.fsm_name.as_str()).collect();
+    assert_eq!(names, ["Counter", "Core", "lsq.LQ", "mem.Mem"]);
+    assert_eq!(machines[2].entry, "lsq.Idle");
+    assert_eq!(machines[3].entry, "mem.Ready");
+}
+
+#[test]
+fn requests_reach_the_namespaced_unit() {
+    let machines = top();
+    let core: Vec<&str> = machines[1]
+        .outputs
+        .iter()
+        .map(|p| p.name.as_str())
+        .collect();
+    assert_eq!(core, ["lsq_LQ_issue_valid", "lsq_LQ_issue_arg0"]);
+    let mem: Vec<&str> = machines[3].inputs.iter().map(|p| p.name.as_str()).collect();
+    assert_eq!(mem, ["lsq_LQ_load_valid", "lsq_LQ_load_a"]);
+    let channel = machines[3].inputs[0].channel.as_ref().unwrap();
+    assert_eq!(channel.unit.as_deref(), Some("lsq.LQ"));
+}
+
+// Dots stay out of the symbols and port names, the states and the initialState agree.
+#[test]
+fn mlir_names_are_mangled() {
+    let mut options = GenerateOptions::default();
+    options.top = true;
+    let mlir = aql_circt::generate(&top(), &options).unwrap();
+    assert!(mlir.contains(
+        "fsm.machine @lsq_LQ_fsm(%Core_issue_valid: i1, %Core_issue_a: i32) -> (i1, i32) \
+         attributes {initialState = \"lsq_Idle\"}"
+    ));
+    assert!(mlir.contains("\tfsm.state @lsq_Busy output {"));
+    assert!(mlir.contains("\t\t\tfsm.transition @lsq_Idle "));
+    assert!(mlir.contains("out lsq_LQ_issue_valid : i1, out lsq_LQ_issue_arg0 : i32"));
+    assert!(mlir.contains("hw.module @mem_Mem(in %clk : !seq.clock, in %rst : i1, in %lsq_LQ"));
+    for line in mlir.lines().filter(|line| !line.trim().starts_with("//")) {
+        assert!(!line.contains("lsq.") && !line.contains("mem."), "{}", line);
+    }
+}
+
+#[test]
+fn mangled_names_must_be_distinct() {
+    let mut machines = top();
+    machines[0].fsm_name = String::from("lsq_LQ");
+    assert_eq!(
+        aql_circt::generate(&machines, &GenerateOptions::default()).err(),
+        Some(Error::Design(String::from(
+            "lsq_LQ and lsq.LQ are both @lsq_LQ in MLIR, rename one of them."
+        )))
+    );
+}
+
+// mem.aql is only in lib/, both files importing it look there.
+#[test]
+fn search_paths_are_tried_in_order() {
+    let error = load("top.aql", vec![]).err().unwrap();
+    let Error::File { path, error } = error else {
+        panic!("{:?}", error);
+    };
+    assert_eq!(path, root().join("lsq.aql"));
+    assert_eq!(
+        *error,
+        Error::Import(format!(
+            "can't find \"mem.aql\", looked in {}",
+            root().display()
+        ))
+    );
+
+    let missing = root().join("missing");
+    let machines = load("top.aql", vec![missing, root().join("lib")]).unwrap();
+    assert_eq!(machines.len(), 4);
+}
+
+#[test]
+fn cycles_are_rejected() {
+    let error = load("cycle_a.aql", vec![]).err().unwrap();
+    let shown = |name: &str| root().join(name).display().to_string();
+    assert_eq!(
+        error,
+        Error::File {
+            path: root().join("cycle_b.aql"),
+            error: Box::new(Error::Import(format!(
+                "import cycle: {} -> {} -> {}",
+                shown("cycle_a.aql"),
+                shown("cycle_b.aql"),
+                shown("cycle_a.aql")
+            ))),
+        }
+    );
+}
+
+// Loading a file imported before gives nothing new, loading it without its namespace is an error.
+#[test]
+fn files_are_read_once() {
+    let mut loader = Loader::new(vec![root().join("lib")]);
+    loader.load(&root().join("top.aql")).unwrap();
+    assert_eq!(
+        loader.load(&root().join("top.aql")),
+        Ok(aql_circt::ast::ASTNode::Top(vec![]))
+    );
+    assert_eq!(
+        loader.load(&root().join("lib/mem.aql")),
+        Err(Error::Import(String::from(
+            "already imported as mem, it can't also be loaded without a namespace"
+        )))
+    );
+}
@@ -0,0 +1,192 @@
+// A session with the language server, messages framed as an editor sends them.
+
+use std::io::Cursor;
+
+const SOURCE: &str = "controller_entry LQ {\n\
+                      \x20   init_entry = Idle\n\
+                      \x20   i32 count = 0\n\
+                      \x20   element_ordering order = FIFO\n\
+                      }\n\
+                      /// Waits.\n\
+                      state Idle (controller: LQ) {\n\
+                      \x20   count = count + 1\n\
+                      \x20   transition Busy\n\
+                      }\n\
+                      state Busy (controller: LQ) {\n\
+                      \x20   transition Idle\n\
+                      }\n";
+
+fn framed(messages: &[String]) -> Vec<u8> {
+    let mut ret = vec![];
+    for message in messages {
+        ret.extend(format!("Content-Length: {}\r\n\r\n{}", message.len(), message).bytes());
+    }
+    ret
+}
+
+// The bodies of the server's messages, in order.
+fn session(messages: &[String]) -> Vec<String> {
+    let mut output = vec![];
+    aql_circt::lsp::serve(Cursor::new(framed(messages)), &mut output, vec![]).unwrap();
+    let output = String::from_utf8(output).unwrap();
+    let mut ret = vec![];
+    let mut rest = output.as_str();
+    while let Some((header, tail)) = rest.split_once("\r\n\r\n") {
+        let length: usize = header["Content-Length: ".len()..].parse().unwrap();
+        ret.push(tail[..length].to_string());
+        rest = &tail[length..];
+    }
+    ret
+}
+
+fn escaped(text: &str) -> String {
+    text.replace('\n', "\\n")
+}
+
+fn open(text: &str) -> String {
+    format!(
+        r#"{{"jsonrpc":"2.0","method":"textDocument/didOpen","params":{{"textDocument":{{"uri":"untitled:a.aql","languageId":"aql","version":1,"text":"{}"}}}}}}"#,
+        escaped(text)
+    )
+}
+
+fn request(id: usize, method: &str, line: usize, character: usize) -> String {
+    format!(
+        r#"{{"jsonrpc":"2.0","id":{},"method":"{}","params":{{"textDocument":{{"uri":"untitled:a.aql"}},"position":{{"line":{},"character":{}}}}}}}"#,
+        id, method, line, character
+    )
+}
+
+#[test]
+fn session_with_an_editor() {
+    let replies = session(&[
+        r#"{"jsonrpc":"2.0","id":0,"method":"initialize","params":{"capabilities":{}}}"#.into(),
+        r#"{"jsonrpc":"2.0","method":"initialized","params":{}}"#.into(),
+        open(SOURCE),
+        // `Busy` in `transition Busy`.
+        request(1, "textDocument/definition", 8, 17),
+        // `count` in `count = count + 1`.
+        request(2, "textDocument/definition", 7, 13),
+        request(3, "textDocument/hover", 3, 22),
+        request(4, "textDocument/hover", 6, 7),
+        request(5, "textDocument/completion", 11, 15),
+        request(6, "textDocument/documentSymbol", 0, 0),
+        request(7, "textDocument/formatting", 0, 0),
+        r#"{"jsonrpc":"2.0","id":8,"method":"shutdown"}"#.into(),
+        r#"{"jsonrpc":"2.0","method":"exit"}"#.into(),
+        request(9, "textDocument/hover", 0, 0),
+    ]);
+
+    assert_eq!(replies.len(), 10, "{:#?}", replies);
+    assert!(replies[0].contains(r#""definitionProvider":true"#));
+    assert_eq!(
+        replies[1],
+        r#"{"jsonrpc":"2.0","method":"textDocument/publishDiagnostics","params":{"uri":"untitled:a.aql","diagnostics":[]}}"#
+    );
+    assert!(replies[2].contains(
+        r#""range":{"start":{"line":10,"character":6},"end":{"line":10,"character":10}}"#
+    ));
+    assert!(replies[3]
+        .contains(r#""range":{"start":{"line":2,"character":8},"end":{"line":2,"character":13}}"#));
+    assert!(replies[4].contains(
+        r#"element_ordering order\n```\n\nenum element_ordering { FIFO, Hash, Stack }, `i2`"#
+    ));
+    assert!(replies[5].contains(r#"state Idle\n```\n\nWaits."#));
+    assert!(replies[6].contains(r#""label":"Idle""#) && replies[6].contains(r#""label":"Busy""#));
+    assert!(!replies[6].contains(r#""label":"LQ""#));
+    assert!(replies[7].contains(r#""name":"LQ","detail":"controller_entry""#));
+    assert!(replies[8].contains(r#""error":{"code":-32601"#));
+    assert_eq!(replies[9], r#"{"jsonrpc":"2.0","id":8,"result":null}"#);
+}
+
+#[test]
+fn diagnostics() {
+    let broken = SOURCE.replace("count = count + 1", "count = = 1");
+    let unreachable = SOURCE.replace("transition Busy", "transition Idle");
+    let replies = session(&[open(&broken), open(&unreachable)]);
+
+    assert!(
+        replies[0].contains(r#""range":{"start":{"line":7,"character":12}"#),
+        "{}",
+        replies[0]
+    );
+    assert!(replies[0].contains(r#""severity":1"#));
+    assert_eq!(replies[0].matches(r#""severity":1"#).count(), 1);
+
+    assert!(
+        replies[1].contains(r#""range":{"start":{"line":10,"character":6}"#),
+        "{}",
+        replies[1]
+    );
+    assert!(replies[1].contains(r#""severity":2"#));
+
+    // A second controller with its own Busy, found by the controller that owns it.
+    let second = "controller_entry SB {\n    init_entry = Idle\n}\n\
+                  state Idle (controller: SB) {\n    transition Idle\n}\n\
+                  state Busy (controller: SB) {\n    transition Idle\n}\n";
+    let replies = session(&[open(&(SOURCE.to_string() + second))]);
+    assert_eq!(
+        replies[0].matches(r#""severity":2"#).count(),
+        1,
+        "{}",
+        replies[0]
+    );
+    assert!(
+        replies[0].contains(r#""range":{"start":{"line":19,"character":6}"#),
+        "{}",
+        replies[0]
+    );
+
+    // Every syntax error, and the document still has its symbols.
+    let broken = broken.replace("transition Idle\n}", "transition Idle Idle\n}");
+    let replies = session(&[
+        open(&broken),
+        request(1, "textDocument/documentSymbol", 0, 0),
+    ]);
+    assert_eq!(
+        replies[0].matches(r#""severity":1"#).count(),
+        2,
+        "{}",
+        replies[0]
+    );
+    assert!(replies[1].contains(r#""name":"Busy""#), "{}", replies[1]);
+}
+
+// Columns go both ways in UTF-16 code units: 𝄞 is one char but two units.
+#[test]
+fn positions_are_utf16() {
+    let source = SOURCE
+        .replace("state Busy", "/* 𝄞𝄞 é */ state Busy")
+        .replace("    count = count + 1", "    /* 𝄞𝄞𝄞𝄞𝄞 */ count = count + 1");
+    let replies = session(&[
+        r#"{"jsonrpc":"2.0","id":0,"method":"initialize","params":{"capabilities":{}}}"#.into(),
+        open(&source),
+        request(1, "textDocument/documentSymbol", 0, 0),
+        // The `=`, five units right of where it is in chars.
+        request(2, "textDocument/hover", 7, 27),
+        // The second `count`.
+        request(3, "textDocument/hover", 7, 29),
+    ]);
+
+    assert!(replies[0].contains(r#""positionEncoding":"utf-16""#));
+    assert!(
+        replies[2].contains(
+            r#""name":"Busy","detail":"state","kind":22,"location":{"uri":"untitled:a.aql","range":{"start":{"line":10,"character":19},"end":{"line":10,"character":23}}}"#
+        ),
+        "{}",
+        replies[2]
+    );
+    assert_eq!(replies[3], r#"{"jsonrpc":"2.0","id":2,"result":null}"#);
+    assert!(replies[4].contains(r#"i32 count"#), "{}", replies[4]);
+
+    // The checker's finding on Busy, once nothing transitions there.
+    let unreachable = source.replace("transition Busy", "transition Idle");
+    let replies = session(&[open(&unreachable)]);
+    assert!(
+        replies[0].contains(
+            r#""range":{"start":{"line":10,"character":19},"end":{"line":10,"character":20}}"#
+        ),
+        "{}",
+        replies[0]
+    );
+}
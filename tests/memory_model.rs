@@ -0,0 +1,188 @@
+// Lowers tests/memory_model/design.aql under each memory model, compares `--emit cfg` with the
+// snapshots next to it and checks the states and transitions each rule adds.
+
+mod common;
+
+use aql_circt::{ConvertOptions, Error, MemoryModel, StateMachine};
+use common::read;
+
+fn convert(source: &str, model: Option<MemoryModel>) -> Result<Vec<StateMachine>, Error> {
+    let ast = aql_circt::parse(source)?;
+    let mut options = ConvertOptions::default();
+    options.memory_model = model;
+    aql_circt::convert(ast, &options)
+}
+
+const MODELS: [MemoryModel; 3] = [MemoryModel::Sc, MemoryModel::Tso, MemoryModel::Rvwmo];
+
+// `--emit cfg` of one controller.
+fn describe(model: Option<MemoryModel>, controller: &str) -> String {
+    let machines = convert(&read("memory_model/design.aql"), model).unwrap();
+    let machine = machines.iter().find(|m| m.fsm_name == controller).unwrap();
+    machine.describe().unwrap()
+}
+
+#[test]
+fn lowering_matches_the_snapshots() {
+    for model in MODELS {
+        let machines = convert(&read("memory_model/design.aql"), Some(model)).unwrap();
+        let described: Vec<String> = machines.iter().map(|m| m.describe().unwrap()).collect();
+        let name = format!("memory_model/{}.cfg", model.name().to_lowercase());
+        assert_eq!(described.join("\n"), read(&name), "{}", name);
+    }
+}
+
+// Without a memory model only `performed` states change, and the design has none.
+#[test]
+fn no_model_adds_nothing() {
+    for controller in ["LQ", "SB"] {
+        let described = describe(None, controller);
+        assert!(!described.contains("_await_"), "{}", described);
+        assert!(!described.contains("MemSys_invalidate"), "{}", described);
+    }
+}
+
+// Loads are squashed by invalidations under SC and TSO, RVWMO lets them be reordered.
+#[test]
+fn loads_replay_on_invalidation() {
+    for model in MODELS {
+        let lq = describe(Some(model), "LQ");
+        let squashed = lq.contains(
+            "-> lq_init if MemSys_invalidate_valid == 1 && \
+             MemSys_invalidate_invalidated_line == phys_addr",
+        );
+        assert_eq!(squashed, model != MemoryModel::Rvwmo, "{}", lq);
+        // The init_entry isn't performed yet, there is nothing to squash.
+        let init = lq.split("  state lq_load\n").next().unwrap();
+        assert!(!init.contains("MemSys_invalidate_valid =="), "{}", lq);
+    }
+}
+
+// Only SC keeps loads behind the stores still in the store buffer.
+#[test]
+fn loads_wait_for_the_store_buffer_under_sc() {
+    for model in MODELS {
+        let lq = describe(Some(model), "LQ");
+        let gated = lq.contains("-> lq_load_await_drained if Core_issue_valid == 1")
+            && lq.contains(
+                "  state lq_load_await_drained\n    0: -> lq_load if SB_drained_valid == 1\n",
+            );
+        assert_eq!(gated, model == MemoryModel::Sc, "{}", lq);
+    }
+}
+
+// SC and TSO drain the store buffer in order, from the head of its queue.
+#[test]
+fn stores_drain_in_order() {
+    for model in MODELS {
+        let sb = describe(Some(model), "SB");
+        let gated = sb.contains("-> sb_store_await_head if Core_store_valid == 1")
+            && sb.contains(
+                "  state sb_store_await_head\n    0: -> sb_store if SB_head_valid == 1\n",
+            );
+        assert_eq!(gated, model != MemoryModel::Rvwmo, "{}", sb);
+    }
+}
+
+#[test]
+fn store_buffers_must_be_fifo() {
+    let source = read("memory_model/design.aql").replace("order = FIFO", "order = Hash");
+    for model in MODELS {
+        let result = convert(&source, Some(model));
+        if model == MemoryModel::Rvwmo {
+            assert!(result.is_ok());
+        } else {
+            assert_eq!(
+                result.err(),
+                Some(Error::Design(format!(
+                    "{:?} needs store buffer SB to drain in order, but its queue is Hash.",
+                    model
+                )))
+            );
+        }
+    }
+}
+
+// Tagged states are squashed under every model, the untagged states of the load queue aren't.
+#[test]
+fn performed_states_replay_under_every_model() {
+    let source = read("memory_model/design.aql").replace(
+        "state lq_init (controller: LQ)",
+        "state lq_init (controller: LQ, performed)",
+    );
+    for model in [None, Some(MemoryModel::Sc), Some(MemoryModel::Rvwmo)] {
+        let machines = convert(&source, model).unwrap();
+        let lq = machines[0].describe().unwrap();
+        let (init, load) = lq.split_once("  state lq_load\n").unwrap();
+        assert!(
+            init.contains("MemSys_invalidate_invalidated_line == phys_addr"),
+            "{}",
+            lq
+        );
+        assert!(!load.contains("MemSys_invalidate"), "{}", lq);
+    }
+}
+
+#[test]
+fn roles_are_checked() {
+    let source = read("memory_model/design.aql").replace("role: load_queue", "role: loader");
+    assert_eq!(
+        convert(&source, Some(MemoryModel::Sc)).err(),
+        Some(Error::Design(String::from(
+            "Unknown controller role: loader, expected load_queue or store_buffer."
+        )))
+    );
+
+    let source = read("memory_model/design.aql").replace(
+        "i32 phys_addr = 0\n}\n\nstate lq_init",
+        "i32 vaddr = 0\n}\n\nstate lq_init",
+    );
+    assert_eq!(
+        convert(&source, Some(MemoryModel::Tso)).err(),
+        Some(Error::Design(String::from(
+            "LQ needs a phys_addr field to be squashed by invalidations."
+        )))
+    );
+}
+
+#[test]
+fn fences_wait_for_the_store_buffer() {
+    for model in [MemoryModel::Tso, MemoryModel::Rvwmo] {
+        let sb = describe(Some(model), "SB");
+        assert!(
+            sb.contains(
+                "-> sb_fence_await_drained if Core_store_valid != 1 && Core_fence_valid == 1"
+            ),
+            "{}",
+            sb
+        );
+        assert!(sb.contains(
+            "  state sb_fence_await_drained\n    0: -> sb_fence if SB_drained_valid == 1\n"
+        ));
+    }
+}
+
+// SC already orders every access, the fence has nothing to wait for.
+#[test]
+fn fences_are_free_under_sc() {
+    let sb = describe(Some(MemoryModel::Sc), "SB");
+    assert!(!sb.contains("sb_fence_await_drained"), "{}", sb);
+    assert!(sb.contains("-> sb_fence if Core_store_valid != 1 && Core_fence_valid == 1"));
+}
+
+#[test]
+fn fences_need_a_store_buffer() {
+    let source = read("memory_model/design.aql").replace(" (role: store_buffer)", "");
+    for model in [MemoryModel::Tso, MemoryModel::Rvwmo] {
+        assert_eq!(
+            convert(&source, Some(model)).err(),
+            Some(Error::Design(format!(
+                "sb_fence is a fence, but no controller has the store_buffer role for it to wait \
+                 for under {}.",
+                model.name()
+            )))
+        );
+    }
+    assert!(convert(&source, Some(MemoryModel::Sc)).is_ok());
+    assert!(convert(&source, None).is_ok());
+}
@@ -0,0 +1,48 @@
+// `--cores 2` on tests/multicore/design.aql, compared with the snapshot next to it.
+
+mod common;
+
+use aql_circt::{ConvertOptions, Error, GenerateOptions};
+use common::read;
+
+fn generate(top: bool) -> Result<String, Error> {
+    let ast = aql_circt::parse(&read("multicore/design.aql"))?;
+    let machines = aql_circt::convert(ast, &ConvertOptions::default())?;
+    let mut options = GenerateOptions::default();
+    options.cores = Some(2);
+    options.top = top;
+    aql_circt::generate(&machines, &options)
+}
+
+#[test]
+fn top_matches_the_snapshot() {
+    assert_eq!(generate(false).unwrap(), read("multicore/top.mlir"));
+}
+
+// Every core gets the queue module of SB, each of its entries requests on its own.
+#[test]
+fn queues_are_replicated() {
+    let mlir = generate(false).unwrap();
+    for core in 0..2 {
+        let module = format!("hw.module @SB_queue_core{}(", core);
+        assert!(mlir.contains(&module), "{}", module);
+        let instance = format!(
+            "hw.instance \"SB_queue_core{}_inst\" @SB_queue_core{}(",
+            core, core
+        );
+        assert!(mlir.contains(&instance), "{}", instance);
+    }
+    // Two entries in two cores.
+    assert!(mlir.contains("%MemSys_store_granted_3 = seq.compreg"));
+    assert!(!mlir.contains("%MemSys_store_granted_4 = seq.compreg"));
+}
+
+#[test]
+fn top_needs_a_single_core() {
+    assert_eq!(
+        generate(true).err(),
+        Some(Error::Design(String::from(
+            "--top wraps a single core, --cores already instantiates every core in hw.module @top"
+        )))
+    );
+}
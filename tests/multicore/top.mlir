@@ -0,0 +1,340 @@
+module {
+	fsm.machine @LQ_core0(%MemSys_load_response_valid: i1, %MemSys_load_response_value: i32) -> (i1, i32) attributes {initialState = "Idle"} {
+		%addr = fsm.variable "addr" {initValue = 0 : i32 } : i32
+		fsm.state @Idle output {
+			%0 = hw.constant 1 : i1
+			fsm.output %0, %addr : i1, i32
+		} transitions {
+			fsm.transition @Idle guard {
+				%0 = hw.constant 1 : i1
+				%1 = comb.icmp eq %MemSys_load_response_valid, %0 : i1
+				%fsm_output = comb.and %1 : i1
+				fsm.return %fsm_output
+			} action {
+				fsm.update %addr, %MemSys_load_response_value : i32
+			}
+
+		}
+
+	}
+
+	fsm.machine @SB_core0() -> (i1, i32, i32) attributes {initialState = "Drain"} {
+		%data = fsm.variable "data" {initValue = 0 : i32 } : i32
+		fsm.state @Drain output {
+			%0 = hw.constant 1 : i1
+			fsm.output %0, %data, %data : i1, i32, i32
+		} transitions {
+			fsm.transition @Drain 
+
+		}
+
+	}
+
+	// Fifo queue with 2 entries of @SB_core0.
+	hw.module @SB_queue_core0(in %clk : !seq.clock, in %rst : i1, in %alloc : i1, in %alloc_key : i32, in %free : i1, in %search_key : i32, out full : i1, out empty : i1, out alloc_idx : i1, out free_idx : i1, out search_hit : i1, out search_idx : i1, out MemSys_store_valid_0 : i1, out MemSys_store_arg0_0 : i32, out MemSys_store_arg1_0 : i32, out MemSys_store_valid_1 : i1, out MemSys_store_arg0_1 : i32, out MemSys_store_arg1_1 : i32) {
+		%t1 = comb.icmp eq %key_0, %search_key : i32
+		%t2 = comb.and %valid_0, %t1 : i1
+		%t3 = comb.icmp eq %key_1, %search_key : i32
+		%t4 = comb.and %valid_1, %t3 : i1
+		%c0_i1 = hw.constant 0 : i1
+		%t5 = comb.or %c0_i1, %t2 : i1
+		%t6 = comb.or %t5, %t4 : i1
+		%c1_i1 = hw.constant 1 : i1
+		%t7 = comb.mux %t4, %c1_i1, %c0_i1 : i1
+		%t8 = comb.mux %t2, %c0_i1, %t7 : i1
+		%c2_i2 = hw.constant 2 : i2
+		%c0_i2 = hw.constant 0 : i2
+		%t9 = comb.icmp eq %count, %c2_i2 : i2
+		%t10 = comb.icmp eq %count, %c0_i2 : i2
+		%t11 = comb.xor %t9, %c1_i1 : i1
+		%t12 = comb.xor %t10, %c1_i1 : i1
+		%t13 = comb.and %alloc, %t11 : i1
+		%t14 = comb.and %free, %t12 : i1
+		%t15 = comb.add %tail, %c1_i1 : i1
+		%t16 = comb.icmp eq %tail, %c1_i1 : i1
+		%t17 = comb.mux %t16, %c0_i1, %t15 : i1
+		%t18 = comb.mux %t13, %t17, %tail : i1
+		%t19 = comb.add %head, %c1_i1 : i1
+		%t20 = comb.icmp eq %head, %c1_i1 : i1
+		%t21 = comb.mux %t20, %c0_i1, %t19 : i1
+		%t22 = comb.mux %t14, %t21, %head : i1
+		%tail = seq.compreg %t18, %clk reset %rst, %c0_i1 : i1
+		%head = seq.compreg %t22, %clk reset %rst, %c0_i1 : i1
+		%c1_i2 = hw.constant 1 : i2
+		%t23 = comb.add %count, %c1_i2 : i2
+		%t24 = comb.sub %count, %c1_i2 : i2
+		%t25 = comb.xor %t14, %c1_i1 : i1
+		%t26 = comb.xor %t13, %c1_i1 : i1
+		%t27 = comb.and %t13, %t25 : i1
+		%t28 = comb.and %t14, %t26 : i1
+		%t29 = comb.mux %t28, %t24, %count : i2
+		%t30 = comb.mux %t27, %t23, %t29 : i2
+		%count = seq.compreg %t30, %clk reset %rst, %c0_i2 : i2
+		%t31 = comb.icmp eq %tail, %c0_i1 : i1
+		%t32 = comb.icmp eq %head, %c0_i1 : i1
+		%t33 = comb.and %t13, %t31 : i1
+		%t34 = comb.and %t14, %t32 : i1
+		%t35 = comb.xor %t34, %c1_i1 : i1
+		%t36 = comb.and %valid_0, %t35 : i1
+		%t37 = comb.or %t33, %t36 : i1
+		%t38 = comb.mux %t33, %alloc_key, %key_0 : i32
+		%c0_i32 = hw.constant 0 : i32
+		%valid_0 = seq.compreg %t37, %clk reset %rst, %c0_i1 : i1
+		%key_0 = seq.compreg %t38, %clk reset %rst, %c0_i32 : i32
+		%t39 = comb.or %rst, %t33 : i1
+		%t40 = comb.and %valid_0, %t32 : i1
+		%entry_0:3 = fsm.hw_instance "entry_0" @SB_core0(), clock %clk, reset %t39 : () -> (i1, i32, i32)
+		%t41 = comb.icmp eq %tail, %c1_i1 : i1
+		%t42 = comb.icmp eq %head, %c1_i1 : i1
+		%t43 = comb.and %t13, %t41 : i1
+		%t44 = comb.and %t14, %t42 : i1
+		%t45 = comb.xor %t44, %c1_i1 : i1
+		%t46 = comb.and %valid_1, %t45 : i1
+		%t47 = comb.or %t43, %t46 : i1
+		%t48 = comb.mux %t43, %alloc_key, %key_1 : i32
+		%valid_1 = seq.compreg %t47, %clk reset %rst, %c0_i1 : i1
+		%key_1 = seq.compreg %t48, %clk reset %rst, %c0_i32 : i32
+		%t49 = comb.or %rst, %t43 : i1
+		%t50 = comb.and %valid_1, %t42 : i1
+		%entry_1:3 = fsm.hw_instance "entry_1" @SB_core0(), clock %clk, reset %t49 : () -> (i1, i32, i32)
+		hw.output %t9, %t10, %tail, %head, %t6, %t8, %entry_0#0, %entry_0#1, %entry_0#2, %entry_1#0, %entry_1#1, %entry_1#2 : i1, i1, i1, i1, i1, i1, i1, i32, i32, i1, i32, i32
+	}
+
+	fsm.machine @LQ_core1(%MemSys_load_response_valid: i1, %MemSys_load_response_value: i32) -> (i1, i32) attributes {initialState = "Idle"} {
+		%addr = fsm.variable "addr" {initValue = 0 : i32 } : i32
+		fsm.state @Idle output {
+			%0 = hw.constant 1 : i1
+			fsm.output %0, %addr : i1, i32
+		} transitions {
+			fsm.transition @Idle guard {
+				%0 = hw.constant 1 : i1
+				%1 = comb.icmp eq %MemSys_load_response_valid, %0 : i1
+				%fsm_output = comb.and %1 : i1
+				fsm.return %fsm_output
+			} action {
+				fsm.update %addr, %MemSys_load_response_value : i32
+			}
+
+		}
+
+	}
+
+	fsm.machine @SB_core1() -> (i1, i32, i32) attributes {initialState = "Drain"} {
+		%data = fsm.variable "data" {initValue = 0 : i32 } : i32
+		fsm.state @Drain output {
+			%0 = hw.constant 1 : i1
+			fsm.output %0, %data, %data : i1, i32, i32
+		} transitions {
+			fsm.transition @Drain 
+
+		}
+
+	}
+
+	// Fifo queue with 2 entries of @SB_core1.
+	hw.module @SB_queue_core1(in %clk : !seq.clock, in %rst : i1, in %alloc : i1, in %alloc_key : i32, in %free : i1, in %search_key : i32, out full : i1, out empty : i1, out alloc_idx : i1, out free_idx : i1, out search_hit : i1, out search_idx : i1, out MemSys_store_valid_0 : i1, out MemSys_store_arg0_0 : i32, out MemSys_store_arg1_0 : i32, out MemSys_store_valid_1 : i1, out MemSys_store_arg0_1 : i32, out MemSys_store_arg1_1 : i32) {
+		%t1 = comb.icmp eq %key_0, %search_key : i32
+		%t2 = comb.and %valid_0, %t1 : i1
+		%t3 = comb.icmp eq %key_1, %search_key : i32
+		%t4 = comb.and %valid_1, %t3 : i1
+		%c0_i1 = hw.constant 0 : i1
+		%t5 = comb.or %c0_i1, %t2 : i1
+		%t6 = comb.or %t5, %t4 : i1
+		%c1_i1 = hw.constant 1 : i1
+		%t7 = comb.mux %t4, %c1_i1, %c0_i1 : i1
+		%t8 = comb.mux %t2, %c0_i1, %t7 : i1
+		%c2_i2 = hw.constant 2 : i2
+		%c0_i2 = hw.constant 0 : i2
+		%t9 = comb.icmp eq %count, %c2_i2 : i2
+		%t10 = comb.icmp eq %count, %c0_i2 : i2
+		%t11 = comb.xor %t9, %c1_i1 : i1
+		%t12 = comb.xor %t10, %c1_i1 : i1
+		%t13 = comb.and %alloc, %t11 : i1
+		%t14 = comb.and %free, %t12 : i1
+		%t15 = comb.add %tail, %c1_i1 : i1
+		%t16 = comb.icmp eq %tail, %c1_i1 : i1
+		%t17 = comb.mux %t16, %c0_i1, %t15 : i1
+		%t18 = comb.mux %t13, %t17, %tail : i1
+		%t19 = comb.add %head, %c1_i1 : i1
+		%t20 = comb.icmp eq %head, %c1_i1 : i1
+		%t21 = comb.mux %t20, %c0_i1, %t19 : i1
+		%t22 = comb.mux %t14, %t21, %head : i1
+		%tail = seq.compreg %t18, %clk reset %rst, %c0_i1 : i1
+		%head = seq.compreg %t22, %clk reset %rst, %c0_i1 : i1
+		%c1_i2 = hw.constant 1 : i2
+		%t23 = comb.add %count, %c1_i2 : i2
+		%t24 = comb.sub %count, %c1_i2 : i2
+		%t25 = comb.xor %t14, %c1_i1 : i1
+		%t26 = comb.xor %t13, %c1_i1 : i1
+		%t27 = comb.and %t13, %t25 : i1
+		%t28 = comb.and %t14, %t26 : i1
+		%t29 = comb.mux %t28, %t24, %count : i2
+		%t30 = comb.mux %t27, %t23, %t29 : i2
+		%count = seq.compreg %t30, %clk reset %rst, %c0_i2 : i2
+		%t31 = comb.icmp eq %tail, %c0_i1 : i1
+		%t32 = comb.icmp eq %head, %c0_i1 : i1
+		%t33 = comb.and %t13, %t31 : i1
+		%t34 = comb.and %t14, %t32 : i1
+		%t35 = comb.xor %t34, %c1_i1 : i1
+		%t36 = comb.and %valid_0, %t35 : i1
+		%t37 = comb.or %t33, %t36 : i1
+		%t38 = comb.mux %t33, %alloc_key, %key_0 : i32
+		%c0_i32 = hw.constant 0 : i32
+		%valid_0 = seq.compreg %t37, %clk reset %rst, %c0_i1 : i1
+		%key_0 = seq.compreg %t38, %clk reset %rst, %c0_i32 : i32
+		%t39 = comb.or %rst, %t33 : i1
+		%t40 = comb.and %valid_0, %t32 : i1
+		%entry_0:3 = fsm.hw_instance "entry_0" @SB_core1(), clock %clk, reset %t39 : () -> (i1, i32, i32)
+		%t41 = comb.icmp eq %tail, %c1_i1 : i1
+		%t42 = comb.icmp eq %head, %c1_i1 : i1
+		%t43 = comb.and %t13, %t41 : i1
+		%t44 = comb.and %t14, %t42 : i1
+		%t45 = comb.xor %t44, %c1_i1 : i1
+		%t46 = comb.and %valid_1, %t45 : i1
+		%t47 = comb.or %t43, %t46 : i1
+		%t48 = comb.mux %t43, %alloc_key, %key_1 : i32
+		%valid_1 = seq.compreg %t47, %clk reset %rst, %c0_i1 : i1
+		%key_1 = seq.compreg %t48, %clk reset %rst, %c0_i32 : i32
+		%t49 = comb.or %rst, %t43 : i1
+		%t50 = comb.and %valid_1, %t42 : i1
+		%entry_1:3 = fsm.hw_instance "entry_1" @SB_core1(), clock %clk, reset %t49 : () -> (i1, i32, i32)
+		hw.output %t9, %t10, %tail, %head, %t6, %t8, %entry_0#0, %entry_0#1, %entry_0#2, %entry_1#0, %entry_1#1, %entry_1#2 : i1, i1, i1, i1, i1, i1, i1, i32, i32, i1, i32, i32
+	}
+
+	hw.module @top(in %clk : !seq.clock, in %rst : i1, in %MemSys_load_response_valid : i1, in %MemSys_load_response_core : i1, in %MemSys_load_response_arg0 : i32, in %core0_SB_alloc : i1, in %core0_SB_alloc_key : i32, in %core0_SB_free : i1, in %core0_SB_search_key : i32, in %core1_SB_alloc : i1, in %core1_SB_alloc_key : i32, in %core1_SB_free : i1, in %core1_SB_search_key : i32, out core0_SB_full : i1, out core0_SB_empty : i1, out core0_SB_alloc_idx : i1, out core0_SB_free_idx : i1, out core0_SB_search_hit : i1, out core0_SB_search_idx : i1, out core1_SB_full : i1, out core1_SB_empty : i1, out core1_SB_alloc_idx : i1, out core1_SB_free_idx : i1, out core1_SB_search_hit : i1, out core1_SB_search_idx : i1, out MemSys_load_valid : i1, out MemSys_load_core : i1, out MemSys_load_arg0 : i32, out MemSys_store_valid : i1, out MemSys_store_core : i1, out MemSys_store_arg0 : i32, out MemSys_store_arg1 : i32) {
+		%LQ_core0_inst:2 = fsm.hw_instance "LQ_core0_inst" @LQ_core0(%t2, %MemSys_load_response_arg0), clock %clk, reset %rst : (i1, i32) -> (i1, i32)
+		%SB_queue_core0_inst:12 = hw.instance "SB_queue_core0_inst" @SB_queue_core0(clk: %clk: !seq.clock, rst: %rst: i1, alloc: %core0_SB_alloc: i1, alloc_key: %core0_SB_alloc_key: i32, free: %core0_SB_free: i1, search_key: %core0_SB_search_key: i32) -> (full: i1, empty: i1, alloc_idx: i1, free_idx: i1, search_hit: i1, search_idx: i1, MemSys_store_valid_0: i1, MemSys_store_arg0_0: i32, MemSys_store_arg1_0: i32, MemSys_store_valid_1: i1, MemSys_store_arg0_1: i32, MemSys_store_arg1_1: i32)
+		%LQ_core1_inst:2 = fsm.hw_instance "LQ_core1_inst" @LQ_core1(%t4, %MemSys_load_response_arg0), clock %clk, reset %rst : (i1, i32) -> (i1, i32)
+		%SB_queue_core1_inst:12 = hw.instance "SB_queue_core1_inst" @SB_queue_core1(clk: %clk: !seq.clock, rst: %rst: i1, alloc: %core1_SB_alloc: i1, alloc_key: %core1_SB_alloc_key: i32, free: %core1_SB_free: i1, search_key: %core1_SB_search_key: i32) -> (full: i1, empty: i1, alloc_idx: i1, free_idx: i1, search_hit: i1, search_idx: i1, MemSys_store_valid_0: i1, MemSys_store_arg0_0: i32, MemSys_store_arg1_0: i32, MemSys_store_valid_1: i1, MemSys_store_arg0_1: i32, MemSys_store_arg1_1: i32)
+		%c0_i1 = hw.constant 0 : i1
+		%t1 = comb.icmp eq %MemSys_load_response_core, %c0_i1 : i1
+		%t2 = comb.and %MemSys_load_response_valid, %t1 : i1
+		%c1_i1 = hw.constant 1 : i1
+		%t3 = comb.icmp eq %MemSys_load_response_core, %c1_i1 : i1
+		%t4 = comb.and %MemSys_load_response_valid, %t3 : i1
+		%t5 = comb.xor %MemSys_load_granted_0, %c1_i1 : i1
+		%t6 = comb.and %LQ_core0_inst#0, %t5 : i1
+		%t7 = comb.or %t6, %MemSys_load_pending_0 : i1
+		%t8 = comb.xor %c0_i1, %c1_i1 : i1
+		%t9 = comb.and %t7, %t8 : i1
+		%t10 = comb.or %c0_i1, %t7 : i1
+		%t11 = comb.xor %t9, %c1_i1 : i1
+		%t12 = comb.and %t7, %t11 : i1
+		%t13 = comb.or %MemSys_load_granted_0, %t9 : i1
+		%t14 = comb.or %c0_i1, %t2 : i1
+		%t15 = comb.xor %t14, %c1_i1 : i1
+		%t16 = comb.and %t13, %t15 : i1
+		%t17 = comb.and %LQ_core0_inst#0, %t16 : i1
+		%MemSys_load_pending_0 = seq.compreg %t12, %clk reset %rst, %c0_i1 : i1
+		%MemSys_load_granted_0 = seq.compreg %t17, %clk reset %rst, %c0_i1 : i1
+		%t18 = comb.mux %t6, %LQ_core0_inst#1, %MemSys_load_arg0_saved_0 : i32
+		%c0_i32 = hw.constant 0 : i32
+		%MemSys_load_arg0_saved_0 = seq.compreg %t18, %clk reset %rst, %c0_i32 : i32
+		%t19 = comb.xor %MemSys_load_granted_1, %c1_i1 : i1
+		%t20 = comb.and %LQ_core1_inst#0, %t19 : i1
+		%t21 = comb.or %t20, %MemSys_load_pending_1 : i1
+		%t22 = comb.xor %t10, %c1_i1 : i1
+		%t23 = comb.and %t21, %t22 : i1
+		%t24 = comb.or %t10, %t21 : i1
+		%t25 = comb.xor %t23, %c1_i1 : i1
+		%t26 = comb.and %t21, %t25 : i1
+		%t27 = comb.or %MemSys_load_granted_1, %t23 : i1
+		%t28 = comb.or %c0_i1, %t4 : i1
+		%t29 = comb.xor %t28, %c1_i1 : i1
+		%t30 = comb.and %t27, %t29 : i1
+		%t31 = comb.and %LQ_core1_inst#0, %t30 : i1
+		%MemSys_load_pending_1 = seq.compreg %t26, %clk reset %rst, %c0_i1 : i1
+		%MemSys_load_granted_1 = seq.compreg %t31, %clk reset %rst, %c0_i1 : i1
+		%t32 = comb.mux %t20, %LQ_core1_inst#1, %MemSys_load_arg0_saved_1 : i32
+		%MemSys_load_arg0_saved_1 = seq.compreg %t32, %clk reset %rst, %c0_i32 : i32
+		%t33 = comb.mux %t23, %c1_i1, %c0_i1 : i1
+		%t34 = comb.mux %t9, %c0_i1, %t33 : i1
+		%t35 = comb.mux %t23, %t32, %c0_i32 : i32
+		%t36 = comb.mux %t9, %t18, %t35 : i32
+		%t37 = comb.xor %MemSys_store_granted_0, %c1_i1 : i1
+		%t38 = comb.and %SB_queue_core0_inst#6, %t37 : i1
+		%t39 = comb.or %t38, %MemSys_store_pending_0 : i1
+		%t40 = comb.xor %c0_i1, %c1_i1 : i1
+		%t41 = comb.and %t39, %t40 : i1
+		%t42 = comb.or %c0_i1, %t39 : i1
+		%t43 = comb.xor %t41, %c1_i1 : i1
+		%t44 = comb.and %t39, %t43 : i1
+		%t45 = comb.or %MemSys_store_granted_0, %t41 : i1
+		%t46 = comb.xor %c0_i1, %c1_i1 : i1
+		%t47 = comb.and %t45, %t46 : i1
+		%t48 = comb.and %SB_queue_core0_inst#6, %t47 : i1
+		%MemSys_store_pending_0 = seq.compreg %t44, %clk reset %rst, %c0_i1 : i1
+		%MemSys_store_granted_0 = seq.compreg %t48, %clk reset %rst, %c0_i1 : i1
+		%t49 = comb.mux %t38, %SB_queue_core0_inst#7, %MemSys_store_arg0_saved_0 : i32
+		%MemSys_store_arg0_saved_0 = seq.compreg %t49, %clk reset %rst, %c0_i32 : i32
+		%t50 = comb.mux %t38, %SB_queue_core0_inst#8, %MemSys_store_arg1_saved_0 : i32
+		%MemSys_store_arg1_saved_0 = seq.compreg %t50, %clk reset %rst, %c0_i32 : i32
+		%t51 = comb.xor %MemSys_store_granted_1, %c1_i1 : i1
+		%t52 = comb.and %SB_queue_core0_inst#9, %t51 : i1
+		%t53 = comb.or %t52, %MemSys_store_pending_1 : i1
+		%t54 = comb.xor %t42, %c1_i1 : i1
+		%t55 = comb.and %t53, %t54 : i1
+		%t56 = comb.or %t42, %t53 : i1
+		%t57 = comb.xor %t55, %c1_i1 : i1
+		%t58 = comb.and %t53, %t57 : i1
+		%t59 = comb.or %MemSys_store_granted_1, %t55 : i1
+		%t60 = comb.xor %c0_i1, %c1_i1 : i1
+		%t61 = comb.and %t59, %t60 : i1
+		%t62 = comb.and %SB_queue_core0_inst#9, %t61 : i1
+		%MemSys_store_pending_1 = seq.compreg %t58, %clk reset %rst, %c0_i1 : i1
+		%MemSys_store_granted_1 = seq.compreg %t62, %clk reset %rst, %c0_i1 : i1
+		%t63 = comb.mux %t52, %SB_queue_core0_inst#10, %MemSys_store_arg0_saved_1 : i32
+		%MemSys_store_arg0_saved_1 = seq.compreg %t63, %clk reset %rst, %c0_i32 : i32
+		%t64 = comb.mux %t52, %SB_queue_core0_inst#11, %MemSys_store_arg1_saved_1 : i32
+		%MemSys_store_arg1_saved_1 = seq.compreg %t64, %clk reset %rst, %c0_i32 : i32
+		%t65 = comb.xor %MemSys_store_granted_2, %c1_i1 : i1
+		%t66 = comb.and %SB_queue_core1_inst#6, %t65 : i1
+		%t67 = comb.or %t66, %MemSys_store_pending_2 : i1
+		%t68 = comb.xor %t56, %c1_i1 : i1
+		%t69 = comb.and %t67, %t68 : i1
+		%t70 = comb.or %t56, %t67 : i1
+		%t71 = comb.xor %t69, %c1_i1 : i1
+		%t72 = comb.and %t67, %t71 : i1
+		%t73 = comb.or %MemSys_store_granted_2, %t69 : i1
+		%t74 = comb.xor %c0_i1, %c1_i1 : i1
+		%t75 = comb.and %t73, %t74 : i1
+		%t76 = comb.and %SB_queue_core1_inst#6, %t75 : i1
+		%MemSys_store_pending_2 = seq.compreg %t72, %clk reset %rst, %c0_i1 : i1
+		%MemSys_store_granted_2 = seq.compreg %t76, %clk reset %rst, %c0_i1 : i1
+		%t77 = comb.mux %t66, %SB_queue_core1_inst#7, %MemSys_store_arg0_saved_2 : i32
+		%MemSys_store_arg0_saved_2 = seq.compreg %t77, %clk reset %rst, %c0_i32 : i32
+		%t78 = comb.mux %t66, %SB_queue_core1_inst#8, %MemSys_store_arg1_saved_2 : i32
+		%MemSys_store_arg1_saved_2 = seq.compreg %t78, %clk reset %rst, %c0_i32 : i32
+		%t79 = comb.xor %MemSys_store_granted_3, %c1_i1 : i1
+		%t80 = comb.and %SB_queue_core1_inst#9, %t79 : i1
+		%t81 = comb.or %t80, %MemSys_store_pending_3 : i1
+		%t82 = comb.xor %t70, %c1_i1 : i1
+		%t83 = comb.and %t81, %t82 : i1
+		%t84 = comb.or %t70, %t81 : i1
+		%t85 = comb.xor %t83, %c1_i1 : i1
+		%t86 = comb.and %t81, %t85 : i1
+		%t87 = comb.or %MemSys_store_granted_3, %t83 : i1
+		%t88 = comb.xor %c0_i1, %c1_i1 : i1
+		%t89 = comb.and %t87, %t88 : i1
+		%t90 = comb.and %SB_queue_core1_inst#9, %t89 : i1
+		%MemSys_store_pending_3 = seq.compreg %t86, %clk reset %rst, %c0_i1 : i1
+		%MemSys_store_granted_3 = seq.compreg %t90, %clk reset %rst, %c0_i1 : i1
+		%t91 = comb.mux %t80, %SB_queue_core1_inst#10, %MemSys_store_arg0_saved_3 : i32
+		%MemSys_store_arg0_saved_3 = seq.compreg %t91, %clk reset %rst, %c0_i32 : i32
+		%t92 = comb.mux %t80, %SB_queue_core1_inst#11, %MemSys_store_arg1_saved_3 : i32
+		%MemSys_store_arg1_saved_3 = seq.compreg %t92, %clk reset %rst, %c0_i32 : i32
+		%t93 = comb.mux %t83, %c1_i1, %c0_i1 : i1
+		%t94 = comb.mux %t69, %c1_i1, %t93 : i1
+		%t95 = comb.mux %t55, %c0_i1, %t94 : i1
+		%t96 = comb.mux %t41, %c0_i1, %t95 : i1
+		%t97 = comb.mux %t83, %t91, %c0_i32 : i32
+		%t98 = comb.mux %t69, %t77, %t97 : i32
+		%t99 = comb.mux %t55, %t63, %t98 : i32
+		%t100 = comb.mux %t41, %t49, %t99 : i32
+		%t101 = comb.mux %t83, %t92, %c0_i32 : i32
+		%t102 = comb.mux %t69, %t78, %t101 : i32
+		%t103 = comb.mux %t55, %t64, %t102 : i32
+		%t104 = comb.mux %t41, %t50, %t103 : i32
+		hw.output %SB_queue_core0_inst#0, %SB_queue_core0_inst#1, %SB_queue_core0_inst#2, %SB_queue_core0_inst#3, %SB_queue_core0_inst#4, %SB_queue_core0_inst#5, %SB_queue_core1_inst#0, %SB_queue_core1_inst#1, %SB_queue_core1_inst#2, %SB_queue_core1_inst#3, %SB_queue_core1_inst#4, %SB_queue_core1_inst#5, %t24, %t34, %t36, %t84, %t96, %t100, %t104 : i1, i1, i1, i1, i1, i1, i1, i1, i1, i1, i1, i1, i1, i1, i32, i1, i1, i32, i32
+	}
+}
@@ -0,0 +1,70 @@
+// Lowers the state_queue of tests/queue/design.aql with each element ordering and compares the MLIR
+// with the snapshots next to it.
+
+mod common;
+
+use aql_circt::{ConvertOptions, GenerateOptions};
+use common::read;
+
+fn generate(ordering: &str) -> String {
+    let source = read("queue/design.aql").replace("order = FIFO", &format!("order = {}", ordering));
+    let ast = aql_circt::parse(&source).unwrap();
+    let machines = aql_circt::convert(ast, &ConvertOptions::default()).unwrap();
+    aql_circt::generate(&machines, &GenerateOptions::default()).unwrap()
+}
+
+fn registers(mlir: &str) -> Vec<&str> {
+    mlir.lines()
+        .filter(|line| line.contains("seq.compreg"))
+        .map(|line| line.trim().split(' ').next().unwrap())
+        .collect()
+}
+
+const SLOTS: [&str; 7] = [
+    "%count", "%valid_0", "%key_0", "%valid_1", "%key_1", "%valid_2", "%key_2",
+];
+
+#[test]
+fn fifo_matches_the_snapshot() {
+    assert_eq!(generate("FIFO"), read("queue/fifo.mlir"));
+}
+
+#[test]
+fn stack_matches_the_snapshot() {
+    assert_eq!(generate("Stack"), read("queue/stack.mlir"));
+}
+
+#[test]
+fn hash_matches_the_snapshot() {
+    assert_eq!(generate("Hash"), read("queue/hash.mlir"));
+}
+
+// Only FIFO keeps pointers, the stack allocates at its count and Hash picks a free slot.
+#[test]
+fn orderings_keep_their_own_registers() {
+    let fifo = generate("FIFO");
+    assert_eq!(registers(&fifo)[..2], ["%tail", "%head"]);
+    assert_eq!(registers(&fifo)[2..], SLOTS);
+    assert!(fifo.contains("// Fifo queue with 3 entries of @LQ."));
+    for ordering in ["Stack", "Hash"] {
+        let mlir = generate(ordering);
+        assert_eq!(registers(&mlir), SLOTS, "{}", ordering);
+        assert!(mlir.contains(&format!("// {} queue with 3 entries of @LQ.", ordering)));
+    }
+}
+
+// Every entry gets its own machine, raised by `head()` when `free` would release it.
+#[test]
+fn entries_are_instantiated() {
+    for ordering in ["FIFO", "Stack", "Hash"] {
+        let mlir = generate(ordering);
+        for i in 0..3 {
+            let instance = format!("%entry_{}:2 = fsm.hw_instance \"entry_{}\" @LQ(", i, i);
+            assert!(mlir.contains(&instance), "{} in {}", instance, ordering);
+        }
+        assert!(
+            mlir.contains("hw.module @LQ_queue(in %clk : !seq.clock, in %rst : i1, in %alloc : i1")
+        );
+        assert!(mlir.contains("out alloc_idx : i2, out free_idx : i2"));
+    }
+}
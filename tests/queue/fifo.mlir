@@ -0,0 +1,115 @@
+module {
+	fsm.machine @LQ(%LQ_head_valid: i1) -> (i1, i32) attributes {initialState = "Wait"} {
+		%addr = fsm.variable "addr" {initValue = 0 : i32 } : i32
+		fsm.state @Wait output {
+			%0 = hw.constant 0 : i1
+			%1 = hw.constant 0 : i32
+			fsm.output %0, %1 : i1, i32
+		} transitions {
+			fsm.transition @Load guard {
+				%0 = hw.constant 1 : i1
+				%1 = comb.icmp eq %LQ_head_valid, %0 : i1
+				%fsm_output = comb.and %1 : i1
+				fsm.return %fsm_output
+			}
+
+		}
+
+		fsm.state @Load output {
+			%0 = hw.constant 1 : i1
+			fsm.output %0, %addr : i1, i32
+		} transitions {
+			fsm.transition @Wait 
+
+		}
+
+	}
+
+	// Fifo queue with 3 entries of @LQ.
+	hw.module @LQ_queue(in %clk : !seq.clock, in %rst : i1, in %alloc : i1, in %alloc_key : i32, in %free : i1, in %search_key : i32, out full : i1, out empty : i1, out alloc_idx : i2, out free_idx : i2, out search_hit : i1, out search_idx : i2, out MemSys_load_valid_0 : i1, out MemSys_load_arg0_0 : i32, out MemSys_load_valid_1 : i1, out MemSys_load_arg0_1 : i32, out MemSys_load_valid_2 : i1, out MemSys_load_arg0_2 : i32) {
+		%t1 = comb.icmp eq %key_0, %search_key : i32
+		%t2 = comb.and %valid_0, %t1 : i1
+		%t3 = comb.icmp eq %key_1, %search_key : i32
+		%t4 = comb.and %valid_1, %t3 : i1
+		%t5 = comb.icmp eq %key_2, %search_key : i32
+		%t6 = comb.and %valid_2, %t5 : i1
+		%c0_i1 = hw.constant 0 : i1
+		%t7 = comb.or %c0_i1, %t2 : i1
+		%t8 = comb.or %t7, %t4 : i1
+		%t9 = comb.or %t8, %t6 : i1
+		%c0_i2 = hw.constant 0 : i2
+		%c2_i2 = hw.constant 2 : i2
+		%t10 = comb.mux %t6, %c2_i2, %c0_i2 : i2
+		%c1_i2 = hw.constant 1 : i2
+		%t11 = comb.mux %t4, %c1_i2, %t10 : i2
+		%t12 = comb.mux %t2, %c0_i2, %t11 : i2
+		%c3_i2 = hw.constant 3 : i2
+		%t13 = comb.icmp eq %count, %c3_i2 : i2
+		%t14 = comb.icmp eq %count, %c0_i2 : i2
+		%c1_i1 = hw.constant 1 : i1
+		%t15 = comb.xor %t13, %c1_i1 : i1
+		%t16 = comb.xor %t14, %c1_i1 : i1
+		%t17 = comb.and %alloc, %t15 : i1
+		%t18 = comb.and %free, %t16 : i1
+		%t19 = comb.add %tail, %c1_i2 : i2
+		%t20 = comb.icmp eq %tail, %c2_i2 : i2
+		%t21 = comb.mux %t20, %c0_i2, %t19 : i2
+		%t22 = comb.mux %t17, %t21, %tail : i2
+		%t23 = comb.add %head, %c1_i2 : i2
+		%t24 = comb.icmp eq %head, %c2_i2 : i2
+		%t25 = comb.mux %t24, %c0_i2, %t23 : i2
+		%t26 = comb.mux %t18, %t25, %head : i2
+		%tail = seq.compreg %t22, %clk reset %rst, %c0_i2 : i2
+		%head = seq.compreg %t26, %clk reset %rst, %c0_i2 : i2
+		%t27 = comb.add %count, %c1_i2 : i2
+		%t28 = comb.sub %count, %c1_i2 : i2
+		%t29 = comb.xor %t18, %c1_i1 : i1
+		%t30 = comb.xor %t17, %c1_i1 : i1
+		%t31 = comb.and %t17, %t29 : i1
+		%t32 = comb.and %t18, %t30 : i1
+		%t33 = comb.mux %t32, %t28, %count : i2
+		%t34 = comb.mux %t31, %t27, %t33 : i2
+		%count = seq.compreg %t34, %clk reset %rst, %c0_i2 : i2
+		%t35 = comb.icmp eq %tail, %c0_i2 : i2
+		%t36 = comb.icmp eq %head, %c0_i2 : i2
+		%t37 = comb.and %t17, %t35 : i1
+		%t38 = comb.and %t18, %t36 : i1
+		%t39 = comb.xor %t38, %c1_i1 : i1
+		%t40 = comb.and %valid_0, %t39 : i1
+		%t41 = comb.or %t37, %t40 : i1
+		%t42 = comb.mux %t37, %alloc_key, %key_0 : i32
+		%c0_i32 = hw.constant 0 : i32
+		%valid_0 = seq.compreg %t41, %clk reset %rst, %c0_i1 : i1
+		%key_0 = seq.compreg %t42, %clk reset %rst, %c0_i32 : i32
+		%t43 = comb.or %rst, %t37 : i1
+		%t44 = comb.and %valid_0, %t36 : i1
+		%entry_0:2 = fsm.hw_instance "entry_0" @LQ(%t44), clock %clk, reset %t43 : (i1) -> (i1, i32)
+		%t45 = comb.icmp eq %tail, %c1_i2 : i2
+		%t46 = comb.icmp eq %head, %c1_i2 : i2
+		%t47 = comb.and %t17, %t45 : i1
+		%t48 = comb.and %t18, %t46 : i1
+		%t49 = comb.xor %t48, %c1_i1 : i1
+		%t50 = comb.and %valid_1, %t49 : i1
+		%t51 = comb.or %t47, %t50 : i1
+		%t52 = comb.mux %t47, %alloc_key, %key_1 : i32
+		%valid_1 = seq.compreg %t51, %clk reset %rst, %c0_i1 : i1
+		%key_1 = seq.compreg %t52, %clk reset %rst, %c0_i32 : i32
+		%t53 = comb.or %rst, %t47 : i1
+		%t54 = comb.and %valid_1, %t46 : i1
+		%entry_1:2 = fsm.hw_instance "entry_1" @LQ(%t54), clock %clk, reset %t53 : (i1) -> (i1, i32)
+		%t55 = comb.icmp eq %tail, %c2_i2 : i2
+		%t56 = comb.icmp eq %head, %c2_i2 : i2
+		%t57 = comb.and %t17, %t55 : i1
+		%t58 = comb.and %t18, %t56 : i1
+		%t59 = comb.xor %t58, %c1_i1 : i1
+		%t60 = comb.and %valid_2, %t59 : i1
+		%t61 = comb.or %t57, %t60 : i1
+		%t62 = comb.mux %t57, %alloc_key, %key_2 : i32
+		%valid_2 = seq.compreg %t61, %clk reset %rst, %c0_i1 : i1
+		%key_2 = seq.compreg %t62, %clk reset %rst, %c0_i32 : i32
+		%t63 = comb.or %rst, %t57 : i1
+		%t64 = comb.and %valid_2, %t56 : i1
+		%entry_2:2 = fsm.hw_instance "entry_2" @LQ(%t64), clock %clk, reset %t63 : (i1) -> (i1, i32)
+		hw.output %t13, %t14, %tail, %head, %t9, %t12, %entry_0#0, %entry_0#1, %entry_1#0, %entry_1#1, %entry_2#0, %entry_2#1 : i1, i1, i2, i2, i1, i2, i1, i32, i1, i32, i1, i32
+	}
+}
@@ -0,0 +1,111 @@
+module {
+	fsm.machine @LQ(%LQ_head_valid: i1) -> (i1, i32) attributes {initialState = "Wait"} {
+		%addr = fsm.variable "addr" {initValue = 0 : i32 } : i32
+		fsm.state @Wait output {
+			%0 = hw.constant 0 : i1
+			%1 = hw.constant 0 : i32
+			fsm.output %0, %1 : i1, i32
+		} transitions {
+			fsm.transition @Load guard {
+				%0 = hw.constant 1 : i1
+				%1 = comb.icmp eq %LQ_head_valid, %0 : i1
+				%fsm_output = comb.and %1 : i1
+				fsm.return %fsm_output
+			}
+
+		}
+
+		fsm.state @Load output {
+			%0 = hw.constant 1 : i1
+			fsm.output %0, %addr : i1, i32
+		} transitions {
+			fsm.transition @Wait 
+
+		}
+
+	}
+
+	// Hash queue with 3 entries of @LQ.
+	hw.module @LQ_queue(in %clk : !seq.clock, in %rst : i1, in %alloc : i1, in %alloc_key : i32, in %free : i1, in %search_key : i32, out full : i1, out empty : i1, out alloc_idx : i2, out free_idx : i2, out search_hit : i1, out search_idx : i2, out MemSys_load_valid_0 : i1, out MemSys_load_arg0_0 : i32, out MemSys_load_valid_1 : i1, out MemSys_load_arg0_1 : i32, out MemSys_load_valid_2 : i1, out MemSys_load_arg0_2 : i32) {
+		%t1 = comb.icmp eq %key_0, %search_key : i32
+		%t2 = comb.and %valid_0, %t1 : i1
+		%t3 = comb.icmp eq %key_1, %search_key : i32
+		%t4 = comb.and %valid_1, %t3 : i1
+		%t5 = comb.icmp eq %key_2, %search_key : i32
+		%t6 = comb.and %valid_2, %t5 : i1
+		%c0_i1 = hw.constant 0 : i1
+		%t7 = comb.or %c0_i1, %t2 : i1
+		%t8 = comb.or %t7, %t4 : i1
+		%t9 = comb.or %t8, %t6 : i1
+		%c0_i2 = hw.constant 0 : i2
+		%c2_i2 = hw.constant 2 : i2
+		%t10 = comb.mux %t6, %c2_i2, %c0_i2 : i2
+		%c1_i2 = hw.constant 1 : i2
+		%t11 = comb.mux %t4, %c1_i2, %t10 : i2
+		%t12 = comb.mux %t2, %c0_i2, %t11 : i2
+		%c3_i2 = hw.constant 3 : i2
+		%t13 = comb.icmp eq %count, %c3_i2 : i2
+		%t14 = comb.icmp eq %count, %c0_i2 : i2
+		%c1_i1 = hw.constant 1 : i1
+		%t15 = comb.xor %t13, %c1_i1 : i1
+		%t16 = comb.xor %t14, %c1_i1 : i1
+		%t17 = comb.and %alloc, %t15 : i1
+		%t18 = comb.and %free, %t9 : i1
+		%t19 = comb.xor %valid_0, %c1_i1 : i1
+		%t20 = comb.xor %valid_1, %c1_i1 : i1
+		%t21 = comb.xor %valid_2, %c1_i1 : i1
+		%t22 = comb.mux %t21, %c2_i2, %c0_i2 : i2
+		%t23 = comb.mux %t20, %c1_i2, %t22 : i2
+		%t24 = comb.mux %t19, %c0_i2, %t23 : i2
+		%t25 = comb.add %count, %c1_i2 : i2
+		%t26 = comb.sub %count, %c1_i2 : i2
+		%t27 = comb.xor %t18, %c1_i1 : i1
+		%t28 = comb.xor %t17, %c1_i1 : i1
+		%t29 = comb.and %t17, %t27 : i1
+		%t30 = comb.and %t18, %t28 : i1
+		%t31 = comb.mux %t30, %t26, %count : i2
+		%t32 = comb.mux %t29, %t25, %t31 : i2
+		%count = seq.compreg %t32, %clk reset %rst, %c0_i2 : i2
+		%t33 = comb.icmp eq %t24, %c0_i2 : i2
+		%t34 = comb.icmp eq %t12, %c0_i2 : i2
+		%t35 = comb.and %t17, %t33 : i1
+		%t36 = comb.and %t18, %t34 : i1
+		%t37 = comb.xor %t36, %c1_i1 : i1
+		%t38 = comb.and %valid_0, %t37 : i1
+		%t39 = comb.or %t35, %t38 : i1
+		%t40 = comb.mux %t35, %alloc_key, %key_0 : i32
+		%c0_i32 = hw.constant 0 : i32
+		%valid_0 = seq.compreg %t39, %clk reset %rst, %c0_i1 : i1
+		%key_0 = seq.compreg %t40, %clk reset %rst, %c0_i32 : i32
+		%t41 = comb.or %rst, %t35 : i1
+		%t42 = comb.and %valid_0, %t34 : i1
+		%entry_0:2 = fsm.hw_instance "entry_0" @LQ(%t42), clock %clk, reset %t41 : (i1) -> (i1, i32)
+		%t43 = comb.icmp eq %t24, %c1_i2 : i2
+		%t44 = comb.icmp eq %t12, %c1_i2 : i2
+		%t45 = comb.and %t17, %t43 : i1
+		%t46 = comb.and %t18, %t44 : i1
+		%t47 = comb.xor %t46, %c1_i1 : i1
+		%t48 = comb.and %valid_1, %t47 : i1
+		%t49 = comb.or %t45, %t48 : i1
+		%t50 = comb.mux %t45, %alloc_key, %key_1 : i32
+		%valid_1 = seq.compreg %t49, %clk reset %rst, %c0_i1 : i1
+		%key_1 = seq.compreg %t50, %clk reset %rst, %c0_i32 : i32
+		%t51 = comb.or %rst, %t45 : i1
+		%t52 = comb.and %valid_1, %t44 : i1
+		%entry_1:2 = fsm.hw_instance "entry_1" @LQ(%t52), clock %clk, reset %t51 : (i1) -> (i1, i32)
+		%t53 = comb.icmp eq %t24, %c2_i2 : i2
+		%t54 = comb.icmp eq %t12, %c2_i2 : i2
+		%t55 = comb.and %t17, %t53 : i1
+		%t56 = comb.and %t18, %t54 : i1
+		%t57 = comb.xor %t56, %c1_i1 : i1
+		%t58 = comb.and %valid_2, %t57 : i1
+		%t59 = comb.or %t55, %t58 : i1
+		%t60 = comb.mux %t55, %alloc_key, %key_2 : i32
+		%valid_2 = seq.compreg %t59, %clk reset %rst, %c0_i1 : i1
+		%key_2 = seq.compreg %t60, %clk reset %rst, %c0_i32 : i32
+		%t61 = comb.or %rst, %t55 : i1
+		%t62 = comb.and %valid_2, %t54 : i1
+		%entry_2:2 = fsm.hw_instance "entry_2" @LQ(%t62), clock %clk, reset %t61 : (i1) -> (i1, i32)
+		hw.output %t13, %t14, %t24, %t12, %t9, %t12, %entry_0#0, %entry_0#1, %entry_1#0, %entry_1#1, %entry_2#0, %entry_2#1 : i1, i1, i2, i2, i1, i2, i1, i32, i1, i32, i1, i32
+	}
+}
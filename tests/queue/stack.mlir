@@ -0,0 +1,108 @@
+module {
+	fsm.machine @LQ(%LQ_head_valid: i1) -> (i1, i32) attributes {initialState = "Wait"} {
+		%addr = fsm.variable "addr" {initValue = 0 : i32 } : i32
+		fsm.state @Wait output {
+			%0 = hw.constant 0 : i1
+			%1 = hw.constant 0 : i32
+			fsm.output %0, %1 : i1, i32
+		} transitions {
+			fsm.transition @Load guard {
+				%0 = hw.constant 1 : i1
+				%1 = comb.icmp eq %LQ_head_valid, %0 : i1
+				%fsm_output = comb.and %1 : i1
+				fsm.return %fsm_output
+			}
+
+		}
+
+		fsm.state @Load output {
+			%0 = hw.constant 1 : i1
+			fsm.output %0, %addr : i1, i32
+		} transitions {
+			fsm.transition @Wait 
+
+		}
+
+	}
+
+	// Stack queue with 3 entries of @LQ.
+	hw.module @LQ_queue(in %clk : !seq.clock, in %rst : i1, in %alloc : i1, in %alloc_key : i32, in %free : i1, in %search_key : i32, out full : i1, out empty : i1, out alloc_idx : i2, out free_idx : i2, out search_hit : i1, out search_idx : i2, out MemSys_load_valid_0 : i1, out MemSys_load_arg0_0 : i32, out MemSys_load_valid_1 : i1, out MemSys_load_arg0_1 : i32, out MemSys_load_valid_2 : i1, out MemSys_load_arg0_2 : i32) {
+		%t1 = comb.icmp eq %key_0, %search_key : i32
+		%t2 = comb.and %valid_0, %t1 : i1
+		%t3 = comb.icmp eq %key_1, %search_key : i32
+		%t4 = comb.and %valid_1, %t3 : i1
+		%t5 = comb.icmp eq %key_2, %search_key : i32
+		%t6 = comb.and %valid_2, %t5 : i1
+		%c0_i1 = hw.constant 0 : i1
+		%t7 = comb.or %c0_i1, %t2 : i1
+		%t8 = comb.or %t7, %t4 : i1
+		%t9 = comb.or %t8, %t6 : i1
+		%c0_i2 = hw.constant 0 : i2
+		%c2_i2 = hw.constant 2 : i2
+		%t10 = comb.mux %t6, %c2_i2, %c0_i2 : i2
+		%c1_i2 = hw.constant 1 : i2
+		%t11 = comb.mux %t4, %c1_i2, %t10 : i2
+		%t12 = comb.mux %t2, %c0_i2, %t11 : i2
+		%c3_i2 = hw.constant 3 : i2
+		%t13 = comb.icmp eq %count, %c3_i2 : i2
+		%t14 = comb.icmp eq %count, %c0_i2 : i2
+		%c1_i1 = hw.constant 1 : i1
+		%t15 = comb.xor %t13, %c1_i1 : i1
+		%t16 = comb.xor %t14, %c1_i1 : i1
+		%t17 = comb.and %alloc, %t15 : i1
+		%t18 = comb.and %free, %t16 : i1
+		%t19 = comb.xor %free, %c1_i1 : i1
+		%t20 = comb.and %t17, %t19 : i1
+		%t21 = comb.sub %count, %c1_i2 : i2
+		%t22 = comb.add %count, %c1_i2 : i2
+		%t23 = comb.sub %count, %c1_i2 : i2
+		%t24 = comb.xor %t18, %c1_i1 : i1
+		%t25 = comb.xor %t20, %c1_i1 : i1
+		%t26 = comb.and %t20, %t24 : i1
+		%t27 = comb.and %t18, %t25 : i1
+		%t28 = comb.mux %t27, %t23, %count : i2
+		%t29 = comb.mux %t26, %t22, %t28 : i2
+		%count = seq.compreg %t29, %clk reset %rst, %c0_i2 : i2
+		%t30 = comb.icmp eq %count, %c0_i2 : i2
+		%t31 = comb.icmp eq %t21, %c0_i2 : i2
+		%t32 = comb.and %t20, %t30 : i1
+		%t33 = comb.and %t18, %t31 : i1
+		%t34 = comb.xor %t33, %c1_i1 : i1
+		%t35 = comb.and %valid_0, %t34 : i1
+		%t36 = comb.or %t32, %t35 : i1
+		%t37 = comb.mux %t32, %alloc_key, %key_0 : i32
+		%c0_i32 = hw.constant 0 : i32
+		%valid_0 = seq.compreg %t36, %clk reset %rst, %c0_i1 : i1
+		%key_0 = seq.compreg %t37, %clk reset %rst, %c0_i32 : i32
+		%t38 = comb.or %rst, %t32 : i1
+		%t39 = comb.and %valid_0, %t31 : i1
+		%entry_0:2 = fsm.hw_instance "entry_0" @LQ(%t39), clock %clk, reset %t38 : (i1) -> (i1, i32)
+		%t40 = comb.icmp eq %count, %c1_i2 : i2
+		%t41 = comb.icmp eq %t21, %c1_i2 : i2
+		%t42 = comb.and %t20, %t40 : i1
+		%t43 = comb.and %t18, %t41 : i1
+		%t44 = comb.xor %t43, %c1_i1 : i1
+		%t45 = comb.and %valid_1, %t44 : i1
+		%t46 = comb.or %t42, %t45 : i1
+		%t47 = comb.mux %t42, %alloc_key, %key_1 : i32
+		%valid_1 = seq.compreg %t46, %clk reset %rst, %c0_i1 : i1
+		%key_1 = seq.compreg %t47, %clk reset %rst, %c0_i32 : i32
+		%t48 = comb.or %rst, %t42 : i1
+		%t49 = comb.and %valid_1, %t41 : i1
+		%entry_1:2 = fsm.hw_instance "entry_1" @LQ(%t49), clock %clk, reset %t48 : (i1) -> (i1, i32)
+		%t50 = comb.icmp eq %count, %c2_i2 : i2
+		%t51 = comb.icmp eq %t21, %c2_i2 : i2
+		%t52 = comb.and %t20, %t50 : i1
+		%t53 = comb.and %t18, %t51 : i1
+		%t54 = comb.xor %t53, %c1_i1 : i1
+		%t55 = comb.and %valid_2, %t54 : i1
+		%t56 = comb.or %t52, %t55 : i1
+		%t57 = comb.mux %t52, %alloc_key, %key_2 : i32
+		%valid_2 = seq.compreg %t56, %clk reset %rst, %c0_i1 : i1
+		%key_2 = seq.compreg %t57, %clk reset %rst, %c0_i32 : i32
+		%t58 = comb.or %rst, %t52 : i1
+		%t59 = comb.and %valid_2, %t51 : i1
+		%entry_2:2 = fsm.hw_instance "entry_2" @LQ(%t59), clock %clk, reset %t58 : (i1) -> (i1, i32)
+		hw.output %t13, %t14, %count, %t21, %t9, %t12, %entry_0#0, %entry_0#1, %entry_1#0, %entry_1#1, %entry_2#0, %entry_2#1 : i1, i1, i2, i2, i1, i2, i1, i32, i1, i32, i1, i32
+	}
+}
@@ -0,0 +1,39 @@
+// `--top SB` on tests/top/design.aql wraps the second controller only.
+
+mod common;
+
+use aql_circt::{ConvertOptions, Error, GenerateOptions};
+use common::read;
+
+fn generate(controller: Option<&str>) -> Result<String, Error> {
+    let ast = aql_circt::parse(&read("top/design.aql"))?;
+    let machines = aql_circt::convert(ast, &ConvertOptions::default())?;
+    let mut options = GenerateOptions::default();
+    options.top = true;
+    options.top_controller = controller.map(String::from);
+    aql_circt::generate(&machines, &options)
+}
+
+#[test]
+fn top_matches_the_snapshot() {
+    assert_eq!(generate(Some("SB")).unwrap(), read("top/design.mlir"));
+}
+
+// Without a name every controller gets its module.
+#[test]
+fn every_controller_is_wrapped_by_default() {
+    let mlir = generate(None).unwrap();
+    assert!(mlir.contains("fsm.machine @LQ_fsm() -> (i1, i32)"));
+    assert!(mlir.contains("hw.module @LQ(in %clk : !seq.clock, in %rst : i1"));
+    assert!(mlir.contains("hw.module @SB(in %clk : !seq.clock, in %rst : i1"));
+}
+
+#[test]
+fn unknown_controllers_are_rejected() {
+    assert_eq!(
+        generate(Some("ROB")).err(),
+        Some(Error::Design(String::from(
+            "--top ROB is not a controller, expected one of LQ, SB."
+        )))
+    );
+}
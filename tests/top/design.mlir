@@ -0,0 +1,38 @@
+module {
+	fsm.machine @LQ() -> (i1, i32) attributes {initialState = "lq_idle"} {
+		%addr = fsm.variable "addr" {initValue = 0 : i32 } : i32
+		fsm.state @lq_idle output {
+			%0 = hw.constant 1 : i1
+			fsm.output %0, %addr : i1, i32
+		} transitions {
+			fsm.transition @lq_idle  action {
+				%0 = hw.constant 4 : i32
+				%1 = comb.add %addr, %0 : i32
+				fsm.update %addr, %1 : i32
+			}
+
+		}
+
+	}
+
+	fsm.machine @SB_fsm() -> (i1, i32) attributes {initialState = "sb_idle"} {
+		%data = fsm.variable "data" {initValue = 0 : i32 } : i32
+		fsm.state @sb_idle output {
+			%0 = hw.constant 1 : i1
+			fsm.output %0, %data : i1, i32
+		} transitions {
+			fsm.transition @sb_idle  action {
+				%0 = hw.constant 1 : i32
+				%1 = comb.add %data, %0 : i32
+				fsm.update %data, %1 : i32
+			}
+
+		}
+
+	}
+
+	hw.module @SB(in %clk : !seq.clock, in %rst : i1, out MemSys_store_valid : i1, out MemSys_store_arg0 : i32) {
+		%SB_inst:2 = fsm.hw_instance "SB_inst" @SB_fsm(), clock %clk, reset %rst : () -> (i1, i32)
+		hw.output %SB_inst#0, %SB_inst#1 : i1, i32
+	}
+}
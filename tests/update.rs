@@ -0,0 +1,71 @@
+// Actions on tests/update/design.aql, compared with the snapshot next to it: every update has the
+// type of its variable, and declarations in a state are temporaries rather than registers.
+
+mod common;
+
+use aql_circt::{interp::Interpreter, ConvertOptions, Error, GenerateOptions, StateMachine};
+use common::read;
+
+fn convert(source: &str) -> Vec<StateMachine> {
+    let ast = aql_circt::parse(source).unwrap();
+    aql_circt::convert(ast, &ConvertOptions::default()).unwrap()
+}
+
+fn generate(source: &str) -> Result<String, Error> {
+    aql_circt::generate(&convert(source), &GenerateOptions::default())
+}
+
+#[test]
+fn updates_match_the_snapshot() {
+    assert_eq!(
+        generate(&read("update/design.aql")).unwrap(),
+        read("update/design.mlir")
+    );
+}
+
+// A literal takes the width of the variable it's stored in, a narrower value is zero-extended.
+#[test]
+fn updates_have_the_type_of_their_variable() {
+    let mlir = generate(&read("update/design.aql")).unwrap();
+    assert!(mlir.contains("%2 = hw.constant 1 : i1\n\t\t\t\tfsm.update %busy, %2 : i1"));
+    assert!(mlir.contains("%0 = hw.constant 0 : i1\n\t\t\t\tfsm.update %busy, %0 : i1"));
+    assert!(mlir.contains("%3 = comb.concat %2, %1 : i32, i32\n\t\t\t\tfsm.update %wide, %3 : i64"));
+}
+
+// `next` is computed again in every region that reads it, guards included, and never stored.
+#[test]
+fn temporaries_are_not_registers() {
+    let mlir = generate(&read("update/design.aql")).unwrap();
+    assert!(!mlir.contains("%next"));
+    assert!(!mlir.contains("\"next\""));
+    let computed = mlir.matches("%1 = comb.add %count, %0 : i32").count();
+    assert_eq!(computed, 4);
+    assert!(mlir.contains("%3 = comb.icmp ugt %1, %2 : i32"));
+    assert!(mlir.contains("fsm.update %count, %1 : i32"));
+
+    let machines = convert(&read("update/design.aql"));
+    let variables: Vec<String> = Interpreter::new(&machines[0])
+        .unwrap()
+        .variables()
+        .into_iter()
+        .map(|(name, _)| name)
+        .collect();
+    assert_eq!(variables, ["count", "busy", "wide"]);
+}
+
+// A register resets to a constant, the build and the simulation both refuse anything else.
+#[test]
+fn initial_values_are_constants() {
+    let source = read("update/design.aql").replace("i64 wide = 0", "i64 wide = count + 1");
+    let expected = Error::Design(String::from(
+        "wide starts as count + 1, the initial value of a variable has to be a constant.",
+    ));
+    assert_eq!(generate(&source).err(), Some(expected.clone()));
+    let machines = convert(&source);
+    assert_eq!(Interpreter::new(&machines[0]).err(), Some(expected));
+
+    let source = read("update/design.aql").replace("i64 wide = 0", "i64 wide = -1");
+    assert!(generate(&source)
+        .unwrap()
+        .contains("%wide = fsm.variable \"wide\" {initValue = -1 : i64 } : i64"));
+}
@@ -0,0 +1,47 @@
+module {
+	fsm.machine @LQ() attributes {initialState = "Idle"} {
+		%count = fsm.variable "count" {initValue = 0 : i32 } : i32
+		%busy = fsm.variable "busy" {initValue = 0 : i1 } : i1
+		%wide = fsm.variable "wide" {initValue = 0 : i64 } : i64
+		fsm.state @Idle transitions {
+			fsm.transition @Done guard {
+				%0 = hw.constant 1 : i32
+				%1 = comb.add %count, %0 : i32
+				%2 = hw.constant 3 : i32
+				%3 = comb.icmp ugt %1, %2 : i32
+				%fsm_output = comb.and %3 : i1
+				fsm.return %fsm_output
+			} action {
+				%0 = hw.constant 1 : i32
+				%1 = comb.add %count, %0 : i32
+				%2 = hw.constant 1 : i1
+				fsm.update %busy, %2 : i1
+			}
+			fsm.transition @Idle guard {
+				%0 = hw.constant 1 : i32
+				%1 = comb.add %count, %0 : i32
+				%2 = hw.constant 3 : i32
+				%3 = comb.icmp ule %1, %2 : i32
+				%fsm_output = comb.and %3 : i1
+				fsm.return %fsm_output
+			} action {
+				%0 = hw.constant 1 : i32
+				%1 = comb.add %count, %0 : i32
+				%2 = hw.constant 0 : i32
+				%3 = comb.concat %2, %1 : i32, i32
+				fsm.update %wide, %3 : i64
+				fsm.update %count, %1 : i32
+			}
+
+		}
+
+		fsm.state @Done transitions {
+			fsm.transition @Idle  action {
+				%0 = hw.constant 0 : i1
+				fsm.update %busy, %0 : i1
+			}
+
+		}
+
+	}
+}
@@ -0,0 +1,75 @@
+// assert and assume in tests/verif/design.aql, in a state and for the whole controller, lowered to
+// verif ops with both backends and compared with the snapshots next to it.
+
+mod common;
+
+use aql_circt::{ConvertOptions, Format, GenerateOptions};
+use common::read;
+
+fn emit(format: Format) -> String {
+    let ast = aql_circt::parse(&read("verif/design.aql")).unwrap();
+    let machines = aql_circt::convert(ast, &ConvertOptions::default()).unwrap();
+    let mut options = GenerateOptions::default();
+    options.format = format;
+    aql_circt::generate(&machines, &options).unwrap()
+}
+
+#[test]
+fn fsm_matches_the_snapshot() {
+    assert_eq!(emit(Format::Mlir), read("verif/design.mlir"));
+}
+
+#[test]
+fn hw_matches_the_snapshot() {
+    assert_eq!(emit(Format::HwMlir), read("verif/design.hw.mlir"));
+}
+
+// The output region of a state checks its own properties, then the ones of the controller.
+#[test]
+fn states_check_the_controller_properties() {
+    let mlir = emit(Format::Mlir);
+    let labels = |state: &str| -> Vec<String> {
+        let start = mlir
+            .find(&format!("fsm.state @{} output {{", state))
+            .unwrap();
+        let end = start + mlir[start..].find("fsm.output").unwrap();
+        mlir[start..end]
+            .lines()
+            .filter_map(|line| line.trim().strip_prefix("verif."))
+            .map(|op| {
+                op.split(' ').next().unwrap().to_string() + " " + op.rsplit('"').nth(1).unwrap()
+            })
+            .collect()
+    };
+    assert_eq!(
+        labels("Idle"),
+        [
+            "assume LQ.Idle.assume0",
+            "assert LQ.assert0",
+            "assume LQ.assume1"
+        ]
+    );
+    assert_eq!(
+        labels("Busy"),
+        [
+            "assert LQ.Busy.assert0",
+            "assert LQ.Busy.assert1",
+            "assert LQ.assert0",
+            "assume LQ.assume1"
+        ]
+    );
+}
+
+// A state property only has to hold in its state, a controller one always does.
+#[test]
+fn hw_properties_are_guarded_by_their_state() {
+    let mlir = emit(Format::HwMlir);
+    assert!(mlir.contains("%t9 = comb.and %t1, %t8 : i1"));
+    assert!(mlir.contains(
+        "%t25 = comb.xor %t9, %c1_i1 : i1\n\t\tverif.assert %t25 label \"LQ.Busy.assert0\""
+    ));
+    assert!(mlir
+        .contains("%t27 = comb.xor %t23, %c1_i1 : i1\n\t\tverif.assert %t27 label \"LQ.assert0\""));
+    assert!(mlir.contains("%t21 = comb.or %t20, %t19 : i1"));
+    assert!(mlir.contains("verif.assume %t24 : i1"));
+}
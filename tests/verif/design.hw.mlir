@@ -0,0 +1,46 @@
+module {
+	hw.module @LQ(in %clk : !seq.clock, in %rst : i1) {
+		%c0_i1 = hw.constant 0 : i1
+		%c0_i32 = hw.constant 0 : i32
+		%c1_i1 = hw.constant 1 : i1
+		%t1 = comb.icmp eq %reg.state, %c1_i1 : i1
+		%c1_i32 = hw.constant 1 : i32
+		%t2 = comb.sub %reg.count, %c1_i32 : i32
+		%t3 = comb.mux %c1_i1, %c0_i1, %reg.state : i1
+		%t4 = comb.mux %c1_i1, %t2, %reg.count : i32
+		%t5 = comb.mux %t1, %t3, %reg.state : i1
+		%t6 = comb.mux %t1, %t4, %reg.count : i32
+		%t7 = comb.icmp ugt %reg.count, %c0_i32 : i32
+		%t8 = comb.xor %t7, %c1_i1 : i1
+		%t9 = comb.and %t1, %t8 : i1
+		%c4_i32 = hw.constant 4 : i32
+		%t10 = comb.icmp ule %reg.count, %c4_i32 : i32
+		%t11 = comb.xor %t10, %c1_i1 : i1
+		%t12 = comb.and %t1, %t11 : i1
+		%t13 = comb.icmp eq %reg.state, %c0_i1 : i1
+		%t14 = comb.add %reg.count, %c1_i32 : i32
+		%t15 = comb.mux %c1_i1, %c1_i1, %reg.state : i1
+		%t16 = comb.mux %c1_i1, %t14, %reg.count : i32
+		%t17 = comb.mux %t13, %t15, %t5 : i1
+		%t18 = comb.mux %t13, %t16, %t6 : i32
+		%t19 = comb.icmp ult %reg.count, %c4_i32 : i32
+		%t20 = comb.xor %t13, %c1_i1 : i1
+		%t21 = comb.or %t20, %t19 : i1
+		%c8_i32 = hw.constant 8 : i32
+		%t22 = comb.icmp ult %reg.count, %c8_i32 : i32
+		%t23 = comb.xor %t22, %c1_i1 : i1
+		%c7_i32 = hw.constant 7 : i32
+		%t24 = comb.icmp ne %reg.count, %c7_i32 : i32
+		%reg.state = seq.compreg %t17, %clk reset %rst, %c0_i1 : i1
+		%reg.count = seq.compreg %t18, %clk reset %rst, %c0_i32 : i32
+		verif.assume %t21 : i1
+		verif.assume %t24 : i1
+		%t25 = comb.xor %t9, %c1_i1 : i1
+		verif.assert %t25 label "LQ.Busy.assert0" : i1
+		%t26 = comb.xor %t12, %c1_i1 : i1
+		verif.assert %t26 label "LQ.Busy.assert1" : i1
+		%t27 = comb.xor %t23, %c1_i1 : i1
+		verif.assert %t27 label "LQ.assert0" : i1
+		hw.output
+	}
+}
@@ -0,0 +1,48 @@
+module {
+	fsm.machine @LQ() attributes {initialState = "Idle"} {
+		%count = fsm.variable "count" {initValue = 0 : i32 } : i32
+		fsm.state @Idle output {
+			%0 = hw.constant 4 : i32
+			%1 = comb.icmp ult %count, %0 : i32
+			verif.assume %1 label "LQ.Idle.assume0" : i1
+			%2 = hw.constant 8 : i32
+			%3 = comb.icmp ult %count, %2 : i32
+			verif.assert %3 label "LQ.assert0" : i1
+			%4 = hw.constant 7 : i32
+			%5 = comb.icmp ne %count, %4 : i32
+			verif.assume %5 label "LQ.assume1" : i1
+			fsm.output
+		} transitions {
+			fsm.transition @Busy  action {
+				%0 = hw.constant 1 : i32
+				%1 = comb.add %count, %0 : i32
+				fsm.update %count, %1 : i32
+			}
+
+		}
+
+		fsm.state @Busy output {
+			%0 = hw.constant 0 : i32
+			%1 = comb.icmp ugt %count, %0 : i32
+			verif.assert %1 label "LQ.Busy.assert0" : i1
+			%2 = hw.constant 4 : i32
+			%3 = comb.icmp ule %count, %2 : i32
+			verif.assert %3 label "LQ.Busy.assert1" : i1
+			%4 = hw.constant 8 : i32
+			%5 = comb.icmp ult %count, %4 : i32
+			verif.assert %5 label "LQ.assert0" : i1
+			%6 = hw.constant 7 : i32
+			%7 = comb.icmp ne %count, %6 : i32
+			verif.assume %7 label "LQ.assume1" : i1
+			fsm.output
+		} transitions {
+			fsm.transition @Idle  action {
+				%0 = hw.constant 1 : i32
+				%1 = comb.sub %count, %0 : i32
+				fsm.update %count, %1 : i32
+			}
+
+		}
+
+	}
+}